@@ -0,0 +1,128 @@
+use std::time::Instant;
+
+use axum::{
+    body::HttpBody,
+    extract::{MatchedPath, Request, State},
+    http::HeaderValue,
+    middleware::Next,
+    response::Response,
+};
+use serde::Serialize;
+
+use crate::request_stats;
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+
+/// Id of the request being handled by the current task, if any.
+pub fn current_request_id() -> Option<String> {
+    REQUEST_ID.try_with(|id| id.clone()).ok()
+}
+
+/// How access log records are written to stdout.
+#[derive(Debug, Clone, Copy, Default)]
+pub enum AccessLogFormat {
+    /// One human-readable line per request
+    #[default]
+    Pretty,
+    /// One JSON object per line, for log shippers
+    Json,
+}
+
+impl AccessLogFormat {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "pretty" => Some(AccessLogFormat::Pretty),
+            "json" => Some(AccessLogFormat::Json),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct AccessRecord<'a> {
+    timestamp: String,
+    request_id: &'a str,
+    method: &'a str,
+    // Route template such as `/api/todos/:id`, so records group by endpoint rather than by id
+    path: &'a str,
+    status: u16,
+    latency_ms: f64,
+    // Unknown for streamed responses
+    bytes: Option<u64>,
+    // There are no user accounts yet
+    user_id: Option<&'a str>,
+}
+
+impl AccessRecord<'_> {
+    fn pretty(&self) -> String {
+        let bytes = self
+            .bytes
+            .map_or_else(|| "-".to_string(), |bytes| format!("{bytes}B"));
+        format!(
+            "{} {} {} {} {:.1}ms {bytes} user={} req={}",
+            self.timestamp,
+            self.method,
+            self.path,
+            self.status,
+            self.latency_ms,
+            self.user_id.unwrap_or("-"),
+            self.request_id,
+        )
+    }
+}
+
+fn valid_request_id(value: &HeaderValue) -> Option<String> {
+    let id = value.to_str().ok()?;
+    (!id.is_empty() && id.len() <= 128).then(|| id.to_string())
+}
+
+/// Emits one access log record per request and echoes the request id in the response.
+pub async fn access_log_layer(
+    State(format): State<AccessLogFormat>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let started = Instant::now();
+    let request_id = request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(valid_request_id)
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    let method = request.method().clone();
+    let path = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|matched| matched.as_str().to_string())
+        .unwrap_or_else(|| request.uri().path().to_string());
+
+    let mut response = REQUEST_ID
+        .scope(request_id.clone(), next.run(request))
+        .await;
+
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    request_stats::record(response.status().as_u16());
+    let record = AccessRecord {
+        timestamp: chrono::Utc::now().to_rfc3339(),
+        request_id: &request_id,
+        method: method.as_str(),
+        path: &path,
+        status: response.status().as_u16(),
+        latency_ms: started.elapsed().as_secs_f64() * 1000.0,
+        bytes: response.body().size_hint().exact(),
+        user_id: None,
+    };
+    match format {
+        AccessLogFormat::Pretty => println!("{}", record.pretty()),
+        AccessLogFormat::Json => match serde_json::to_string(&record) {
+            Ok(line) => println!("{line}"),
+            Err(e) => eprintln!("Failed to serialize access log record: {e}"),
+        },
+    }
+    response
+}
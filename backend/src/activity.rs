@@ -0,0 +1,168 @@
+use axum::{
+    extract::{Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::Json,
+};
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+
+use crate::access_log;
+use crate::database::{self, ActivityActor, ActivityFilter, DbPool, Todo};
+
+/// How long activity is kept
+pub const RETENTION_DAYS: u32 = 90;
+pub const ACTIONS: [&str; 6] = [
+    "create",
+    "update",
+    "complete",
+    "delete",
+    "bulk_delete",
+    "escalate",
+];
+/// Longer `X-Actor` values are cut off
+pub const ACTOR_MAX_LEN: usize = 100;
+const DEFAULT_LIMIT: u32 = 100;
+const MAX_LIMIT: u32 = 1000;
+
+#[derive(Debug, Deserialize)]
+pub struct ActivityParams {
+    /// RFC 3339 time; only activity from then on
+    pub since: Option<String>,
+    /// The id of the last entry already seen
+    pub after: Option<i64>,
+    pub limit: Option<u32>,
+    pub todo_id: Option<String>,
+    pub action: Option<String>,
+}
+
+fn error(status: StatusCode, message: &str) -> (StatusCode, Json<Value>) {
+    (status, Json(json!({"error": message})))
+}
+
+fn actor(headers: &HeaderMap) -> ActivityActor {
+    let header = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+    };
+    ActivityActor {
+        actor: header("x-actor").map(|actor| actor.chars().take(ACTOR_MAX_LEN).collect()),
+        client_ip: header("x-forwarded-for").map(str::to_string),
+        user_agent: header(header::USER_AGENT.as_str()).map(str::to_string),
+        request_id: access_log::current_request_id(),
+    }
+}
+
+/// Logs a change. The change itself already happened, so a failure here is only logged.
+pub fn record(
+    pool: &DbPool,
+    headers: &HeaderMap,
+    action: &str,
+    todo_id: Option<&str>,
+    payload: Value,
+) {
+    write(pool, headers, action, todo_id, payload, None);
+}
+
+/// Logs a change to one todo, with the todo as the payload.
+pub fn record_todo(pool: &DbPool, headers: &HeaderMap, action: &str, todo: &Todo) {
+    let payload = serde_json::to_value(todo).unwrap_or(Value::Null);
+    record(pool, headers, action, Some(&todo.id), payload);
+}
+
+/// Logs an update of one todo, with the todo after it as the payload and the fields it changed.
+pub fn record_todo_change(
+    pool: &DbPool,
+    headers: &HeaderMap,
+    action: &str,
+    before: &Todo,
+    after: &Todo,
+) {
+    let payload = serde_json::to_value(after).unwrap_or(Value::Null);
+    let changes = database::revision_changes(before, after).unwrap_or_default();
+    write(
+        pool,
+        headers,
+        action,
+        Some(&after.id),
+        payload,
+        Some(&changes),
+    );
+}
+
+fn write(
+    pool: &DbPool,
+    headers: &HeaderMap,
+    action: &str,
+    todo_id: Option<&str>,
+    payload: Value,
+    changes: Option<&Map<String, Value>>,
+) {
+    let actor = actor(headers);
+    if let Err(e) = database::record_activity(pool, action, todo_id, &actor, &payload, changes) {
+        eprintln!("Failed to record {action} activity: {e}");
+    }
+}
+
+/// The activity log, oldest first. Pages are continued by passing `next_after` as `after`.
+pub async fn get_activity_handler(
+    State(pool): State<DbPool>,
+    Query(params): Query<ActivityParams>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let limit = params.limit.unwrap_or(DEFAULT_LIMIT);
+    if !(1..=MAX_LIMIT).contains(&limit) {
+        return Err(error(
+            StatusCode::BAD_REQUEST,
+            &format!("`limit` must be between 1 and {MAX_LIMIT}"),
+        ));
+    }
+    let since = params
+        .since
+        .as_deref()
+        .map(|since| {
+            database::parse_timestamp(since)
+                .map(database::format_timestamp)
+                .map_err(|_| {
+                    error(
+                        StatusCode::BAD_REQUEST,
+                        "`since` must be an RFC 3339 time, e.g. 2024-01-31T09:00:00Z",
+                    )
+                })
+        })
+        .transpose()?;
+    if let Some(action) = params.action.as_deref() {
+        if !ACTIONS.contains(&action) {
+            return Err(error(
+                StatusCode::BAD_REQUEST,
+                &format!("`action` must be one of: {}", ACTIONS.join(", ")),
+            ));
+        }
+    }
+    let after = params.after.unwrap_or(0);
+    if after < 0 {
+        return Err(error(
+            StatusCode::BAD_REQUEST,
+            "`after` must not be negative",
+        ));
+    }
+
+    let filter = ActivityFilter {
+        after,
+        since,
+        todo_id: params.todo_id,
+        action: params.action,
+    };
+    // One extra entry tells whether there is another page
+    let mut entries = database::get_activity(&pool, &filter, limit + 1)
+        .map_err(|_| error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to get activity"))?;
+    let has_more = entries.len() > limit as usize;
+    entries.truncate(limit as usize);
+    let next_after = entries.last().map_or(after, |entry| entry.id);
+    Ok(Json(json!({
+        "entries": entries,
+        "next_after": next_after,
+        "has_more": has_more,
+    })))
+}
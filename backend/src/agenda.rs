@@ -0,0 +1,158 @@
+use axum::{
+    extract::{Query, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Json, Response},
+};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::database::{self, DbPool, SortDirection, SortKey, Todo, TodoFilter};
+
+// Characters per line on a 58mm receipt printer
+const LINE_WIDTH: usize = 32;
+
+const ESC_INIT: &[u8] = &[0x1B, 0x40];
+const ESC_BOLD_ON: &[u8] = &[0x1B, 0x45, 0x01];
+const ESC_BOLD_OFF: &[u8] = &[0x1B, 0x45, 0x00];
+const ESC_ALIGN_CENTER: &[u8] = &[0x1B, 0x61, 0x01];
+const ESC_ALIGN_LEFT: &[u8] = &[0x1B, 0x61, 0x00];
+// Feed a few lines, then partial cut
+const GS_FEED_AND_CUT: &[u8] = &[0x1D, 0x56, 0x42, 0x03];
+
+#[derive(Debug, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum PrintFormat {
+    #[default]
+    Text,
+    Escpos,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PrintParams {
+    #[serde(default)]
+    pub format: PrintFormat,
+}
+
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut current = String::new();
+    for word in text.split_whitespace() {
+        if !current.is_empty() && current.chars().count() + 1 + word.chars().count() > width {
+            lines.push(std::mem::take(&mut current));
+        }
+        if !current.is_empty() {
+            current.push(' ');
+        }
+        current.push_str(word);
+    }
+    if !current.is_empty() {
+        lines.push(current);
+    }
+    lines
+}
+
+fn agenda_lines(todos: &[Todo]) -> Vec<String> {
+    let mut lines = Vec::new();
+    if todos.is_empty() {
+        lines.push("Nothing to do today.".to_string());
+    }
+    for todo in todos {
+        // Hanging indent so wrapped titles line up after the checkbox
+        for (i, line) in wrap(&todo.title, LINE_WIDTH - 4).into_iter().enumerate() {
+            let prefix = if i == 0 { "[ ] " } else { "    " };
+            lines.push(format!("{prefix}{line}"));
+        }
+    }
+    lines
+}
+
+fn render_text(date: &str, todos: &[Todo]) -> String {
+    let mut out = format!("TODAY {date}\n{}\n", "-".repeat(LINE_WIDTH));
+    for line in agenda_lines(todos) {
+        out.push_str(&line);
+        out.push('\n');
+    }
+    out
+}
+
+fn render_escpos(date: &str, todos: &[Todo]) -> Vec<u8> {
+    // Receipt printers use single-byte code pages, so anything outside ASCII is replaced
+    let ascii = |text: &str| -> Vec<u8> {
+        text.chars()
+            .map(|c| if c.is_ascii() { c as u8 } else { b'?' })
+            .collect()
+    };
+
+    let mut out = Vec::new();
+    out.extend_from_slice(ESC_INIT);
+    out.extend_from_slice(ESC_ALIGN_CENTER);
+    out.extend_from_slice(ESC_BOLD_ON);
+    out.extend(ascii(&format!("TODAY {date}\n")));
+    out.extend_from_slice(ESC_BOLD_OFF);
+    out.extend_from_slice(ESC_ALIGN_LEFT);
+    out.extend(ascii(&format!("{}\n", "-".repeat(LINE_WIDTH))));
+    for line in agenda_lines(todos) {
+        out.extend(ascii(&line));
+        out.push(b'\n');
+    }
+    out.extend_from_slice(GS_FEED_AND_CUT);
+    out
+}
+
+// The start of tomorrow in the server's time zone, as a normalized due date
+fn end_of_today() -> Option<String> {
+    let tomorrow = chrono::Local::now().date_naive().succ_opt()?;
+    let midnight = tomorrow.and_hms_opt(0, 0, 0)?;
+    let midnight = midnight.and_local_timezone(chrono::Local).earliest()?;
+    database::normalize_due_date(&midnight.to_rfc3339()).ok()
+}
+
+/// Today's agenda (open todos due today or overdue, earliest first) formatted for a receipt
+/// printer. Archived todos and todos deferred to a later start date are left out.
+pub async fn print_agenda_handler(
+    State(pool): State<DbPool>,
+    Query(params): Query<PrintParams>,
+) -> Response {
+    let Some(due_before) = end_of_today() else {
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Failed to work out today's date"})),
+        )
+            .into_response();
+    };
+    let filter = TodoFilter {
+        completed: Some(false),
+        archived: Some(false),
+        scheduled: Some(false),
+        due_before: Some(due_before),
+        sort: vec![SortKey {
+            column: "due_date",
+            direction: SortDirection::Asc,
+        }],
+        ..Default::default()
+    };
+    let todos = match database::get_todos(&pool, &filter) {
+        Ok(todos) => todos,
+        Err(_) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": "Failed to get todos"})),
+            )
+                .into_response()
+        }
+    };
+    let date = chrono::Local::now().format("%Y-%m-%d").to_string();
+
+    match params.format {
+        PrintFormat::Text => (
+            [(header::CONTENT_TYPE, "text/plain; charset=utf-8")],
+            render_text(&date, &todos),
+        )
+            .into_response(),
+        PrintFormat::Escpos => (
+            [(header::CONTENT_TYPE, "application/octet-stream")],
+            render_escpos(&date, &todos),
+        )
+            .into_response(),
+    }
+}
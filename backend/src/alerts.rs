@@ -0,0 +1,220 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::Json,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::database::{self, AlertRule, DbPool, METADATA_MAX_KEY_LEN};
+use crate::json_body::JsonBody;
+use crate::reminders::Notifier;
+use crate::request_stats;
+
+pub const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// `created`, `updated` and `deleted` count todo events; `error_rate` is the percentage of
+/// responses with a 5xx status.
+pub const METRICS: [&str; 4] = ["created", "updated", "deleted", "error_rate"];
+
+/// The error rate of a handful of requests says little, so it needs at least this many
+pub const MIN_RESPONSES: u64 = 20;
+
+#[derive(Debug, Deserialize)]
+pub struct CreateAlertRule {
+    pub name: String,
+    pub metric: String,
+    pub threshold: f64,
+    #[serde(default = "default_window_minutes")]
+    pub window_minutes: u32,
+}
+
+fn default_window_minutes() -> u32 {
+    60
+}
+
+fn error(status: StatusCode, message: &str) -> (StatusCode, Json<Value>) {
+    (status, Json(json!({"error": message})))
+}
+
+/// One line describing a firing rule, for notifications.
+pub fn describe(rule: &AlertRule, value: f64) -> String {
+    let measured = match rule.metric.as_str() {
+        "error_rate" => format!("error rate {value:.1}% (threshold {}%)", rule.threshold),
+        metric => format!("{value} todos {metric} (threshold {})", rule.threshold),
+    };
+    format!(
+        "Alert '{}': {measured} over the last {} minutes",
+        rule.name, rule.window_minutes
+    )
+}
+
+// The metric's current value and whether the rule should fire on it
+fn evaluate(
+    pool: &DbPool,
+    rule: &AlertRule,
+) -> Result<(f64, bool), Box<dyn std::error::Error + Send + Sync>> {
+    let value = match rule.metric.as_str() {
+        "error_rate" => {
+            let (responses, errors) = request_stats::counts(rule.window_minutes);
+            if responses < MIN_RESPONSES {
+                return Ok((0.0, false));
+            }
+            errors as f64 * 100.0 / responses as f64
+        }
+        metric if METRICS.contains(&metric) => {
+            database::count_recent_events(pool, &format!("todo.{metric}"), rule.window_minutes)?
+                as f64
+        }
+        metric => return Err(format!("unknown metric '{metric}'").into()),
+    };
+    Ok((value, value > rule.threshold))
+}
+
+// Notifies when a rule starts firing; it stays quiet until the metric drops back below
+fn check_alerts(
+    pool: &DbPool,
+    notifier: &dyn Notifier,
+) -> Result<usize, Box<dyn std::error::Error + Send + Sync>> {
+    let mut fired = 0;
+    for rule in database::get_alert_rules(pool)? {
+        let (value, firing) = match evaluate(pool, &rule) {
+            Ok(outcome) => outcome,
+            Err(e) => {
+                eprintln!("Alert rule '{}' could not be checked: {e}", rule.name);
+                continue;
+            }
+        };
+        let mut notified = false;
+        if firing && !rule.firing {
+            match notifier.alert(&rule, value) {
+                Ok(()) => notified = true,
+                // Not marked as firing, so the next check tries again
+                Err(e) => {
+                    eprintln!("Alert '{}' via {} failed: {e}", rule.name, notifier.name());
+                    continue;
+                }
+            }
+        }
+        database::record_alert_check(pool, &rule.id, value, firing, notified)?;
+        fired += usize::from(notified);
+    }
+    Ok(fired)
+}
+
+/// Background task that checks every alert rule once a minute.
+pub async fn run_alert_evaluator(pool: DbPool, notifier: Arc<dyn Notifier>) {
+    let mut interval = tokio::time::interval(CHECK_INTERVAL);
+    loop {
+        interval.tick().await;
+        let pool = pool.clone();
+        let notifier = notifier.clone();
+        match tokio::task::spawn_blocking(move || check_alerts(&pool, notifier.as_ref())).await {
+            Ok(Ok(_)) => {}
+            Ok(Err(e)) => eprintln!("Alert check failed: {e}"),
+            Err(e) => eprintln!("Alert check panicked: {e}"),
+        }
+    }
+}
+
+pub async fn get_alert_rules_handler(
+    State(pool): State<DbPool>,
+) -> Result<Json<Vec<AlertRule>>, (StatusCode, Json<Value>)> {
+    database::get_alert_rules(&pool).map(Json).map_err(|_| {
+        error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to get alert rules",
+        )
+    })
+}
+
+pub async fn create_alert_rule_handler(
+    State(pool): State<DbPool>,
+    JsonBody(body): JsonBody<CreateAlertRule>,
+) -> Result<(StatusCode, Json<AlertRule>), (StatusCode, Json<Value>)> {
+    let name = body.name.trim();
+    if !database::is_valid_metadata_key(name) {
+        return Err(error(
+            StatusCode::BAD_REQUEST,
+            &format!(
+                "Invalid rule name '{name}': use 1-{METADATA_MAX_KEY_LEN} characters of [A-Za-z0-9_-]"
+            ),
+        ));
+    }
+    if !METRICS.contains(&body.metric.as_str()) {
+        return Err(error(
+            StatusCode::BAD_REQUEST,
+            &format!("`metric` must be one of: {}", METRICS.join(", ")),
+        ));
+    }
+    let max_threshold = if body.metric == "error_rate" {
+        100.0
+    } else {
+        f64::MAX
+    };
+    if !(0.0..max_threshold).contains(&body.threshold) {
+        return Err(error(
+            StatusCode::BAD_REQUEST,
+            "`threshold` must be at least 0, and below 100 for `error_rate`",
+        ));
+    }
+    if !(1..=request_stats::MAX_WINDOW_MINUTES).contains(&body.window_minutes) {
+        return Err(error(
+            StatusCode::BAD_REQUEST,
+            &format!(
+                "`window_minutes` must be between 1 and {}",
+                request_stats::MAX_WINDOW_MINUTES
+            ),
+        ));
+    }
+
+    match database::find_alert_rule_by_name(&pool, name) {
+        Ok(Some(existing)) => {
+            return Err((
+                StatusCode::CONFLICT,
+                Json(
+                    json!({"error": "An alert rule with this name already exists", "existing": existing}),
+                ),
+            ))
+        }
+        Ok(None) => {}
+        Err(_) => {
+            return Err(error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to check rule name",
+            ))
+        }
+    }
+
+    database::create_alert_rule(
+        &pool,
+        name,
+        &body.metric,
+        body.threshold,
+        body.window_minutes,
+    )
+    .map(|rule| (StatusCode::CREATED, Json(rule)))
+    .map_err(|_| {
+        error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to create alert rule",
+        )
+    })
+}
+
+pub async fn delete_alert_rule_handler(
+    State(pool): State<DbPool>,
+    Path(id): Path<String>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    match database::delete_alert_rule(&pool, &id) {
+        Ok(true) => Ok(Json(json!({"message": "Alert rule deleted successfully"}))),
+        Ok(false) => Err(error(StatusCode::NOT_FOUND, "Alert rule not found")),
+        Err(_) => Err(error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to delete alert rule",
+        )),
+    }
+}
@@ -0,0 +1,390 @@
+use std::path::{Path as FsPath, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use axum::{
+    body::Body,
+    extract::{multipart::Field, Multipart, Path, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Json, Response},
+};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use tokio::io::AsyncWriteExt;
+use tokio_util::io::ReaderStream;
+
+use crate::config::Config;
+use crate::database::{self, Attachment, DbPool};
+
+const FILE_FIELD: &str = "file";
+const FILENAME_MAX_LEN: usize = 255;
+const DEFAULT_CONTENT_TYPE: &str = "application/octet-stream";
+// Files without a row that are younger than this may belong to an upload in progress
+const ORPHAN_GRACE: Duration = Duration::from_secs(60 * 60);
+
+// Held while a file's references are checked and changed, so an upload reusing a file and a
+// delete or cleanup removing it cannot interleave
+static BLOB_LOCK: Mutex<()> = Mutex::new(());
+
+fn error(status: StatusCode, message: &str) -> (StatusCode, Json<Value>) {
+    (status, Json(json!({"error": message})))
+}
+
+pub fn blob_path(dir: &FsPath, name: &str) -> PathBuf {
+    dir.join(name)
+}
+
+// Keeps only the last path component and drops control characters
+fn clean_filename(name: Option<&str>) -> String {
+    let name = name
+        .and_then(|name| name.rsplit(['/', '\\']).next())
+        .unwrap_or_default();
+    let name: String = name
+        .chars()
+        .filter(|c| !c.is_control())
+        .take(FILENAME_MAX_LEN)
+        .collect();
+    match name.trim() {
+        "" | "." | ".." => "attachment".to_string(),
+        name => name.to_string(),
+    }
+}
+
+// `filename` for old clients, `filename*` (RFC 6266) for the exact UTF-8 name
+pub fn content_disposition(filename: &str) -> String {
+    let fallback: String = filename
+        .chars()
+        .map(|c| match c {
+            ' '..='~' if c != '"' && c != '\\' => c,
+            _ => '_',
+        })
+        .collect();
+    let encoded: String = filename
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'.' | b'-' | b'_' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{byte:02X}"),
+        })
+        .collect();
+    format!("attachment; filename=\"{fallback}\"; filename*=UTF-8''{encoded}")
+}
+
+// Streams the field to `path`, stopping as soon as it grows past `max_bytes`.
+// Returns the size and the hex SHA-256 of the contents.
+async fn write_field(
+    field: &mut Field<'_>,
+    path: &FsPath,
+    max_bytes: u64,
+) -> Result<(u64, String), (StatusCode, Json<Value>)> {
+    let write_error = |_| {
+        error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to store attachment",
+        )
+    };
+    let mut file = tokio::fs::File::create(path).await.map_err(write_error)?;
+    let mut size = 0u64;
+    let mut hasher = Sha256::new();
+    while let Some(chunk) = field
+        .chunk()
+        .await
+        .map_err(|e| error(StatusCode::BAD_REQUEST, &format!("Invalid upload: {e}")))?
+    {
+        size += chunk.len() as u64;
+        if size > max_bytes {
+            return Err(error(
+                StatusCode::PAYLOAD_TOO_LARGE,
+                &format!("Attachments can be at most {max_bytes} bytes"),
+            ));
+        }
+        hasher.update(&chunk);
+        file.write_all(&chunk).await.map_err(write_error)?;
+    }
+    file.flush().await.map_err(write_error)?;
+    Ok((size, hex::encode(hasher.finalize())))
+}
+
+// Moves the upload into place under its hash, or drops it when the same contents are already
+// stored, and records the attachment
+fn store_blob(
+    pool: &DbPool,
+    dir: &FsPath,
+    upload: &FsPath,
+    attachment: &Attachment,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let _guard = BLOB_LOCK.lock().unwrap();
+    let path = blob_path(dir, attachment.blob_name());
+    if path.exists() {
+        let _ = std::fs::remove_file(upload);
+    } else {
+        std::fs::rename(upload, &path)?;
+    }
+    // A file left without a reference is removed by the orphan sweep
+    database::create_attachment(pool, attachment)
+}
+
+// Deletes the attachment, and its file once no other attachment shares it
+fn release_blob(
+    pool: &DbPool,
+    dir: &FsPath,
+    attachment: &Attachment,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let _guard = BLOB_LOCK.lock().unwrap();
+    database::delete_attachment(pool, &attachment.id)?;
+    if database::count_blob_references(pool, attachment.blob_name())? == 0 {
+        // A file left behind is removed by the orphan sweep
+        let _ = std::fs::remove_file(blob_path(dir, attachment.blob_name()));
+    }
+    Ok(())
+}
+
+/// Attachments of a todo, oldest first.
+pub async fn get_attachments_handler(
+    State(pool): State<DbPool>,
+    Path(todo_id): Path<String>,
+) -> Result<Json<Vec<Attachment>>, (StatusCode, Json<Value>)> {
+    match database::get_todo(&pool, &todo_id) {
+        Ok(Some(_)) => {}
+        Ok(None) => return Err(error(StatusCode::NOT_FOUND, "Todo not found")),
+        Err(_) => {
+            return Err(error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to get todo",
+            ))
+        }
+    }
+    database::get_attachments(&pool, &todo_id)
+        .map(Json)
+        .map_err(|_| {
+            error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to get attachments",
+            )
+        })
+}
+
+/// Uploads one file, sent as the `file` field of a `multipart/form-data` body. Contents that
+/// are already stored, e.g. the same file attached to another todo, are not stored again.
+pub async fn upload_attachment_handler(
+    State(pool): State<DbPool>,
+    State(config): State<Arc<Config>>,
+    Path(todo_id): Path<String>,
+    mut multipart: Multipart,
+) -> Result<(StatusCode, Json<Attachment>), (StatusCode, Json<Value>)> {
+    match database::get_todo(&pool, &todo_id) {
+        Ok(Some(_)) => {}
+        Ok(None) => return Err(error(StatusCode::NOT_FOUND, "Todo not found")),
+        Err(_) => {
+            return Err(error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to get todo",
+            ))
+        }
+    }
+
+    let mut field = loop {
+        match multipart.next_field().await {
+            Ok(Some(field)) if field.name() == Some(FILE_FIELD) => break field,
+            Ok(Some(_)) => continue,
+            Ok(None) => {
+                return Err(error(
+                    StatusCode::BAD_REQUEST,
+                    "Send the file as the `file` field of a multipart/form-data body",
+                ))
+            }
+            Err(e) => {
+                return Err(error(
+                    StatusCode::BAD_REQUEST,
+                    &format!("Invalid upload: {e}"),
+                ))
+            }
+        }
+    };
+
+    let upload = receive_field(&config, &mut field).await?;
+    store_upload(&pool, &config, &todo_id, upload)
+        .map(|attachment| (StatusCode::CREATED, Json(attachment)))
+}
+
+/// A file received into the attachments directory under a temporary name, not yet attached to
+/// a todo. Pass it to `store_upload`, or to `discard_upload` if it is not needed after all.
+pub struct Upload {
+    id: String,
+    path: PathBuf,
+    filename: String,
+    content_type: String,
+    size: u64,
+    sha256: String,
+}
+
+/// Streams a multipart file field to disk, enforcing the attachment size limit.
+pub async fn receive_field(
+    config: &Config,
+    field: &mut Field<'_>,
+) -> Result<Upload, (StatusCode, Json<Value>)> {
+    let id = uuid::Uuid::new_v4().to_string();
+    let filename = clean_filename(field.file_name());
+    let content_type = field
+        .content_type()
+        .unwrap_or(DEFAULT_CONTENT_TYPE)
+        .to_string();
+    tokio::fs::create_dir_all(&config.attachments_dir)
+        .await
+        .map_err(|_| {
+            error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to store attachment",
+            )
+        })?;
+    // Written under a temporary name until the hash of the contents is known
+    let path = blob_path(&config.attachments_dir, &format!(".upload-{id}"));
+    match write_field(field, &path, config.attachment_max_bytes).await {
+        Ok((size, sha256)) => Ok(Upload {
+            id,
+            path,
+            filename,
+            content_type,
+            size,
+            sha256,
+        }),
+        Err(e) => {
+            let _ = tokio::fs::remove_file(&path).await;
+            Err(e)
+        }
+    }
+}
+
+/// Attaches a received file to a todo. Contents that are already stored, e.g. the same file
+/// attached to another todo, are not stored again.
+pub fn store_upload(
+    pool: &DbPool,
+    config: &Config,
+    todo_id: &str,
+    upload: Upload,
+) -> Result<Attachment, (StatusCode, Json<Value>)> {
+    let attachment = Attachment {
+        id: upload.id,
+        todo_id: todo_id.to_string(),
+        filename: upload.filename,
+        content_type: upload.content_type,
+        size: upload.size as i64,
+        sha256: Some(upload.sha256),
+        created_at: chrono::Utc::now().to_rfc3339(),
+    };
+    if store_blob(pool, &config.attachments_dir, &upload.path, &attachment).is_err() {
+        let _ = std::fs::remove_file(&upload.path);
+        return Err(error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to store attachment",
+        ));
+    }
+    Ok(attachment)
+}
+
+/// Removes a received file that will not be attached.
+pub async fn discard_upload(upload: Upload) {
+    let _ = tokio::fs::remove_file(&upload.path).await;
+}
+
+/// Streams the file back with the type and name it was uploaded with.
+pub async fn download_attachment_handler(
+    State(pool): State<DbPool>,
+    State(config): State<Arc<Config>>,
+    Path(id): Path<String>,
+) -> Result<Response, (StatusCode, Json<Value>)> {
+    let attachment = match database::get_attachment(&pool, &id) {
+        Ok(Some(attachment)) => attachment,
+        Ok(None) => return Err(error(StatusCode::NOT_FOUND, "Attachment not found")),
+        Err(_) => {
+            return Err(error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to get attachment",
+            ))
+        }
+    };
+    let file = tokio::fs::File::open(blob_path(&config.attachments_dir, attachment.blob_name()))
+        .await
+        .map_err(|_| {
+            error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Attachment contents are missing",
+            )
+        })?;
+    Ok((
+        [
+            (header::CONTENT_TYPE, attachment.content_type),
+            (header::CONTENT_LENGTH, attachment.size.to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                content_disposition(&attachment.filename),
+            ),
+            // Uploaded HTML must not be rendered as part of this site
+            (header::X_CONTENT_TYPE_OPTIONS, "nosniff".to_string()),
+        ],
+        Body::from_stream(ReaderStream::new(file)),
+    )
+        .into_response())
+}
+
+pub async fn delete_attachment_handler(
+    State(pool): State<DbPool>,
+    State(config): State<Arc<Config>>,
+    Path(id): Path<String>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let attachment = match database::get_attachment(&pool, &id) {
+        Ok(Some(attachment)) => attachment,
+        Ok(None) => return Err(error(StatusCode::NOT_FOUND, "Attachment not found")),
+        Err(_) => {
+            return Err(error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to get attachment",
+            ))
+        }
+    };
+    match release_blob(&pool, &config.attachments_dir, &attachment) {
+        Ok(()) => Ok(Json(json!({"message": "Attachment deleted successfully"}))),
+        Err(_) => Err(error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to delete attachment",
+        )),
+    }
+}
+
+/// Removes files no attachment refers to any more, e.g. because their todos were purged, and
+/// abandoned uploads. Returns how many were removed.
+pub fn remove_orphaned_files(
+    pool: &DbPool,
+    dir: &FsPath,
+) -> Result<usize, Box<dyn std::error::Error + Send + Sync>> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        // Nothing was uploaded yet
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e.into()),
+    };
+    let mut removed = 0;
+    for entry in entries {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        let age = metadata
+            .modified()
+            .ok()
+            .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+            .unwrap_or_default();
+        if !metadata.is_file() || age < ORPHAN_GRACE {
+            continue;
+        }
+        let name = entry.file_name();
+        let Some(name) = name.to_str() else {
+            continue;
+        };
+        let _guard = BLOB_LOCK.lock().unwrap();
+        if database::count_blob_references(pool, name)? == 0 {
+            std::fs::remove_file(entry.path())?;
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
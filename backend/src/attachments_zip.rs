@@ -0,0 +1,200 @@
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use async_zip::{
+    error::ZipError, tokio::write::ZipFileWriter, Compression, ZipDateTime, ZipEntryBuilder,
+};
+use axum::{
+    body::Body,
+    extract::{Path, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Json, Response},
+};
+use chrono::{DateTime, Utc};
+use serde_json::{json, Value};
+use tokio::io::DuplexStream;
+use tokio::sync::mpsc;
+use tokio_stream::{wrappers::ReceiverStream, StreamExt};
+use tokio_util::{compat::FuturesAsyncWriteCompatExt, io::ReaderStream};
+
+use crate::attachments;
+use crate::config::Config;
+use crate::database::{self, DbPool};
+use crate::lists;
+
+pub const CONTENT_TYPE: &str = "application/zip";
+pub const MANIFEST_NAME: &str = "manifest.json";
+// Bytes buffered between the archive writer and the client
+const PIPE_BUFFER_SIZE: usize = 64 * 1024;
+const FOLDER_NAME_MAX_LEN: usize = 100;
+
+fn error(status: StatusCode, message: &str) -> (StatusCode, Json<Value>) {
+    (status, Json(json!({"error": message})))
+}
+
+// Something usable as a folder or file name on any system
+fn path_component(name: &str, max_len: usize) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .take(max_len)
+        .collect();
+    match name.trim().trim_start_matches('.') {
+        "" => "untitled".to_string(),
+        name => name.to_string(),
+    }
+}
+
+// Appends " (2)", " (3)", ... before the extension until the path is not taken
+fn unique_path(taken: &mut HashSet<String>, folder: &str, name: &str) -> String {
+    let (stem, extension) = match name.rfind('.') {
+        Some(dot) if dot > 0 => name.split_at(dot),
+        _ => (name, ""),
+    };
+    let mut path = format!("{folder}{name}");
+    let mut n = 2;
+    while !taken.insert(path.to_lowercase()) {
+        path = format!("{folder}{stem} ({n}){extension}");
+        n += 1;
+    }
+    path
+}
+
+struct ArchiveFile {
+    path: String,
+    source: PathBuf,
+    modified: DateTime<Utc>,
+}
+
+fn zip_error(e: ZipError) -> std::io::Error {
+    match e {
+        ZipError::UpstreamReadError(e) => e,
+        e => std::io::Error::other(e),
+    }
+}
+
+fn entry(path: &str, modified: DateTime<Utc>) -> ZipEntryBuilder {
+    ZipEntryBuilder::new(path.to_string().into(), Compression::Stored)
+        .last_modification_date(ZipDateTime::from_chrono(&modified))
+}
+
+// Contents are stored uncompressed; attachments are mostly compressed formats already
+async fn write_archive(
+    writer: DuplexStream,
+    manifest: Vec<u8>,
+    files: Vec<ArchiveFile>,
+) -> Result<(), std::io::Error> {
+    let mut zip = ZipFileWriter::with_tokio(writer);
+    zip.write_entry_whole(entry(MANIFEST_NAME, Utc::now()), &manifest)
+        .await
+        .map_err(zip_error)?;
+    for file in files {
+        let mut source = tokio::fs::File::open(&file.source).await?;
+        let mut contents = zip
+            .write_entry_stream(entry(&file.path, file.modified))
+            .await
+            .map_err(zip_error)?
+            .compat_write();
+        tokio::io::copy(&mut source, &mut contents).await?;
+        contents.into_inner().close().await.map_err(zip_error)?;
+    }
+    zip.close().await.map_err(zip_error)?;
+    Ok(())
+}
+
+/// Streams every attachment of the list's todos as one zip archive, one folder per todo,
+/// with a `manifest.json` describing each file.
+pub async fn download_list_attachments_handler(
+    State(pool): State<DbPool>,
+    State(config): State<Arc<Config>>,
+    Path(id): Path<String>,
+) -> Result<Response, (StatusCode, Json<Value>)> {
+    let list = lists::find_list(&pool, &id)?;
+    let attachments = database::get_list_attachments(&pool, &id).map_err(|_| {
+        error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to get attachments",
+        )
+    })?;
+
+    let mut taken = HashSet::from([MANIFEST_NAME.to_string()]);
+    let mut folders: Vec<(String, String)> = Vec::new();
+    let mut entries = Vec::with_capacity(attachments.len());
+    let mut files = Vec::with_capacity(attachments.len());
+    for (attachment, todo_title) in attachments {
+        let folder = match folders
+            .iter()
+            .find(|(todo_id, _)| *todo_id == attachment.todo_id)
+        {
+            Some((_, folder)) => folder.clone(),
+            None => {
+                let name = path_component(&todo_title, FOLDER_NAME_MAX_LEN);
+                let folder = unique_path(&mut taken, "", &name) + "/";
+                folders.push((attachment.todo_id.clone(), folder.clone()));
+                folder
+            }
+        };
+        let path = unique_path(
+            &mut taken,
+            &folder,
+            &path_component(&attachment.filename, usize::MAX),
+        );
+        entries.push(json!({
+            "path": path,
+            "id": attachment.id,
+            "todo_id": attachment.todo_id,
+            "todo_title": todo_title,
+            "filename": attachment.filename,
+            "content_type": attachment.content_type,
+            "size": attachment.size,
+            "sha256": attachment.sha256,
+            "created_at": attachment.created_at,
+        }));
+        files.push(ArchiveFile {
+            source: attachments::blob_path(&config.attachments_dir, attachment.blob_name()),
+            modified: database::parse_timestamp(&attachment.created_at)
+                .unwrap_or_else(|_| Utc::now()),
+            path,
+        });
+    }
+    let manifest = serde_json::to_vec_pretty(&json!({
+        "list": {"id": list.id, "name": list.name},
+        "exported_at": database::now_timestamp(),
+        "attachments": entries,
+    }))
+    .map_err(|_| {
+        error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to build manifest",
+        )
+    })?;
+    let (reader, writer) = tokio::io::duplex(PIPE_BUFFER_SIZE);
+    // Headers are already sent, so the best we can do is abort the body after what was written
+    let (failed_tx, failed_rx) = mpsc::channel(1);
+    tokio::spawn(async move {
+        if let Err(e) = write_archive(writer, manifest, files).await {
+            let _ = failed_tx.send(Err(e)).await;
+        }
+    });
+
+    let filename = format!(
+        "{}-attachments.zip",
+        path_component(&list.name, FOLDER_NAME_MAX_LEN)
+    );
+    Ok((
+        [
+            (header::CONTENT_TYPE, CONTENT_TYPE.to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                attachments::content_disposition(&filename),
+            ),
+        ],
+        Body::from_stream(ReaderStream::new(reader).chain(ReceiverStream::new(failed_rx))),
+    )
+        .into_response())
+}
@@ -0,0 +1,254 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::Json,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::database::{self, DbPool};
+
+// Changes are read back from the events table, like the realtime relay does
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+const EVENT_BATCH: u32 = 1000;
+const DEFAULT_LIMIT: usize = 10;
+pub const MAX_LIMIT: usize = 50;
+
+#[derive(Debug, Deserialize)]
+pub struct AutocompleteParams {
+    pub q: Option<String>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TagSuggestion {
+    pub name: String,
+    pub color: Option<String>,
+    pub todo_count: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TodoSuggestion {
+    pub id: String,
+    pub title: String,
+}
+
+#[derive(Debug, Default)]
+struct Index {
+    /// Title of every todo outside the trash, by id
+    titles: HashMap<String, String>,
+    /// Lowercased title word to the todos containing it; sorted for prefix lookups
+    words: BTreeMap<String, BTreeSet<String>>,
+    /// Keyed by lowercased name
+    tags: BTreeMap<String, TagSuggestion>,
+}
+
+fn words(title: &str) -> BTreeSet<String> {
+    title
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+// Everything in `map` whose key starts with `prefix`
+fn with_prefix<V>(
+    map: &BTreeMap<String, V>,
+    prefix: String,
+) -> impl Iterator<Item = (&String, &V)> {
+    map.range(prefix.clone()..)
+        .take_while(move |(key, _)| key.starts_with(&prefix))
+}
+
+impl Index {
+    fn remove_todo(&mut self, id: &str) {
+        let Some(title) = self.titles.remove(id) else {
+            return;
+        };
+        for word in words(&title) {
+            if let Some(ids) = self.words.get_mut(&word) {
+                ids.remove(id);
+                if ids.is_empty() {
+                    self.words.remove(&word);
+                }
+            }
+        }
+    }
+
+    fn insert_todo(&mut self, id: String, title: String) {
+        self.remove_todo(&id);
+        for word in words(&title) {
+            self.words.entry(word).or_default().insert(id.clone());
+        }
+        self.titles.insert(id, title);
+    }
+
+    fn set_tags(&mut self, tags: Vec<database::Tag>) {
+        self.tags = tags
+            .into_iter()
+            .map(|tag| {
+                (
+                    tag.name.to_lowercase(),
+                    TagSuggestion {
+                        name: tag.name,
+                        color: tag.color,
+                        todo_count: tag.todo_count,
+                    },
+                )
+            })
+            .collect();
+    }
+}
+
+/// Tags and title words of the todos outside the trash, kept in memory so that typing in a
+/// search box does not cost a database query per keystroke.
+#[derive(Clone, Default)]
+pub struct AutocompleteIndex {
+    index: Arc<RwLock<Index>>,
+}
+
+impl AutocompleteIndex {
+    /// Builds the index from the database. Returns the event cursor to follow changes from.
+    pub fn load(pool: &DbPool) -> Result<(Self, i64), Box<dyn std::error::Error + Send + Sync>> {
+        // Read first, so that changes made while loading are replayed rather than missed
+        let (_, after) = database::get_event_bounds(pool)?;
+        let mut index = Index::default();
+        for (id, title) in database::get_todo_titles(pool, None)? {
+            index.insert_todo(id, title);
+        }
+        index.set_tags(database::get_tags(pool)?);
+        Ok((
+            AutocompleteIndex {
+                index: Arc::new(RwLock::new(index)),
+            },
+            after,
+        ))
+    }
+
+    /// Reloads the tags; renaming, recoloring or deleting one does not touch any todo and
+    /// so is not seen through the events.
+    pub fn reload_tags(&self, pool: &DbPool) {
+        match database::get_tags(pool) {
+            Ok(tags) => self.index.write().unwrap().set_tags(tags),
+            Err(e) => eprintln!("Autocomplete index failed to reload tags: {e}"),
+        }
+    }
+
+    fn refresh_todos(
+        &self,
+        pool: &DbPool,
+        ids: &[String],
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let titles = database::get_todo_titles(pool, Some(ids))?;
+        let tags = database::get_tags(pool)?;
+        let mut index = self.index.write().unwrap();
+        // Todos that are gone or in the trash are not returned, so they drop out
+        for id in ids {
+            index.remove_todo(id);
+        }
+        for (id, title) in titles {
+            index.insert_todo(id, title);
+        }
+        index.set_tags(tags);
+        Ok(())
+    }
+
+    /// Tags whose name starts with `q`, most used first, and todos having a title word that
+    /// starts with each word of `q`, by title.
+    pub fn suggest(&self, q: &str, limit: usize) -> (Vec<TagSuggestion>, Vec<TodoSuggestion>) {
+        let index = self.index.read().unwrap();
+
+        let mut tags: Vec<TagSuggestion> = with_prefix(&index.tags, q.trim().to_lowercase())
+            .map(|(_, tag)| tag.clone())
+            .collect();
+        tags.sort_by(|a, b| b.todo_count.cmp(&a.todo_count).then(a.name.cmp(&b.name)));
+        tags.truncate(limit);
+
+        let mut matches: Option<HashSet<&String>> = None;
+        for term in words(q) {
+            let ids: HashSet<&String> = with_prefix(&index.words, term)
+                .flat_map(|(_, ids)| ids)
+                .collect();
+            matches = Some(match matches {
+                Some(previous) => previous.intersection(&ids).copied().collect(),
+                None => ids,
+            });
+        }
+        let mut todos: Vec<TodoSuggestion> = matches
+            .unwrap_or_default()
+            .into_iter()
+            .map(|id| TodoSuggestion {
+                id: id.clone(),
+                title: index.titles[id].clone(),
+            })
+            .collect();
+        todos.sort_by(|a, b| {
+            a.title
+                .to_lowercase()
+                .cmp(&b.title.to_lowercase())
+                .then_with(|| a.id.cmp(&b.id))
+        });
+        todos.truncate(limit);
+
+        (tags, todos)
+    }
+}
+
+/// Background task that applies the changes recorded after `after` to the index.
+pub async fn run_index_updater(pool: DbPool, index: AutocompleteIndex, mut after: i64) {
+    let mut interval = tokio::time::interval(POLL_INTERVAL);
+    loop {
+        interval.tick().await;
+        let pool = pool.clone();
+        let index = index.clone();
+        let result = tokio::task::spawn_blocking(move || {
+            let events = database::get_events(&pool, after, EVENT_BATCH)?;
+            let Some(last) = events.last().map(|event| event.seq) else {
+                return Ok(after);
+            };
+            let ids: Vec<String> = events
+                .into_iter()
+                .map(|event| event.todo_id)
+                .collect::<BTreeSet<_>>()
+                .into_iter()
+                .collect();
+            index.refresh_todos(&pool, &ids)?;
+            Ok::<_, Box<dyn std::error::Error + Send + Sync>>(last)
+        })
+        .await;
+        match result {
+            Ok(Ok(seq)) => after = seq,
+            Ok(Err(e)) => eprintln!("Autocomplete index update failed: {e}"),
+            Err(e) => eprintln!("Autocomplete index update panicked: {e}"),
+        }
+    }
+}
+
+fn error(status: StatusCode, message: &str) -> (StatusCode, Json<Value>) {
+    (status, Json(json!({"error": message})))
+}
+
+/// Suggestions for a search box, answered from memory.
+pub async fn autocomplete_handler(
+    State(index): State<AutocompleteIndex>,
+    Query(params): Query<AutocompleteParams>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let q = params
+        .q
+        .as_deref()
+        .filter(|q| !q.trim().is_empty())
+        .ok_or_else(|| error(StatusCode::BAD_REQUEST, "`q` is required"))?;
+    let limit = params.limit.unwrap_or(DEFAULT_LIMIT);
+    if !(1..=MAX_LIMIT).contains(&limit) {
+        return Err(error(
+            StatusCode::BAD_REQUEST,
+            &format!("limit must be between 1 and {MAX_LIMIT}"),
+        ));
+    }
+    let (tags, todos) = index.suggest(q, limit);
+    Ok(Json(json!({ "tags": tags, "todos": todos })))
+}
@@ -0,0 +1,184 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use axum::{
+    extract::{Query, State},
+    http::{HeaderMap, StatusCode},
+    response::Json,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::activity;
+use crate::config::Config;
+use crate::database::{self, CreateTodo, DbPool, List, Todo, UpdateTodo};
+use crate::handlers;
+use crate::ingest;
+use crate::json_body::{self, JsonBody};
+use crate::lists;
+use crate::locks;
+use crate::recurrence;
+
+/// Most todos a single bulk request can carry; larger imports go through `/api/todos/ingest`
+pub const MAX_ITEMS: usize = 1000;
+
+#[derive(Debug, Serialize)]
+pub struct ItemError {
+    /// Position of the item in the request array
+    pub index: usize,
+    pub error: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BulkCreateReport {
+    pub created: Vec<Todo>,
+    pub errors: Vec<ItemError>,
+}
+
+/// The same `PUT /api/todos/:id` body applied to every listed todo.
+#[derive(Debug, Deserialize)]
+pub struct BulkUpdate {
+    pub ids: Vec<String>,
+    pub patch: UpdateTodo,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BulkUpdateParams {
+    /// Who is saving, so their own edit locks do not get in the way
+    pub holder: Option<String>,
+    /// Save even though someone else holds an edit lock
+    #[serde(default)]
+    pub force: bool,
+}
+
+fn error(status: StatusCode, message: &str) -> (StatusCode, Json<Value>) {
+    (status, Json(json!({"error": message})))
+}
+
+/// Creates every valid todo of a JSON array in one transaction, in order. Invalid items are
+/// reported by index and skipped; they do not stop the others.
+pub async fn bulk_create_handler(
+    State(pool): State<DbPool>,
+    State(config): State<Arc<Config>>,
+    headers: HeaderMap,
+    JsonBody(items): JsonBody<Vec<Value>>,
+) -> Result<(StatusCode, Json<BulkCreateReport>), (StatusCode, Json<Value>)> {
+    if items.is_empty() {
+        return Err(error(
+            StatusCode::BAD_REQUEST,
+            "Send a non-empty array of todos",
+        ));
+    }
+    if items.len() > MAX_ITEMS {
+        return Err(error(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            &format!("A bulk request can create at most {MAX_ITEMS} todos"),
+        ));
+    }
+    let strict = json_body::is_strict(&config, &headers);
+    let lists: HashMap<String, List> = database::get_lists(&pool)
+        .map_err(|_| error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to get lists"))?
+        .into_iter()
+        .map(|list| (list.id.clone(), list))
+        .collect();
+    let list_ids: HashSet<String> = lists.keys().cloned().collect();
+    let fields = database::get_custom_fields(&pool).map_err(|_| {
+        error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to get custom fields",
+        )
+    })?;
+
+    let mut errors = Vec::new();
+    let mut todos: Vec<CreateTodo> = Vec::new();
+    for (index, item) in items.iter().enumerate() {
+        let bytes = serde_json::to_vec(item).unwrap_or_default();
+        match ingest::parse_todo(&bytes, strict, &list_ids, &fields) {
+            Ok(mut todo) => {
+                if let Some(list) = todo.list_id.as_ref().and_then(|id| lists.get(id)) {
+                    lists::apply_defaults(list, &mut todo);
+                }
+                todos.push(todo);
+            }
+            Err(error) => errors.push(ItemError { index, error }),
+        }
+    }
+    let created = database::create_todos(&pool, todos)
+        .map_err(|_| error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to create todos"))?;
+    for todo in &created {
+        activity::record_todo(&pool, &headers, "create", todo);
+    }
+    let status = if created.is_empty() {
+        StatusCode::UNPROCESSABLE_ENTITY
+    } else {
+        StatusCode::CREATED
+    };
+    Ok((status, Json(BulkCreateReport { created, errors })))
+}
+
+/// Updates every listed todo with the same patch in one transaction: either all of them
+/// change or none do.
+pub async fn bulk_update_handler(
+    State(pool): State<DbPool>,
+    Query(params): Query<BulkUpdateParams>,
+    headers: HeaderMap,
+    JsonBody(mut body): JsonBody<BulkUpdate>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    if body.ids.is_empty() {
+        return Err(error(StatusCode::BAD_REQUEST, "`ids` must not be empty"));
+    }
+    if body.ids.len() > MAX_ITEMS {
+        return Err(error(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            &format!("A bulk request can update at most {MAX_ITEMS} todos"),
+        ));
+    }
+    let mut seen = HashSet::new();
+    let ids: Vec<String> = body
+        .ids
+        .into_iter()
+        .filter(|id| seen.insert(id.clone()))
+        .collect();
+    handlers::prepare_update(&pool, &mut body.patch)?;
+    for id in &ids {
+        locks::check_lock(&pool, id, params.holder.as_deref(), params.force)?;
+    }
+
+    let completing = body.patch.completed == Some(true);
+    let updated = match database::update_todos(&pool, &ids, &body.patch) {
+        Ok(Some(updated)) => updated,
+        Ok(None) => {
+            let found: HashSet<String> = database::get_todo_titles(&pool, Some(&ids))
+                .map_err(|_| error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to get todos"))?
+                .into_iter()
+                .map(|(id, _)| id)
+                .collect();
+            let missing: Vec<&String> = ids.iter().filter(|id| !found.contains(*id)).collect();
+            return Err((
+                StatusCode::NOT_FOUND,
+                Json(json!({"error": "Todos not found", "missing": missing})),
+            ));
+        }
+        Err(_) => {
+            return Err(error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to update todos",
+            ))
+        }
+    };
+    if completing {
+        recurrence::notify_completed();
+    }
+    let mut todos = Vec::with_capacity(updated.len());
+    for (before, after) in updated {
+        // Only closing an open todo counts as completing it in the activity log
+        let action = if !before.completed && after.completed {
+            "complete"
+        } else {
+            "update"
+        };
+        activity::record_todo_change(&pool, &headers, action, &before, &after);
+        todos.push(after);
+    }
+    Ok(Json(json!({ "updated": todos })))
+}
@@ -0,0 +1,276 @@
+use std::sync::Arc;
+
+use axum::{extract::State, response::Json};
+use serde_json::{json, Value};
+
+use crate::activity;
+use crate::alerts;
+use crate::attachments_zip;
+use crate::autocomplete;
+use crate::bulk;
+use crate::config::Config;
+use crate::confirmation;
+use crate::csv_import;
+use crate::database::{
+    CustomFieldType, CHECKLIST_MAX_ITEMS, CHECKLIST_TEXT_MAX_LEN, COLOR_NAME_MAX_LEN,
+    CUSTOM_FIELDS_MAX, CUSTOM_FIELD_TEXT_MAX_LEN, ESTIMATE_MAX_MINUTES, INBOUND_HOOK_NAME_MAX_LEN,
+    INBOX_LIST_ID, LIST_NAME_MAX_LEN, METADATA_MAX_BYTES, METADATA_MAX_KEY_LEN, REVISION_FIELDS,
+    TAG_MAX_LEN, TEMPLATE_NAME_MAX_LEN,
+};
+use crate::dedupe;
+use crate::deprecation;
+use crate::escalations;
+use crate::etag;
+use crate::events;
+use crate::export::ExportFormat;
+use crate::export_schedules;
+use crate::handlers;
+use crate::history;
+use crate::icons;
+use crate::idempotency;
+use crate::inbound_hooks;
+use crate::list_snapshots;
+use crate::locks;
+use crate::macros;
+use crate::maintenance::MaintenanceMode;
+use crate::planning;
+use crate::realtime;
+use crate::reminders;
+use crate::request_stats;
+use crate::reviews;
+use crate::search;
+use crate::signed_urls;
+use crate::sql_dump;
+use crate::views;
+
+/// Which optional features this deployment has, and their limits, so clients can adapt.
+pub async fn get_capabilities_handler(
+    State(config): State<Arc<Config>>,
+    State(maintenance): State<MaintenanceMode>,
+) -> Json<Value> {
+    Json(json!({
+        "read_only": maintenance.status().enabled,
+        "id_format": config.id_format.as_str(),
+        "deprecations": deprecation::DEPRECATED_ROUTES,
+        "features": {
+            "auth": {"enabled": false},
+            "attachments": {
+                "enabled": true,
+                "max_bytes": config.attachment_max_bytes,
+                "deduplicated": true,
+                "list_archive": attachments_zip::CONTENT_TYPE,
+            },
+            "webhooks": {"enabled": false},
+            "events": {
+                "enabled": true,
+                "retention_days": events::RETENTION_DAYS,
+            },
+            "realtime": {
+                "enabled": true,
+                "path": "/api/ws",
+                "filters": ["lists", "types"],
+                "presence_timeout_seconds": realtime::PRESENCE_TIMEOUT.as_secs(),
+            },
+            "sync": {"enabled": false},
+            "graphql": {"enabled": false},
+            "metadata": {
+                "enabled": true,
+                "max_bytes": METADATA_MAX_BYTES,
+                "max_key_length": METADATA_MAX_KEY_LEN,
+            },
+            "custom_fields": {
+                "enabled": true,
+                "types": CustomFieldType::ALL,
+                "max_fields": CUSTOM_FIELDS_MAX,
+                "max_text_length": CUSTOM_FIELD_TEXT_MAX_LEN,
+            },
+            "icons": {
+                "enabled": true,
+                "named": icons::NAMED_ICONS,
+                "emoji": icons::EMOJI_ICONS,
+            },
+            "recurrence": {
+                "enabled": true,
+                "format": "rrule",
+                "supported_parts": ["FREQ", "INTERVAL", "BYDAY", "COUNT", "UNTIL"],
+                "stats": true,
+            },
+            "reminders": {
+                "enabled": true,
+                "check_interval_seconds": reminders::CHECK_INTERVAL.as_secs(),
+            },
+            "checklist": {
+                "enabled": true,
+                "max_items": CHECKLIST_MAX_ITEMS,
+                "max_text_length": CHECKLIST_TEXT_MAX_LEN,
+            },
+            "dependencies": {
+                "enabled": true,
+            },
+            "duplicate": {
+                "enabled": true,
+            },
+            "time_tracking": {
+                "enabled": true,
+                "max_estimate_minutes": ESTIMATE_MAX_MINUTES,
+            },
+            "habits": {
+                "enabled": true,
+                "week_starts_on": "monday",
+            },
+            "edit_locks": {
+                "enabled": true,
+                "max_ttl_seconds": locks::MAX_TTL_SECONDS,
+            },
+            "reviews": {
+                "enabled": true,
+                "max_comment_length": reviews::REVIEW_COMMENT_MAX_LEN,
+            },
+            "tags": {
+                "enabled": true,
+                "max_name_length": TAG_MAX_LEN,
+            },
+            "lists": {
+                "enabled": true,
+                "max_name_length": LIST_NAME_MAX_LEN,
+                "inbox_id": INBOX_LIST_ID,
+                "retention_policies": ["archive_completed_after_days", "purge_trash_after_days"],
+            },
+            "trash": {"enabled": true},
+            "palette": {
+                "enabled": true,
+                "max_name_length": COLOR_NAME_MAX_LEN,
+            },
+            "duplicate_detection": {
+                "enabled": true,
+                "similarity_threshold": dedupe::SIMILARITY_THRESHOLD,
+            },
+            "activity": {
+                "enabled": true,
+                "actions": activity::ACTIONS,
+                "actor_header": "X-Actor",
+                "retention_days": activity::RETENTION_DAYS,
+            },
+            "alerts": {
+                "enabled": true,
+                "metrics": alerts::METRICS,
+                "max_window_minutes": request_stats::MAX_WINDOW_MINUTES,
+                "min_responses_for_error_rate": alerts::MIN_RESPONSES,
+            },
+            "escalations": {
+                "enabled": true,
+                "max_after_hours": escalations::MAX_AFTER_HOURS,
+                "check_interval_seconds": escalations::CHECK_INTERVAL.as_secs(),
+            },
+            "planning": {
+                "enabled": true,
+                "default_days": planning::DEFAULT_DAYS,
+                "max_days": planning::MAX_DAYS,
+                "default_hours": planning::DEFAULT_HOURS,
+            },
+            "history": {
+                "enabled": true,
+                "fields": REVISION_FIELDS,
+                "max_limit": history::MAX_LIMIT,
+            },
+            "list_snapshots": {
+                "enabled": true,
+                "interval": "daily",
+                "max_days": list_snapshots::MAX_DAYS,
+            },
+            "csv_import": {
+                "enabled": true,
+                "fields": csv_import::FIELDS,
+                "max_rows": csv_import::MAX_ROWS,
+                "max_columns": csv_import::MAX_COLUMNS,
+                "upload_ttl_hours": csv_import::UPLOAD_TTL_HOURS,
+            },
+            "full_text_search": {
+                "enabled": true,
+                "fields": ["title", "description"],
+                "max_limit": search::MAX_LIMIT,
+            },
+            "autocomplete": {"enabled": true, "max_limit": autocomplete::MAX_LIMIT},
+            "sql_dump": {"enabled": true, "content_type": sql_dump::CONTENT_TYPE},
+            "scheduled_exports": {
+                "enabled": true,
+                "formats": ExportFormat::ALL,
+                "destinations": export_schedules::DESTINATIONS,
+            },
+            "macros": {
+                "enabled": true,
+                "actions": ["archive", "tag", "delete"],
+                "max_steps": macros::MAX_STEPS,
+            },
+            "views": {
+                "enabled": true,
+                "columns": views::COLUMNS,
+                "group_by": views::GROUPINGS,
+            },
+            "pagination": {
+                "enabled": true,
+                "modes": ["offset", "cursor"],
+                "max_limit": handlers::MAX_PAGE_SIZE,
+                "default_cursor_limit": handlers::DEFAULT_CURSOR_PAGE_SIZE,
+            },
+            "signed_urls": {
+                "enabled": true,
+                "resources": ["todo", "attachment"],
+                "default_ttl_seconds": signed_urls::DEFAULT_TTL_SECONDS,
+                "max_ttl_seconds": signed_urls::MAX_TTL_SECONDS,
+            },
+            "templates": {
+                "enabled": true,
+                "max_name_length": TEMPLATE_NAME_MAX_LEN,
+                "max_checklist_items": CHECKLIST_MAX_ITEMS,
+            },
+            "bulk_create": {"enabled": true, "max_items": bulk::MAX_ITEMS},
+            "bulk_update": {"enabled": true, "max_items": bulk::MAX_ITEMS},
+            "bulk_delete": {
+                "enabled": true,
+                "confirmation_ttl_seconds": confirmation::TOKEN_TTL.as_secs(),
+                "max_ids": bulk::MAX_ITEMS,
+            },
+            "inbound_email": {"enabled": config.inbound_email_token.is_some()},
+            "inbound_hooks": {
+                "enabled": true,
+                "fields": inbound_hooks::FIELDS,
+                "max_name_length": INBOUND_HOOK_NAME_MAX_LEN,
+                "dedupe": {
+                    "default_window_minutes": inbound_hooks::DEFAULT_DEDUPE_WINDOW_MINUTES,
+                    "max_window_minutes": inbound_hooks::MAX_DEDUPE_WINDOW_MINUTES,
+                },
+            },
+            "strict_json": {"enabled": config.strict_json},
+            "conditional_requests": {
+                "enabled": true,
+                "require_if_match": config.require_if_match,
+                "if_match_routes": etag::IF_MATCH_ROUTES,
+            },
+            "idempotency": {
+                "enabled": true,
+                "header": "Idempotency-Key",
+                "max_key_length": idempotency::KEY_MAX_LEN,
+                "ttl_hours": idempotency::TTL_HOURS,
+            },
+            "security_headers": {
+                "enabled": true,
+                "hsts": config.hsts_max_age.is_some(),
+            },
+            "ip_access_control": {
+                "admin_allowlist": !config.admin_allowlist.is_empty(),
+                "denylist": !config.ip_denylist.is_empty(),
+            },
+            "github": {
+                "enabled": config.github_webhook_secret.is_some(),
+                "issue_sync": config.github_token.is_some(),
+            },
+            "git_push_hook": {"enabled": config.git_webhook_secret.is_some()},
+            "stale_notifications": {
+                "enabled": config.stale_after_days.is_some(),
+                "after_days": config.stale_after_days,
+            },
+            "agenda_print": {"enabled": true, "formats": ["text", "escpos"]},
+        },
+    }))
+}
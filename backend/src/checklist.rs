@@ -0,0 +1,120 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::Json,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::database::{self, ChecklistItem, DbPool, CHECKLIST_MAX_ITEMS};
+use crate::json_body::JsonBody;
+
+#[derive(Debug, Deserialize)]
+pub struct CreateChecklistItem {
+    pub text: String,
+    #[serde(default)]
+    pub checked: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateChecklistItem {
+    pub text: Option<String>,
+    pub checked: Option<bool>,
+    /// Zero-based index to move the item to
+    pub position: Option<u32>,
+}
+
+fn error(status: StatusCode, message: &str) -> (StatusCode, Json<Value>) {
+    (status, Json(json!({"error": message})))
+}
+
+fn checklist_text(text: &str) -> Result<String, (StatusCode, Json<Value>)> {
+    database::normalize_checklist_text(text)
+        .map_err(|message| error(StatusCode::BAD_REQUEST, &message))
+}
+
+fn ensure_todo_exists(pool: &DbPool, id: &str) -> Result<(), (StatusCode, Json<Value>)> {
+    match database::get_todo(pool, id) {
+        Ok(Some(_)) => Ok(()),
+        Ok(None) => Err(error(StatusCode::NOT_FOUND, "Todo not found")),
+        Err(_) => Err(error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to get todo",
+        )),
+    }
+}
+
+pub async fn get_checklist_handler(
+    State(pool): State<DbPool>,
+    Path(id): Path<String>,
+) -> Result<Json<Vec<ChecklistItem>>, (StatusCode, Json<Value>)> {
+    ensure_todo_exists(&pool, &id)?;
+    database::get_checklist(&pool, &id)
+        .map(Json)
+        .map_err(|_| error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to get checklist"))
+}
+
+/// Adds an item to the end of a todo's checklist.
+pub async fn create_checklist_item_handler(
+    State(pool): State<DbPool>,
+    Path(id): Path<String>,
+    JsonBody(body): JsonBody<CreateChecklistItem>,
+) -> Result<(StatusCode, Json<ChecklistItem>), (StatusCode, Json<Value>)> {
+    let text = checklist_text(&body.text)?;
+    ensure_todo_exists(&pool, &id)?;
+    match database::add_checklist_item(&pool, &id, &text, body.checked) {
+        Ok(Some(item)) => Ok((StatusCode::CREATED, Json(item))),
+        Ok(None) => Err(error(
+            StatusCode::CONFLICT,
+            &format!(
+                "A checklist can have at most {CHECKLIST_MAX_ITEMS} items; use subtasks instead"
+            ),
+        )),
+        Err(_) => Err(error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to add checklist item",
+        )),
+    }
+}
+
+/// Renames, checks or unchecks, or moves a checklist item.
+pub async fn update_checklist_item_handler(
+    State(pool): State<DbPool>,
+    Path((id, item_id)): Path<(String, String)>,
+    JsonBody(body): JsonBody<UpdateChecklistItem>,
+) -> Result<Json<ChecklistItem>, (StatusCode, Json<Value>)> {
+    let text = body.text.as_deref().map(checklist_text).transpose()?;
+    ensure_todo_exists(&pool, &id)?;
+    match database::update_checklist_item(
+        &pool,
+        &id,
+        &item_id,
+        text.as_deref(),
+        body.checked,
+        body.position,
+    ) {
+        Ok(Some(item)) => Ok(Json(item)),
+        Ok(None) => Err(error(StatusCode::NOT_FOUND, "Checklist item not found")),
+        Err(_) => Err(error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to update checklist item",
+        )),
+    }
+}
+
+pub async fn delete_checklist_item_handler(
+    State(pool): State<DbPool>,
+    Path((id, item_id)): Path<(String, String)>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    ensure_todo_exists(&pool, &id)?;
+    match database::delete_checklist_item(&pool, &id, &item_id) {
+        Ok(true) => Ok(Json(
+            json!({"message": "Checklist item deleted successfully"}),
+        )),
+        Ok(false) => Err(error(StatusCode::NOT_FOUND, "Checklist item not found")),
+        Err(_) => Err(error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to delete checklist item",
+        )),
+    }
+}
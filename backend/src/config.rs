@@ -0,0 +1,127 @@
+use std::env;
+use std::path::PathBuf;
+
+use crate::access_log::AccessLogFormat;
+use crate::ids::IdFormat;
+use crate::ip_filter::{self, IpNet};
+use crate::security_headers;
+use crate::slow_query;
+
+/// Deployment settings read from environment variables at startup.
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    /// Secret part of the inbound address (`todo+<token>@your-domain`); inbound email is disabled when unset
+    pub inbound_email_token: Option<String>,
+    /// When set, open todos older than this many days are reported periodically
+    pub stale_after_days: Option<i64>,
+    /// Shared secret of the GitHub webhook; the receiver is disabled when unset
+    pub github_webhook_secret: Option<String>,
+    /// Token used to close and reopen linked issues; todos only follow their issues when unset
+    pub github_token: Option<String>,
+    /// Base URL of the GitHub REST API, e.g. for GitHub Enterprise Server
+    pub github_api_url: String,
+    /// Shared secret of the git push hook; the receiver is disabled when unset
+    pub git_webhook_secret: Option<String>,
+    /// `uuid` (default) or `ulid` for newly created todos
+    pub id_format: IdFormat,
+    /// `pretty` (default) or `json` access log records
+    pub access_log_format: AccessLogFormat,
+    /// Database statements slower than this are logged; `0` disables the log
+    pub slow_query_ms: u64,
+    /// Reject request bodies with unknown fields instead of ignoring them
+    pub strict_json: bool,
+    /// Reject todo updates without `If-Match`, so no client can overwrite changes it has not seen
+    pub require_if_match: bool,
+    /// Where attachment contents are stored
+    pub attachments_dir: PathBuf,
+    /// Largest accepted attachment, in bytes
+    pub attachment_max_bytes: u64,
+    /// Where scheduled exports are written
+    pub export_dir: PathBuf,
+    /// Only these ranges may call the admin routes; empty allows everyone
+    pub admin_allowlist: Vec<IpNet>,
+    /// These ranges are refused on every route
+    pub ip_denylist: Vec<IpNet>,
+    /// Take the client address from `X-Forwarded-For`, for deployments behind a proxy
+    pub trust_forwarded_for: bool,
+    /// Sent with HTML responses
+    pub content_security_policy: String,
+    /// `Strict-Transport-Security` max-age in seconds; the header is not sent when unset
+    pub hsts_max_age: Option<u64>,
+    /// Key for signed URLs; a random one is used when unset, so links die with the process
+    pub url_signing_secret: String,
+}
+
+const DEFAULT_ATTACHMENTS_DIR: &str = "attachments";
+const DEFAULT_ATTACHMENT_MAX_BYTES: u64 = 10 * 1024 * 1024;
+const DEFAULT_EXPORT_DIR: &str = "exports";
+const DEFAULT_GITHUB_API_URL: &str = "https://api.github.com";
+
+fn non_empty_var(name: &str) -> Option<String> {
+    env::var(name).ok().filter(|value| !value.is_empty())
+}
+
+// A typo in an access list must not silently open the server up, so it stops startup
+fn ip_list_var(name: &str) -> Vec<IpNet> {
+    let value = non_empty_var(name).unwrap_or_default();
+    ip_filter::parse_list(&value).unwrap_or_else(|message| {
+        eprintln!("{name}: {message}");
+        std::process::exit(1);
+    })
+}
+
+impl Config {
+    pub fn from_env() -> Self {
+        Config {
+            inbound_email_token: non_empty_var("INBOUND_EMAIL_TOKEN"),
+            stale_after_days: non_empty_var("STALE_AFTER_DAYS")
+                .and_then(|days| days.parse().ok())
+                .filter(|days| *days > 0),
+            github_webhook_secret: non_empty_var("GITHUB_WEBHOOK_SECRET"),
+            github_token: non_empty_var("GITHUB_TOKEN"),
+            github_api_url: non_empty_var("GITHUB_API_URL")
+                .unwrap_or_else(|| DEFAULT_GITHUB_API_URL.to_string())
+                .trim_end_matches('/')
+                .to_string(),
+            git_webhook_secret: non_empty_var("GIT_WEBHOOK_SECRET"),
+            id_format: non_empty_var("ID_FORMAT")
+                .and_then(|format| IdFormat::parse(&format))
+                .unwrap_or_default(),
+            access_log_format: non_empty_var("ACCESS_LOG_FORMAT")
+                .and_then(|format| AccessLogFormat::parse(&format))
+                .unwrap_or_default(),
+            slow_query_ms: non_empty_var("SLOW_QUERY_MS")
+                .and_then(|ms| ms.parse().ok())
+                .unwrap_or(slow_query::DEFAULT_THRESHOLD_MS),
+            strict_json: non_empty_var("STRICT_JSON").is_some_and(|value| value == "true"),
+            require_if_match: non_empty_var("REQUIRE_IF_MATCH")
+                .is_some_and(|value| value == "true"),
+            attachments_dir: non_empty_var("ATTACHMENTS_DIR")
+                .unwrap_or_else(|| DEFAULT_ATTACHMENTS_DIR.to_string())
+                .into(),
+            attachment_max_bytes: non_empty_var("ATTACHMENT_MAX_BYTES")
+                .and_then(|bytes| bytes.parse().ok())
+                .filter(|bytes| *bytes > 0)
+                .unwrap_or(DEFAULT_ATTACHMENT_MAX_BYTES),
+            export_dir: non_empty_var("EXPORT_DIR")
+                .unwrap_or_else(|| DEFAULT_EXPORT_DIR.to_string())
+                .into(),
+            admin_allowlist: ip_list_var("ADMIN_ALLOWLIST"),
+            ip_denylist: ip_list_var("IP_DENYLIST"),
+            trust_forwarded_for: non_empty_var("TRUST_FORWARDED_FOR")
+                .is_some_and(|value| value == "true"),
+            content_security_policy: non_empty_var("CONTENT_SECURITY_POLICY")
+                .unwrap_or_else(|| security_headers::DEFAULT_CONTENT_SECURITY_POLICY.to_string()),
+            hsts_max_age: non_empty_var("HSTS_MAX_AGE")
+                .and_then(|seconds| seconds.parse().ok())
+                .filter(|seconds| *seconds > 0),
+            url_signing_secret: non_empty_var("URL_SIGNING_SECRET").unwrap_or_else(|| {
+                format!(
+                    "{}{}",
+                    uuid::Uuid::new_v4().simple(),
+                    uuid::Uuid::new_v4().simple()
+                )
+            }),
+        }
+    }
+}
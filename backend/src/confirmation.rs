@@ -0,0 +1,43 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+pub const TOKEN_TTL: Duration = Duration::from_secs(5 * 60);
+
+struct PendingOperation {
+    fingerprint: String,
+    expires_at: Instant,
+}
+
+/// One-time tokens that confirm a destructive bulk operation previewed by a dry run.
+/// Each token is bound to a fingerprint of the exact operation it was issued for.
+#[derive(Clone, Default)]
+pub struct ConfirmationTokens(Arc<Mutex<HashMap<String, PendingOperation>>>);
+
+impl ConfirmationTokens {
+    pub fn issue(&self, fingerprint: String) -> String {
+        let token = uuid::Uuid::new_v4().to_string();
+        let mut pending = self.0.lock().unwrap();
+        let now = Instant::now();
+        pending.retain(|_, operation| operation.expires_at > now);
+        pending.insert(
+            token.clone(),
+            PendingOperation {
+                fingerprint,
+                expires_at: now + TOKEN_TTL,
+            },
+        );
+        token
+    }
+
+    /// Consumes the token; returns false if it is unknown, expired or was issued for another operation.
+    pub fn consume(&self, token: &str, fingerprint: &str) -> bool {
+        let mut pending = self.0.lock().unwrap();
+        match pending.remove(token) {
+            Some(operation) => {
+                operation.expires_at > Instant::now() && operation.fingerprint == fingerprint
+            }
+            None => false,
+        }
+    }
+}
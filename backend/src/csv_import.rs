@@ -0,0 +1,315 @@
+use std::collections::{BTreeMap, HashMap};
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::Json,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::database::{self, CreateTodo, DbPool, ImportUpload, Priority};
+use crate::handlers;
+use crate::import;
+use crate::json_body::JsonBody;
+use crate::lists;
+
+pub const MAX_ROWS: usize = 10_000;
+pub const MAX_COLUMNS: usize = 100;
+/// Uploads that are not imported within this time are dropped by the hourly cleanup job
+pub const UPLOAD_TTL_HOURS: u32 = 24;
+// Rows returned in the preview
+const SAMPLE_ROWS: usize = 5;
+// Only the first few errors are reported back
+const MAX_REPORTED_ERRORS: usize = 100;
+
+/// Todo fields a column can be mapped to.
+pub const FIELDS: [&str; 6] = [
+    "title",
+    "description",
+    "due_date",
+    "start_date",
+    "priority",
+    "completed",
+];
+
+#[derive(Debug, Serialize)]
+pub struct Preview {
+    pub id: String,
+    pub columns: Vec<String>,
+    pub row_count: usize,
+    /// The first rows, for the user to check the mapping against
+    pub sample: Vec<Vec<String>>,
+    pub fields: [&'static str; 6],
+    /// Columns named like a field (ignoring case, spaces and dashes), by field
+    pub suggested_mapping: BTreeMap<&'static str, String>,
+    pub created_at: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ImportCsv {
+    /// Column name by field; `title` is required
+    pub mapping: HashMap<String, String>,
+    /// List every imported todo goes to
+    pub list_id: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RowError {
+    /// 1-based, not counting the header
+    pub row: usize,
+    pub error: String,
+}
+
+#[derive(Debug, Serialize, Default)]
+pub struct ImportReport {
+    pub imported: usize,
+    pub failed: usize,
+    pub errors: Vec<RowError>,
+}
+
+fn error(status: StatusCode, message: &str) -> (StatusCode, Json<Value>) {
+    (status, Json(json!({"error": message})))
+}
+
+fn preview(upload: &ImportUpload) -> Preview {
+    let normalize = |name: &str| name.trim().to_lowercase().replace([' ', '-'], "_");
+    let suggested_mapping = FIELDS
+        .iter()
+        .filter_map(|field| {
+            upload
+                .columns
+                .iter()
+                .find(|column| normalize(column) == *field)
+                .map(|column| (*field, column.clone()))
+        })
+        .collect();
+    Preview {
+        id: upload.id.clone(),
+        columns: upload.columns.clone(),
+        row_count: upload.rows.len(),
+        sample: upload.rows.iter().take(SAMPLE_ROWS).cloned().collect(),
+        fields: FIELDS,
+        suggested_mapping,
+        created_at: upload.created_at.clone(),
+    }
+}
+
+/// Reads the header and data rows. Short rows are padded and long ones cut to the header.
+pub fn parse_csv(body: &str) -> Result<(Vec<String>, Vec<Vec<String>>), String> {
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .from_reader(body.as_bytes());
+    let columns: Vec<String> = reader
+        .headers()
+        .map_err(|e| format!("Invalid CSV: {e}"))?
+        .iter()
+        .map(|column| column.trim().to_string())
+        .collect();
+    if columns.iter().all(String::is_empty) {
+        return Err("The CSV file needs a header row".to_string());
+    }
+    if columns.len() > MAX_COLUMNS {
+        return Err(format!("At most {MAX_COLUMNS} columns are supported"));
+    }
+    for (index, column) in columns.iter().enumerate() {
+        if column.is_empty() {
+            return Err(format!("Column {} has no name", index + 1));
+        }
+        if columns[..index].contains(column) {
+            return Err(format!("Column '{column}' appears more than once"));
+        }
+    }
+
+    let mut rows = Vec::new();
+    for record in reader.records() {
+        let record = record.map_err(|e| format!("Invalid CSV: {e}"))?;
+        if record.iter().all(|value| value.trim().is_empty()) {
+            continue;
+        }
+        if rows.len() == MAX_ROWS {
+            return Err(format!("At most {MAX_ROWS} rows can be imported at once"));
+        }
+        let mut row: Vec<String> = record.iter().map(str::to_string).collect();
+        row.resize(columns.len(), String::new());
+        rows.push(row);
+    }
+    Ok((columns, rows))
+}
+
+fn parse_completed(value: &str) -> Result<bool, String> {
+    match value.to_lowercase().as_str() {
+        "true" | "yes" | "y" | "1" | "x" | "done" => Ok(true),
+        "false" | "no" | "n" | "0" | "open" => Ok(false),
+        _ => Err(format!(
+            "Invalid completed value '{value}': expected true or false"
+        )),
+    }
+}
+
+// Column index by field, checked against the upload's header
+fn resolve_mapping(
+    upload: &ImportUpload,
+    mapping: &HashMap<String, String>,
+) -> Result<HashMap<String, usize>, String> {
+    if !mapping.contains_key("title") {
+        return Err("The mapping needs a column for `title`".to_string());
+    }
+    mapping
+        .iter()
+        .map(|(field, column)| {
+            if !FIELDS.contains(&field.as_str()) {
+                return Err(format!(
+                    "Unknown field '{field}': expected one of {}",
+                    FIELDS.join(", ")
+                ));
+            }
+            let index = upload
+                .columns
+                .iter()
+                .position(|name| name == column)
+                .ok_or_else(|| format!("Unknown column '{column}'"))?;
+            Ok((field.clone(), index))
+        })
+        .collect()
+}
+
+// The todo for one row and whether it is already done
+fn row_to_create(
+    row: &[String],
+    mapping: &HashMap<String, usize>,
+    list_id: Option<&String>,
+) -> Result<(CreateTodo, bool), String> {
+    let value = |field: &str| {
+        mapping
+            .get(field)
+            .map(|index| row[*index].trim())
+            .filter(|value| !value.is_empty())
+    };
+    let title = value("title").ok_or("The title is empty")?.to_string();
+    let priority = value("priority")
+        .map(|priority| {
+            Priority::parse(&priority.to_lowercase()).ok_or_else(|| {
+                format!("Invalid priority '{priority}': expected low, medium, high or urgent")
+            })
+        })
+        .transpose()?;
+    let completed = value("completed")
+        .map(parse_completed)
+        .transpose()?
+        .unwrap_or(false);
+    let mut create = CreateTodo {
+        title,
+        description: value("description").map(str::to_string),
+        metadata: None,
+        due_date: value("due_date").map(str::to_string),
+        start_date: value("start_date").map(str::to_string),
+        remind_at: None,
+        estimate_minutes: None,
+        custom_fields: None,
+        priority,
+        parent_id: None,
+        icon: None,
+        recurrence: None,
+        list_id: list_id.cloned(),
+        tags: None,
+    };
+    handlers::validate_create_todo(&mut create)?;
+    Ok((create, completed))
+}
+
+fn find_upload(pool: &DbPool, id: &str) -> Result<ImportUpload, (StatusCode, Json<Value>)> {
+    match database::get_import_upload(pool, id) {
+        Ok(Some(upload)) => Ok(upload),
+        Ok(None) => Err(error(StatusCode::NOT_FOUND, "Upload not found")),
+        Err(_) => Err(error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to get upload",
+        )),
+    }
+}
+
+/// Stores a CSV file and returns its columns and first rows, so a mapping can be chosen.
+pub async fn upload_csv_handler(
+    State(pool): State<DbPool>,
+    body: String,
+) -> Result<(StatusCode, Json<Preview>), (StatusCode, Json<Value>)> {
+    let (columns, rows) =
+        parse_csv(&body).map_err(|message| error(StatusCode::BAD_REQUEST, &message))?;
+    database::create_import_upload(&pool, columns, rows)
+        .map(|upload| (StatusCode::CREATED, Json(preview(&upload))))
+        .map_err(|_| error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to store upload"))
+}
+
+pub async fn get_upload_handler(
+    State(pool): State<DbPool>,
+    Path(id): Path<String>,
+) -> Result<Json<Preview>, (StatusCode, Json<Value>)> {
+    find_upload(&pool, &id).map(|upload| Json(preview(&upload)))
+}
+
+pub async fn delete_upload_handler(
+    State(pool): State<DbPool>,
+    Path(id): Path<String>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    match database::delete_import_upload(&pool, &id) {
+        Ok(true) => Ok(Json(json!({"message": "Upload discarded"}))),
+        Ok(false) => Err(error(StatusCode::NOT_FOUND, "Upload not found")),
+        Err(_) => Err(error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to discard upload",
+        )),
+    }
+}
+
+/// Imports an upload with the given column mapping in one transaction. Rows that fail to
+/// validate are skipped and reported. The upload is removed before any todo is created, so importing it twice at
+/// once creates its todos only once.
+pub async fn import_upload_handler(
+    State(pool): State<DbPool>,
+    Path(id): Path<String>,
+    JsonBody(body): JsonBody<ImportCsv>,
+) -> Result<(StatusCode, Json<ImportReport>), (StatusCode, Json<Value>)> {
+    let upload = find_upload(&pool, &id)?;
+    let mapping = resolve_mapping(&upload, &body.mapping)
+        .map_err(|message| error(StatusCode::BAD_REQUEST, &message))?;
+    if let Some(list_id) = &body.list_id {
+        lists::ensure_list_exists(&pool, list_id)?;
+    }
+    // Whoever removes the upload imports it; a concurrent request finds it gone
+    let upload = match database::take_import_upload(&pool, &id) {
+        Ok(Some(upload)) => upload,
+        Ok(None) => return Err(error(StatusCode::NOT_FOUND, "Upload not found")),
+        Err(_) => {
+            return Err(error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to claim upload",
+            ))
+        }
+    };
+
+    let mut report = ImportReport::default();
+    let mut todos = Vec::new();
+    for (index, row) in upload.rows.iter().enumerate() {
+        match row_to_create(row, &mapping, body.list_id.as_ref()) {
+            Ok((create, completed)) => todos.push(import::SourceTodo {
+                create,
+                completed,
+                labels: Vec::new(),
+            }),
+            Err(message) => {
+                report.failed += 1;
+                if report.errors.len() < MAX_REPORTED_ERRORS {
+                    report.errors.push(RowError {
+                        row: index + 1,
+                        error: message,
+                    });
+                }
+            }
+        }
+    }
+    report.imported = import::import_todos(&pool, todos)
+        .map_err(|_| error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to import todos"))?
+        .len();
+    Ok((StatusCode::CREATED, Json(report)))
+}
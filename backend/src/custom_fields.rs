@@ -0,0 +1,112 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::Json,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::database::{
+    self, CustomField, CustomFieldType, DbPool, Metadata, CUSTOM_FIELDS_MAX, METADATA_MAX_KEY_LEN,
+};
+use crate::json_body::JsonBody;
+
+#[derive(Debug, Deserialize)]
+pub struct CreateCustomField {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub field_type: CustomFieldType,
+}
+
+fn error(status: StatusCode, message: &str) -> (StatusCode, Json<Value>) {
+    (status, Json(json!({"error": message})))
+}
+
+/// Checks the custom field values sent in a todo body against the registered fields.
+pub fn check_values(pool: &DbPool, values: &Metadata) -> Result<(), (StatusCode, Json<Value>)> {
+    if values.is_empty() {
+        return Ok(());
+    }
+    let fields = database::get_custom_fields(pool).map_err(|_| {
+        error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to get custom fields",
+        )
+    })?;
+    database::validate_custom_field_values(&fields, values)
+        .map_err(|message| error(StatusCode::BAD_REQUEST, &message))
+}
+
+pub async fn get_custom_fields_handler(
+    State(pool): State<DbPool>,
+) -> Result<Json<Vec<CustomField>>, (StatusCode, Json<Value>)> {
+    database::get_custom_fields(&pool).map(Json).map_err(|_| {
+        error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to get custom fields",
+        )
+    })
+}
+
+pub async fn create_custom_field_handler(
+    State(pool): State<DbPool>,
+    JsonBody(body): JsonBody<CreateCustomField>,
+) -> Result<(StatusCode, Json<CustomField>), (StatusCode, Json<Value>)> {
+    let name = body.name.trim();
+    if !database::is_valid_metadata_key(name) {
+        return Err(error(
+            StatusCode::BAD_REQUEST,
+            &format!(
+                "Invalid custom field name '{name}': use 1-{METADATA_MAX_KEY_LEN} characters of [A-Za-z0-9_-]"
+            ),
+        ));
+    }
+    let fields = database::get_custom_fields(&pool).map_err(|_| {
+        error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to get custom fields",
+        )
+    })?;
+    if let Some(existing) = fields
+        .iter()
+        .find(|field| field.name.eq_ignore_ascii_case(name))
+    {
+        return Err((
+            StatusCode::CONFLICT,
+            Json(
+                json!({"error": "A custom field with this name already exists", "existing": existing}),
+            ),
+        ));
+    }
+    if fields.len() >= CUSTOM_FIELDS_MAX {
+        return Err(error(
+            StatusCode::CONFLICT,
+            &format!("At most {CUSTOM_FIELDS_MAX} custom fields can be registered"),
+        ));
+    }
+    database::create_custom_field(&pool, name, body.field_type)
+        .map(|field| (StatusCode::CREATED, Json(field)))
+        .map_err(|_| {
+            error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to create custom field",
+            )
+        })
+}
+
+/// Deletes a field together with its values on every todo.
+pub async fn delete_custom_field_handler(
+    State(pool): State<DbPool>,
+    Path(name): Path<String>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    match database::delete_custom_field(&pool, &name) {
+        Ok(true) => Ok(Json(
+            json!({"message": "Custom field deleted successfully"}),
+        )),
+        Ok(false) => Err(error(StatusCode::NOT_FOUND, "Custom field not found")),
+        Err(_) => Err(error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to delete custom field",
+        )),
+    }
+}
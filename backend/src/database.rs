@@ -1,192 +1,6726 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use chrono::{DateTime, SubsecRound, Utc};
 use r2d2::Pool;
 use r2d2_sqlite::SqliteConnectionManager;
-use rusqlite::Result;
-use serde::{Deserialize, Serialize};
+use rusqlite::{types::Value as SqlValue, OptionalExtension, Result};
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::{Map, Value};
+
+use crate::slow_query;
+
+pub type Metadata = Map<String, Value>;
+
+// Limits for the free-form metadata object integrations can attach to a todo
+pub const METADATA_MAX_BYTES: usize = 4096;
+pub const METADATA_MAX_KEY_LEN: usize = 64;
+// A month of round-the-clock work; anything longer should be split up
+pub const ESTIMATE_MAX_MINUTES: u32 = 60 * 24 * 30;
+pub const CUSTOM_FIELDS_MAX: usize = 50;
+pub const CUSTOM_FIELD_TEXT_MAX_LEN: usize = 500;
+
+/// Stored as an integer so that sorting by priority orders by urgency.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    Low = 0,
+    #[default]
+    Medium = 1,
+    High = 2,
+    Urgent = 3,
+}
+
+impl Priority {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "low" => Some(Priority::Low),
+            "medium" => Some(Priority::Medium),
+            "high" => Some(Priority::High),
+            "urgent" => Some(Priority::Urgent),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Priority::Low => "low",
+            Priority::Medium => "medium",
+            Priority::High => "high",
+            Priority::Urgent => "urgent",
+        }
+    }
+
+    fn from_level(level: i64) -> Self {
+        match level {
+            i64::MIN..=0 => Priority::Low,
+            1 => Priority::Medium,
+            2 => Priority::High,
+            _ => Priority::Urgent,
+        }
+    }
+}
+
+/// Sign-off state of a todo completed in a list that requires review.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ReviewStatus {
+    Pending,
+    Approved,
+    Rejected,
+}
+
+impl ReviewStatus {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "pending" => Some(ReviewStatus::Pending),
+            "approved" => Some(ReviewStatus::Approved),
+            "rejected" => Some(ReviewStatus::Rejected),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ReviewStatus::Pending => "pending",
+            ReviewStatus::Approved => "approved",
+            ReviewStatus::Rejected => "rejected",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Review {
+    pub status: ReviewStatus,
+    pub reviewer: Option<String>,
+    /// When the todo was approved or rejected
+    pub reviewed_at: Option<String>,
+    pub comment: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Habit {
+    /// Days a week the habit should be done, 1-7
+    pub target_per_week: u8,
+    /// Check-ins since Monday (UTC)
+    pub checkins_this_week: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Todo {
+    pub id: String,
+    pub title: String,
+    pub description: Option<String>,
+    pub completed: bool,
+    pub created_at: DateTime<Utc>,
+    pub metadata: Metadata,
+    /// RFC3339 deadline, normalized to UTC
+    pub due_date: Option<DateTime<Utc>>,
+    /// Until this RFC3339 time the todo is deferred: it is only listed by
+    /// `GET /api/todos/scheduled`
+    pub start_date: Option<DateTime<Utc>>,
+    pub priority: Priority,
+    /// Names of the attached tags, alphabetically
+    pub tags: Vec<String>,
+    /// The todo this one is a subtask of
+    pub parent_id: Option<String>,
+    /// Direct subtasks only
+    pub subtask_count: i64,
+    pub completed_subtask_count: i64,
+    /// An allowlisted emoji or icon name
+    pub icon: Option<String>,
+    /// iCalendar RRULE, e.g. `FREQ=WEEKLY;BYDAY=MO`
+    pub recurrence: Option<String>,
+    pub list_id: Option<String>,
+    /// Manual ordering, ascending; set with `POST /api/todos/:id/move`
+    pub position: f64,
+    /// When the todo was last completed; unknown for todos completed before this was tracked
+    pub completed_at: Option<DateTime<Utc>>,
+    /// Set by the list's retention policy; archived todos are hidden from lists by default
+    pub archived_at: Option<DateTime<Utc>>,
+    /// Set while the todo is in the trash
+    pub deleted_at: Option<DateTime<Utc>>,
+    /// Checklist items in their order
+    pub checklist: Vec<ChecklistItem>,
+    /// Advisory edit lock, while it has not expired
+    pub lock: Option<TodoLock>,
+    /// Set once the todo is completed in a list that requires review
+    pub review: Option<Review>,
+    /// Ids of the todos that have to be done before this one, completed or not
+    pub blocked_by: Vec<String>,
+    /// Ids of the todos waiting on this one
+    pub blocks: Vec<String>,
+    /// For occurrences of a recurring todo, the id of the todo the series started with
+    pub series_id: Option<String>,
+    /// Set when the todo is tracked as a habit
+    pub habit: Option<Habit>,
+    pub estimate_minutes: Option<u32>,
+    /// Seconds tracked with the timer, including the running entry
+    pub time_spent: i64,
+    /// When the running timer was started, while there is one
+    pub timer_started_at: Option<DateTime<Utc>>,
+    /// Pinned todos are listed before all others, whatever the sort order
+    pub pinned: bool,
+    /// Values of the fields registered with `/api/custom-fields`, by field name
+    pub custom_fields: Metadata,
+    /// RFC3339 time to send a reminder at, normalized to UTC
+    pub remind_at: Option<DateTime<Utc>>,
+    /// When the reminder was sent; cleared whenever `remind_at` is set
+    pub reminded_at: Option<DateTime<Utc>>,
+    /// When the todo's own fields last changed; set by the database on every update
+    pub updated_at: DateTime<Utc>,
+    /// Whole days since the todo was created, computed when it is read
+    pub age_days: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateTodo {
+    pub title: String,
+    pub description: Option<String>,
+    pub metadata: Option<Metadata>,
+    /// `null` values are left out
+    pub custom_fields: Option<Metadata>,
+    pub due_date: Option<String>,
+    pub start_date: Option<String>,
+    pub remind_at: Option<String>,
+    pub estimate_minutes: Option<u32>,
+    /// Defaults to medium
+    pub priority: Option<Priority>,
+    /// Set by `POST /api/todos/:id/children`, never read from the request body
+    #[serde(skip)]
+    pub parent_id: Option<String>,
+    pub icon: Option<String>,
+    pub recurrence: Option<String>,
+    pub list_id: Option<String>,
+    /// Tag names to attach. In a list with default tags, leaving this out attaches those and
+    /// `[]` attaches none.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tags: Option<Vec<String>>,
+}
+
+/// A field that is `null` becomes `Some(None)`, so it can be told apart from one left out.
+pub fn nullable<'de, T, D>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct UpdateTodo {
+    pub title: Option<String>,
+    /// `null` or an empty string removes the description; leaving it out keeps it
+    #[serde(default, deserialize_with = "nullable")]
+    pub description: Option<Option<String>>,
+    pub completed: Option<bool>,
+    pub metadata: Option<Metadata>,
+    /// Merged into the todo's values; `null` removes a value
+    pub custom_fields: Option<Metadata>,
+    /// An empty string removes the due date
+    pub due_date: Option<String>,
+    /// An empty string removes the start date
+    pub start_date: Option<String>,
+    /// An empty string removes the reminder
+    pub remind_at: Option<String>,
+    /// `0` removes the estimate
+    pub estimate_minutes: Option<u32>,
+    pub priority: Option<Priority>,
+    /// An empty string removes the icon
+    pub icon: Option<String>,
+    /// An empty string stops the todo from recurring
+    pub recurrence: Option<String>,
+    /// An empty string takes the todo out of its list
+    pub list_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SortDirection {
+    Asc,
+    Desc,
+}
+
+#[derive(Debug, Clone)]
+pub struct SortKey {
+    pub column: &'static str,
+    pub direction: SortDirection,
+}
+
+// Whitelist of sortable fields, so user input never reaches the ORDER BY clause directly
+pub fn sort_column(field: &str) -> Option<&'static str> {
+    match field {
+        "title" => Some("title COLLATE NOCASE"),
+        // `seq` follows creation order exactly, even for todos created in the same instant
+        "created_at" => Some("seq"),
+        "completed" => Some("completed"),
+        "priority" => Some("priority"),
+        "position" => Some("position"),
+        "start_date" => Some("start_date"),
+        // Todos without a due date come last either way
+        "due_date" => Some("due_date IS NULL, due_date"),
+        "updated_at" => Some("updated_at"),
+        "completed_at" => Some("completed_at"),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct TodoFilter {
+    /// Only these todos
+    pub ids: Option<Vec<String>>,
+    pub completed: Option<bool>,
+    /// RFC3339 timestamps, both exclusive
+    pub created_before: Option<String>,
+    pub created_after: Option<String>,
+    /// Exclusive, in the `updated_at` format (UTC with milliseconds)
+    pub updated_before: Option<String>,
+    /// Case-insensitive (for ASCII) substring of the title
+    pub title_contains: Option<String>,
+    /// Normalized due dates, both exclusive; todos without a due date never match
+    pub due_before: Option<String>,
+    pub due_after: Option<String>,
+    /// Matches any of the listed priorities
+    pub priorities: Vec<Priority>,
+    /// Todos must carry every listed tag
+    pub tags: Vec<String>,
+    /// Direct subtasks of this todo
+    pub parent_id: Option<String>,
+    pub list_id: Option<String>,
+    pub archived: Option<bool>,
+    /// Only todos in the trash instead of only todos outside it
+    pub deleted: bool,
+    pub review: Option<ReviewStatus>,
+    /// Only todos deferred to a future start date instead of only todos that have started
+    pub scheduled: Option<bool>,
+    /// `(key, value)` pairs matched against top-level metadata keys
+    pub metadata: Vec<(String, String)>,
+    /// `(field, value)` pairs matched against custom field values
+    pub custom_fields: Vec<(String, String)>,
+    /// Applied in order after pinned todos are put first; the manual `position` ordering
+    /// breaks remaining ties
+    pub sort: Vec<SortKey>,
+    pub limit: Option<u32>,
+    pub offset: u32,
+    /// Pages by `(created_at, id)` instead of the sort keys and `offset`
+    pub keyset: Option<Keyset>,
+}
+
+/// Keyset pagination: todos come in `(created_at, id)` order, so a page boundary stays put
+/// while todos are added or removed.
+#[derive(Debug, Clone)]
+pub struct Keyset {
+    pub direction: SortDirection,
+    /// `(created_at, id)` of the last todo on the previous page; `None` for the first page
+    pub after: Option<(String, String)>,
+}
+
+/// Assembles the `WHERE` and `ORDER BY` parts of a list query.
+/// Conditions are ANDed together and sort keys take precedence in the order they were added.
+#[derive(Debug, Default)]
+pub struct QueryBuilder {
+    conditions: Vec<String>,
+    params: Vec<SqlValue>,
+    order_by: Vec<String>,
+    limit: Option<(u32, u32)>,
+}
+
+impl QueryBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn and_where(
+        &mut self,
+        condition: &str,
+        params: impl IntoIterator<Item = SqlValue>,
+    ) -> &mut Self {
+        self.conditions.push(condition.to_string());
+        self.params.extend(params);
+        self
+    }
+
+    pub fn order_by(&mut self, column: &str, direction: SortDirection) -> &mut Self {
+        let direction = match direction {
+            SortDirection::Asc => "ASC",
+            SortDirection::Desc => "DESC",
+        };
+        self.order_by.push(format!("{column} {direction}"));
+        self
+    }
+
+    pub fn paginate(&mut self, limit: u32, offset: u32) -> &mut Self {
+        self.limit = Some((limit, offset));
+        self
+    }
+
+    pub fn sql(&self, select: &str) -> String {
+        let mut sql = select.to_string();
+        if !self.conditions.is_empty() {
+            sql.push_str(" WHERE ");
+            sql.push_str(&self.conditions.join(" AND "));
+        }
+        if !self.order_by.is_empty() {
+            sql.push_str(" ORDER BY ");
+            sql.push_str(&self.order_by.join(", "));
+        }
+        if let Some((limit, offset)) = self.limit {
+            sql.push_str(&format!(" LIMIT {limit} OFFSET {offset}"));
+        }
+        sql
+    }
+
+    pub fn params(&self) -> rusqlite::ParamsFromIter<&Vec<SqlValue>> {
+        rusqlite::params_from_iter(&self.params)
+    }
+}
+
+impl TodoFilter {
+    pub fn apply(&self, query: &mut QueryBuilder) {
+        self.apply_conditions(query);
+        if let Some(keyset) = &self.keyset {
+            if let Some((created_at, id)) = &keyset.after {
+                let comparison = match keyset.direction {
+                    SortDirection::Asc => ">",
+                    SortDirection::Desc => "<",
+                };
+                query.and_where(
+                    &format!("(created_at, id) {comparison} (?, ?)"),
+                    [
+                        SqlValue::Text(created_at.clone()),
+                        SqlValue::Text(id.clone()),
+                    ],
+                );
+            }
+            query.order_by("created_at", keyset.direction);
+            query.order_by("id", keyset.direction);
+            if let Some(limit) = self.limit {
+                query.paginate(limit, 0);
+            }
+            return;
+        }
+        query.order_by("pinned", SortDirection::Desc);
+        for key in &self.sort {
+            query.order_by(key.column, key.direction);
+        }
+        query.order_by("position", SortDirection::Asc);
+        query.order_by("seq", SortDirection::Desc);
+        if let Some(limit) = self.limit {
+            query.paginate(limit, self.offset);
+        }
+    }
+
+    pub fn apply_conditions(&self, query: &mut QueryBuilder) {
+        if let Some(ids) = &self.ids {
+            let placeholders = vec!["?"; ids.len()].join(", ");
+            query.and_where(
+                &format!("id IN ({placeholders})"),
+                ids.iter().map(|id| SqlValue::Text(id.clone())),
+            );
+        }
+        if let Some(completed) = self.completed {
+            query.and_where("completed = ?", [SqlValue::Integer(completed as i64)]);
+        }
+        if let Some(created_before) = &self.created_before {
+            query.and_where("created_at < ?", [SqlValue::Text(created_before.clone())]);
+        }
+        if let Some(created_after) = &self.created_after {
+            query.and_where("created_at > ?", [SqlValue::Text(created_after.clone())]);
+        }
+        if let Some(updated_before) = &self.updated_before {
+            query.and_where("updated_at < ?", [SqlValue::Text(updated_before.clone())]);
+        }
+        if let Some(text) = &self.title_contains {
+            // The search text is matched literally, wildcards included
+            let escaped = text
+                .replace('\\', "\\\\")
+                .replace('%', "\\%")
+                .replace('_', "\\_");
+            query.and_where(
+                "title LIKE ? ESCAPE '\\'",
+                [SqlValue::Text(format!("%{escaped}%"))],
+            );
+        }
+        if let Some(due_before) = &self.due_before {
+            query.and_where("due_date < ?", [SqlValue::Text(due_before.clone())]);
+        }
+        if let Some(due_after) = &self.due_after {
+            query.and_where("due_date > ?", [SqlValue::Text(due_after.clone())]);
+        }
+        if let Some(parent_id) = &self.parent_id {
+            query.and_where("parent_id = ?", [SqlValue::Text(parent_id.clone())]);
+        }
+        if let Some(list_id) = &self.list_id {
+            query.and_where("list_id = ?", [SqlValue::Text(list_id.clone())]);
+        }
+        if self.deleted {
+            query.and_where("deleted_at IS NOT NULL", []);
+        } else {
+            query.and_where("deleted_at IS NULL", []);
+        }
+        match self.scheduled {
+            Some(true) => {
+                query.and_where("start_date > ?", [SqlValue::Text(now_timestamp())]);
+            }
+            Some(false) => {
+                query.and_where(
+                    "(start_date IS NULL OR start_date <= ?)",
+                    [SqlValue::Text(now_timestamp())],
+                );
+            }
+            None => {}
+        }
+        if let Some(review) = self.review {
+            query.and_where(
+                "review_status = ?",
+                [SqlValue::Text(review.as_str().to_string())],
+            );
+        }
+        match self.archived {
+            Some(true) => {
+                query.and_where("archived_at IS NOT NULL", []);
+            }
+            Some(false) => {
+                query.and_where("archived_at IS NULL", []);
+            }
+            None => {}
+        }
+        for tag in &self.tags {
+            query.and_where(
+                "id IN (SELECT tt.todo_id FROM todo_tags tt JOIN tags t ON t.id = tt.tag_id
+                        WHERE t.name = ?)",
+                [SqlValue::Text(tag.clone())],
+            );
+        }
+        if !self.priorities.is_empty() {
+            let placeholders = vec!["?"; self.priorities.len()].join(", ");
+            query.and_where(
+                &format!("priority IN ({placeholders})"),
+                self.priorities
+                    .iter()
+                    .map(|priority| SqlValue::Integer(*priority as i64)),
+            );
+        }
+        for (key, value) in &self.metadata {
+            // Keys are validated before they get here, quoting keeps `$.` paths unambiguous
+            json_value_equals(query, "metadata", key, value);
+        }
+        for (name, value) in &self.custom_fields {
+            json_value_equals(query, "custom_fields", name, value);
+        }
+    }
+}
+
+// Matches todos whose JSON `column` holds `value` under `key`. Booleans compare as
+// `true`/`false` rather than SQLite's 1/0, and numbers as their text, e.g. `3` or `2.5`.
+fn json_value_equals(query: &mut QueryBuilder, column: &str, key: &str, value: &str) {
+    let path = format!("$.\"{key}\"");
+    query.and_where(
+        &format!(
+            "CASE json_type({column}, ?) WHEN 'true' THEN 'true' WHEN 'false' THEN 'false'
+             ELSE CAST(json_extract({column}, ?) AS TEXT) END = ?"
+        ),
+        [
+            SqlValue::Text(path.clone()),
+            SqlValue::Text(path),
+            SqlValue::Text(value.to_string()),
+        ],
+    );
+}
+
+const TODO_COLUMNS: &str =
+    "id, title, description, completed, created_at, metadata, due_date, priority,
+    (SELECT json_group_array(t.name) FROM todo_tags tt JOIN tags t ON t.id = tt.tag_id
+     WHERE tt.todo_id = todos.id),
+    parent_id,
+    (SELECT COUNT(*) FROM todos c WHERE c.parent_id = todos.id AND c.deleted_at IS NULL),
+    (SELECT COUNT(*) FROM todos c
+     WHERE c.parent_id = todos.id AND c.completed AND c.deleted_at IS NULL),
+    icon, recurrence, list_id, position, completed_at, archived_at, deleted_at,
+    (SELECT json_group_array(json_object(
+        'id', ci.id, 'text', ci.text,
+        'checked', json(CASE WHEN ci.checked THEN 'true' ELSE 'false' END),
+        'position', ci.position))
+     FROM checklist_items ci WHERE ci.todo_id = todos.id),
+    (SELECT json_object('holder', l.holder, 'acquired_at', l.acquired_at, 'expires_at', l.expires_at)
+     FROM todo_locks l
+     WHERE l.todo_id = todos.id AND l.expires_at > strftime('%Y-%m-%dT%H:%M:%SZ', 'now')),
+    review_status, reviewer, reviewed_at, review_comment,
+    (SELECT json_group_array(d.blocker_id) FROM todo_dependencies d
+     JOIN todos b ON b.id = d.blocker_id
+     WHERE d.blocked_id = todos.id AND b.deleted_at IS NULL),
+    (SELECT json_group_array(d.blocked_id) FROM todo_dependencies d
+     JOIN todos b ON b.id = d.blocked_id
+     WHERE d.blocker_id = todos.id AND b.deleted_at IS NULL),
+    series_id, start_date, habit_target_per_week,
+    (SELECT COUNT(*) FROM habit_checkins h
+     WHERE h.todo_id = todos.id AND h.date >= date('now', '-6 days', 'weekday 1')),
+    estimate_minutes,
+    (SELECT COALESCE(SUM(
+        strftime('%s', COALESCE(e.stopped_at, 'now')) - strftime('%s', e.started_at)), 0)
+     FROM time_entries e WHERE e.todo_id = todos.id),
+    (SELECT e.started_at FROM time_entries e
+     WHERE e.todo_id = todos.id AND e.stopped_at IS NULL),
+    pinned, custom_fields, remind_at, reminded_at, updated_at";
+
+pub fn age_days(created_at: DateTime<Utc>) -> i64 {
+    (Utc::now() - created_at).num_days()
+}
+
+/// Parses an RFC3339 timestamp in any offset into UTC.
+pub fn parse_timestamp(value: &str) -> Result<DateTime<Utc>, String> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|time| time.with_timezone(&Utc))
+        .map_err(|_| format!("Invalid timestamp '{value}': expected RFC3339"))
+}
+
+/// Reads an RFC3339 column; anything else is a conversion error rather than a silent default.
+fn timestamp_column(row: &rusqlite::Row, idx: usize) -> Result<DateTime<Utc>> {
+    let value: String = row.get(idx)?;
+    parse_timestamp(&value).map_err(|e| {
+        rusqlite::Error::FromSqlConversionFailure(idx, rusqlite::types::Type::Text, e.into())
+    })
+}
+
+fn optional_timestamp_column(row: &rusqlite::Row, idx: usize) -> Result<Option<DateTime<Utc>>> {
+    match row.get_ref(idx)? {
+        rusqlite::types::ValueRef::Null => Ok(None),
+        _ => timestamp_column(row, idx).map(Some),
+    }
+}
+
+fn row_to_todo(row: &rusqlite::Row) -> Result<Todo> {
+    let metadata: String = row.get(5)?;
+    let created_at = timestamp_column(row, 4)?;
+    let mut tags: Vec<String> = serde_json::from_str(&row.get::<_, String>(8)?).unwrap_or_default();
+    tags.sort_by_key(|tag| tag.to_lowercase());
+    let mut checklist: Vec<ChecklistItem> =
+        serde_json::from_str(&row.get::<_, String>(19)?).unwrap_or_default();
+    checklist.sort_by_key(|item| item.position);
+    let mut blocked_by: Vec<String> =
+        serde_json::from_str(&row.get::<_, String>(25)?).unwrap_or_default();
+    blocked_by.sort();
+    let mut blocks: Vec<String> =
+        serde_json::from_str(&row.get::<_, String>(26)?).unwrap_or_default();
+    blocks.sort();
+    Ok(Todo {
+        id: row.get(0)?,
+        title: row.get(1)?,
+        description: row
+            .get::<_, Option<String>>(2)?
+            .filter(|description| !description.is_empty()),
+        completed: row.get::<_, i32>(3)? != 0,
+        age_days: age_days(created_at),
+        created_at,
+        metadata: serde_json::from_str(&metadata).unwrap_or_default(),
+        due_date: optional_timestamp_column(row, 6)?,
+        priority: Priority::from_level(row.get(7)?),
+        tags,
+        parent_id: row.get(9)?,
+        subtask_count: row.get(10)?,
+        completed_subtask_count: row.get(11)?,
+        icon: row.get(12)?,
+        recurrence: row.get(13)?,
+        list_id: row.get(14)?,
+        position: row.get(15)?,
+        completed_at: optional_timestamp_column(row, 16)?,
+        archived_at: optional_timestamp_column(row, 17)?,
+        deleted_at: optional_timestamp_column(row, 18)?,
+        checklist,
+        lock: row
+            .get::<_, Option<String>>(20)?
+            .and_then(|lock| serde_json::from_str(&lock).ok()),
+        review: match row
+            .get::<_, Option<String>>(21)?
+            .as_deref()
+            .and_then(ReviewStatus::parse)
+        {
+            Some(status) => Some(Review {
+                status,
+                reviewer: row.get(22)?,
+                reviewed_at: row.get(23)?,
+                comment: row.get(24)?,
+            }),
+            None => None,
+        },
+        blocked_by,
+        blocks,
+        series_id: row.get(27)?,
+        start_date: optional_timestamp_column(row, 28)?,
+        habit: match row.get::<_, Option<u8>>(29)? {
+            Some(target_per_week) => Some(Habit {
+                target_per_week,
+                checkins_this_week: row.get(30)?,
+            }),
+            None => None,
+        },
+        estimate_minutes: row.get(31)?,
+        time_spent: row.get(32)?,
+        timer_started_at: optional_timestamp_column(row, 33)?,
+        pinned: row.get(34)?,
+        custom_fields: serde_json::from_str(&row.get::<_, String>(35)?).unwrap_or_default(),
+        remind_at: optional_timestamp_column(row, 36)?,
+        reminded_at: optional_timestamp_column(row, 37)?,
+        updated_at: timestamp_column(row, 38)?,
+    })
+}
+
+/// The current time in the same format as normalized due dates.
+pub fn now_timestamp() -> String {
+    format_timestamp(Utc::now())
+}
+
+/// Formats a time the way normalized due dates are stored, so they compare correctly as text.
+pub fn format_timestamp(time: DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
+}
+
+pub fn validate_estimate(minutes: u32) -> Result<(), String> {
+    if !(1..=ESTIMATE_MAX_MINUTES).contains(&minutes) {
+        return Err(format!(
+            "estimate_minutes must be between 1 and {ESTIMATE_MAX_MINUTES}"
+        ));
+    }
+    Ok(())
+}
+
+pub fn normalize_start_date(value: &str) -> Result<String, String> {
+    normalize_due_date(value)
+        .map_err(|_| format!("Invalid start date '{value}': expected an RFC3339 timestamp"))
+}
+
+pub fn normalize_remind_at(value: &str) -> Result<String, String> {
+    normalize_due_date(value)
+        .map_err(|_| format!("Invalid reminder time '{value}': expected an RFC3339 timestamp"))
+}
+
+/// Parses an RFC3339 timestamp and rewrites it in UTC with second precision, so due dates
+/// compare correctly as text in range filters.
+pub fn normalize_due_date(value: &str) -> Result<String, String> {
+    chrono::DateTime::parse_from_rfc3339(value)
+        .map(|due| {
+            due.with_timezone(&chrono::Utc)
+                .to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
+        })
+        .map_err(|_| format!("Invalid due date '{value}': expected an RFC3339 timestamp"))
+}
+
+pub fn is_valid_metadata_key(key: &str) -> bool {
+    !key.is_empty()
+        && key.len() <= METADATA_MAX_KEY_LEN
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+pub fn validate_metadata(metadata: &Metadata) -> Result<(), String> {
+    if let Some(key) = metadata.keys().find(|key| !is_valid_metadata_key(key)) {
+        return Err(format!(
+            "Invalid metadata key '{key}': use 1-{METADATA_MAX_KEY_LEN} characters of [A-Za-z0-9_-]"
+        ));
+    }
+    let size = serde_json::to_string(metadata)
+        .map(|json| json.len())
+        .unwrap_or(usize::MAX);
+    if size > METADATA_MAX_BYTES {
+        return Err(format!(
+            "Metadata is too large ({size} bytes, limit is {METADATA_MAX_BYTES})"
+        ));
+    }
+    Ok(())
+}
+
+// WANTED EXAMPLE: Poolの使い方、genericの使い方
+pub type DbPool = Pool<SqliteConnectionManager>;
+
+const DATABASE_FILE: &str = "todos.db";
+const MIN_IDLE_CONNECTIONS: u32 = 2;
+// Bump whenever `migrate` changes the schema
+pub const SCHEMA_VERSION: i64 = 45;
+const EXPECTED_TABLES: &[&str] = &[
+    "todos",
+    "pomodoro_sessions",
+    "tags",
+    "todo_tags",
+    "palette_colors",
+    "lists",
+    "events",
+    "event_consumers",
+    "attachments",
+    "checklist_items",
+    "todo_locks",
+    "todo_dependencies",
+    "recurrence_completions",
+    "habit_checkins",
+    "time_entries",
+    "custom_fields",
+    "macros",
+    "export_schedules",
+    "import_uploads",
+    "list_snapshots",
+    "todo_revisions",
+    "activity",
+    "alert_rules",
+    "templates",
+    "todos_fts",
+    "views",
+    "inbound_hooks",
+    "escalation_rules",
+    "todo_escalations",
+    "idempotency_keys",
+];
+
+/// Opens the pool, migrates the schema and checks the database is usable, so a broken
+/// setup fails at startup with a clear message instead of on the first request.
+pub fn create_pool() -> Result<DbPool, Box<dyn std::error::Error + Send + Sync>> {
+    // Foreign keys are off by default in SQLite and must be enabled per connection.
+    // WAL lets readers keep a consistent snapshot without blocking writers.
+    let manager = SqliteConnectionManager::file(DATABASE_FILE).with_init(|conn| {
+        conn.profile(Some(slow_query::record));
+        conn.execute_batch("PRAGMA foreign_keys = ON; PRAGMA journal_mode = WAL;")
+    });
+    // `build` opens the idle connections up front and fails if it cannot
+    let pool = Pool::builder()
+        .min_idle(Some(MIN_IDLE_CONNECTIONS))
+        .connection_timeout(Duration::from_secs(5))
+        .build(manager)
+        .map_err(|e| format!("cannot open {DATABASE_FILE}: {e}"))?;
+
+    let conn = pool.get()?;
+    let version: i64 = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    if version > SCHEMA_VERSION {
+        return Err(format!(
+            "{DATABASE_FILE} has schema version {version}, but this build only knows up to {SCHEMA_VERSION}; upgrade the server"
+        )
+        .into());
+    }
+    migrate(&conn).map_err(|e| format!("schema migration failed: {e}"))?;
+    conn.pragma_update(None, "user_version", SCHEMA_VERSION)?;
+    drop(conn);
+
+    self_check(&pool)?;
+    Ok(pool)
+}
+
+fn self_check(pool: &DbPool) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    conn.query_row("SELECT 1", [], |row| row.get::<_, i64>(0))
+        .map_err(|e| format!("database self-check query failed: {e}"))?;
+    for table in EXPECTED_TABLES {
+        let exists: bool = conn.query_row(
+            "SELECT COUNT(*) > 0 FROM sqlite_master WHERE type = 'table' AND name = ?1",
+            [table],
+            |row| row.get(0),
+        )?;
+        if !exists {
+            return Err(format!("database self-check failed: table `{table}` is missing").into());
+        }
+    }
+    Ok(())
+}
+
+// Every statement is idempotent, so running this against an up-to-date database is a no-op
+fn migrate(conn: &rusqlite::Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS todos (
+            id TEXT PRIMARY KEY,
+            title TEXT NOT NULL,
+            description TEXT,
+            completed BOOLEAN NOT NULL DEFAULT 0,
+            created_at TEXT NOT NULL
+        )",
+        [],
+    )?;
+    add_column_if_missing(conn, "todos", "metadata", "TEXT NOT NULL DEFAULT '{}'")?;
+    if add_column_if_missing(conn, "todos", "seq", "INTEGER NOT NULL DEFAULT 0")? {
+        // Number existing rows in their creation order
+        conn.execute(
+            "UPDATE todos SET seq = (
+                SELECT n FROM (
+                    SELECT id, ROW_NUMBER() OVER (ORDER BY created_at, rowid) AS n FROM todos
+                ) ranked WHERE ranked.id = todos.id
+            )",
+            [],
+        )?;
+    }
+    add_column_if_missing(conn, "todos", "due_date", "TEXT")?;
+    add_column_if_missing(conn, "todos", "priority", "INTEGER NOT NULL DEFAULT 1")?;
+    // Subtasks are deleted together with their parent
+    add_column_if_missing(
+        conn,
+        "todos",
+        "parent_id",
+        "TEXT REFERENCES todos(id) ON DELETE CASCADE",
+    )?;
+    conn.execute_batch(
+        "CREATE UNIQUE INDEX IF NOT EXISTS idx_todos_seq ON todos(seq);
+         CREATE INDEX IF NOT EXISTS idx_todos_completed ON todos(completed, seq);
+         CREATE INDEX IF NOT EXISTS idx_todos_due_date ON todos(due_date);
+         CREATE INDEX IF NOT EXISTS idx_todos_parent ON todos(parent_id);",
+    )?;
+    add_column_if_missing(conn, "todos", "icon", "TEXT")?;
+    add_column_if_missing(conn, "todos", "recurrence", "TEXT")?;
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_todos_recurring ON todos(completed)
+         WHERE recurrence IS NOT NULL",
+        [],
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS pomodoro_sessions (
+            id TEXT PRIMARY KEY,
+            todo_id TEXT NOT NULL REFERENCES todos(id) ON DELETE CASCADE,
+            planned_minutes INTEGER NOT NULL,
+            started_at TEXT NOT NULL,
+            ended_at TEXT
+        )",
+        [],
+    )?;
+    // At most one running session per todo. Sessions started twice before the index existed
+    // keep only the newest one running.
+    conn.execute(
+        "UPDATE pomodoro_sessions SET ended_at = started_at
+         WHERE ended_at IS NULL AND EXISTS (
+             SELECT 1 FROM pomodoro_sessions newer
+             WHERE newer.todo_id = pomodoro_sessions.todo_id AND newer.ended_at IS NULL
+               AND (newer.started_at, newer.id) > (pomodoro_sessions.started_at, pomodoro_sessions.id)
+         )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE UNIQUE INDEX IF NOT EXISTS idx_pomodoro_sessions_running
+         ON pomodoro_sessions(todo_id) WHERE ended_at IS NULL",
+        [],
+    )?;
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS tags (
+            id TEXT PRIMARY KEY,
+            name TEXT NOT NULL UNIQUE COLLATE NOCASE,
+            created_at TEXT NOT NULL
+        );
+        CREATE TABLE IF NOT EXISTS todo_tags (
+            todo_id TEXT NOT NULL REFERENCES todos(id) ON DELETE CASCADE,
+            tag_id TEXT NOT NULL REFERENCES tags(id) ON DELETE CASCADE,
+            PRIMARY KEY (todo_id, tag_id)
+        );
+        CREATE INDEX IF NOT EXISTS idx_todo_tags_tag ON todo_tags(tag_id);",
+    )?;
+    let has_palette: bool = conn.query_row(
+        "SELECT COUNT(*) > 0 FROM sqlite_master WHERE type = 'table' AND name = 'palette_colors'",
+        [],
+        |row| row.get(0),
+    )?;
+    if !has_palette {
+        conn.execute(
+            "CREATE TABLE palette_colors (
+                name TEXT PRIMARY KEY COLLATE NOCASE,
+                hex TEXT NOT NULL,
+                created_at TEXT NOT NULL
+            )",
+            [],
+        )?;
+        // Seeded once, so colors removed later stay removed
+        let created_at = chrono::Utc::now().to_rfc3339();
+        for (name, hex) in DEFAULT_PALETTE {
+            conn.execute(
+                "INSERT INTO palette_colors (name, hex, created_at) VALUES (?1, ?2, ?3)",
+                [name, hex, &created_at.as_str()],
+            )?;
+        }
+    }
+    // Tags lose their color when it is removed from the palette
+    add_column_if_missing(
+        conn,
+        "tags",
+        "color",
+        "TEXT REFERENCES palette_colors(name) ON DELETE SET NULL",
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS lists (
+            id TEXT PRIMARY KEY,
+            name TEXT NOT NULL UNIQUE COLLATE NOCASE,
+            icon TEXT,
+            color TEXT REFERENCES palette_colors(name) ON DELETE SET NULL,
+            created_at TEXT NOT NULL
+        )",
+        [],
+    )?;
+    // Todos of deleted lists are moved here, so it always exists
+    conn.execute(
+        "INSERT OR IGNORE INTO lists (id, name, icon, created_at) VALUES (?1, 'Inbox', 'inbox', ?2)",
+        [INBOX_LIST_ID, &chrono::Utc::now().to_rfc3339()],
+    )?;
+    add_column_if_missing(
+        conn,
+        "todos",
+        "list_id",
+        "TEXT REFERENCES lists(id) ON DELETE SET NULL",
+    )?;
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_todos_list ON todos(list_id)",
+        [],
+    )?;
+    if add_column_if_missing(conn, "todos", "position", "REAL NOT NULL DEFAULT 0")? {
+        // Keep the newest-first order existing clients are used to
+        conn.execute("UPDATE todos SET position = -seq", [])?;
+    }
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_todos_position ON todos(position)",
+        [],
+    )?;
+    add_column_if_missing(conn, "todos", "completed_at", "TEXT")?;
+    add_column_if_missing(conn, "todos", "archived_at", "TEXT")?;
+    add_column_if_missing(conn, "todos", "deleted_at", "TEXT")?;
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_todos_deleted ON todos(deleted_at)
+         WHERE deleted_at IS NOT NULL",
+        [],
+    )?;
+    // Retention policies, in days; NULL keeps todos forever
+    add_column_if_missing(conn, "lists", "archive_completed_after_days", "INTEGER")?;
+    add_column_if_missing(conn, "lists", "purge_trash_after_days", "INTEGER")?;
+    // Completing a todo in such a list waits for someone to approve or reject it
+    add_column_if_missing(
+        conn,
+        "lists",
+        "require_review",
+        "BOOLEAN NOT NULL DEFAULT 0",
+    )?;
+    add_column_if_missing(conn, "todos", "review_status", "TEXT")?;
+    add_column_if_missing(conn, "todos", "reviewer", "TEXT")?;
+    add_column_if_missing(conn, "todos", "reviewed_at", "TEXT")?;
+    add_column_if_missing(conn, "todos", "review_comment", "TEXT")?;
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_todos_review_pending ON todos(completed_at)
+         WHERE review_status = 'pending'",
+        [],
+    )?;
+
+    // AUTOINCREMENT so pruned sequence numbers are never handed out again
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS events (
+            seq INTEGER PRIMARY KEY AUTOINCREMENT,
+            type TEXT NOT NULL,
+            todo_id TEXT NOT NULL,
+            created_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%SZ', 'now'))
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS event_consumers (
+            name TEXT PRIMARY KEY,
+            acked_seq INTEGER NOT NULL DEFAULT 0,
+            updated_at TEXT NOT NULL
+        )",
+        [],
+    )?;
+    // File contents live on disk under the attachments directory, named by their SHA-256,
+    // or by id for files uploaded before contents were deduplicated
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS attachments (
+            id TEXT PRIMARY KEY,
+            todo_id TEXT NOT NULL REFERENCES todos(id) ON DELETE CASCADE,
+            filename TEXT NOT NULL,
+            content_type TEXT NOT NULL,
+            size INTEGER NOT NULL,
+            created_at TEXT NOT NULL
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_attachments_todo ON attachments(todo_id)",
+        [],
+    )?;
+    add_column_if_missing(conn, "attachments", "sha256", "TEXT")?;
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_attachments_sha256 ON attachments(sha256)",
+        [],
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS checklist_items (
+            id TEXT PRIMARY KEY,
+            todo_id TEXT NOT NULL REFERENCES todos(id) ON DELETE CASCADE,
+            text TEXT NOT NULL,
+            checked BOOLEAN NOT NULL DEFAULT 0,
+            position INTEGER NOT NULL,
+            created_at TEXT NOT NULL
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_checklist_items_todo ON checklist_items(todo_id, position)",
+        [],
+    )?;
+    // Expired locks are ignored when read and replaced by the next lock
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS todo_locks (
+            todo_id TEXT PRIMARY KEY REFERENCES todos(id) ON DELETE CASCADE,
+            holder TEXT NOT NULL,
+            acquired_at TEXT NOT NULL,
+            expires_at TEXT NOT NULL
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS todo_dependencies (
+            blocker_id TEXT NOT NULL REFERENCES todos(id) ON DELETE CASCADE,
+            blocked_id TEXT NOT NULL REFERENCES todos(id) ON DELETE CASCADE,
+            created_at TEXT NOT NULL,
+            PRIMARY KEY (blocker_id, blocked_id)
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_todo_dependencies_blocked
+         ON todo_dependencies(blocked_id)",
+        [],
+    )?;
+    // Occurrences of a recurring todo point at the todo the series started with
+    add_column_if_missing(conn, "todos", "series_id", "TEXT")?;
+    add_column_if_missing(conn, "todos", "start_date", "TEXT")?;
+    // Habits are todos with a weekly target, kept up by checking in on the days they were done
+    add_column_if_missing(conn, "todos", "habit_target_per_week", "INTEGER")?;
+    add_column_if_missing(conn, "todos", "estimate_minutes", "INTEGER")?;
+    add_column_if_missing(conn, "todos", "pinned", "INTEGER NOT NULL DEFAULT 0")?;
+    // Typed fields clients register once and then set on any todo
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS custom_fields (
+            name TEXT PRIMARY KEY COLLATE NOCASE,
+            type TEXT NOT NULL,
+            created_at TEXT NOT NULL
+        )",
+        [],
+    )?;
+    add_column_if_missing(conn, "todos", "custom_fields", "TEXT NOT NULL DEFAULT '{}'")?;
+    // Named sequences of bulk actions; `steps` is the JSON array as saved
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS macros (
+            id TEXT PRIMARY KEY,
+            name TEXT NOT NULL UNIQUE COLLATE NOCASE,
+            steps TEXT NOT NULL,
+            created_at TEXT NOT NULL,
+            last_run_at TEXT
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS templates (
+            id TEXT PRIMARY KEY,
+            name TEXT NOT NULL UNIQUE COLLATE NOCASE,
+            title TEXT NOT NULL,
+            description TEXT,
+            priority INTEGER,
+            tags TEXT NOT NULL,
+            checklist TEXT NOT NULL,
+            created_at TEXT NOT NULL,
+            updated_at TEXT NOT NULL
+        )",
+        [],
+    )?;
+    // Named list views shared by every client; `filters` and `columns` are JSON as saved.
+    // At most one view is the default.
+    let seed_views = !conn.query_row(
+        "SELECT COUNT(*) > 0 FROM sqlite_master WHERE type = 'table' AND name = 'views'",
+        [],
+        |row| row.get::<_, bool>(0),
+    )?;
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS views (
+            id TEXT PRIMARY KEY,
+            name TEXT NOT NULL UNIQUE COLLATE NOCASE,
+            columns TEXT NOT NULL,
+            filters TEXT NOT NULL,
+            sort TEXT,
+            group_by TEXT,
+            is_default INTEGER NOT NULL DEFAULT 0,
+            created_at TEXT NOT NULL,
+            updated_at TEXT NOT NULL
+        );
+        CREATE UNIQUE INDEX IF NOT EXISTS idx_views_default ON views(is_default) WHERE is_default;",
+    )?;
+    if seed_views {
+        let now = chrono::Utc::now().to_rfc3339();
+        conn.execute(
+            "INSERT INTO views (id, name, columns, filters, sort, group_by, is_default, created_at, updated_at)
+             VALUES (?1, 'Today', ?2, ?3, 'due_date', NULL, 1, ?4, ?4),
+                    (?5, 'Upcoming', ?2, ?6, 'due_date', 'due_date', 0, ?4, ?4)",
+            rusqlite::params![
+                uuid::Uuid::new_v4().to_string(),
+                r#"["title","due_date","priority","tags"]"#,
+                r#"{"completed":"false","due_before":"tomorrow"}"#,
+                now,
+                uuid::Uuid::new_v4().to_string(),
+                r#"{"completed":"false","due_after":"tomorrow","due_before":"today+8d"}"#,
+            ],
+        )?;
+    }
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS export_schedules (
+            id TEXT PRIMARY KEY,
+            name TEXT NOT NULL UNIQUE COLLATE NOCASE,
+            format TEXT NOT NULL,
+            filter TEXT NOT NULL,
+            destination TEXT NOT NULL,
+            hour_utc INTEGER NOT NULL,
+            created_at TEXT NOT NULL,
+            last_run_at TEXT,
+            last_file TEXT,
+            last_error TEXT
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS import_uploads (
+            id TEXT PRIMARY KEY,
+            columns TEXT NOT NULL,
+            rows TEXT NOT NULL,
+            created_at TEXT NOT NULL
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS list_snapshots (
+            list_id TEXT NOT NULL REFERENCES lists(id) ON DELETE CASCADE,
+            date TEXT NOT NULL,
+            open_count INTEGER NOT NULL,
+            closed_count INTEGER NOT NULL,
+            taken_at TEXT NOT NULL,
+            PRIMARY KEY (list_id, date)
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS todo_revisions (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            todo_id TEXT NOT NULL REFERENCES todos(id) ON DELETE CASCADE,
+            changed_at TEXT NOT NULL,
+            changes TEXT NOT NULL
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_todo_revisions_todo ON todo_revisions(todo_id, id)",
+        [],
+    )?;
+    // No foreign key: entries outlive the todos they are about
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS activity (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            action TEXT NOT NULL,
+            todo_id TEXT,
+            actor TEXT,
+            client_ip TEXT,
+            user_agent TEXT,
+            request_id TEXT,
+            payload TEXT NOT NULL,
+            created_at TEXT NOT NULL
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_activity_created_at ON activity(created_at)",
+        [],
+    )?;
+    // Alert rules count recent events by type
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_events_type_created_at ON events(type, created_at)",
+        [],
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS alert_rules (
+            id TEXT PRIMARY KEY,
+            name TEXT NOT NULL UNIQUE COLLATE NOCASE,
+            metric TEXT NOT NULL,
+            threshold REAL NOT NULL,
+            window_minutes INTEGER NOT NULL,
+            created_at TEXT NOT NULL,
+            firing INTEGER NOT NULL DEFAULT 0,
+            last_value REAL,
+            last_checked_at TEXT,
+            last_fired_at TEXT
+        )",
+        [],
+    )?;
+    // The token is the secret part of the hook's URL
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS inbound_hooks (
+            id TEXT PRIMARY KEY,
+            name TEXT NOT NULL UNIQUE COLLATE NOCASE,
+            token TEXT NOT NULL UNIQUE,
+            mapping TEXT NOT NULL,
+            list_id TEXT REFERENCES lists(id) ON DELETE SET NULL,
+            created_at TEXT NOT NULL,
+            updated_at TEXT NOT NULL,
+            last_received_at TEXT
+        )",
+        [],
+    )?;
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS escalation_rules (
+            id TEXT PRIMARY KEY,
+            list_id TEXT NOT NULL REFERENCES lists(id) ON DELETE CASCADE,
+            after_hours INTEGER NOT NULL,
+            priority INTEGER,
+            notify INTEGER NOT NULL,
+            created_at TEXT NOT NULL
+        );
+        CREATE INDEX IF NOT EXISTS idx_escalation_rules_list ON escalation_rules(list_id);
+        -- No foreign key to the rule, so the history outlives it
+        CREATE TABLE IF NOT EXISTS todo_escalations (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            todo_id TEXT NOT NULL REFERENCES todos(id) ON DELETE CASCADE,
+            rule_id TEXT NOT NULL,
+            after_hours INTEGER NOT NULL,
+            due_date TEXT NOT NULL,
+            priority INTEGER,
+            notified INTEGER NOT NULL,
+            escalated_at TEXT NOT NULL,
+            UNIQUE (rule_id, todo_id, due_date)
+        );
+        CREATE INDEX IF NOT EXISTS idx_todo_escalations_todo ON todo_escalations(todo_id);",
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS idempotency_keys (
+            key TEXT PRIMARY KEY,
+            request_hash TEXT NOT NULL,
+            status INTEGER,
+            response TEXT,
+            created_at TEXT NOT NULL
+        )",
+        [],
+    )?;
+    add_column_if_missing(conn, "inbound_hooks", "dedupe_key", "TEXT")?;
+    add_column_if_missing(
+        conn,
+        "inbound_hooks",
+        "dedupe_window_minutes",
+        "INTEGER NOT NULL DEFAULT 60",
+    )?;
+    add_column_if_missing(conn, "todos", "remind_at", "TEXT")?;
+    add_column_if_missing(conn, "todos", "reminded_at", "TEXT")?;
+    // The reminder scheduler only ever looks at reminders that have not been sent
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_todos_remind_at ON todos(remind_at)
+         WHERE remind_at IS NOT NULL AND reminded_at IS NULL",
+        [],
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS time_entries (
+            id TEXT PRIMARY KEY,
+            todo_id TEXT NOT NULL REFERENCES todos(id) ON DELETE CASCADE,
+            started_at TEXT NOT NULL,
+            stopped_at TEXT
+        )",
+        [],
+    )?;
+    // At most one running timer per todo
+    conn.execute(
+        "CREATE UNIQUE INDEX IF NOT EXISTS idx_time_entries_running ON time_entries(todo_id)
+         WHERE stopped_at IS NULL",
+        [],
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS habit_checkins (
+            todo_id TEXT NOT NULL REFERENCES todos(id) ON DELETE CASCADE,
+            date TEXT NOT NULL,
+            created_at TEXT NOT NULL,
+            PRIMARY KEY (todo_id, date)
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_todos_start_date ON todos(start_date)
+         WHERE start_date IS NOT NULL",
+        [],
+    )?;
+    // One row per completed occurrence; no foreign keys, so the history outlives the todos
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS recurrence_completions (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            series_id TEXT NOT NULL,
+            todo_id TEXT NOT NULL,
+            due_date TEXT,
+            completed_at TEXT,
+            missed_after INTEGER NOT NULL DEFAULT 0
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_recurrence_completions_series
+         ON recurrence_completions(series_id)",
+        [],
+    )?;
+    // Lets realtime subscribers filter by list; `previous_list_id` is set when a todo moves
+    add_column_if_missing(conn, "events", "list_id", "TEXT")?;
+    add_column_if_missing(conn, "events", "previous_list_id", "TEXT")?;
+    // The fields an update changed, as recorded in the todo's history
+    add_column_if_missing(conn, "events", "changes", "TEXT")?;
+    add_column_if_missing(conn, "activity", "changes", "TEXT")?;
+    // Applied to todos created in the list; NULL and `[]` set nothing
+    add_column_if_missing(conn, "lists", "default_priority", "INTEGER")?;
+    add_column_if_missing(conn, "lists", "default_tags", "TEXT NOT NULL DEFAULT '[]'")?;
+    add_column_if_missing(conn, "lists", "default_remind_before_minutes", "INTEGER")?;
+    let backfill_updated_at = add_column_if_missing(conn, "todos", "updated_at", "TEXT")?;
+    // Triggers run in the same transaction as the change, so every write is recorded,
+    // whichever code path or background job made it. They are recreated on every start so
+    // changes to them reach existing databases.
+    conn.execute_batch(
+        "DROP TRIGGER IF EXISTS todos_insert_event;
+        CREATE TRIGGER todos_insert_event AFTER INSERT ON todos
+        BEGIN
+            INSERT INTO events (type, todo_id, list_id)
+            VALUES ('todo.created', new.id, new.list_id);
+        END;
+        DROP TRIGGER IF EXISTS todos_touch;
+        CREATE TRIGGER todos_touch AFTER UPDATE ON todos
+        WHEN new.updated_at IS old.updated_at
+        BEGIN
+            UPDATE todos SET updated_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now')
+            WHERE id = new.id;
+        END;
+        -- Skips the follow-up update made by `todos_touch`, which only sets `updated_at`
+        DROP TRIGGER IF EXISTS todos_update_event;
+        CREATE TRIGGER todos_update_event AFTER UPDATE ON todos
+        WHEN (old.deleted_at IS NULL OR new.deleted_at IS NULL)
+          AND new.updated_at IS old.updated_at
+        BEGIN
+            INSERT INTO events (type, todo_id, list_id, previous_list_id) VALUES (
+                CASE
+                    WHEN new.deleted_at IS NOT NULL THEN 'todo.deleted'
+                    WHEN old.deleted_at IS NOT NULL THEN 'todo.restored'
+                    ELSE 'todo.updated'
+                END,
+                new.id,
+                new.list_id,
+                CASE WHEN old.list_id IS NOT new.list_id THEN old.list_id END
+            );
+        END;
+        DROP TRIGGER IF EXISTS todos_delete_event;
+        CREATE TRIGGER todos_delete_event AFTER DELETE ON todos
+        BEGIN
+            INSERT INTO events (type, todo_id, list_id)
+            VALUES ('todo.purged', old.id, old.list_id);
+        END;
+        DROP TRIGGER IF EXISTS todo_tags_insert_event;
+        CREATE TRIGGER todo_tags_insert_event AFTER INSERT ON todo_tags
+        WHEN (SELECT deleted_at IS NULL FROM todos WHERE id = new.todo_id)
+        BEGIN
+            INSERT INTO events (type, todo_id, list_id)
+            VALUES ('todo.updated', new.todo_id, (SELECT list_id FROM todos WHERE id = new.todo_id));
+        END;
+        DROP TRIGGER IF EXISTS todo_tags_delete_event;
+        CREATE TRIGGER todo_tags_delete_event AFTER DELETE ON todo_tags
+        WHEN (SELECT deleted_at IS NULL FROM todos WHERE id = old.todo_id)
+        BEGIN
+            INSERT INTO events (type, todo_id, list_id)
+            VALUES ('todo.updated', old.todo_id, (SELECT list_id FROM todos WHERE id = old.todo_id));
+        END;
+        DROP TRIGGER IF EXISTS habit_checkins_insert_event;
+        CREATE TRIGGER habit_checkins_insert_event AFTER INSERT ON habit_checkins
+        WHEN (SELECT deleted_at IS NULL FROM todos WHERE id = new.todo_id)
+        BEGIN
+            INSERT INTO events (type, todo_id, list_id)
+            VALUES ('todo.updated', new.todo_id, (SELECT list_id FROM todos WHERE id = new.todo_id));
+        END;
+        DROP TRIGGER IF EXISTS habit_checkins_delete_event;
+        CREATE TRIGGER habit_checkins_delete_event AFTER DELETE ON habit_checkins
+        WHEN (SELECT deleted_at IS NULL FROM todos WHERE id = old.todo_id)
+        BEGIN
+            INSERT INTO events (type, todo_id, list_id)
+            VALUES ('todo.updated', old.todo_id, (SELECT list_id FROM todos WHERE id = old.todo_id));
+        END;
+        DROP TRIGGER IF EXISTS time_entries_insert_event;
+        CREATE TRIGGER time_entries_insert_event AFTER INSERT ON time_entries
+        WHEN (SELECT deleted_at IS NULL FROM todos WHERE id = new.todo_id)
+        BEGIN
+            INSERT INTO events (type, todo_id, list_id)
+            VALUES ('todo.updated', new.todo_id, (SELECT list_id FROM todos WHERE id = new.todo_id));
+        END;
+        DROP TRIGGER IF EXISTS time_entries_update_event;
+        CREATE TRIGGER time_entries_update_event AFTER UPDATE ON time_entries
+        WHEN (SELECT deleted_at IS NULL FROM todos WHERE id = new.todo_id)
+        BEGIN
+            INSERT INTO events (type, todo_id, list_id)
+            VALUES ('todo.updated', new.todo_id, (SELECT list_id FROM todos WHERE id = new.todo_id));
+        END;
+        DROP TRIGGER IF EXISTS checklist_insert_event;
+        CREATE TRIGGER checklist_insert_event AFTER INSERT ON checklist_items
+        WHEN (SELECT deleted_at IS NULL FROM todos WHERE id = new.todo_id)
+        BEGIN
+            INSERT INTO events (type, todo_id, list_id)
+            VALUES ('todo.updated', new.todo_id, (SELECT list_id FROM todos WHERE id = new.todo_id));
+        END;
+        DROP TRIGGER IF EXISTS checklist_update_event;
+        CREATE TRIGGER checklist_update_event AFTER UPDATE ON checklist_items
+        WHEN (SELECT deleted_at IS NULL FROM todos WHERE id = new.todo_id)
+        BEGIN
+            INSERT INTO events (type, todo_id, list_id)
+            VALUES ('todo.updated', new.todo_id, (SELECT list_id FROM todos WHERE id = new.todo_id));
+        END;
+        DROP TRIGGER IF EXISTS checklist_delete_event;
+        CREATE TRIGGER checklist_delete_event AFTER DELETE ON checklist_items
+        WHEN (SELECT deleted_at IS NULL FROM todos WHERE id = old.todo_id)
+        BEGIN
+            INSERT INTO events (type, todo_id, list_id)
+            VALUES ('todo.updated', old.todo_id, (SELECT list_id FROM todos WHERE id = old.todo_id));
+        END;
+        DROP TRIGGER IF EXISTS todo_locks_insert_event;
+        CREATE TRIGGER todo_locks_insert_event AFTER INSERT ON todo_locks
+        BEGIN
+            INSERT INTO events (type, todo_id, list_id)
+            VALUES ('todo.locked', new.todo_id, (SELECT list_id FROM todos WHERE id = new.todo_id));
+        END;
+        DROP TRIGGER IF EXISTS todo_locks_update_event;
+        CREATE TRIGGER todo_locks_update_event AFTER UPDATE ON todo_locks
+        BEGIN
+            INSERT INTO events (type, todo_id, list_id)
+            VALUES ('todo.locked', new.todo_id, (SELECT list_id FROM todos WHERE id = new.todo_id));
+        END;
+        DROP TRIGGER IF EXISTS todo_locks_delete_event;
+        CREATE TRIGGER todo_locks_delete_event AFTER DELETE ON todo_locks
+        WHEN (SELECT deleted_at IS NULL FROM todos WHERE id = old.todo_id)
+        BEGIN
+            INSERT INTO events (type, todo_id, list_id)
+            VALUES ('todo.unlocked', old.todo_id, (SELECT list_id FROM todos WHERE id = old.todo_id));
+        END;
+        DROP TRIGGER IF EXISTS todo_dependencies_insert_event;
+        CREATE TRIGGER todo_dependencies_insert_event AFTER INSERT ON todo_dependencies
+        BEGIN
+            INSERT INTO events (type, todo_id, list_id)
+            SELECT 'todo.updated', id, list_id FROM todos
+            WHERE id IN (new.blocker_id, new.blocked_id) AND deleted_at IS NULL;
+        END;
+        DROP TRIGGER IF EXISTS todo_dependencies_delete_event;
+        CREATE TRIGGER todo_dependencies_delete_event AFTER DELETE ON todo_dependencies
+        BEGIN
+            INSERT INTO events (type, todo_id, list_id)
+            SELECT 'todo.updated', id, list_id FROM todos
+            WHERE id IN (old.blocker_id, old.blocked_id) AND deleted_at IS NULL;
+        END;
+        DROP TRIGGER IF EXISTS todos_unblocked_event;
+        CREATE TRIGGER todos_unblocked_event AFTER UPDATE OF completed ON todos
+        WHEN new.completed AND NOT old.completed AND new.deleted_at IS NULL
+        BEGIN
+            INSERT INTO events (type, todo_id, list_id)
+            SELECT 'todo.unblocked', t.id, t.list_id
+            FROM todo_dependencies d JOIN todos t ON t.id = d.blocked_id
+            WHERE d.blocker_id = new.id AND NOT t.completed AND t.deleted_at IS NULL
+              AND NOT EXISTS (
+                SELECT 1 FROM todo_dependencies o JOIN todos b ON b.id = o.blocker_id
+                WHERE o.blocked_id = t.id AND NOT b.completed AND b.deleted_at IS NULL
+              );
+        END;",
+    )?;
+    // Full-text index over titles and descriptions. It reads the text from `todos` (keyed by
+    // `seq`, which never changes) instead of keeping a copy, and is kept current by triggers.
+    let index_text = !conn.query_row(
+        "SELECT COUNT(*) > 0 FROM sqlite_master WHERE name = 'todos_fts'",
+        [],
+        |row| row.get::<_, bool>(0),
+    )?;
+    conn.execute_batch(
+        "CREATE VIRTUAL TABLE IF NOT EXISTS todos_fts USING fts5(
+            title, description,
+            content = 'todos', content_rowid = 'seq',
+            tokenize = 'unicode61 remove_diacritics 2'
+        );
+        DROP TRIGGER IF EXISTS todos_fts_insert;
+        CREATE TRIGGER todos_fts_insert AFTER INSERT ON todos
+        BEGIN
+            INSERT INTO todos_fts (rowid, title, description)
+            VALUES (new.seq, new.title, new.description);
+        END;
+        DROP TRIGGER IF EXISTS todos_fts_delete;
+        CREATE TRIGGER todos_fts_delete AFTER DELETE ON todos
+        BEGIN
+            INSERT INTO todos_fts (todos_fts, rowid, title, description)
+            VALUES ('delete', old.seq, old.title, old.description);
+        END;
+        DROP TRIGGER IF EXISTS todos_fts_update;
+        CREATE TRIGGER todos_fts_update AFTER UPDATE OF title, description, seq ON todos
+        BEGIN
+            INSERT INTO todos_fts (todos_fts, rowid, title, description)
+            VALUES ('delete', old.seq, old.title, old.description);
+            INSERT INTO todos_fts (rowid, title, description)
+            VALUES (new.seq, new.title, new.description);
+        END;",
+    )?;
+    if index_text {
+        conn.execute("INSERT INTO todos_fts (todos_fts) VALUES ('rebuild')", [])?;
+    }
+    if backfill_updated_at {
+        // Runs after the triggers are in place, so it is not recorded as an update
+        conn.execute(
+            "UPDATE todos SET updated_at = COALESCE(completed_at, created_at)",
+            [],
+        )?;
+    }
+
+    Ok(())
+}
+
+// Databases created before a column existed are upgraded in place on startup.
+// Returns true when the column had to be added.
+fn add_column_if_missing(
+    conn: &rusqlite::Connection,
+    table: &str,
+    column: &str,
+    definition: &str,
+) -> Result<bool> {
+    let exists: bool = conn.query_row(
+        "SELECT COUNT(*) > 0 FROM pragma_table_info(?1) WHERE name = ?2",
+        [table, column],
+        |row| row.get(0),
+    )?;
+    if !exists {
+        conn.execute(
+            &format!("ALTER TABLE {table} ADD COLUMN {column} {definition}"),
+            [],
+        )?;
+    }
+    Ok(!exists)
+}
+
+// This topic is explained in `.copilot/explanation/rust-error-types.md`
+pub fn create_todo(
+    pool: &DbPool,
+    create_todo: CreateTodo,
+) -> Result<Todo, Box<dyn std::error::Error + Send + Sync>> {
+    let mut conn = pool.get()?;
+    let tx = conn.transaction()?;
+    let todo = insert_todo(&tx, create_todo)?;
+    tx.commit()?;
+    Ok(todo)
+}
+
+/// Inserts all todos in a single transaction; either all of them are created or none.
+pub fn create_todos(
+    pool: &DbPool,
+    todos: Vec<CreateTodo>,
+) -> Result<Vec<Todo>, Box<dyn std::error::Error + Send + Sync>> {
+    let mut conn = pool.get()?;
+    let tx = conn.transaction()?;
+    let mut created = Vec::with_capacity(todos.len());
+    for todo in todos {
+        created.push(insert_todo(&tx, todo)?);
+    }
+    tx.commit()?;
+    Ok(created)
+}
+
+/// A todo brought in from another tool, in the state it has there.
+pub struct ImportedTodo {
+    /// Its `tags` are already normalized
+    pub create: CreateTodo,
+    pub completed: bool,
+}
+
+/// Creates the todos of one import in a single transaction; either all of them are created or
+/// none. Tags are created on first use, like with `attach_tag`.
+pub fn create_imported_todos(
+    pool: &DbPool,
+    todos: Vec<ImportedTodo>,
+) -> Result<Vec<Todo>, Box<dyn std::error::Error + Send + Sync>> {
+    let mut conn = pool.get()?;
+    let tx = conn.transaction()?;
+    let mut created = Vec::with_capacity(todos.len());
+    for todo in todos {
+        created.push(insert_todo_with_state(&tx, todo.create, todo.completed)?);
+    }
+    tx.commit()?;
+    Ok(created)
+}
+
+fn insert_todo(
+    conn: &rusqlite::Connection,
+    create_todo: CreateTodo,
+) -> Result<Todo, Box<dyn std::error::Error + Send + Sync>> {
+    insert_todo_with_state(conn, create_todo, false)
+}
+
+// A completed todo is inserted as such, so nothing that completing a todo sets off runs
+fn insert_todo_with_state(
+    conn: &rusqlite::Connection,
+    create_todo: CreateTodo,
+    completed: bool,
+) -> Result<Todo, Box<dyn std::error::Error + Send + Sync>> {
+    let id = crate::ids::new_todo_id();
+    let created_at = Utc::now();
+    let updated_at = created_at.trunc_subsecs(3);
+    let due_date = create_todo
+        .due_date
+        .as_deref()
+        .map(parse_timestamp)
+        .transpose()?;
+    let start_date = create_todo
+        .start_date
+        .as_deref()
+        .map(parse_timestamp)
+        .transpose()?;
+    let remind_at = create_todo
+        .remind_at
+        .as_deref()
+        .map(parse_timestamp)
+        .transpose()?;
+    let description = create_todo.description.clone().unwrap_or_default();
+    let metadata = create_todo.metadata.unwrap_or_default();
+    let metadata_json = serde_json::to_string(&metadata)?;
+    let custom_fields: Metadata = create_todo
+        .custom_fields
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|(_, value)| !value.is_null())
+        .collect();
+    let custom_fields_json = serde_json::to_string(&custom_fields)?;
+    let priority = create_todo.priority.unwrap_or_default();
+    let completed_at = completed.then(|| created_at.trunc_subsecs(0));
+    let tags = create_todo.tags.unwrap_or_default();
+
+    // New todos go to the top of the manual ordering
+    let position: f64 = conn.query_row(
+        "INSERT INTO todos (id, title, description, completed, created_at, metadata, due_date, priority, parent_id, icon, recurrence, list_id, start_date, estimate_minutes, custom_fields, remind_at, updated_at, completed_at, seq, position)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18,
+                 (SELECT COALESCE(MAX(seq), 0) + 1 FROM todos),
+                 (SELECT COALESCE(MIN(position), 0) - 1 FROM todos))
+         RETURNING position",
+        rusqlite::params![
+            id,
+            create_todo.title,
+            description,
+            completed,
+            created_at.to_rfc3339(),
+            metadata_json,
+            create_todo.due_date,
+            priority as i64,
+            create_todo.parent_id,
+            create_todo.icon,
+            create_todo.recurrence,
+            create_todo.list_id,
+            create_todo.start_date,
+            create_todo.estimate_minutes,
+            custom_fields_json,
+            create_todo.remind_at,
+            // Same format as the `todos_touch` trigger writes
+            updated_at.to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+            completed_at.map(format_timestamp),
+        ],
+        |row| row.get(0),
+    )?;
+
+    let todo = Todo {
+        id,
+        title: create_todo.title,
+        description: create_todo.description,
+        completed,
+        created_at,
+        metadata,
+        due_date,
+        start_date,
+        priority,
+        tags: Vec::new(),
+        parent_id: create_todo.parent_id,
+        subtask_count: 0,
+        completed_subtask_count: 0,
+        icon: create_todo.icon,
+        recurrence: create_todo.recurrence,
+        list_id: create_todo.list_id,
+        position,
+        completed_at,
+        archived_at: None,
+        deleted_at: None,
+        checklist: Vec::new(),
+        lock: None,
+        review: None,
+        blocked_by: Vec::new(),
+        blocks: Vec::new(),
+        series_id: None,
+        habit: None,
+        estimate_minutes: create_todo.estimate_minutes,
+        time_spent: 0,
+        timer_started_at: None,
+        pinned: false,
+        custom_fields,
+        remind_at,
+        reminded_at: None,
+        updated_at,
+        age_days: 0,
+    };
+    if tags.is_empty() {
+        return Ok(todo);
+    }
+    // Tags that already exist keep the case they were created with
+    tag_todos_in(conn, std::slice::from_ref(&todo.id), &tags, &[])?;
+    Ok(get_todo_in(conn, &todo.id)?.unwrap_or(todo))
+}
+
+pub fn get_todos(
+    pool: &DbPool,
+    filter: &TodoFilter,
+) -> Result<Vec<Todo>, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    query_todos(&conn, filter)
+}
+
+/// Runs `read` inside a single read transaction, so every query it makes sees the same
+/// snapshot of the database even while other connections are writing.
+pub fn with_snapshot<T>(
+    pool: &DbPool,
+    read: impl FnOnce(&rusqlite::Connection) -> Result<T, Box<dyn std::error::Error + Send + Sync>>,
+) -> Result<T, Box<dyn std::error::Error + Send + Sync>> {
+    let mut conn = pool.get()?;
+    let tx = conn.transaction_with_behavior(rusqlite::TransactionBehavior::Deferred)?;
+    let result = read(&tx)?;
+    tx.commit()?;
+    Ok(result)
+}
+
+/// One page of todos together with the total number of matches, read from the same snapshot.
+pub fn get_todos_page(
+    pool: &DbPool,
+    filter: &TodoFilter,
+) -> Result<(Vec<Todo>, usize), Box<dyn std::error::Error + Send + Sync>> {
+    with_snapshot(pool, |conn| {
+        Ok((query_todos(conn, filter)?, query_count(conn, filter)?))
+    })
+}
+
+fn query_todos(
+    conn: &rusqlite::Connection,
+    filter: &TodoFilter,
+) -> Result<Vec<Todo>, Box<dyn std::error::Error + Send + Sync>> {
+    let mut query = QueryBuilder::new();
+    filter.apply(&mut query);
+    let mut stmt = conn.prepare(&query.sql(&format!("SELECT {TODO_COLUMNS} FROM todos")))?;
+
+    // WANTED EXAMPLE:　stmt.query_mapの使い方
+    let todos = stmt.query_map(query.params(), row_to_todo)?;
+
+    let mut result = Vec::new();
+    for todo in todos {
+        result.push(todo?);
+    }
+    Ok(result)
+}
+
+/// Marks the start and end of each match in search snippets; control characters cannot
+/// appear in the text, so they are unambiguous.
+pub const SEARCH_MATCH_START: char = '\u{1}';
+pub const SEARCH_MATCH_END: char = '\u{2}';
+
+/// A full-text search match.
+#[derive(Debug)]
+pub struct SearchHit {
+    pub todo: Todo,
+    /// Excerpts of the title and description around the matches, which are wrapped in
+    /// `SEARCH_MATCH_START` and `SEARCH_MATCH_END`
+    pub title: String,
+    pub description: Option<String>,
+    /// BM25 score with title matches weighing more; lower is more relevant
+    pub rank: f64,
+}
+
+/// Todos outside the trash matching the FTS5 `query`, most relevant first.
+pub fn search_todos(
+    pool: &DbPool,
+    query: &str,
+    limit: u32,
+) -> Result<Vec<SearchHit>, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {TODO_COLUMNS}, m.title_snippet, m.description_snippet, m.score
+         FROM (SELECT rowid AS match_seq,
+                      snippet(todos_fts, 0, char(1), char(2), '…', 16) AS title_snippet,
+                      snippet(todos_fts, 1, char(1), char(2), '…', 16) AS description_snippet,
+                      bm25(todos_fts, 10.0, 1.0) AS score
+               FROM todos_fts WHERE todos_fts MATCH ?1) m
+         JOIN todos ON todos.seq = m.match_seq
+         WHERE todos.deleted_at IS NULL
+         ORDER BY m.score, todos.seq DESC
+         LIMIT ?2"
+    ))?;
+    let hits = stmt.query_map(rusqlite::params![query, limit], |row| {
+        let extra = row.as_ref().column_count() - 3;
+        Ok(SearchHit {
+            todo: row_to_todo(row)?,
+            title: row.get(extra)?,
+            description: row
+                .get::<_, Option<String>>(extra + 1)?
+                .filter(|snippet| !snippet.is_empty()),
+            rank: row.get(extra + 2)?,
+        })
+    })?;
+
+    let mut result = Vec::new();
+    for hit in hits {
+        result.push(hit?);
+    }
+    Ok(result)
+}
+
+/// `(id, title)` of the todos outside the trash, either all of them or only those in `ids`.
+pub fn get_todo_titles(
+    pool: &DbPool,
+    ids: Option<&[String]>,
+) -> Result<Vec<(String, String)>, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let mut sql = String::from("SELECT id, title FROM todos WHERE deleted_at IS NULL");
+    if let Some(ids) = ids {
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        let placeholders = vec!["?"; ids.len()].join(", ");
+        sql.push_str(&format!(" AND id IN ({placeholders})"));
+    }
+    let mut stmt = conn.prepare(&sql)?;
+    let rows = stmt.query_map(rusqlite::params_from_iter(ids.unwrap_or_default()), |row| {
+        Ok((row.get(0)?, row.get(1)?))
+    })?;
+
+    let mut result = Vec::new();
+    for row in rows {
+        result.push(row?);
+    }
+    Ok(result)
+}
+
+/// Like `get_todos`, but hands each row to `visit` as it is read instead of collecting them.
+/// Iteration stops early when `visit` returns false.
+pub fn for_each_todo(
+    pool: &DbPool,
+    filter: &TodoFilter,
+    mut visit: impl FnMut(Todo) -> bool,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+
+    let mut query = QueryBuilder::new();
+    filter.apply(&mut query);
+    let mut stmt = conn.prepare(&query.sql(&format!("SELECT {TODO_COLUMNS} FROM todos")))?;
+
+    for todo in stmt.query_map(query.params(), row_to_todo)? {
+        if !visit(todo?) {
+            break;
+        }
+    }
+    Ok(())
+}
+
+// Candidates are the open todos sharing the start of a word with `title`: near-identical
+// titles share most of their trigrams, so they cannot differ in the start of every word
+const DUPLICATE_CANDIDATES: u32 = 200;
+const DUPLICATE_PREFIX_LEN: usize = 3;
+
+// An open todo whose title is near-identical to `title`, if there is one
+fn find_duplicate_in(
+    conn: &rusqlite::Connection,
+    title: &str,
+) -> Result<Option<Todo>, Box<dyn std::error::Error + Send + Sync>> {
+    let normalized = crate::dedupe::normalize_title(title);
+    let mut prefixes: Vec<String> = normalized
+        .split_whitespace()
+        .map(|word| {
+            let prefix: String = word.chars().take(DUPLICATE_PREFIX_LEN).collect();
+            format!("\"{prefix}\"*")
+        })
+        .collect();
+    prefixes.sort();
+    prefixes.dedup();
+    if prefixes.is_empty() {
+        return Ok(None);
+    }
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {TODO_COLUMNS}
+         FROM (SELECT rowid AS match_seq, bm25(todos_fts) AS score
+               FROM todos_fts WHERE todos_fts MATCH ?1
+               ORDER BY score LIMIT ?2) m
+         JOIN todos ON todos.seq = m.match_seq
+         WHERE NOT todos.completed AND todos.deleted_at IS NULL
+         ORDER BY m.score"
+    ))?;
+    let query = format!("title : ({})", prefixes.join(" OR "));
+    let candidates = stmt.query_map(rusqlite::params![query, DUPLICATE_CANDIDATES], row_to_todo)?;
+    for candidate in candidates {
+        let candidate = candidate?;
+        if crate::dedupe::is_duplicate_title(&candidate.title, title) {
+            return Ok(Some(candidate));
+        }
+    }
+    Ok(None)
+}
+
+/// What `create_todo_unless_duplicate` did
+pub enum Deduplicated {
+    Created(Todo),
+    /// An open todo with a near-identical title, so nothing was created
+    Duplicate(Todo),
+}
+
+/// Creates a todo unless an open todo with a near-identical title exists. Runs under the write
+/// lock, so two identical todos created at once cannot both get through.
+pub fn create_todo_unless_duplicate(
+    pool: &DbPool,
+    create_todo: CreateTodo,
+) -> Result<Deduplicated, Box<dyn std::error::Error + Send + Sync>> {
+    let mut conn = pool.get()?;
+    let tx = conn.transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)?;
+    if let Some(existing) = find_duplicate_in(&tx, &create_todo.title)? {
+        return Ok(Deduplicated::Duplicate(existing));
+    }
+    let todo = insert_todo(&tx, create_todo)?;
+    tx.commit()?;
+    Ok(Deduplicated::Created(todo))
+}
+
+pub fn get_todo(
+    pool: &DbPool,
+    id: &str,
+) -> Result<Option<Todo>, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    get_todo_in(&conn, id)
+}
+
+fn get_todo_in(
+    conn: &rusqlite::Connection,
+    id: &str,
+) -> Result<Option<Todo>, Box<dyn std::error::Error + Send + Sync>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {TODO_COLUMNS} FROM todos WHERE id = ?1 AND deleted_at IS NULL"
+    ))?;
+
+    let mut todos = stmt.query_map([id], row_to_todo)?;
+
+    match todos.next() {
+        Some(todo) => Ok(Some(todo?)),
+        None => Ok(None),
+    }
+}
+
+/// A todo that is in the trash; `get_todo` only finds todos outside it.
+pub fn get_trashed_todo(
+    pool: &DbPool,
+    id: &str,
+) -> Result<Option<Todo>, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {TODO_COLUMNS} FROM todos WHERE id = ?1 AND deleted_at IS NOT NULL"
+    ))?;
+    let mut todos = stmt.query_map([id], row_to_todo)?;
+    match todos.next() {
+        Some(todo) => Ok(Some(todo?)),
+        None => Ok(None),
+    }
+}
+
+// Shortest id prefix accepted as a reference, keeps accidental matches unlikely
+pub const MIN_ID_PREFIX_LEN: usize = 8;
+
+/// Todos whose id starts with `prefix`; callers decide what to do with ambiguous prefixes.
+pub fn find_todos_by_id_prefix(
+    pool: &DbPool,
+    prefix: &str,
+) -> Result<Vec<Todo>, Box<dyn std::error::Error + Send + Sync>> {
+    // UUIDs and ULIDs only contain these, which also keeps LIKE wildcards out
+    let is_id_like = prefix
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-');
+    if prefix.len() < MIN_ID_PREFIX_LEN || !is_id_like {
+        return Ok(Vec::new());
+    }
+    let conn = pool.get()?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {TODO_COLUMNS} FROM todos WHERE id LIKE ?1 || '%' AND deleted_at IS NULL"
+    ))?;
+    // LIKE is case-insensitive for ASCII, so `t-01j...` still finds ULIDs
+    let todos = stmt.query_map([prefix], row_to_todo)?;
+
+    let mut result = Vec::new();
+    for todo in todos {
+        result.push(todo?);
+    }
+    Ok(result)
+}
+
+pub fn update_todo(
+    pool: &DbPool,
+    id: &str,
+    update: UpdateTodo,
+) -> Result<Option<Todo>, Box<dyn std::error::Error + Send + Sync>> {
+    let mut conn = pool.get()?;
+    let tx = conn.transaction()?;
+    let updated = update_todo_in(&tx, id, update)?;
+    tx.commit()?;
+    Ok(updated.map(|(_, after)| after))
+}
+
+/// Updates a todo only if `precondition` holds for it as it is. The todo is read under the
+/// write lock, so no other update can come between the check and this one. Returns the todo
+/// before and after.
+pub fn update_todo_if(
+    pool: &DbPool,
+    id: &str,
+    update: UpdateTodo,
+    precondition: impl FnOnce(&Todo) -> bool,
+) -> Result<Conditional<TodoChange>, Box<dyn std::error::Error + Send + Sync>> {
+    change_todo_if(pool, id, precondition, |tx, _| {
+        Ok(update_todo_in(tx, id, update)?.ok_or("the todo went away")?)
+    })
+}
+
+/// What a change guarded by a precondition did
+pub enum Conditional<T> {
+    Changed(T),
+    /// The precondition did not hold, so nothing changed; the todo as it is
+    Failed(Box<Todo>),
+    NotFound,
+}
+
+// Runs `change` on a todo only if `precondition` holds for it as it is. `change` gets the
+// todo as it was read under the write lock.
+fn change_todo_if<T>(
+    pool: &DbPool,
+    id: &str,
+    precondition: impl FnOnce(&Todo) -> bool,
+    change: impl FnOnce(
+        &rusqlite::Transaction,
+        Todo,
+    ) -> Result<T, Box<dyn std::error::Error + Send + Sync>>,
+) -> Result<Conditional<T>, Box<dyn std::error::Error + Send + Sync>> {
+    let mut conn = pool.get()?;
+    let tx = conn.transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)?;
+    let Some(current) = get_todo_in(&tx, id)? else {
+        return Ok(Conditional::NotFound);
+    };
+    if !precondition(&current) {
+        return Ok(Conditional::Failed(Box::new(current)));
+    }
+    let changed = change(&tx, current)?;
+    tx.commit()?;
+    Ok(Conditional::Changed(changed))
+}
+
+/// What `create_or_update_todo` did
+pub enum Upserted {
+    Created(Todo),
+    Updated(Todo),
+}
+
+/// Updates the first todo matching `existing` when `update` returns a change for it, and
+/// creates `create` otherwise. Runs under the write lock, so two calls for the same todo
+/// cannot both create it or lose each other's update.
+pub fn create_or_update_todo(
+    pool: &DbPool,
+    existing: &TodoFilter,
+    create: CreateTodo,
+    update: impl FnOnce(Todo) -> Option<UpdateTodo>,
+) -> Result<Upserted, Box<dyn std::error::Error + Send + Sync>> {
+    let mut conn = pool.get()?;
+    let tx = conn.transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)?;
+    let current = query_todos(&tx, existing)?.into_iter().next();
+    let update = current.and_then(|todo| Some(todo.id.clone()).zip(update(todo)));
+    let upserted = match update {
+        Some((id, update)) => {
+            // Read in this transaction, so it is still there
+            let (_, after) = update_todo_in(&tx, &id, update)?.ok_or("Todo not found")?;
+            Upserted::Updated(after)
+        }
+        None => Upserted::Created(insert_todo(&tx, create)?),
+    };
+    tx.commit()?;
+    Ok(upserted)
+}
+
+/// A todo as it was before an update and as it is after it
+pub type TodoChange = (Todo, Todo);
+
+/// Applies the same update to every todo in `ids`, in one transaction. Returns each todo
+/// as it was before and after, or `None` without changing anything if one does not exist.
+pub fn update_todos(
+    pool: &DbPool,
+    ids: &[String],
+    update: &UpdateTodo,
+) -> Result<Option<Vec<TodoChange>>, Box<dyn std::error::Error + Send + Sync>> {
+    let mut conn = pool.get()?;
+    let tx = conn.transaction()?;
+    let mut updated = Vec::with_capacity(ids.len());
+    for id in ids {
+        match update_todo_in(&tx, id, update.clone())? {
+            Some(todo) => updated.push(todo),
+            // Dropping the transaction rolls the earlier updates back
+            None => return Ok(None),
+        }
+    }
+    tx.commit()?;
+    Ok(Some(updated))
+}
+
+fn update_todo_in(
+    tx: &rusqlite::Transaction,
+    id: &str,
+    update: UpdateTodo,
+) -> Result<Option<TodoChange>, Box<dyn std::error::Error + Send + Sync>> {
+    // Check if todo exists first
+    let Some(before) = get_todo_in(tx, id)? else {
+        return Ok(None);
+    };
+
+    let completing = update.completed == Some(true);
+
+    // Build dynamic update query
+    let mut updates = Vec::new();
+    let mut params: Vec<String> = Vec::new();
+
+    if let Some(title) = update.title {
+        updates.push("title = ?");
+        params.push(title);
+    }
+    if let Some(description) = update.description {
+        updates.push("description = NULLIF(?, '')");
+        params.push(description.unwrap_or_default());
+    }
+    if let Some(completed) = update.completed {
+        // Assignments see the old row, so completing an already completed todo keeps its time
+        if completed {
+            updates.push("completed_at = CASE WHEN completed THEN completed_at ELSE ? END");
+            params.push(now_timestamp());
+            updates.push(START_REVIEW);
+        } else {
+            // Reopening also brings an archived todo back and drops its review
+            updates.push("completed_at = NULL, archived_at = NULL");
+            updates.push(CLEAR_REVIEW);
+        }
+        updates.push("completed = ?");
+        params.push(if completed {
+            "1".to_string()
+        } else {
+            "0".to_string()
+        });
+    }
+    if let Some(metadata) = update.metadata {
+        updates.push("metadata = ?");
+        params.push(serde_json::to_string(&metadata)?);
+    }
+    if let Some(custom_fields) = update.custom_fields {
+        // A JSON merge patch: `null` removes the value
+        updates.push("custom_fields = json_patch(custom_fields, ?)");
+        params.push(serde_json::to_string(&custom_fields)?);
+    }
+    if let Some(due_date) = update.due_date {
+        updates.push("due_date = NULLIF(?, '')");
+        params.push(due_date);
+    }
+    if let Some(start_date) = update.start_date {
+        updates.push("start_date = NULLIF(?, '')");
+        params.push(start_date);
+    }
+    if let Some(remind_at) = update.remind_at {
+        // A new reminder time is sent again, even if an earlier one already was
+        updates.push("remind_at = NULLIF(?, ''), reminded_at = NULL");
+        params.push(remind_at);
+    }
+    if let Some(estimate) = update.estimate_minutes {
+        updates.push("estimate_minutes = NULLIF(CAST(? AS INTEGER), 0)");
+        params.push(estimate.to_string());
+    }
+    if let Some(priority) = update.priority {
+        updates.push("priority = ?");
+        params.push((priority as i64).to_string());
+    }
+    if let Some(icon) = update.icon {
+        updates.push("icon = NULLIF(?, '')");
+        params.push(icon);
+    }
+    if let Some(recurrence) = update.recurrence {
+        updates.push("recurrence = NULLIF(?, '')");
+        params.push(recurrence);
+    }
+    if let Some(list_id) = update.list_id {
+        updates.push("list_id = NULLIF(?, '')");
+        params.push(list_id);
+    }
+
+    if updates.is_empty() {
+        return Ok(Some((before.clone(), before)));
+    }
+
+    params.push(id.to_string());
+    let query = format!("UPDATE todos SET {} WHERE id = ?", updates.join(", "));
+
+    let param_refs: Vec<&dyn rusqlite::ToSql> =
+        params.iter().map(|p| p as &dyn rusqlite::ToSql).collect();
+    tx.execute(&query, param_refs.as_slice())?;
+    finish_update(tx, id, before, completing)
+}
+
+// Stops the timer of a todo that was completed and records the revision of an update
+fn finish_update(
+    tx: &rusqlite::Transaction,
+    id: &str,
+    before: Todo,
+    completing: bool,
+) -> Result<Option<TodoChange>, Box<dyn std::error::Error + Send + Sync>> {
+    // The event of the update itself; stopping the timer records another one
+    let event_seq: Option<i64> = tx.query_row(
+        "SELECT MAX(seq) FROM events WHERE todo_id = ?1",
+        [id],
+        |row| row.get(0),
+    )?;
+    // Time stops counting when the work is done
+    if completing {
+        tx.execute(
+            "UPDATE time_entries SET stopped_at = ?1 WHERE todo_id = ?2 AND stopped_at IS NULL",
+            [&now_timestamp(), id],
+        )?;
+    }
+
+    let Some(after) = get_todo_in(tx, id)? else {
+        return Ok(None);
+    };
+    let changes = revision_changes(&before, &after)?;
+    if !changes.is_empty() {
+        let changes = serde_json::to_string(&changes)?;
+        tx.execute(
+            "INSERT INTO todo_revisions (todo_id, changed_at, changes) VALUES (?1, ?2, ?3)",
+            [id, &now_timestamp(), &changes],
+        )?;
+        tx.execute(
+            "UPDATE events SET changes = ?1 WHERE seq = ?2",
+            rusqlite::params![changes, event_seq],
+        )?;
+    }
+    Ok(Some((before, after)))
+}
+
+/// Completes an open todo or reopens a completed one in a single statement, so two clients
+/// toggling at once cannot both read the same state. Returns the todo before and after.
+pub fn toggle_completed(
+    pool: &DbPool,
+    id: &str,
+    precondition: impl FnOnce(&Todo) -> bool,
+) -> Result<Conditional<TodoChange>, Box<dyn std::error::Error + Send + Sync>> {
+    // The state read under the write lock is the one that gets flipped
+    change_todo_if(pool, id, precondition, |tx, before| {
+        // Every assignment sees the old row: completing starts a review where the list asks
+        // for one, reopening brings an archived todo back and drops its review
+        tx.execute(
+            "UPDATE todos SET
+                 completed_at = CASE WHEN completed THEN NULL ELSE ?1 END,
+                 archived_at = CASE WHEN completed THEN NULL ELSE archived_at END,
+                 review_status = CASE WHEN completed THEN NULL
+                     WHEN (SELECT require_review FROM lists WHERE lists.id = todos.list_id)
+                     THEN 'pending'
+                 END,
+                 reviewer = NULL, reviewed_at = NULL, review_comment = NULL,
+                 completed = NOT completed
+             WHERE id = ?2",
+            [&now_timestamp(), id],
+        )?;
+        let completing = !before.completed;
+        let toggled = finish_update(tx, id, before, completing)?;
+        Ok(toggled.ok_or("the todo went away")?)
+    })
+}
+
+/// Fields of a todo whose changes are kept in its history.
+pub const REVISION_FIELDS: [&str; 13] = [
+    "title",
+    "description",
+    "completed",
+    "priority",
+    "due_date",
+    "start_date",
+    "remind_at",
+    "estimate_minutes",
+    "icon",
+    "recurrence",
+    "list_id",
+    "metadata",
+    "custom_fields",
+];
+
+/// `{field: {"from": old, "to": new}}` for every field in `REVISION_FIELDS` that differs.
+pub fn revision_changes(
+    before: &Todo,
+    after: &Todo,
+) -> Result<Map<String, Value>, Box<dyn std::error::Error + Send + Sync>> {
+    let (Value::Object(before), Value::Object(after)) =
+        (serde_json::to_value(before)?, serde_json::to_value(after)?)
+    else {
+        return Ok(Map::new());
+    };
+    let mut changes = Map::new();
+    for field in REVISION_FIELDS {
+        let from = before.get(field).cloned().unwrap_or(Value::Null);
+        let to = after.get(field).cloned().unwrap_or(Value::Null);
+        if from != to {
+            changes.insert(
+                field.to_string(),
+                serde_json::json!({"from": from, "to": to}),
+            );
+        }
+    }
+    Ok(changes)
+}
+
+/// A change made to a todo with `update_todo`.
+#[derive(Debug, Serialize, Clone)]
+pub struct TodoRevision {
+    pub id: i64,
+    pub changed_at: String,
+    /// The old and new value of every field that changed, by field
+    pub changes: Map<String, Value>,
+}
+
+fn row_to_revision(row: &rusqlite::Row) -> Result<TodoRevision> {
+    let changes: String = row.get(2)?;
+    Ok(TodoRevision {
+        id: row.get(0)?,
+        changed_at: row.get(1)?,
+        changes: serde_json::from_str(&changes).unwrap_or_default(),
+    })
+}
+
+/// One revision of a todo, or `None` if the todo has no revision with that id.
+pub fn get_todo_revision(
+    pool: &DbPool,
+    todo_id: &str,
+    revision: i64,
+) -> Result<Option<TodoRevision>, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let revision = conn
+        .query_row(
+            "SELECT id, changed_at, changes FROM todo_revisions WHERE id = ?1 AND todo_id = ?2",
+            rusqlite::params![revision, todo_id],
+            row_to_revision,
+        )
+        .optional()?;
+    Ok(revision)
+}
+
+/// The revisions of a todo made after `revision`, newest first, or `None` if the todo has no
+/// revision with that id.
+pub fn get_todo_revisions_after(
+    pool: &DbPool,
+    todo_id: &str,
+    revision: i64,
+) -> Result<Option<Vec<TodoRevision>>, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let exists: bool = conn.query_row(
+        "SELECT EXISTS (SELECT 1 FROM todo_revisions WHERE id = ?1 AND todo_id = ?2)",
+        rusqlite::params![revision, todo_id],
+        |row| row.get(0),
+    )?;
+    if !exists {
+        return Ok(None);
+    }
+    let mut stmt = conn.prepare(
+        "SELECT id, changed_at, changes FROM todo_revisions
+         WHERE todo_id = ?1 AND id > ?2 ORDER BY id DESC",
+    )?;
+    let revisions = stmt.query_map(rusqlite::params![todo_id, revision], row_to_revision)?;
+
+    let mut result = Vec::new();
+    for revision in revisions {
+        result.push(revision?);
+    }
+    Ok(Some(result))
+}
+
+/// A todo's revisions, newest first.
+pub fn get_todo_revisions(
+    pool: &DbPool,
+    todo_id: &str,
+    limit: u32,
+) -> Result<Vec<TodoRevision>, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let mut stmt = conn.prepare(
+        "SELECT id, changed_at, changes FROM todo_revisions
+         WHERE todo_id = ?1 ORDER BY id DESC LIMIT ?2",
+    )?;
+    let revisions = stmt.query_map(rusqlite::params![todo_id, limit], row_to_revision)?;
+
+    let mut result = Vec::new();
+    for revision in revisions {
+        result.push(revision?);
+    }
+    Ok(result)
+}
+
+// Assignments for completing a todo: it waits for review if its list requires one.
+// They see the old row, so an already completed todo keeps its review.
+const START_REVIEW: &str = "review_status = CASE WHEN completed THEN review_status
+        WHEN (SELECT require_review FROM lists WHERE lists.id = todos.list_id) THEN 'pending'
+    END,
+    reviewer = CASE WHEN completed THEN reviewer END,
+    reviewed_at = CASE WHEN completed THEN reviewed_at END,
+    review_comment = CASE WHEN completed THEN review_comment END";
+const CLEAR_REVIEW: &str =
+    "review_status = NULL, reviewer = NULL, reviewed_at = NULL, review_comment = NULL";
+
+/// Approves or rejects a todo waiting for review; a rejected todo is reopened.
+/// Returns `None` unless the todo is pending review.
+pub fn review_todo(
+    pool: &DbPool,
+    id: &str,
+    status: ReviewStatus,
+    reviewer: Option<&str>,
+    comment: Option<&str>,
+) -> Result<Option<Todo>, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let reopen = if status == ReviewStatus::Rejected {
+        ", completed = 0, completed_at = NULL"
+    } else {
+        ""
+    };
+    let rows_affected = conn.execute(
+        &format!(
+            "UPDATE todos SET review_status = ?1, reviewer = ?2, reviewed_at = ?3,
+                review_comment = ?4{reopen}
+             WHERE id = ?5 AND review_status = 'pending' AND deleted_at IS NULL"
+        ),
+        rusqlite::params![status.as_str(), reviewer, now_timestamp(), comment, id],
+    )?;
+    if rows_affected == 0 {
+        return Ok(None);
+    }
+    get_todo(pool, id)
+}
+
+/// Marks every subtask below `id`, at any depth, as completed. Returns how many changed.
+pub fn complete_descendants(
+    pool: &DbPool,
+    id: &str,
+) -> Result<usize, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let rows_affected = conn.execute(
+        &format!(
+            "WITH RECURSIVE descendants(id) AS (
+            SELECT id FROM todos WHERE parent_id = ?1
+            UNION
+            SELECT t.id FROM todos t JOIN descendants d ON t.parent_id = d.id
+        )
+        UPDATE todos SET completed = 1, completed_at = ?2, {START_REVIEW}
+        WHERE completed = 0 AND deleted_at IS NULL AND id IN descendants"
+        ),
+        [id, &now_timestamp()],
+    )?;
+    Ok(rows_affected)
+}
+
+/// Where `POST /api/todos/:id/move` puts a todo in the manual ordering.
+#[derive(Debug)]
+pub enum MoveTarget {
+    After(String),
+    Before(String),
+    /// Zero-based index among all todos, clamped to the end
+    Index(u32),
+}
+
+// Renumbering is only needed once repeated halving has used up the float's precision
+fn renumber_positions(conn: &rusqlite::Connection) -> Result<()> {
+    conn.execute(
+        "UPDATE todos SET position = (
+            SELECT n FROM (
+                SELECT id, ROW_NUMBER() OVER (ORDER BY position, seq DESC) AS n FROM todos
+            ) ranked WHERE ranked.id = todos.id
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+// Positions of the todos the moved one will sit between, in that order
+fn move_neighbours(
+    conn: &rusqlite::Connection,
+    id: &str,
+    target: &MoveTarget,
+) -> Result<Option<(Option<f64>, Option<f64>)>> {
+    let position_of = |other: &str| -> Result<Option<f64>> {
+        conn.query_row(
+            "SELECT position FROM todos WHERE id = ?1 AND deleted_at IS NULL",
+            [other],
+            |row| row.get(0),
+        )
+        .optional()
+    };
+    let nth = |index: i64| -> Result<Option<f64>> {
+        if index < 0 {
+            return Ok(None);
+        }
+        conn.query_row(
+            "SELECT position FROM todos WHERE id != ?1 AND deleted_at IS NULL
+             ORDER BY position, seq DESC
+             LIMIT 1 OFFSET ?2",
+            rusqlite::params![id, index],
+            |row| row.get(0),
+        )
+        .optional()
+    };
+    Ok(match target {
+        MoveTarget::After(other) => match position_of(other)? {
+            Some(previous) => {
+                let next = conn.query_row(
+                    "SELECT MIN(position) FROM todos
+                     WHERE position > ?1 AND id != ?2 AND deleted_at IS NULL",
+                    rusqlite::params![previous, id],
+                    |row| row.get(0),
+                )?;
+                Some((Some(previous), next))
+            }
+            None => None,
+        },
+        MoveTarget::Before(other) => match position_of(other)? {
+            Some(next) => {
+                let previous = conn.query_row(
+                    "SELECT MAX(position) FROM todos
+                     WHERE position < ?1 AND id != ?2 AND deleted_at IS NULL",
+                    rusqlite::params![next, id],
+                    |row| row.get(0),
+                )?;
+                Some((previous, Some(next)))
+            }
+            None => None,
+        },
+        MoveTarget::Index(index) => {
+            let index = *index as i64;
+            Some((nth(index - 1)?, nth(index)?))
+        }
+    })
+}
+
+fn position_between(previous: Option<f64>, next: Option<f64>) -> Option<f64> {
+    let position = match (previous, next) {
+        (None, None) => 0.0,
+        (Some(previous), None) => previous + 1.0,
+        (None, Some(next)) => next - 1.0,
+        (Some(previous), Some(next)) => previous + (next - previous) / 2.0,
+    };
+    let fits = previous.is_none_or(|previous| position > previous)
+        && next.is_none_or(|next| position < next);
+    fits.then_some(position)
+}
+
+/// Moves a todo in the manual ordering. Returns `None` if the todo it should be placed next
+/// to does not exist.
+pub fn move_todo(
+    pool: &DbPool,
+    id: &str,
+    target: &MoveTarget,
+    precondition: impl FnOnce(&Todo) -> bool,
+) -> Result<Conditional<Option<Todo>>, Box<dyn std::error::Error + Send + Sync>> {
+    change_todo_if(pool, id, precondition, |tx, _| {
+        let Some((previous, next)) = move_neighbours(tx, id, target)? else {
+            return Ok(None);
+        };
+        let position = match position_between(previous, next) {
+            Some(position) => position,
+            None => {
+                renumber_positions(tx)?;
+                let (previous, next) = move_neighbours(tx, id, target)?.unwrap_or_default();
+                position_between(previous, next).ok_or("cannot find a free position")?
+            }
+        };
+        tx.execute(
+            "UPDATE todos SET position = ?1 WHERE id = ?2",
+            rusqlite::params![position, id],
+        )?;
+        get_todo_in(tx, id)
+    })
+}
+
+/// Pins an unpinned todo and unpins a pinned one.
+pub fn toggle_pin(
+    pool: &DbPool,
+    id: &str,
+    precondition: impl FnOnce(&Todo) -> bool,
+) -> Result<Conditional<Todo>, Box<dyn std::error::Error + Send + Sync>> {
+    change_todo_if(pool, id, precondition, |tx, todo| {
+        tx.execute("UPDATE todos SET pinned = NOT pinned WHERE id = ?1", [id])?;
+        Ok(get_todo_in(tx, id)?.unwrap_or(todo))
+    })
+}
+
+/// Open todos whose reminder time has come and whose reminder has not been sent yet.
+pub fn get_due_reminders(
+    pool: &DbPool,
+) -> Result<Vec<Todo>, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {TODO_COLUMNS} FROM todos
+         WHERE remind_at <= ?1 AND reminded_at IS NULL AND NOT completed AND deleted_at IS NULL
+         ORDER BY remind_at"
+    ))?;
+    let todos = stmt.query_map([now_timestamp()], row_to_todo)?;
+
+    let mut result = Vec::new();
+    for todo in todos {
+        result.push(todo?);
+    }
+    Ok(result)
+}
+
+/// Records that the reminder at `remind_at` was sent, unless it has been moved meanwhile.
+pub fn mark_reminded(
+    pool: &DbPool,
+    id: &str,
+    remind_at: &str,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    conn.execute(
+        "UPDATE todos SET reminded_at = ?1 WHERE id = ?2 AND remind_at = ?3",
+        [&now_timestamp(), id, remind_at],
+    )?;
+    Ok(())
+}
+
+/// Completed todos whose next occurrence has not been created yet.
+pub fn get_completed_recurring_todos(
+    pool: &DbPool,
+) -> Result<Vec<Todo>, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {TODO_COLUMNS} FROM todos
+         WHERE completed AND recurrence IS NOT NULL AND deleted_at IS NULL ORDER BY seq"
+    ))?;
+    let todos = stmt.query_map([], row_to_todo)?;
+
+    let mut result = Vec::new();
+    for todo in todos {
+        result.push(todo?);
+    }
+    Ok(result)
+}
+
+/// Moves the recurrence of a completed todo onto a copy of it due at `next`'s timestamp
+/// and carrying `next`'s rule; with `None` the rule has run out and is only cleared.
+/// The completion is recorded in the series' history along with the `missed` occurrences
+/// that passed before the next one.
+/// Returns false when the todo was already handled, e.g. reopened or materialized.
+pub fn materialize_occurrence(
+    pool: &DbPool,
+    todo: &Todo,
+    next: Option<(String, String)>,
+    missed: u32,
+) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+    let mut conn = pool.get()?;
+    let tx = conn.transaction()?;
+    let claimed = tx.execute(
+        "UPDATE todos SET recurrence = NULL
+         WHERE id = ?1 AND completed AND recurrence IS NOT NULL",
+        [&todo.id],
+    )?;
+    if claimed == 0 {
+        return Ok(false);
+    }
+    let series_id = todo.series_id.as_deref().unwrap_or(&todo.id);
+    tx.execute(
+        "INSERT INTO recurrence_completions
+            (series_id, todo_id, due_date, completed_at, missed_after)
+         VALUES (?1, ?2, ?3, ?4, ?5)",
+        rusqlite::params![
+            series_id,
+            todo.id,
+            todo.due_date.map(format_timestamp),
+            todo.completed_at.map(format_timestamp),
+            missed
+        ],
+    )?;
+    if let Some((due_date, recurrence)) = next {
+        // The occurrence is deferred and reminded about as long before its due date as
+        // this one was
+        let start_date = same_lead(todo.start_date, todo, &due_date);
+        let remind_at = same_lead(todo.remind_at, todo, &due_date);
+        let occurrence = insert_todo(
+            &tx,
+            CreateTodo {
+                title: todo.title.clone(),
+                description: todo.description.clone(),
+                metadata: Some(todo.metadata.clone()),
+                due_date: Some(due_date),
+                start_date,
+                remind_at,
+                estimate_minutes: todo.estimate_minutes,
+                custom_fields: Some(todo.custom_fields.clone()),
+                priority: Some(todo.priority),
+                parent_id: todo.parent_id.clone(),
+                icon: todo.icon.clone(),
+                recurrence: Some(recurrence),
+                list_id: todo.list_id.clone(),
+                tags: None,
+            },
+        )?;
+        tx.execute(
+            "UPDATE todos SET series_id = ?1 WHERE id = ?2",
+            [series_id, &occurrence.id],
+        )?;
+        tx.execute(
+            "INSERT INTO todo_tags (todo_id, tag_id)
+             SELECT ?1, tag_id FROM todo_tags WHERE todo_id = ?2",
+            [&occurrence.id, &todo.id],
+        )?;
+        // The next occurrence starts with its checklist unchecked
+        for item in &todo.checklist {
+            tx.execute(
+                "INSERT INTO checklist_items (id, todo_id, text, checked, position, created_at)
+                 VALUES (?1, ?2, ?3, 0, ?4, ?5)",
+                rusqlite::params![
+                    uuid::Uuid::new_v4().to_string(),
+                    occurrence.id,
+                    item.text,
+                    item.position,
+                    chrono::Utc::now().to_rfc3339(),
+                ],
+            )?;
+        }
+    }
+    tx.commit()?;
+    Ok(true)
+}
+
+// `time` moved to be as long before `next_due` as it was before the todo's due date
+fn same_lead(time: Option<DateTime<Utc>>, todo: &Todo, next_due: &str) -> Option<String> {
+    let lead = todo.due_date? - time?;
+    let next_due = parse_timestamp(next_due).ok()?;
+    Some(format_timestamp(next_due - lead))
+}
+
+/// A completed occurrence of a recurring todo.
+#[derive(Debug, Serialize, Clone)]
+pub struct RecurrenceCompletion {
+    pub todo_id: String,
+    pub due_date: Option<String>,
+    pub completed_at: Option<String>,
+    /// Occurrences that passed without being done before the next one was scheduled
+    pub missed_after: u32,
+}
+
+/// The occurrence of a series that is still to be done, if any.
+pub fn get_open_occurrence(
+    pool: &DbPool,
+    series_id: &str,
+) -> Result<Option<Todo>, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let todo = conn
+        .query_row(
+            &format!(
+                "SELECT {TODO_COLUMNS} FROM todos
+                 WHERE (id = ?1 OR series_id = ?1) AND recurrence IS NOT NULL
+                   AND NOT completed AND deleted_at IS NULL
+                 ORDER BY seq DESC LIMIT 1"
+            ),
+            [series_id],
+            row_to_todo,
+        )
+        .optional()?;
+    Ok(todo)
+}
+
+/// The completion history of a series, oldest first.
+pub fn get_recurrence_completions(
+    pool: &DbPool,
+    series_id: &str,
+) -> Result<Vec<RecurrenceCompletion>, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let mut stmt = conn.prepare(
+        "SELECT todo_id, due_date, completed_at, missed_after FROM recurrence_completions
+         WHERE series_id = ?1 ORDER BY id",
+    )?;
+    let completions = stmt.query_map([series_id], |row| {
+        Ok(RecurrenceCompletion {
+            todo_id: row.get(0)?,
+            due_date: row.get(1)?,
+            completed_at: row.get(2)?,
+            missed_after: row.get(3)?,
+        })
+    })?;
+
+    let mut result = Vec::new();
+    for completion in completions {
+        result.push(completion?);
+    }
+    Ok(result)
+}
+
+// Millisecond precision, so todos trashed together can be told apart from the next deletion
+fn deletion_timestamp() -> String {
+    chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true)
+}
+
+// Subtasks go to the trash with their parent and share its `deleted_at`, which is how
+// `restore_todo` finds them again
+fn trash_descendants(conn: &rusqlite::Connection, deleted_at: &str) -> Result<usize> {
+    conn.execute(
+        "WITH RECURSIVE descendants(id) AS (
+            SELECT id FROM todos
+            WHERE parent_id IN (SELECT id FROM todos WHERE deleted_at = ?1)
+            UNION
+            SELECT t.id FROM todos t JOIN descendants d ON t.parent_id = d.id
+        )
+        UPDATE todos SET deleted_at = ?1 WHERE deleted_at IS NULL AND id IN descendants",
+        [deleted_at],
+    )
+}
+
+/// Moves a todo and its subtasks to the trash. Returns the todo as it was.
+pub fn delete_todo(
+    pool: &DbPool,
+    id: &str,
+    precondition: impl FnOnce(&Todo) -> bool,
+) -> Result<Conditional<Todo>, Box<dyn std::error::Error + Send + Sync>> {
+    change_todo_if(pool, id, precondition, |tx, todo| {
+        let deleted_at = deletion_timestamp();
+        tx.execute(
+            "UPDATE todos SET deleted_at = ?1 WHERE id = ?2",
+            [&deleted_at, id],
+        )?;
+        trash_descendants(tx, &deleted_at)?;
+        Ok(todo)
+    })
+}
+
+/// Takes a todo out of the trash together with the subtasks that were trashed with it.
+pub fn restore_todo(
+    pool: &DbPool,
+    id: &str,
+) -> Result<Option<Todo>, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    conn.execute(
+        "WITH RECURSIVE family(id) AS (
+            SELECT ?1
+            UNION
+            SELECT t.id FROM todos t JOIN family f ON t.parent_id = f.id
+        )
+        UPDATE todos SET deleted_at = NULL
+        WHERE id IN family
+          AND deleted_at = (SELECT deleted_at FROM todos WHERE id = ?1)",
+        [id],
+    )?;
+    get_todo(pool, id)
+}
+
+/// Permanently deletes a todo that is in the trash, with its subtasks.
+pub fn purge_todo(
+    pool: &DbPool,
+    id: &str,
+) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let rows_affected = conn.execute(
+        "DELETE FROM todos WHERE id = ?1 AND deleted_at IS NOT NULL",
+        [id],
+    )?;
+    Ok(rows_affected > 0)
+}
+
+/// Permanently deletes trashed todos whose list's `purge_trash_after_days` have passed.
+pub fn purge_expired_trash(
+    pool: &DbPool,
+) -> Result<usize, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let rows_affected = conn.execute(
+        "DELETE FROM todos
+         WHERE deleted_at IS NOT NULL
+           AND julianday(?1) - julianday(deleted_at) >=
+               (SELECT purge_trash_after_days FROM lists WHERE lists.id = todos.list_id)",
+        [now_timestamp()],
+    )?;
+    Ok(rows_affected)
+}
+
+/// Number of todos matching the filter's conditions; sorting and pagination are ignored.
+pub fn count_todos(
+    pool: &DbPool,
+    filter: &TodoFilter,
+) -> Result<usize, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    query_count(&conn, filter)
+}
+
+fn query_count(
+    conn: &rusqlite::Connection,
+    filter: &TodoFilter,
+) -> Result<usize, Box<dyn std::error::Error + Send + Sync>> {
+    let mut query = QueryBuilder::new();
+    filter.apply_conditions(&mut query);
+    let count: i64 = conn.query_row(
+        &query.sql("SELECT COUNT(*) FROM todos"),
+        query.params(),
+        |row| row.get(0),
+    )?;
+    Ok(count as usize)
+}
+
+/// Completions are counted per day for this many days, today included
+pub const STATS_COMPLETION_DAYS: u32 = 30;
+
+#[derive(Debug, Serialize)]
+pub struct CountByTag {
+    pub tag: String,
+    pub total: i64,
+    pub open: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CountByList {
+    /// `null` for todos outside any list
+    pub list_id: Option<String>,
+    pub name: Option<String>,
+    pub total: i64,
+    pub open: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CompletionsOnDay {
+    /// UTC day, `YYYY-MM-DD`
+    pub date: String,
+    pub completed: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TodoStats {
+    pub total: i64,
+    pub open: i64,
+    pub completed: i64,
+    /// Open todos whose due date has passed
+    pub overdue: i64,
+    /// Most used first
+    pub by_tag: Vec<CountByTag>,
+    pub by_list: Vec<CountByList>,
+    /// Oldest first, days without completions included
+    pub completions_per_day: Vec<CompletionsOnDay>,
+}
+
+/// Counts of the todos matching the filter's conditions, computed in the database.
+pub fn get_todo_stats(
+    pool: &DbPool,
+    filter: &TodoFilter,
+) -> Result<TodoStats, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let mut query = QueryBuilder::new();
+    filter.apply_conditions(&mut query);
+    // Every aggregate reads the same filtered set of todos
+    let scope = query.sql("SELECT * FROM todos");
+    let now = now_timestamp();
+
+    let (total, open, overdue): (i64, i64, i64) = conn.query_row(
+        &format!(
+            "WITH scope AS ({scope})
+             SELECT COUNT(*),
+                    COALESCE(SUM(NOT completed), 0),
+                    COALESCE(SUM(NOT completed AND due_date < '{now}'), 0)
+             FROM scope"
+        ),
+        query.params(),
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+    )?;
+
+    let mut stmt = conn.prepare(&format!(
+        "WITH scope AS ({scope})
+         SELECT tags.name, COUNT(*), COALESCE(SUM(NOT scope.completed), 0)
+         FROM scope
+         JOIN todo_tags ON todo_tags.todo_id = scope.id
+         JOIN tags ON tags.id = todo_tags.tag_id
+         GROUP BY tags.id
+         ORDER BY COUNT(*) DESC, tags.name COLLATE NOCASE"
+    ))?;
+    let by_tag = stmt
+        .query_map(query.params(), |row| {
+            Ok(CountByTag {
+                tag: row.get(0)?,
+                total: row.get(1)?,
+                open: row.get(2)?,
+            })
+        })?
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut stmt = conn.prepare(&format!(
+        "WITH scope AS ({scope})
+         SELECT scope.list_id, lists.name, COUNT(*), COALESCE(SUM(NOT scope.completed), 0)
+         FROM scope
+         LEFT JOIN lists ON lists.id = scope.list_id
+         GROUP BY scope.list_id
+         ORDER BY scope.list_id IS NULL, lists.name COLLATE NOCASE"
+    ))?;
+    let by_list = stmt
+        .query_map(query.params(), |row| {
+            Ok(CountByList {
+                list_id: row.get(0)?,
+                name: row.get(1)?,
+                total: row.get(2)?,
+                open: row.get(3)?,
+            })
+        })?
+        .collect::<Result<Vec<_>>>()?;
+
+    let today = Utc::now().date_naive();
+    let first_day = today - chrono::Duration::days(i64::from(STATS_COMPLETION_DAYS) - 1);
+    let mut stmt = conn.prepare(&format!(
+        "WITH scope AS ({scope})
+         SELECT substr(completed_at, 1, 10) AS day, COUNT(*)
+         FROM scope
+         WHERE completed AND completed_at >= '{first_day}'
+         GROUP BY day"
+    ))?;
+    let completed_by_day: BTreeMap<String, i64> = stmt
+        .query_map(query.params(), |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<Result<_>>()?;
+    let completions_per_day = first_day
+        .iter_days()
+        .take(STATS_COMPLETION_DAYS as usize)
+        .map(|day| {
+            let date = day.to_string();
+            CompletionsOnDay {
+                completed: completed_by_day.get(&date).copied().unwrap_or(0),
+                date,
+            }
+        })
+        .collect();
+
+    Ok(TodoStats {
+        total,
+        open,
+        completed: total - open,
+        overdue,
+        by_tag,
+        by_list,
+        completions_per_day,
+    })
+}
+
+/// Moves every todo matching the filter, and their subtasks, to the trash.
+pub fn delete_todos(
+    pool: &DbPool,
+    filter: &TodoFilter,
+) -> Result<usize, Box<dyn std::error::Error + Send + Sync>> {
+    let mut conn = pool.get()?;
+    let tx = conn.transaction()?;
+    let rows_affected = delete_todos_in(&tx, filter)?;
+    tx.commit()?;
+    Ok(rows_affected)
+}
+
+fn delete_todos_in(conn: &rusqlite::Connection, filter: &TodoFilter) -> Result<usize> {
+    let deleted_at = deletion_timestamp();
+    let mut query = QueryBuilder::new();
+    filter.apply_conditions(&mut query);
+    // The SET placeholder comes before the filter's
+    let timestamp = SqlValue::Text(deleted_at.clone());
+    let rows_affected = conn.execute(
+        &query.sql("UPDATE todos SET deleted_at = ?"),
+        rusqlite::params_from_iter(std::iter::once(&timestamp).chain(&query.params)),
+    )?;
+    trash_descendants(conn, &deleted_at)?;
+    Ok(rows_affected)
+}
+
+/// Archives the completed todos matching the filter; open todos are left alone.
+fn archive_todos_in(conn: &rusqlite::Connection, filter: &TodoFilter) -> Result<usize> {
+    let mut query = QueryBuilder::new();
+    filter.apply_conditions(&mut query);
+    query.and_where("completed AND archived_at IS NULL", []);
+    let timestamp = SqlValue::Text(now_timestamp());
+    conn.execute(
+        &query.sql("UPDATE todos SET archived_at = ?"),
+        rusqlite::params_from_iter(std::iter::once(&timestamp).chain(&query.params)),
+    )
+}
+
+/// One step of a saved macro, applied to every todo matching its filter.
+pub enum BulkAction {
+    Archive(TodoFilter),
+    Tag {
+        filter: TodoFilter,
+        add: Vec<String>,
+        remove: Vec<String>,
+    },
+    Delete(TodoFilter),
+}
+
+/// Runs the actions in order in one transaction: if one fails, none of them is applied.
+/// Returns how many todos each action archived, tagged or deleted.
+pub fn run_bulk_actions(
+    pool: &DbPool,
+    actions: &[BulkAction],
+) -> Result<Vec<usize>, Box<dyn std::error::Error + Send + Sync>> {
+    let mut conn = pool.get()?;
+    let tx = conn.transaction()?;
+    let mut affected = Vec::new();
+    for action in actions {
+        affected.push(match action {
+            BulkAction::Archive(filter) => archive_todos_in(&tx, filter)?,
+            BulkAction::Tag {
+                filter,
+                add,
+                remove,
+            } => {
+                let todo_ids = select_ids_in(&tx, filter)?;
+                tag_todos_in(&tx, &todo_ids, add, remove)?;
+                todo_ids.len()
+            }
+            BulkAction::Delete(filter) => delete_todos_in(&tx, filter)?,
+        });
+    }
+    tx.commit()?;
+    Ok(affected)
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct PomodoroSession {
+    pub id: String,
+    pub todo_id: String,
+    pub planned_minutes: i64,
+    pub started_at: String,
+    pub ended_at: Option<String>,
+}
+
+const POMODORO_COLUMNS: &str = "id, todo_id, planned_minutes, started_at, ended_at";
+
+fn row_to_pomodoro(row: &rusqlite::Row) -> Result<PomodoroSession> {
+    Ok(PomodoroSession {
+        id: row.get(0)?,
+        todo_id: row.get(1)?,
+        planned_minutes: row.get(2)?,
+        started_at: row.get(3)?,
+        ended_at: row.get(4)?,
+    })
+}
+
+pub fn get_active_pomodoro(
+    pool: &DbPool,
+    todo_id: &str,
+) -> Result<Option<PomodoroSession>, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {POMODORO_COLUMNS} FROM pomodoro_sessions WHERE todo_id = ?1 AND ended_at IS NULL"
+    ))?;
+    let mut sessions = stmt.query_map([todo_id], row_to_pomodoro)?;
+    match sessions.next() {
+        Some(session) => Ok(Some(session?)),
+        None => Ok(None),
+    }
+}
+
+/// Starts a session, or returns `None` when one is already running for the todo.
+pub fn start_pomodoro(
+    pool: &DbPool,
+    todo_id: &str,
+    planned_minutes: i64,
+) -> Result<Option<PomodoroSession>, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let session = PomodoroSession {
+        id: uuid::Uuid::new_v4().to_string(),
+        todo_id: todo_id.to_string(),
+        planned_minutes,
+        started_at: chrono::Utc::now().to_rfc3339(),
+        ended_at: None,
+    };
+    // The partial unique index turns a second running session into a no-op
+    let rows_affected = conn.execute(
+        "INSERT OR IGNORE INTO pomodoro_sessions (id, todo_id, planned_minutes, started_at) VALUES (?1, ?2, ?3, ?4)",
+        rusqlite::params![session.id, session.todo_id, session.planned_minutes, session.started_at],
+    )?;
+    Ok((rows_affected > 0).then_some(session))
+}
+
+/// Ends the running session of a todo, if there is one.
+pub fn stop_pomodoro(
+    pool: &DbPool,
+    todo_id: &str,
+) -> Result<Option<PomodoroSession>, Box<dyn std::error::Error + Send + Sync>> {
+    let Some(mut session) = get_active_pomodoro(pool, todo_id)? else {
+        return Ok(None);
+    };
+    let conn = pool.get()?;
+    let ended_at = chrono::Utc::now().to_rfc3339();
+    conn.execute(
+        "UPDATE pomodoro_sessions SET ended_at = ?1 WHERE id = ?2",
+        [&ended_at, &session.id],
+    )?;
+    session.ended_at = Some(ended_at);
+    Ok(Some(session))
+}
+
+pub fn get_pomodoros(
+    pool: &DbPool,
+    todo_id: &str,
+) -> Result<Vec<PomodoroSession>, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {POMODORO_COLUMNS} FROM pomodoro_sessions WHERE todo_id = ?1 ORDER BY started_at DESC"
+    ))?;
+    let sessions = stmt.query_map([todo_id], row_to_pomodoro)?;
+
+    let mut result = Vec::new();
+    for session in sessions {
+        result.push(session?);
+    }
+    Ok(result)
+}
+
+/// A finished session with what the focus report groups it by
+#[derive(Debug, Clone)]
+pub struct FinishedPomodoro {
+    pub session: PomodoroSession,
+    pub title: String,
+    pub list_id: Option<String>,
+    pub list_name: Option<String>,
+    pub tags: Vec<String>,
+}
+
+/// Finished sessions started at or after `since`, with the title, list and tags of their todo.
+pub fn get_finished_pomodoros_since(
+    pool: &DbPool,
+    since: &str,
+) -> Result<Vec<FinishedPomodoro>, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    // Tag names cannot contain commas, so they can be joined with them
+    let mut stmt = conn.prepare(
+        "SELECT s.id, s.todo_id, s.planned_minutes, s.started_at, s.ended_at, t.title,
+                t.list_id, l.name,
+                (SELECT GROUP_CONCAT(g.name, ',') FROM todo_tags tt JOIN tags g ON g.id = tt.tag_id
+                 WHERE tt.todo_id = t.id)
+         FROM pomodoro_sessions s JOIN todos t ON t.id = s.todo_id
+         LEFT JOIN lists l ON l.id = t.list_id
+         WHERE s.ended_at IS NOT NULL AND s.started_at >= ?1
+         ORDER BY s.started_at",
+    )?;
+    let sessions = stmt.query_map([since], |row| {
+        let tags: Option<String> = row.get(8)?;
+        Ok(FinishedPomodoro {
+            session: row_to_pomodoro(row)?,
+            title: row.get(5)?,
+            list_id: row.get(6)?,
+            list_name: row.get(7)?,
+            tags: tags
+                .map(|tags| tags.split(',').map(str::to_string).collect())
+                .unwrap_or_default(),
+        })
+    })?;
+
+    let mut result = Vec::new();
+    for session in sessions {
+        result.push(session?);
+    }
+    Ok(result)
+}
+
+// Longest accepted tag name, in characters
+pub const TAG_MAX_LEN: usize = 50;
+
+#[derive(Debug, Serialize, Clone)]
+pub struct Tag {
+    pub id: String,
+    pub name: String,
+    pub created_at: String,
+    /// Number of todos carrying the tag
+    pub todo_count: i64,
+    /// Name of a palette color
+    pub color: Option<String>,
+}
+
+const TAG_COLUMNS: &str = "id, name, created_at,
+    (SELECT COUNT(*) FROM todo_tags tt JOIN todos t ON t.id = tt.todo_id
+     WHERE tt.tag_id = tags.id AND t.deleted_at IS NULL),
+    color";
+
+fn row_to_tag(row: &rusqlite::Row) -> Result<Tag> {
+    Ok(Tag {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        created_at: row.get(2)?,
+        todo_count: row.get(3)?,
+        color: row.get(4)?,
+    })
+}
+
+/// Trims the name and checks it can be used in `?tag=` filters, where commas separate tags.
+pub fn normalize_tag_name(name: &str) -> Result<String, String> {
+    let name = name.trim();
+    if name.is_empty() || name.chars().count() > TAG_MAX_LEN {
+        return Err(format!("Tag names must be 1-{TAG_MAX_LEN} characters"));
+    }
+    if name.contains(',') {
+        return Err("Tag names cannot contain commas".to_string());
+    }
+    Ok(name.to_string())
+}
+
+/// Normalizes tag names and drops repeated ones, ignoring case like tag lookups do.
+pub fn normalize_tag_names(names: &[String]) -> Result<Vec<String>, String> {
+    let mut normalized: Vec<String> = Vec::with_capacity(names.len());
+    for name in names {
+        let name = normalize_tag_name(name)?;
+        if !normalized
+            .iter()
+            .any(|seen| seen.to_lowercase() == name.to_lowercase())
+        {
+            normalized.push(name);
+        }
+    }
+    Ok(normalized)
+}
+
+pub fn get_tags(pool: &DbPool) -> Result<Vec<Tag>, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let mut stmt = conn.prepare(&format!("SELECT {TAG_COLUMNS} FROM tags ORDER BY name"))?;
+    let tags = stmt.query_map([], row_to_tag)?;
+
+    let mut result = Vec::new();
+    for tag in tags {
+        result.push(tag?);
+    }
+    Ok(result)
+}
+
+pub fn get_tag(
+    pool: &DbPool,
+    id: &str,
+) -> Result<Option<Tag>, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let mut stmt = conn.prepare(&format!("SELECT {TAG_COLUMNS} FROM tags WHERE id = ?1"))?;
+    let mut tags = stmt.query_map([id], row_to_tag)?;
+    match tags.next() {
+        Some(tag) => Ok(Some(tag?)),
+        None => Ok(None),
+    }
+}
+
+/// Looks a tag up by name; names compare case-insensitively.
+pub fn find_tag_by_name(
+    pool: &DbPool,
+    name: &str,
+) -> Result<Option<Tag>, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let mut stmt = conn.prepare(&format!("SELECT {TAG_COLUMNS} FROM tags WHERE name = ?1"))?;
+    let mut tags = stmt.query_map([name], row_to_tag)?;
+    match tags.next() {
+        Some(tag) => Ok(Some(tag?)),
+        None => Ok(None),
+    }
+}
+
+pub fn create_tag(
+    pool: &DbPool,
+    name: &str,
+    color: Option<&str>,
+) -> Result<Tag, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let tag = Tag {
+        id: uuid::Uuid::new_v4().to_string(),
+        name: name.to_string(),
+        created_at: chrono::Utc::now().to_rfc3339(),
+        todo_count: 0,
+        color: color.map(str::to_string),
+    };
+    conn.execute(
+        "INSERT INTO tags (id, name, created_at, color) VALUES (?1, ?2, ?3, ?4)",
+        rusqlite::params![tag.id, tag.name, tag.created_at, tag.color],
+    )?;
+    Ok(tag)
+}
+
+/// Renames and/or recolors a tag; an empty `color` removes it.
+pub fn update_tag(
+    pool: &DbPool,
+    id: &str,
+    name: Option<&str>,
+    color: Option<&str>,
+) -> Result<Option<Tag>, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    if let Some(name) = name {
+        conn.execute("UPDATE tags SET name = ?1 WHERE id = ?2", [name, id])?;
+    }
+    if let Some(color) = color {
+        conn.execute(
+            "UPDATE tags SET color = NULLIF(?1, '') WHERE id = ?2",
+            [color, id],
+        )?;
+    }
+    get_tag(pool, id)
+}
+
+/// Deletes the tag and detaches it from every todo.
+pub fn delete_tag(
+    pool: &DbPool,
+    id: &str,
+) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let rows_affected = conn.execute("DELETE FROM tags WHERE id = ?1", [id])?;
+    Ok(rows_affected > 0)
+}
+
+/// Attaches the tag called `name` to a todo, creating the tag if it does not exist yet.
+/// Attaching a tag the todo already has is a no-op.
+pub fn attach_tag(
+    pool: &DbPool,
+    todo_id: &str,
+    name: &str,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let tag = match find_tag_by_name(pool, name)? {
+        Some(tag) => tag,
+        None => create_tag(pool, name, None)?,
+    };
+    let conn = pool.get()?;
+    conn.execute(
+        "INSERT OR IGNORE INTO todo_tags (todo_id, tag_id) VALUES (?1, ?2)",
+        [todo_id, &tag.id],
+    )?;
+    Ok(())
+}
+
+/// Which todos a bulk operation applies to.
+pub enum BulkSelection {
+    Ids(Vec<String>),
+    Filter(Box<TodoFilter>),
+}
+
+#[derive(Debug, Serialize)]
+pub struct BulkTagResult {
+    /// Todos the tags were added to or removed from
+    pub matched: usize,
+    /// Tags newly attached, counted once per todo
+    pub added: usize,
+    pub removed: usize,
+    /// Requested ids that do not exist or are in the trash
+    pub not_found: Vec<String>,
+}
+
+/// Attaches the `add` tags to and detaches the `remove` tags from every selected todo, in one
+/// transaction. Tags are created on first use, like with `attach_tag`.
+pub fn bulk_tag(
+    pool: &DbPool,
+    selection: &BulkSelection,
+    add: &[String],
+    remove: &[String],
+) -> Result<BulkTagResult, Box<dyn std::error::Error + Send + Sync>> {
+    let mut conn = pool.get()?;
+    let tx = conn.transaction()?;
+    let (todo_ids, not_found) = match selection {
+        BulkSelection::Ids(ids) => {
+            let mut stmt =
+                tx.prepare("SELECT COUNT(*) > 0 FROM todos WHERE id = ?1 AND deleted_at IS NULL")?;
+            let mut found = Vec::new();
+            let mut not_found = Vec::new();
+            for id in ids {
+                if stmt.query_row([id], |row| row.get(0))? {
+                    found.push(id.clone());
+                } else {
+                    not_found.push(id.clone());
+                }
+            }
+            (found, not_found)
+        }
+        BulkSelection::Filter(filter) => (select_ids_in(&tx, filter)?, Vec::new()),
+    };
+    let (added, removed) = tag_todos_in(&tx, &todo_ids, add, remove)?;
+    tx.commit()?;
+    Ok(BulkTagResult {
+        matched: todo_ids.len(),
+        added,
+        removed,
+        not_found,
+    })
+}
+
+fn select_ids_in(conn: &rusqlite::Connection, filter: &TodoFilter) -> Result<Vec<String>> {
+    let mut query = QueryBuilder::new();
+    filter.apply_conditions(&mut query);
+    let mut stmt = conn.prepare(&query.sql("SELECT id FROM todos"))?;
+    let ids = stmt.query_map(query.params(), |row| row.get(0))?;
+    ids.collect()
+}
+
+// Returns how many tags were attached and detached, counted once per todo
+fn tag_todos_in(
+    conn: &rusqlite::Connection,
+    todo_ids: &[String],
+    add: &[String],
+    remove: &[String],
+) -> Result<(usize, usize)> {
+    let mut added = 0;
+    for name in add {
+        conn.execute(
+            "INSERT OR IGNORE INTO tags (id, name, created_at) VALUES (?1, ?2, ?3)",
+            rusqlite::params![
+                uuid::Uuid::new_v4().to_string(),
+                name,
+                chrono::Utc::now().to_rfc3339()
+            ],
+        )?;
+        let tag_id: String =
+            conn.query_row("SELECT id FROM tags WHERE name = ?1", [name], |row| {
+                row.get(0)
+            })?;
+        let mut stmt =
+            conn.prepare("INSERT OR IGNORE INTO todo_tags (todo_id, tag_id) VALUES (?1, ?2)")?;
+        for todo_id in todo_ids {
+            added += stmt.execute([todo_id, &tag_id])?;
+        }
+    }
+    let mut removed = 0;
+    for name in remove {
+        let mut stmt = conn.prepare(
+            "DELETE FROM todo_tags
+             WHERE todo_id = ?1 AND tag_id = (SELECT id FROM tags WHERE name = ?2)",
+        )?;
+        for todo_id in todo_ids {
+            removed += stmt.execute([todo_id, name])?;
+        }
+    }
+    Ok((added, removed))
+}
+
+/// Removes the tag called `name` from a todo; the tag itself is kept.
+pub fn detach_tag(
+    pool: &DbPool,
+    todo_id: &str,
+    name: &str,
+) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let rows_affected = conn.execute(
+        "DELETE FROM todo_tags
+         WHERE todo_id = ?1 AND tag_id = (SELECT id FROM tags WHERE name = ?2)",
+        [todo_id, name],
+    )?;
+    Ok(rows_affected > 0)
+}
+
+// Applied when the palette table is first created
+const DEFAULT_PALETTE: &[(&str, &str)] = &[
+    ("red", "#e5484d"),
+    ("orange", "#f76b15"),
+    ("yellow", "#ffc53d"),
+    ("green", "#30a46c"),
+    ("teal", "#12a594"),
+    ("blue", "#0090ff"),
+    ("purple", "#8e4ec6"),
+    ("pink", "#d6409f"),
+    ("gray", "#8b8d98"),
+];
+
+pub const COLOR_NAME_MAX_LEN: usize = 30;
+
+#[derive(Debug, Serialize, Clone)]
+pub struct PaletteColor {
+    pub name: String,
+    /// `#rrggbb`, lowercase
+    pub hex: String,
+    pub created_at: String,
+}
+
+const PALETTE_COLUMNS: &str = "name, hex, created_at";
+
+fn row_to_palette_color(row: &rusqlite::Row) -> Result<PaletteColor> {
+    Ok(PaletteColor {
+        name: row.get(0)?,
+        hex: row.get(1)?,
+        created_at: row.get(2)?,
+    })
+}
+
+/// Accepts `#rgb` or `#rrggbb` and returns the long lowercase form.
+pub fn normalize_hex_color(value: &str) -> Result<String, String> {
+    let digits = value.trim().strip_prefix('#').unwrap_or_default();
+    if !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(format!("Invalid color '{value}': expected #rrggbb"));
+    }
+    let digits: String = match digits.len() {
+        3 => digits.chars().flat_map(|c| [c, c]).collect(),
+        6 => digits.to_string(),
+        _ => return Err(format!("Invalid color '{value}': expected #rrggbb")),
+    };
+    Ok(format!("#{}", digits.to_ascii_lowercase()))
+}
+
+pub fn normalize_color_name(name: &str) -> Result<String, String> {
+    let name = name.trim();
+    if name.is_empty() || name.chars().count() > COLOR_NAME_MAX_LEN {
+        return Err(format!(
+            "Color names must be 1-{COLOR_NAME_MAX_LEN} characters"
+        ));
+    }
+    Ok(name.to_string())
+}
+
+pub fn get_palette(
+    pool: &DbPool,
+) -> Result<Vec<PaletteColor>, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {PALETTE_COLUMNS} FROM palette_colors ORDER BY created_at, rowid"
+    ))?;
+    let colors = stmt.query_map([], row_to_palette_color)?;
+
+    let mut result = Vec::new();
+    for color in colors {
+        result.push(color?);
+    }
+    Ok(result)
+}
+
+/// Looks a palette color up by name; names compare case-insensitively.
+pub fn get_palette_color(
+    pool: &DbPool,
+    name: &str,
+) -> Result<Option<PaletteColor>, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {PALETTE_COLUMNS} FROM palette_colors WHERE name = ?1"
+    ))?;
+    let mut colors = stmt.query_map([name], row_to_palette_color)?;
+    match colors.next() {
+        Some(color) => Ok(Some(color?)),
+        None => Ok(None),
+    }
+}
+
+pub fn create_palette_color(
+    pool: &DbPool,
+    name: &str,
+    hex: &str,
+) -> Result<PaletteColor, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let color = PaletteColor {
+        name: name.to_string(),
+        hex: hex.to_string(),
+        created_at: chrono::Utc::now().to_rfc3339(),
+    };
+    conn.execute(
+        "INSERT INTO palette_colors (name, hex, created_at) VALUES (?1, ?2, ?3)",
+        [&color.name, &color.hex, &color.created_at],
+    )?;
+    Ok(color)
+}
+
+pub fn update_palette_color(
+    pool: &DbPool,
+    name: &str,
+    hex: &str,
+) -> Result<Option<PaletteColor>, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    conn.execute(
+        "UPDATE palette_colors SET hex = ?1 WHERE name = ?2",
+        [hex, name],
+    )?;
+    get_palette_color(pool, name)
+}
+
+/// Removes a color from the palette; tags using it are left without a color.
+pub fn delete_palette_color(
+    pool: &DbPool,
+    name: &str,
+) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let rows_affected = conn.execute("DELETE FROM palette_colors WHERE name = ?1", [name])?;
+    Ok(rows_affected > 0)
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum CustomFieldType {
+    Text,
+    Number,
+    Boolean,
+    /// `YYYY-MM-DD`
+    Date,
+}
+
+impl CustomFieldType {
+    pub const ALL: [&'static str; 4] = ["text", "number", "boolean", "date"];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            CustomFieldType::Text => "text",
+            CustomFieldType::Number => "number",
+            CustomFieldType::Boolean => "boolean",
+            CustomFieldType::Date => "date",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "text" => Some(CustomFieldType::Text),
+            "number" => Some(CustomFieldType::Number),
+            "boolean" => Some(CustomFieldType::Boolean),
+            "date" => Some(CustomFieldType::Date),
+            _ => None,
+        }
+    }
+
+    fn accepts(self, value: &Value) -> bool {
+        match self {
+            CustomFieldType::Text => value
+                .as_str()
+                .is_some_and(|text| text.chars().count() <= CUSTOM_FIELD_TEXT_MAX_LEN),
+            CustomFieldType::Number => value.is_number(),
+            CustomFieldType::Boolean => value.is_boolean(),
+            CustomFieldType::Date => value
+                .as_str()
+                .is_some_and(|date| chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d").is_ok()),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct CustomField {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub field_type: CustomFieldType,
+    pub created_at: String,
+}
+
+const CUSTOM_FIELD_COLUMNS: &str = "name, type, created_at";
+
+fn row_to_custom_field(row: &rusqlite::Row) -> Result<CustomField> {
+    let field_type: String = row.get(1)?;
+    Ok(CustomField {
+        name: row.get(0)?,
+        field_type: CustomFieldType::parse(&field_type).unwrap_or(CustomFieldType::Text),
+        created_at: row.get(2)?,
+    })
+}
+
+/// Checks every value names a registered field and has its type; `null` is always allowed.
+pub fn validate_custom_field_values(
+    fields: &[CustomField],
+    values: &Metadata,
+) -> Result<(), String> {
+    for (name, value) in values {
+        let Some(field) = fields.iter().find(|field| field.name == *name) else {
+            return Err(format!(
+                "Unknown custom field '{name}': register it with POST /api/custom-fields first"
+            ));
+        };
+        if !value.is_null() && !field.field_type.accepts(value) {
+            return Err(match field.field_type {
+                CustomFieldType::Text => format!(
+                    "Custom field '{name}' must be a string of at most {CUSTOM_FIELD_TEXT_MAX_LEN} characters"
+                ),
+                CustomFieldType::Date => {
+                    format!("Custom field '{name}' must be a date in the form YYYY-MM-DD")
+                }
+                field_type => format!("Custom field '{name}' must be a {}", field_type.as_str()),
+            });
+        }
+    }
+    Ok(())
+}
+
+pub fn get_custom_fields(
+    pool: &DbPool,
+) -> Result<Vec<CustomField>, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {CUSTOM_FIELD_COLUMNS} FROM custom_fields ORDER BY created_at, rowid"
+    ))?;
+    let fields = stmt.query_map([], row_to_custom_field)?;
+
+    let mut result = Vec::new();
+    for field in fields {
+        result.push(field?);
+    }
+    Ok(result)
+}
+
+pub fn create_custom_field(
+    pool: &DbPool,
+    name: &str,
+    field_type: CustomFieldType,
+) -> Result<CustomField, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let field = CustomField {
+        name: name.to_string(),
+        field_type,
+        created_at: chrono::Utc::now().to_rfc3339(),
+    };
+    conn.execute(
+        "INSERT INTO custom_fields (name, type, created_at) VALUES (?1, ?2, ?3)",
+        rusqlite::params![field.name, field_type.as_str(), field.created_at],
+    )?;
+    Ok(field)
+}
+
+/// Deletes a field and removes its values from all todos, including those in the trash.
+pub fn delete_custom_field(
+    pool: &DbPool,
+    name: &str,
+) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+    let mut conn = pool.get()?;
+    let tx = conn.transaction()?;
+    let Some(name) = tx
+        .query_row(
+            "SELECT name FROM custom_fields WHERE name = ?1",
+            [name],
+            |row| row.get::<_, String>(0),
+        )
+        .optional()?
+    else {
+        return Ok(false);
+    };
+    let path = format!("$.\"{name}\"");
+    tx.execute(
+        "UPDATE todos SET custom_fields = json_remove(custom_fields, ?1)
+         WHERE json_type(custom_fields, ?1) IS NOT NULL",
+        [&path],
+    )?;
+    tx.execute("DELETE FROM custom_fields WHERE name = ?1", [&name])?;
+    tx.commit()?;
+    Ok(true)
+}
+
+pub const MACRO_NAME_MAX_LEN: usize = 50;
+
+#[derive(Debug, Serialize, Clone)]
+pub struct Macro {
+    pub id: String,
+    pub name: String,
+    /// The steps as saved; see `macros::MacroStep`
+    pub steps: Value,
+    pub created_at: String,
+    pub last_run_at: Option<String>,
+}
+
+const MACRO_COLUMNS: &str = "id, name, steps, created_at, last_run_at";
+
+fn row_to_macro(row: &rusqlite::Row) -> Result<Macro> {
+    let steps: String = row.get(2)?;
+    Ok(Macro {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        steps: serde_json::from_str(&steps).unwrap_or_default(),
+        created_at: row.get(3)?,
+        last_run_at: row.get(4)?,
+    })
+}
+
+pub fn normalize_macro_name(name: &str) -> Result<String, String> {
+    let name = name.trim();
+    if name.is_empty() || name.chars().count() > MACRO_NAME_MAX_LEN {
+        return Err(format!(
+            "Macro names must be 1-{MACRO_NAME_MAX_LEN} characters"
+        ));
+    }
+    Ok(name.to_string())
+}
+
+pub fn get_macros(pool: &DbPool) -> Result<Vec<Macro>, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let mut stmt = conn.prepare(&format!("SELECT {MACRO_COLUMNS} FROM macros ORDER BY name"))?;
+    let macros = stmt.query_map([], row_to_macro)?;
+
+    let mut result = Vec::new();
+    for macro_ in macros {
+        result.push(macro_?);
+    }
+    Ok(result)
+}
+
+pub fn get_macro(
+    pool: &DbPool,
+    id: &str,
+) -> Result<Option<Macro>, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let macro_ = conn
+        .query_row(
+            &format!("SELECT {MACRO_COLUMNS} FROM macros WHERE id = ?1"),
+            [id],
+            row_to_macro,
+        )
+        .optional()?;
+    Ok(macro_)
+}
+
+/// Looks a macro up by name, ignoring case.
+pub fn find_macro_by_name(
+    pool: &DbPool,
+    name: &str,
+) -> Result<Option<Macro>, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let macro_ = conn
+        .query_row(
+            &format!("SELECT {MACRO_COLUMNS} FROM macros WHERE name = ?1"),
+            [name],
+            row_to_macro,
+        )
+        .optional()?;
+    Ok(macro_)
+}
+
+pub fn create_macro(
+    pool: &DbPool,
+    name: &str,
+    steps: Value,
+) -> Result<Macro, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let macro_ = Macro {
+        id: uuid::Uuid::new_v4().to_string(),
+        name: name.to_string(),
+        steps,
+        created_at: chrono::Utc::now().to_rfc3339(),
+        last_run_at: None,
+    };
+    conn.execute(
+        "INSERT INTO macros (id, name, steps, created_at) VALUES (?1, ?2, ?3, ?4)",
+        rusqlite::params![
+            macro_.id,
+            macro_.name,
+            serde_json::to_string(&macro_.steps)?,
+            macro_.created_at
+        ],
+    )?;
+    Ok(macro_)
+}
+
+/// Renames a macro and/or replaces its steps.
+pub fn update_macro(
+    pool: &DbPool,
+    id: &str,
+    name: Option<&str>,
+    steps: Option<&Value>,
+) -> Result<Option<Macro>, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let steps = steps.map(serde_json::to_string).transpose()?;
+    let rows_affected = conn.execute(
+        "UPDATE macros SET name = COALESCE(?1, name), steps = COALESCE(?2, steps) WHERE id = ?3",
+        rusqlite::params![name, steps, id],
+    )?;
+    if rows_affected == 0 {
+        return Ok(None);
+    }
+    get_macro(pool, id)
+}
+
+pub fn delete_macro(
+    pool: &DbPool,
+    id: &str,
+) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let rows_affected = conn.execute("DELETE FROM macros WHERE id = ?1", [id])?;
+    Ok(rows_affected > 0)
+}
+
+pub fn record_macro_run(
+    pool: &DbPool,
+    id: &str,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    conn.execute(
+        "UPDATE macros SET last_run_at = ?1 WHERE id = ?2",
+        [&now_timestamp(), id],
+    )?;
+    Ok(())
+}
+
+pub const VIEW_NAME_MAX_LEN: usize = 50;
+
+/// A named way of showing todos, rendered the same by every client.
+#[derive(Debug, Serialize, Clone)]
+pub struct View {
+    pub id: String,
+    pub name: String,
+    /// Todo fields to show, in order
+    pub columns: Vec<String>,
+    /// Query parameters of `GET /api/todos`; dates may be relative, e.g. `today+7d`
+    pub filters: BTreeMap<String, String>,
+    /// As the `sort` parameter of `GET /api/todos`
+    pub sort: Option<String>,
+    pub group_by: Option<String>,
+    pub is_default: bool,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+/// Everything about a view that clients can set.
+#[derive(Debug, Clone)]
+pub struct ViewDefinition {
+    pub name: String,
+    pub columns: Vec<String>,
+    pub filters: BTreeMap<String, String>,
+    pub sort: Option<String>,
+    pub group_by: Option<String>,
+    pub is_default: bool,
+}
+
+const VIEW_COLUMNS: &str =
+    "id, name, columns, filters, sort, group_by, is_default, created_at, updated_at";
+
+fn row_to_view(row: &rusqlite::Row) -> Result<View> {
+    let columns: String = row.get(2)?;
+    let filters: String = row.get(3)?;
+    Ok(View {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        columns: serde_json::from_str(&columns).unwrap_or_default(),
+        filters: serde_json::from_str(&filters).unwrap_or_default(),
+        sort: row.get(4)?,
+        group_by: row.get(5)?,
+        is_default: row.get(6)?,
+        created_at: row.get(7)?,
+        updated_at: row.get(8)?,
+    })
+}
+
+pub fn normalize_view_name(name: &str) -> Result<String, String> {
+    let name = name.trim();
+    if name.is_empty() || name.chars().count() > VIEW_NAME_MAX_LEN {
+        return Err(format!(
+            "View names must be 1-{VIEW_NAME_MAX_LEN} characters"
+        ));
+    }
+    Ok(name.to_string())
+}
+
+/// All views, the default first and the rest by name.
+pub fn get_views(pool: &DbPool) -> Result<Vec<View>, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {VIEW_COLUMNS} FROM views ORDER BY is_default DESC, name"
+    ))?;
+    let views = stmt.query_map([], row_to_view)?;
+
+    let mut result = Vec::new();
+    for view in views {
+        result.push(view?);
+    }
+    Ok(result)
+}
+
+pub fn get_view(
+    pool: &DbPool,
+    id: &str,
+) -> Result<Option<View>, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let view = conn
+        .query_row(
+            &format!("SELECT {VIEW_COLUMNS} FROM views WHERE id = ?1"),
+            [id],
+            row_to_view,
+        )
+        .optional()?;
+    Ok(view)
+}
+
+pub fn get_default_view(
+    pool: &DbPool,
+) -> Result<Option<View>, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let view = conn
+        .query_row(
+            &format!("SELECT {VIEW_COLUMNS} FROM views WHERE is_default"),
+            [],
+            row_to_view,
+        )
+        .optional()?;
+    Ok(view)
+}
+
+/// Looks a view up by name, ignoring case.
+pub fn find_view_by_name(
+    pool: &DbPool,
+    name: &str,
+) -> Result<Option<View>, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let view = conn
+        .query_row(
+            &format!("SELECT {VIEW_COLUMNS} FROM views WHERE name = ?1"),
+            [name],
+            row_to_view,
+        )
+        .optional()?;
+    Ok(view)
+}
+
+// Making a view the default takes that from the previous one
+fn write_view(
+    conn: &mut rusqlite::Connection,
+    id: &str,
+    definition: &ViewDefinition,
+    sql: &str,
+) -> Result<usize, Box<dyn std::error::Error + Send + Sync>> {
+    let tx = conn.transaction()?;
+    if definition.is_default {
+        tx.execute(
+            "UPDATE views SET is_default = 0 WHERE is_default AND id != ?1",
+            [id],
+        )?;
+    }
+    let rows_affected = tx.execute(
+        sql,
+        rusqlite::params![
+            id,
+            definition.name,
+            serde_json::to_string(&definition.columns)?,
+            serde_json::to_string(&definition.filters)?,
+            definition.sort,
+            definition.group_by,
+            definition.is_default,
+            chrono::Utc::now().to_rfc3339(),
+        ],
+    )?;
+    // A view that does not exist takes nothing from the default
+    if rows_affected > 0 {
+        tx.commit()?;
+    }
+    Ok(rows_affected)
+}
+
+pub fn create_view(
+    pool: &DbPool,
+    definition: &ViewDefinition,
+) -> Result<View, Box<dyn std::error::Error + Send + Sync>> {
+    let mut conn = pool.get()?;
+    let id = uuid::Uuid::new_v4().to_string();
+    write_view(
+        &mut conn,
+        &id,
+        definition,
+        "INSERT INTO views (id, name, columns, filters, sort, group_by, is_default, created_at, updated_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?8)",
+    )?;
+    get_view(pool, &id)?.ok_or_else(|| "Created view not found".into())
+}
+
+/// Replaces everything about a view but its id and creation time.
+pub fn update_view(
+    pool: &DbPool,
+    id: &str,
+    definition: &ViewDefinition,
+) -> Result<Option<View>, Box<dyn std::error::Error + Send + Sync>> {
+    let mut conn = pool.get()?;
+    let rows_affected = write_view(
+        &mut conn,
+        id,
+        definition,
+        "UPDATE views SET name = ?2, columns = ?3, filters = ?4, sort = ?5, group_by = ?6,
+             is_default = ?7, updated_at = ?8
+         WHERE id = ?1",
+    )?;
+    if rows_affected == 0 {
+        return Ok(None);
+    }
+    get_view(pool, id)
+}
+
+pub fn delete_view(
+    pool: &DbPool,
+    id: &str,
+) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let rows_affected = conn.execute("DELETE FROM views WHERE id = ?1", [id])?;
+    Ok(rows_affected > 0)
+}
+
+/// A daily export of todos to a file.
+pub const TEMPLATE_NAME_MAX_LEN: usize = 50;
+
+/// A blueprint that new todos are created from.
+#[derive(Debug, Serialize, Clone)]
+pub struct Template {
+    pub id: String,
+    pub name: String,
+    /// Title of the todos created from the template
+    pub title: String,
+    pub description: Option<String>,
+    pub priority: Option<Priority>,
+    pub tags: Vec<String>,
+    /// Texts of the checklist items, in order
+    pub checklist: Vec<String>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+const TEMPLATE_COLUMNS: &str =
+    "id, name, title, description, priority, tags, checklist, created_at, updated_at";
+
+fn row_to_template(row: &rusqlite::Row) -> Result<Template> {
+    let priority: Option<i64> = row.get(4)?;
+    let tags: String = row.get(5)?;
+    let checklist: String = row.get(6)?;
+    Ok(Template {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        title: row.get(2)?,
+        description: row.get(3)?,
+        priority: priority.map(Priority::from_level),
+        tags: serde_json::from_str(&tags).unwrap_or_default(),
+        checklist: serde_json::from_str(&checklist).unwrap_or_default(),
+        created_at: row.get(7)?,
+        updated_at: row.get(8)?,
+    })
+}
+
+pub fn normalize_template_name(name: &str) -> Result<String, String> {
+    let name = name.trim();
+    if name.is_empty() || name.chars().count() > TEMPLATE_NAME_MAX_LEN {
+        return Err(format!(
+            "Template names must be 1-{TEMPLATE_NAME_MAX_LEN} characters"
+        ));
+    }
+    Ok(name.to_string())
+}
+
+pub fn get_templates(
+    pool: &DbPool,
+) -> Result<Vec<Template>, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {TEMPLATE_COLUMNS} FROM templates ORDER BY name"
+    ))?;
+    let templates = stmt.query_map([], row_to_template)?;
+
+    let mut result = Vec::new();
+    for template in templates {
+        result.push(template?);
+    }
+    Ok(result)
+}
+
+pub fn get_template(
+    pool: &DbPool,
+    id: &str,
+) -> Result<Option<Template>, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let template = conn
+        .query_row(
+            &format!("SELECT {TEMPLATE_COLUMNS} FROM templates WHERE id = ?1"),
+            [id],
+            row_to_template,
+        )
+        .optional()?;
+    Ok(template)
+}
+
+/// Looks a template up by name, ignoring case.
+pub fn find_template_by_name(
+    pool: &DbPool,
+    name: &str,
+) -> Result<Option<Template>, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let template = conn
+        .query_row(
+            &format!("SELECT {TEMPLATE_COLUMNS} FROM templates WHERE name = ?1"),
+            [name],
+            row_to_template,
+        )
+        .optional()?;
+    Ok(template)
+}
+
+/// Inserts a new template, or replaces every field of an existing one with the same id.
+pub fn save_template(
+    pool: &DbPool,
+    template: &Template,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    conn.execute(
+        "INSERT INTO templates
+            (id, name, title, description, priority, tags, checklist, created_at, updated_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)
+         ON CONFLICT(id) DO UPDATE SET
+             name = excluded.name,
+             title = excluded.title,
+             description = excluded.description,
+             priority = excluded.priority,
+             tags = excluded.tags,
+             checklist = excluded.checklist,
+             updated_at = excluded.updated_at",
+        rusqlite::params![
+            template.id,
+            template.name,
+            template.title,
+            template.description,
+            template.priority.map(|priority| priority as i64),
+            serde_json::to_string(&template.tags)?,
+            serde_json::to_string(&template.checklist)?,
+            template.created_at,
+            template.updated_at,
+        ],
+    )?;
+    Ok(())
+}
+
+pub fn delete_template(
+    pool: &DbPool,
+    id: &str,
+) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let rows_affected = conn.execute("DELETE FROM templates WHERE id = ?1", [id])?;
+    Ok(rows_affected > 0)
+}
+
+/// Creates a todo with the given tags and checklist items, all in one transaction.
+pub fn create_todo_with_extras(
+    pool: &DbPool,
+    create_todo: CreateTodo,
+    tags: &[String],
+    checklist: &[String],
+) -> Result<Todo, Box<dyn std::error::Error + Send + Sync>> {
+    let mut conn = pool.get()?;
+    let tx = conn.transaction()?;
+    let id = insert_todo_with_extras(&tx, create_todo, tags, checklist)?;
+    let todo = get_todo_in(&tx, &id)?.ok_or("created todo not found")?;
+    tx.commit()?;
+    Ok(todo)
+}
+
+// Returns the id of the new todo; its checklist items start unchecked
+fn insert_todo_with_extras(
+    conn: &rusqlite::Connection,
+    create_todo: CreateTodo,
+    tags: &[String],
+    checklist: &[String],
+) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    let id = insert_todo(conn, create_todo)?.id;
+    tag_todos_in(conn, std::slice::from_ref(&id), tags, &[])?;
+    let mut stmt = conn.prepare(
+        "INSERT INTO checklist_items (id, todo_id, text, checked, position, created_at)
+         VALUES (?1, ?2, ?3, 0, ?4, ?5)",
+    )?;
+    for (position, text) in checklist.iter().enumerate() {
+        stmt.execute(rusqlite::params![
+            uuid::Uuid::new_v4().to_string(),
+            id,
+            text,
+            position as i64,
+            chrono::Utc::now().to_rfc3339(),
+        ])?;
+    }
+    Ok(id)
+}
+
+/// Copies a todo as a new open todo, with its tags, checklist (unchecked) and dependencies,
+/// and the same for its subtasks that are not archived. Dependencies between copied todos
+/// point at the copies. Returns `None` if the todo does not exist or is in the trash.
+pub fn duplicate_todo(
+    pool: &DbPool,
+    id: &str,
+) -> Result<Option<Todo>, Box<dyn std::error::Error + Send + Sync>> {
+    let mut conn = pool.get()?;
+    let tx = conn.transaction()?;
+    let Some(todo) = get_todo_in(&tx, id)? else {
+        return Ok(None);
+    };
+    let mut copies = std::collections::HashMap::new();
+    let copy_id = duplicate_tree(&tx, todo, None, &mut copies)?;
+    {
+        let mut blockers = tx.prepare(
+            "SELECT blocker_id FROM todo_dependencies d JOIN todos t ON t.id = d.blocker_id
+             WHERE d.blocked_id = ?1 AND t.deleted_at IS NULL",
+        )?;
+        let mut insert = tx.prepare(
+            "INSERT OR IGNORE INTO todo_dependencies (blocker_id, blocked_id, created_at)
+             VALUES (?1, ?2, ?3)",
+        )?;
+        for (original, copy) in &copies {
+            let ids = blockers
+                .query_map([original], |row| row.get::<_, String>(0))?
+                .collect::<Result<Vec<_>>>()?;
+            for blocker in ids {
+                let blocker = copies.get(&blocker).unwrap_or(&blocker);
+                insert.execute([blocker, copy, &now_timestamp()])?;
+            }
+        }
+    }
+    let copy = get_todo_in(&tx, &copy_id)?;
+    tx.commit()?;
+    Ok(copy)
+}
+
+// Copies `todo` under `parent_id` and then its subtasks, recording original -> copy ids
+fn duplicate_tree(
+    conn: &rusqlite::Connection,
+    todo: Todo,
+    parent_id: Option<String>,
+    copies: &mut std::collections::HashMap<String, String>,
+) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    // A reminder that already went off is not copied, or it would fire again right away
+    let remind_at = todo.remind_at.filter(|remind_at| *remind_at > Utc::now());
+    let create = CreateTodo {
+        title: todo.title,
+        description: todo.description,
+        metadata: Some(todo.metadata),
+        custom_fields: Some(todo.custom_fields),
+        due_date: todo.due_date.map(format_timestamp),
+        start_date: todo.start_date.map(format_timestamp),
+        remind_at: remind_at.map(format_timestamp),
+        estimate_minutes: todo.estimate_minutes,
+        priority: Some(todo.priority),
+        parent_id,
+        icon: todo.icon,
+        recurrence: todo.recurrence,
+        list_id: todo.list_id,
+        tags: None,
+    };
+    let checklist: Vec<String> = todo.checklist.into_iter().map(|item| item.text).collect();
+    let copy_id = insert_todo_with_extras(conn, create, &todo.tags, &checklist)?;
+    copies.insert(todo.id.clone(), copy_id.clone());
+
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {TODO_COLUMNS} FROM todos
+         WHERE parent_id = ?1 AND deleted_at IS NULL AND archived_at IS NULL ORDER BY seq"
+    ))?;
+    let children = stmt
+        .query_map([&todo.id], row_to_todo)?
+        .collect::<Result<Vec<_>>>()?;
+    for child in children {
+        duplicate_tree(conn, child, Some(copy_id.clone()), copies)?;
+    }
+    Ok(copy_id)
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct ExportSchedule {
+    pub id: String,
+    /// Also the start of the exported file names
+    pub name: String,
+    /// `ndjson`, `json` or `csv`
+    pub format: String,
+    /// Query parameters of `GET /api/todos` selecting the exported todos
+    pub filter: std::collections::HashMap<String, String>,
+    /// Only `local` so far: a file in the server's export directory
+    pub destination: String,
+    /// The export runs once a day, at this full hour in UTC
+    pub hour_utc: u8,
+    pub created_at: String,
+    pub last_run_at: Option<String>,
+    /// File name of the last successful export
+    pub last_file: Option<String>,
+    /// Why the last run failed; cleared by the next successful one
+    pub last_error: Option<String>,
+}
+
+const EXPORT_SCHEDULE_COLUMNS: &str =
+    "id, name, format, filter, destination, hour_utc, created_at, last_run_at, last_file, last_error";
+
+fn row_to_export_schedule(row: &rusqlite::Row) -> Result<ExportSchedule> {
+    let filter: String = row.get(3)?;
+    Ok(ExportSchedule {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        format: row.get(2)?,
+        filter: serde_json::from_str(&filter).unwrap_or_default(),
+        destination: row.get(4)?,
+        hour_utc: row.get(5)?,
+        created_at: row.get(6)?,
+        last_run_at: row.get(7)?,
+        last_file: row.get(8)?,
+        last_error: row.get(9)?,
+    })
+}
+
+pub fn get_export_schedules(
+    pool: &DbPool,
+) -> Result<Vec<ExportSchedule>, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {EXPORT_SCHEDULE_COLUMNS} FROM export_schedules ORDER BY name"
+    ))?;
+    let schedules = stmt.query_map([], row_to_export_schedule)?;
+
+    let mut result = Vec::new();
+    for schedule in schedules {
+        result.push(schedule?);
+    }
+    Ok(result)
+}
+
+pub fn get_export_schedule(
+    pool: &DbPool,
+    id: &str,
+) -> Result<Option<ExportSchedule>, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let schedule = conn
+        .query_row(
+            &format!("SELECT {EXPORT_SCHEDULE_COLUMNS} FROM export_schedules WHERE id = ?1"),
+            [id],
+            row_to_export_schedule,
+        )
+        .optional()?;
+    Ok(schedule)
+}
+
+/// Looks a schedule up by name, ignoring case.
+pub fn find_export_schedule_by_name(
+    pool: &DbPool,
+    name: &str,
+) -> Result<Option<ExportSchedule>, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let schedule = conn
+        .query_row(
+            &format!("SELECT {EXPORT_SCHEDULE_COLUMNS} FROM export_schedules WHERE name = ?1"),
+            [name],
+            row_to_export_schedule,
+        )
+        .optional()?;
+    Ok(schedule)
+}
+
+pub fn create_export_schedule(
+    pool: &DbPool,
+    name: &str,
+    format: &str,
+    filter: std::collections::HashMap<String, String>,
+    destination: &str,
+    hour_utc: u8,
+) -> Result<ExportSchedule, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let schedule = ExportSchedule {
+        id: uuid::Uuid::new_v4().to_string(),
+        name: name.to_string(),
+        format: format.to_string(),
+        filter,
+        destination: destination.to_string(),
+        hour_utc,
+        created_at: now_timestamp(),
+        last_run_at: None,
+        last_file: None,
+        last_error: None,
+    };
+    conn.execute(
+        "INSERT INTO export_schedules
+            (id, name, format, filter, destination, hour_utc, created_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        rusqlite::params![
+            schedule.id,
+            schedule.name,
+            schedule.format,
+            serde_json::to_string(&schedule.filter)?,
+            schedule.destination,
+            schedule.hour_utc,
+            schedule.created_at,
+        ],
+    )?;
+    Ok(schedule)
+}
+
+pub fn delete_export_schedule(
+    pool: &DbPool,
+    id: &str,
+) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let rows_affected = conn.execute("DELETE FROM export_schedules WHERE id = ?1", [id])?;
+    Ok(rows_affected > 0)
+}
+
+/// Records a run of a schedule: the written file, or why it failed.
+pub fn record_export_run(
+    pool: &DbPool,
+    id: &str,
+    outcome: std::result::Result<&str, &str>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let (file, error) = match outcome {
+        Ok(file) => (Some(file), None),
+        Err(error) => (None, Some(error)),
+    };
+    conn.execute(
+        "UPDATE export_schedules
+         SET last_run_at = ?1, last_file = COALESCE(?2, last_file), last_error = ?3
+         WHERE id = ?4",
+        rusqlite::params![now_timestamp(), file, error, id],
+    )?;
+    Ok(())
+}
+
+/// A threshold on a metric, checked every minute by the alert evaluator.
+#[derive(Debug, Serialize, Clone)]
+pub struct AlertRule {
+    pub id: String,
+    pub name: String,
+    /// `created`, `updated`, `deleted` or `error_rate`
+    pub metric: String,
+    /// The rule fires once the metric goes above this
+    pub threshold: f64,
+    /// The metric is taken over this many trailing minutes
+    pub window_minutes: u32,
+    pub created_at: String,
+    /// Whether the metric was above the threshold at the last check
+    pub firing: bool,
+    pub last_value: Option<f64>,
+    pub last_checked_at: Option<String>,
+    pub last_fired_at: Option<String>,
+}
+
+const ALERT_RULE_COLUMNS: &str = "id, name, metric, threshold, window_minutes, created_at, firing, last_value, last_checked_at, last_fired_at";
+
+fn row_to_alert_rule(row: &rusqlite::Row) -> Result<AlertRule> {
+    Ok(AlertRule {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        metric: row.get(2)?,
+        threshold: row.get(3)?,
+        window_minutes: row.get(4)?,
+        created_at: row.get(5)?,
+        firing: row.get(6)?,
+        last_value: row.get(7)?,
+        last_checked_at: row.get(8)?,
+        last_fired_at: row.get(9)?,
+    })
+}
+
+pub fn get_alert_rules(
+    pool: &DbPool,
+) -> Result<Vec<AlertRule>, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {ALERT_RULE_COLUMNS} FROM alert_rules ORDER BY name"
+    ))?;
+    let rules = stmt.query_map([], row_to_alert_rule)?;
+
+    let mut result = Vec::new();
+    for rule in rules {
+        result.push(rule?);
+    }
+    Ok(result)
+}
+
+/// Looks a rule up by name, ignoring case.
+pub fn find_alert_rule_by_name(
+    pool: &DbPool,
+    name: &str,
+) -> Result<Option<AlertRule>, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let rule = conn
+        .query_row(
+            &format!("SELECT {ALERT_RULE_COLUMNS} FROM alert_rules WHERE name = ?1"),
+            [name],
+            row_to_alert_rule,
+        )
+        .optional()?;
+    Ok(rule)
+}
+
+pub fn create_alert_rule(
+    pool: &DbPool,
+    name: &str,
+    metric: &str,
+    threshold: f64,
+    window_minutes: u32,
+) -> Result<AlertRule, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let rule = AlertRule {
+        id: uuid::Uuid::new_v4().to_string(),
+        name: name.to_string(),
+        metric: metric.to_string(),
+        threshold,
+        window_minutes,
+        created_at: now_timestamp(),
+        firing: false,
+        last_value: None,
+        last_checked_at: None,
+        last_fired_at: None,
+    };
+    conn.execute(
+        "INSERT INTO alert_rules (id, name, metric, threshold, window_minutes, created_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        rusqlite::params![
+            rule.id,
+            rule.name,
+            rule.metric,
+            rule.threshold,
+            rule.window_minutes,
+            rule.created_at,
+        ],
+    )?;
+    Ok(rule)
+}
+
+pub fn delete_alert_rule(
+    pool: &DbPool,
+    id: &str,
+) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let rows_affected = conn.execute("DELETE FROM alert_rules WHERE id = ?1", [id])?;
+    Ok(rows_affected > 0)
+}
+
+/// Stores the outcome of a check; `fired` means a notification went out for it.
+pub fn record_alert_check(
+    pool: &DbPool,
+    id: &str,
+    value: f64,
+    firing: bool,
+    fired: bool,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let now = now_timestamp();
+    conn.execute(
+        "UPDATE alert_rules
+         SET firing = ?1, last_value = ?2, last_checked_at = ?3,
+             last_fired_at = CASE WHEN ?4 THEN ?3 ELSE last_fired_at END
+         WHERE id = ?5",
+        rusqlite::params![firing, value, now, fired, id],
+    )?;
+    Ok(())
+}
+
+pub const INBOUND_HOOK_NAME_MAX_LEN: usize = 50;
+
+/// A URL that turns JSON posted by another service into todos.
+#[derive(Debug, Serialize, Clone)]
+pub struct InboundHook {
+    pub id: String,
+    pub name: String,
+    /// Secret part of `POST /api/inbound/:token`
+    pub token: String,
+    /// How a todo field is taken from the payload, by field
+    pub mapping: BTreeMap<String, String>,
+    /// List the todos are created in
+    pub list_id: Option<String>,
+    /// Where the payload says which alert it is about, in the same syntax as the mapping
+    pub dedupe_key: Option<String>,
+    /// Payloads with the same key within this many minutes of the last one update its todo
+    pub dedupe_window_minutes: u32,
+    pub created_at: String,
+    pub updated_at: String,
+    pub last_received_at: Option<String>,
+}
+
+/// Everything about an inbound hook that clients can set.
+#[derive(Debug, Clone)]
+pub struct InboundHookDefinition {
+    pub name: String,
+    pub mapping: BTreeMap<String, String>,
+    pub list_id: Option<String>,
+    pub dedupe_key: Option<String>,
+    pub dedupe_window_minutes: u32,
+}
+
+const INBOUND_HOOK_COLUMNS: &str = "id, name, token, mapping, list_id, dedupe_key, dedupe_window_minutes, created_at, updated_at, last_received_at";
+
+fn row_to_inbound_hook(row: &rusqlite::Row) -> Result<InboundHook> {
+    let mapping: String = row.get(3)?;
+    Ok(InboundHook {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        token: row.get(2)?,
+        mapping: serde_json::from_str(&mapping).unwrap_or_default(),
+        list_id: row.get(4)?,
+        dedupe_key: row.get(5)?,
+        dedupe_window_minutes: row.get(6)?,
+        created_at: row.get(7)?,
+        updated_at: row.get(8)?,
+        last_received_at: row.get(9)?,
+    })
+}
+
+pub fn normalize_inbound_hook_name(name: &str) -> Result<String, String> {
+    let name = name.trim();
+    if name.is_empty() || name.chars().count() > INBOUND_HOOK_NAME_MAX_LEN {
+        return Err(format!(
+            "Hook names must be 1-{INBOUND_HOOK_NAME_MAX_LEN} characters"
+        ));
+    }
+    Ok(name.to_string())
+}
+
+pub fn get_inbound_hooks(
+    pool: &DbPool,
+) -> Result<Vec<InboundHook>, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {INBOUND_HOOK_COLUMNS} FROM inbound_hooks ORDER BY name"
+    ))?;
+    let hooks = stmt.query_map([], row_to_inbound_hook)?;
+
+    let mut result = Vec::new();
+    for hook in hooks {
+        result.push(hook?);
+    }
+    Ok(result)
+}
+
+pub fn get_inbound_hook(
+    pool: &DbPool,
+    id: &str,
+) -> Result<Option<InboundHook>, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let hook = conn
+        .query_row(
+            &format!("SELECT {INBOUND_HOOK_COLUMNS} FROM inbound_hooks WHERE id = ?1"),
+            [id],
+            row_to_inbound_hook,
+        )
+        .optional()?;
+    Ok(hook)
+}
+
+pub fn find_inbound_hook_by_token(
+    pool: &DbPool,
+    token: &str,
+) -> Result<Option<InboundHook>, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let hook = conn
+        .query_row(
+            &format!("SELECT {INBOUND_HOOK_COLUMNS} FROM inbound_hooks WHERE token = ?1"),
+            [token],
+            row_to_inbound_hook,
+        )
+        .optional()?;
+    Ok(hook)
+}
+
+/// Looks a hook up by name, ignoring case.
+pub fn find_inbound_hook_by_name(
+    pool: &DbPool,
+    name: &str,
+) -> Result<Option<InboundHook>, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let hook = conn
+        .query_row(
+            &format!("SELECT {INBOUND_HOOK_COLUMNS} FROM inbound_hooks WHERE name = ?1"),
+            [name],
+            row_to_inbound_hook,
+        )
+        .optional()?;
+    Ok(hook)
+}
+
+pub fn create_inbound_hook(
+    pool: &DbPool,
+    definition: &InboundHookDefinition,
+) -> Result<InboundHook, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let id = uuid::Uuid::new_v4().to_string();
+    let now = now_timestamp();
+    conn.execute(
+        "INSERT INTO inbound_hooks (id, name, token, mapping, list_id, dedupe_key,
+             dedupe_window_minutes, created_at, updated_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?8)",
+        rusqlite::params![
+            id,
+            definition.name,
+            uuid::Uuid::new_v4().simple().to_string(),
+            serde_json::to_string(&definition.mapping)?,
+            definition.list_id,
+            definition.dedupe_key,
+            definition.dedupe_window_minutes,
+            now,
+        ],
+    )?;
+    get_inbound_hook(pool, &id)?.ok_or_else(|| "Created hook not found".into())
+}
+
+/// Replaces everything about a hook but its id and creation time; `rotate_token` also
+/// gives it a new URL.
+pub fn update_inbound_hook(
+    pool: &DbPool,
+    id: &str,
+    definition: &InboundHookDefinition,
+    rotate_token: bool,
+) -> Result<Option<InboundHook>, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let token = rotate_token.then(|| uuid::Uuid::new_v4().simple().to_string());
+    let rows_affected = conn.execute(
+        "UPDATE inbound_hooks SET name = ?2, mapping = ?3, list_id = ?4, dedupe_key = ?5,
+             dedupe_window_minutes = ?6, token = COALESCE(?7, token), updated_at = ?8
+         WHERE id = ?1",
+        rusqlite::params![
+            id,
+            definition.name,
+            serde_json::to_string(&definition.mapping)?,
+            definition.list_id,
+            definition.dedupe_key,
+            definition.dedupe_window_minutes,
+            token,
+            now_timestamp(),
+        ],
+    )?;
+    if rows_affected == 0 {
+        return Ok(None);
+    }
+    get_inbound_hook(pool, id)
+}
+
+pub fn delete_inbound_hook(
+    pool: &DbPool,
+    id: &str,
+) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let rows_affected = conn.execute("DELETE FROM inbound_hooks WHERE id = ?1", [id])?;
+    Ok(rows_affected > 0)
+}
+
+pub fn record_inbound_delivery(
+    pool: &DbPool,
+    id: &str,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    conn.execute(
+        "UPDATE inbound_hooks SET last_received_at = ?1 WHERE id = ?2",
+        rusqlite::params![now_timestamp(), id],
+    )?;
+    Ok(())
+}
+
+/// The built-in list that cannot be deleted
+pub const INBOX_LIST_ID: &str = "inbox";
+pub const LIST_NAME_MAX_LEN: usize = 50;
+
+#[derive(Debug, Serialize, Clone)]
+pub struct List {
+    pub id: String,
+    pub name: String,
+    /// An allowlisted emoji or icon name
+    pub icon: Option<String>,
+    /// Name of a palette color
+    pub color: Option<String>,
+    pub created_at: String,
+    pub todo_count: i64,
+    pub open_count: i64,
+    /// Completed todos are archived this many days after completion
+    pub archive_completed_after_days: Option<u32>,
+    /// Deleted todos are purged from the trash after this many days
+    pub purge_trash_after_days: Option<u32>,
+    /// Completed todos wait for approval in `GET /api/reviews/pending`
+    pub require_review: bool,
+    pub defaults: ListDefaults,
+}
+
+/// Applied to todos created in a list, unless the request sets the field itself.
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct ListDefaults {
+    pub priority: Option<Priority>,
+    pub tags: Vec<String>,
+    /// A todo created with a due date but no reminder is reminded this long before it
+    pub remind_before_minutes: Option<u32>,
+}
+
+const LIST_COLUMNS: &str = "id, name, icon, color, created_at,
+    (SELECT COUNT(*) FROM todos WHERE list_id = lists.id AND deleted_at IS NULL),
+    (SELECT COUNT(*) FROM todos
+     WHERE list_id = lists.id AND NOT completed AND deleted_at IS NULL),
+    archive_completed_after_days, purge_trash_after_days, require_review,
+    default_priority, default_tags, default_remind_before_minutes";
+
+fn row_to_list(row: &rusqlite::Row) -> Result<List> {
+    Ok(List {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        icon: row.get(2)?,
+        color: row.get(3)?,
+        created_at: row.get(4)?,
+        todo_count: row.get(5)?,
+        open_count: row.get(6)?,
+        archive_completed_after_days: row.get(7)?,
+        purge_trash_after_days: row.get(8)?,
+        require_review: row.get(9)?,
+        defaults: ListDefaults {
+            priority: row.get::<_, Option<i64>>(10)?.map(Priority::from_level),
+            tags: serde_json::from_str(&row.get::<_, String>(11)?).unwrap_or_default(),
+            remind_before_minutes: row.get(12)?,
+        },
+    })
+}
+
+pub fn normalize_list_name(name: &str) -> Result<String, String> {
+    let name = name.trim();
+    if name.is_empty() || name.chars().count() > LIST_NAME_MAX_LEN {
+        return Err(format!(
+            "List names must be 1-{LIST_NAME_MAX_LEN} characters"
+        ));
+    }
+    Ok(name.to_string())
+}
+
+/// All lists, the Inbox first and the rest in creation order.
+pub fn get_lists(pool: &DbPool) -> Result<Vec<List>, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {LIST_COLUMNS} FROM lists ORDER BY id != ?1, created_at, rowid"
+    ))?;
+    let lists = stmt.query_map([INBOX_LIST_ID], row_to_list)?;
+
+    let mut result = Vec::new();
+    for list in lists {
+        result.push(list?);
+    }
+    Ok(result)
+}
+
+pub fn get_list(
+    pool: &DbPool,
+    id: &str,
+) -> Result<Option<List>, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let mut stmt = conn.prepare(&format!("SELECT {LIST_COLUMNS} FROM lists WHERE id = ?1"))?;
+    let mut lists = stmt.query_map([id], row_to_list)?;
+    match lists.next() {
+        Some(list) => Ok(Some(list?)),
+        None => Ok(None),
+    }
+}
+
+/// Looks a list up by name; names compare case-insensitively.
+pub fn find_list_by_name(
+    pool: &DbPool,
+    name: &str,
+) -> Result<Option<List>, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let mut stmt = conn.prepare(&format!("SELECT {LIST_COLUMNS} FROM lists WHERE name = ?1"))?;
+    let mut lists = stmt.query_map([name], row_to_list)?;
+    match lists.next() {
+        Some(list) => Ok(Some(list?)),
+        None => Ok(None),
+    }
+}
+
+pub fn create_list(
+    pool: &DbPool,
+    name: &str,
+    icon: Option<&str>,
+    color: Option<&str>,
+) -> Result<List, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let list = List {
+        id: uuid::Uuid::new_v4().to_string(),
+        name: name.to_string(),
+        icon: icon.map(str::to_string),
+        color: color.map(str::to_string),
+        created_at: chrono::Utc::now().to_rfc3339(),
+        todo_count: 0,
+        open_count: 0,
+        archive_completed_after_days: None,
+        purge_trash_after_days: None,
+        require_review: false,
+        defaults: ListDefaults::default(),
+    };
+    conn.execute(
+        "INSERT INTO lists (id, name, icon, color, created_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+        rusqlite::params![list.id, list.name, list.icon, list.color, list.created_at],
+    )?;
+    Ok(list)
+}
+
+/// Updates the given fields; an empty `icon` or `color` removes it.
+pub fn update_list(
+    pool: &DbPool,
+    id: &str,
+    name: Option<&str>,
+    icon: Option<&str>,
+    color: Option<&str>,
+) -> Result<Option<List>, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    if let Some(name) = name {
+        conn.execute("UPDATE lists SET name = ?1 WHERE id = ?2", [name, id])?;
+    }
+    if let Some(icon) = icon {
+        conn.execute(
+            "UPDATE lists SET icon = NULLIF(?1, '') WHERE id = ?2",
+            [icon, id],
+        )?;
+    }
+    if let Some(color) = color {
+        conn.execute(
+            "UPDATE lists SET color = NULLIF(?1, '') WHERE id = ?2",
+            [color, id],
+        )?;
+    }
+    get_list(pool, id)
+}
+
+/// Sets a list's retention policies; `Some(None)` turns a policy off.
+pub fn set_list_retention(
+    pool: &DbPool,
+    id: &str,
+    archive_completed_after_days: Option<Option<u32>>,
+    purge_trash_after_days: Option<Option<u32>>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    if let Some(days) = archive_completed_after_days {
+        conn.execute(
+            "UPDATE lists SET archive_completed_after_days = ?1 WHERE id = ?2",
+            rusqlite::params![days, id],
+        )?;
+    }
+    if let Some(days) = purge_trash_after_days {
+        conn.execute(
+            "UPDATE lists SET purge_trash_after_days = ?1 WHERE id = ?2",
+            rusqlite::params![days, id],
+        )?;
+    }
+    Ok(())
+}
+
+pub fn set_list_require_review(
+    pool: &DbPool,
+    id: &str,
+    require_review: bool,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    conn.execute(
+        "UPDATE lists SET require_review = ?1 WHERE id = ?2",
+        rusqlite::params![require_review, id],
+    )?;
+    Ok(())
+}
+
+/// Changes the given defaults of a list; `Some(None)` removes one. Returns the list, or `None`
+/// if it does not exist.
+pub fn set_list_defaults(
+    pool: &DbPool,
+    id: &str,
+    priority: Option<Option<Priority>>,
+    tags: Option<&[String]>,
+    remind_before_minutes: Option<Option<u32>>,
+) -> Result<Option<List>, Box<dyn std::error::Error + Send + Sync>> {
+    let mut conn = pool.get()?;
+    let tx = conn.transaction()?;
+    if let Some(priority) = priority {
+        tx.execute(
+            "UPDATE lists SET default_priority = ?1 WHERE id = ?2",
+            rusqlite::params![priority.map(|priority| priority as i64), id],
+        )?;
+    }
+    if let Some(tags) = tags {
+        tx.execute(
+            "UPDATE lists SET default_tags = ?1 WHERE id = ?2",
+            rusqlite::params![serde_json::to_string(tags)?, id],
+        )?;
+    }
+    if let Some(minutes) = remind_before_minutes {
+        tx.execute(
+            "UPDATE lists SET default_remind_before_minutes = ?1 WHERE id = ?2",
+            rusqlite::params![minutes, id],
+        )?;
+    }
+    let list = tx
+        .query_row(
+            &format!("SELECT {LIST_COLUMNS} FROM lists WHERE id = ?1"),
+            [id],
+            row_to_list,
+        )
+        .optional()?;
+    tx.commit()?;
+    Ok(list)
+}
+
+/// Archives completed todos whose list's `archive_completed_after_days` have passed.
+/// Todos completed before completion times were tracked count from their creation.
+pub fn archive_expired_todos(
+    pool: &DbPool,
+) -> Result<usize, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let rows_affected = conn.execute(
+        "UPDATE todos SET archived_at = ?1
+         WHERE completed AND archived_at IS NULL AND deleted_at IS NULL
+           AND review_status IS NOT 'pending'
+           AND julianday(?1) - julianday(COALESCE(completed_at, created_at)) >=
+               (SELECT archive_completed_after_days FROM lists WHERE lists.id = todos.list_id)",
+        [now_timestamp()],
+    )?;
+    Ok(rows_affected)
+}
+
+/// Deletes a list after moving its todos to the Inbox, or to the trash.
+/// Returns how many todos were moved or deleted, or `None` if the list does not exist.
+pub fn delete_list(
+    pool: &DbPool,
+    id: &str,
+    delete_todos: bool,
+) -> Result<Option<usize>, Box<dyn std::error::Error + Send + Sync>> {
+    let mut conn = pool.get()?;
+    let tx = conn.transaction()?;
+    let affected = if delete_todos {
+        let deleted_at = deletion_timestamp();
+        let trashed = tx.execute(
+            "UPDATE todos SET deleted_at = ?1 WHERE list_id = ?2 AND deleted_at IS NULL",
+            [&deleted_at, id],
+        )?;
+        trash_descendants(&tx, &deleted_at)?;
+        trashed
+    } else {
+        tx.query_row(
+            "SELECT COUNT(*) FROM todos WHERE list_id = ?1 AND deleted_at IS NULL",
+            [id],
+            |row| row.get(0),
+        )?
+    };
+    // Trashed todos move too, so they can be restored and follow the Inbox's retention
+    tx.execute(
+        "UPDATE todos SET list_id = ?1 WHERE list_id = ?2",
+        [INBOX_LIST_ID, id],
+    )?;
+    if tx.execute("DELETE FROM lists WHERE id = ?1", [id])? == 0 {
+        return Ok(None);
+    }
+    tx.commit()?;
+    Ok(Some(affected))
+}
+
+/// Raises or reports the open todos of a list that are overdue by `after_hours`.
+#[derive(Debug, Serialize, Clone)]
+pub struct EscalationRule {
+    pub id: String,
+    pub list_id: String,
+    pub after_hours: u32,
+    /// Todos below this priority are raised to it
+    pub priority: Option<Priority>,
+    /// Whether the notifier is told
+    pub notify: bool,
+    pub created_at: String,
+}
+
+/// A todo a rule escalated, kept for the todo's history.
+#[derive(Debug, Serialize, Clone)]
+pub struct TodoEscalation {
+    pub id: i64,
+    pub rule_id: String,
+    pub after_hours: u32,
+    /// The due date the todo was overdue against
+    pub due_date: String,
+    /// The priority the todo was raised to, if it was
+    pub priority: Option<Priority>,
+    pub notified: bool,
+    pub escalated_at: String,
+}
+
+/// An open todo that a rule has yet to escalate.
+#[derive(Debug)]
+pub struct PendingEscalation {
+    pub rule: EscalationRule,
+    pub todo_id: String,
+    pub due_date: String,
+}
+
+const ESCALATION_RULE_COLUMNS: &str = "id, list_id, after_hours, priority, notify, created_at";
+
+fn row_to_escalation_rule(row: &rusqlite::Row) -> Result<EscalationRule> {
+    let priority: Option<i64> = row.get(3)?;
+    Ok(EscalationRule {
+        id: row.get(0)?,
+        list_id: row.get(1)?,
+        after_hours: row.get(2)?,
+        priority: priority.map(Priority::from_level),
+        notify: row.get(4)?,
+        created_at: row.get(5)?,
+    })
+}
+
+/// A list's rules, soonest to fire first.
+pub fn get_escalation_rules(
+    pool: &DbPool,
+    list_id: &str,
+) -> Result<Vec<EscalationRule>, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {ESCALATION_RULE_COLUMNS} FROM escalation_rules
+         WHERE list_id = ?1 ORDER BY after_hours, created_at"
+    ))?;
+    let rules = stmt.query_map([list_id], row_to_escalation_rule)?;
+
+    let mut result = Vec::new();
+    for rule in rules {
+        result.push(rule?);
+    }
+    Ok(result)
+}
+
+pub fn create_escalation_rule(
+    pool: &DbPool,
+    list_id: &str,
+    after_hours: u32,
+    priority: Option<Priority>,
+    notify: bool,
+) -> Result<EscalationRule, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let rule = EscalationRule {
+        id: uuid::Uuid::new_v4().to_string(),
+        list_id: list_id.to_string(),
+        after_hours,
+        priority,
+        notify,
+        created_at: now_timestamp(),
+    };
+    conn.execute(
+        "INSERT INTO escalation_rules (id, list_id, after_hours, priority, notify, created_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        rusqlite::params![
+            rule.id,
+            rule.list_id,
+            rule.after_hours,
+            rule.priority.map(|priority| priority as i64),
+            rule.notify,
+            rule.created_at,
+        ],
+    )?;
+    Ok(rule)
+}
+
+pub fn delete_escalation_rule(
+    pool: &DbPool,
+    list_id: &str,
+    id: &str,
+) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let rows_affected = conn.execute(
+        "DELETE FROM escalation_rules WHERE id = ?1 AND list_id = ?2",
+        [id, list_id],
+    )?;
+    Ok(rows_affected > 0)
+}
+
+/// Open todos overdue by at least a rule of their list that the rule has not escalated for
+/// their current due date. Moving the due date out arms the rule again.
+pub fn get_pending_escalations(
+    pool: &DbPool,
+) -> Result<Vec<PendingEscalation>, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let mut stmt = conn.prepare(
+        "SELECT r.id, r.list_id, r.after_hours, r.priority, r.notify, r.created_at,
+                t.id, t.due_date
+         FROM escalation_rules r
+         JOIN todos t ON t.list_id = r.list_id
+         WHERE NOT t.completed AND t.deleted_at IS NULL AND t.archived_at IS NULL
+           AND t.due_date IS NOT NULL
+           AND datetime(t.due_date, '+' || r.after_hours || ' hours') <= datetime(?1)
+           AND NOT EXISTS (
+               SELECT 1 FROM todo_escalations e
+               WHERE e.rule_id = r.id AND e.todo_id = t.id AND e.due_date = t.due_date
+           )
+         ORDER BY t.due_date, r.after_hours",
+    )?;
+    let pending = stmt.query_map([now_timestamp()], |row| {
+        Ok(PendingEscalation {
+            rule: row_to_escalation_rule(row)?,
+            todo_id: row.get(6)?,
+            due_date: row.get(7)?,
+        })
+    })?;
+
+    let mut result = Vec::new();
+    for escalation in pending {
+        result.push(escalation?);
+    }
+    Ok(result)
+}
+
+/// Marks a todo as escalated by a rule for the due date it was overdue against.
+pub fn record_escalation(
+    pool: &DbPool,
+    escalation: &PendingEscalation,
+    priority: Option<Priority>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    conn.execute(
+        "INSERT OR IGNORE INTO todo_escalations
+             (todo_id, rule_id, after_hours, due_date, priority, notified, escalated_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        rusqlite::params![
+            escalation.todo_id,
+            escalation.rule.id,
+            escalation.rule.after_hours,
+            escalation.due_date,
+            priority.map(|priority| priority as i64),
+            escalation.rule.notify,
+            now_timestamp(),
+        ],
+    )?;
+    Ok(())
+}
+
+/// A todo's escalations, newest first.
+pub fn get_todo_escalations(
+    pool: &DbPool,
+    todo_id: &str,
+    limit: u32,
+) -> Result<Vec<TodoEscalation>, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let mut stmt = conn.prepare(
+        "SELECT id, rule_id, after_hours, due_date, priority, notified, escalated_at
+         FROM todo_escalations WHERE todo_id = ?1 ORDER BY id DESC LIMIT ?2",
+    )?;
+    let escalations = stmt.query_map(rusqlite::params![todo_id, limit], |row| {
+        let priority: Option<i64> = row.get(4)?;
+        Ok(TodoEscalation {
+            id: row.get(0)?,
+            rule_id: row.get(1)?,
+            after_hours: row.get(2)?,
+            due_date: row.get(3)?,
+            priority: priority.map(Priority::from_level),
+            notified: row.get(5)?,
+            escalated_at: row.get(6)?,
+        })
+    })?;
+
+    let mut result = Vec::new();
+    for escalation in escalations {
+        result.push(escalation?);
+    }
+    Ok(result)
+}
+
+/// What an `Idempotency-Key` has been used for before
+pub enum IdempotencyClaim {
+    /// First use; the caller now owns the key and must complete or release it
+    New,
+    /// A finished request with the same body: its status and response
+    Replay(u16, String),
+    /// The first request with this key is still running
+    InProgress,
+    /// The key was used with a different request
+    Mismatch,
+}
+
+/// Claims `key` for a request with `request_hash`. Keys older than `ttl_hours` are forgotten,
+/// and so are claims whose request has not finished within `abandon_after_seconds`, e.g.
+/// because the server stopped while running it.
+pub fn claim_idempotency_key(
+    pool: &DbPool,
+    key: &str,
+    request_hash: &str,
+    ttl_hours: u32,
+    abandon_after_seconds: u32,
+) -> Result<IdempotencyClaim, Box<dyn std::error::Error + Send + Sync>> {
+    let mut conn = pool.get()?;
+    let tx = conn.transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)?;
+    let now = now_timestamp();
+    tx.execute(
+        "DELETE FROM idempotency_keys WHERE key = ?1
+           AND ((julianday(?2) - julianday(created_at)) * 24 >= ?3
+             OR (status IS NULL AND (julianday(?2) - julianday(created_at)) * 86400 >= ?4))",
+        rusqlite::params![key, now, ttl_hours, abandon_after_seconds],
+    )?;
+    let existing = tx
+        .query_row(
+            "SELECT request_hash, status, response FROM idempotency_keys WHERE key = ?1",
+            [key],
+            |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, Option<u16>>(1)?,
+                    row.get::<_, Option<String>>(2)?,
+                ))
+            },
+        )
+        .optional()?;
+    let claim = match existing {
+        None => {
+            tx.execute(
+                "INSERT INTO idempotency_keys (key, request_hash, created_at) VALUES (?1, ?2, ?3)",
+                rusqlite::params![key, request_hash, now],
+            )?;
+            IdempotencyClaim::New
+        }
+        Some((hash, _, _)) if hash != request_hash => IdempotencyClaim::Mismatch,
+        Some((_, Some(status), Some(response))) => IdempotencyClaim::Replay(status, response),
+        Some(_) => IdempotencyClaim::InProgress,
+    };
+    tx.commit()?;
+    Ok(claim)
+}
+
+/// Stores the response of the request that claimed `key`, to be replayed on retries.
+pub fn complete_idempotency_key(
+    pool: &DbPool,
+    key: &str,
+    status: u16,
+    response: &str,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    conn.execute(
+        "UPDATE idempotency_keys SET status = ?2, response = ?3 WHERE key = ?1",
+        rusqlite::params![key, status, response],
+    )?;
+    Ok(())
+}
+
+/// Gives up a claim whose request failed, so a retry runs it again.
+pub fn release_idempotency_key(
+    pool: &DbPool,
+    key: &str,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    conn.execute(
+        "DELETE FROM idempotency_keys WHERE key = ?1 AND status IS NULL",
+        [key],
+    )?;
+    Ok(())
+}
+
+/// Forgets keys older than `hours`.
+pub fn prune_idempotency_keys(
+    pool: &DbPool,
+    hours: u32,
+) -> Result<usize, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let rows_affected = conn.execute(
+        "DELETE FROM idempotency_keys WHERE (julianday(?1) - julianday(created_at)) * 24 >= ?2",
+        rusqlite::params![now_timestamp(), hours],
+    )?;
+    Ok(rows_affected)
+}
+
+// Longest accepted event consumer name, in characters
+pub const CONSUMER_NAME_MAX_LEN: usize = 50;
+
+#[derive(Debug, Serialize, Clone)]
+pub struct Event {
+    pub seq: i64,
+    /// `todo.created`, `todo.updated`, `todo.deleted` (moved to the trash), `todo.restored`,
+    /// `todo.purged`, `todo.locked`, `todo.unlocked` or `todo.unblocked` (its last open
+    /// blocker was completed)
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub todo_id: String,
+    pub created_at: String,
+    pub list_id: Option<String>,
+    /// The list the todo was moved out of, if this change moved it
+    pub previous_list_id: Option<String>,
+    /// For an update that changed fields kept in the todo's history, the old and new value of
+    /// each, like a revision
+    pub changes: Option<Map<String, Value>>,
+}
+
+fn row_to_event(row: &rusqlite::Row) -> Result<Event> {
+    Ok(Event {
+        seq: row.get(0)?,
+        kind: row.get(1)?,
+        todo_id: row.get(2)?,
+        created_at: row.get(3)?,
+        list_id: row.get(4)?,
+        previous_list_id: row.get(5)?,
+        changes: row
+            .get::<_, Option<String>>(6)?
+            .and_then(|changes| serde_json::from_str(&changes).ok()),
+    })
+}
+
+/// Consumer names go into URLs and logs, so they are kept to `[A-Za-z0-9._-]`.
+pub fn normalize_consumer_name(name: &str) -> Result<String, String> {
+    let name = name.trim();
+    let valid_chars = name
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'));
+    if name.is_empty() || name.len() > CONSUMER_NAME_MAX_LEN || !valid_chars {
+        return Err(format!(
+            "Consumer names must be 1-{CONSUMER_NAME_MAX_LEN} letters, digits, '.', '_' or '-'"
+        ));
+    }
+    Ok(name.to_string())
+}
+
+/// Events after `after`, oldest first.
+pub fn get_events(
+    pool: &DbPool,
+    after: i64,
+    limit: u32,
+) -> Result<Vec<Event>, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let mut stmt = conn.prepare(
+        "SELECT seq, type, todo_id, created_at, list_id, previous_list_id, changes FROM events
+         WHERE seq > ?1 ORDER BY seq LIMIT ?2",
+    )?;
+    let events = stmt.query_map(rusqlite::params![after, limit], row_to_event)?;
+
+    let mut result = Vec::new();
+    for event in events {
+        result.push(event?);
+    }
+    Ok(result)
+}
+
+/// How many events of a type were recorded in the last `minutes`.
+pub fn count_recent_events(
+    pool: &DbPool,
+    kind: &str,
+    minutes: u32,
+) -> Result<i64, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let since = format_timestamp(Utc::now() - chrono::Duration::minutes(i64::from(minutes)));
+    let count = conn.query_row(
+        "SELECT COUNT(*) FROM events WHERE type = ?1 AND created_at > ?2",
+        rusqlite::params![kind, since],
+        |row| row.get(0),
+    )?;
+    Ok(count)
+}
+
+/// The lowest sequence number still stored and the highest ever handed out, both 0 when
+/// nothing was recorded yet.
+pub fn get_event_bounds(
+    pool: &DbPool,
+) -> Result<(i64, i64), Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let oldest: i64 = conn.query_row("SELECT COALESCE(MIN(seq), 0) FROM events", [], |row| {
+        row.get(0)
+    })?;
+    let latest: i64 = conn.query_row(
+        "SELECT COALESCE((SELECT seq FROM sqlite_sequence WHERE name = 'events'), 0)",
+        [],
+        |row| row.get(0),
+    )?;
+    Ok((oldest, latest))
+}
+
+/// Records that `consumer` has processed every event up to `seq` and returns its cursor.
+/// Cursors never move backwards, so replaying an old request is harmless.
+pub fn ack_events(
+    pool: &DbPool,
+    consumer: &str,
+    seq: Option<i64>,
+) -> Result<i64, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let acked = conn.query_row(
+        "INSERT INTO event_consumers (name, acked_seq, updated_at) VALUES (?1, COALESCE(?2, 0), ?3)
+         ON CONFLICT(name) DO UPDATE SET
+             acked_seq = MAX(acked_seq, excluded.acked_seq),
+             updated_at = excluded.updated_at
+         RETURNING acked_seq",
+        rusqlite::params![consumer, seq, now_timestamp()],
+        |row| row.get(0),
+    )?;
+    Ok(acked)
+}
+
+/// Deletes events older than `days`. Consumers that were away longer have to resync.
+pub fn prune_events(
+    pool: &DbPool,
+    days: u32,
+) -> Result<usize, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let rows_affected = conn.execute(
+        "DELETE FROM events WHERE julianday(?1) - julianday(created_at) >= ?2",
+        rusqlite::params![now_timestamp(), days],
+    )?;
+    Ok(rows_affected)
+}
+
+/// A list's todo counts at the end of a day (UTC), or as of `taken_at` for today.
+#[derive(Debug, Serialize, Clone)]
+pub struct ListSnapshot {
+    /// `YYYY-MM-DD`
+    pub date: String,
+    pub open_count: i64,
+    /// Completed todos, including archived ones
+    pub closed_count: i64,
+    pub taken_at: String,
+}
+
+/// Records the current counts of every list as its snapshot for `date`, replacing an
+/// earlier one from the same day. Returns how many lists were recorded.
+pub fn snapshot_lists(
+    pool: &DbPool,
+    date: &str,
+) -> Result<usize, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let rows_affected = conn.execute(
+        "INSERT INTO list_snapshots (list_id, date, open_count, closed_count, taken_at)
+         SELECT l.id, ?1,
+                (SELECT COUNT(*) FROM todos
+                 WHERE list_id = l.id AND NOT completed AND deleted_at IS NULL),
+                (SELECT COUNT(*) FROM todos
+                 WHERE list_id = l.id AND completed AND deleted_at IS NULL),
+                ?2
+         FROM lists l
+         WHERE true -- SQLite needs a WHERE before ON CONFLICT in INSERT ... SELECT
+         ON CONFLICT (list_id, date) DO UPDATE SET
+            open_count = excluded.open_count,
+            closed_count = excluded.closed_count,
+            taken_at = excluded.taken_at",
+        [date, &now_timestamp()],
+    )?;
+    Ok(rows_affected)
+}
+
+/// Snapshots of a list between two `YYYY-MM-DD` dates, both inclusive, oldest first.
+pub fn get_list_snapshots(
+    pool: &DbPool,
+    list_id: &str,
+    from: &str,
+    to: &str,
+) -> Result<Vec<ListSnapshot>, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let mut stmt = conn.prepare(
+        "SELECT date, open_count, closed_count, taken_at FROM list_snapshots
+         WHERE list_id = ?1 AND date >= ?2 AND date <= ?3
+         ORDER BY date",
+    )?;
+    let snapshots = stmt.query_map([list_id, from, to], |row| {
+        Ok(ListSnapshot {
+            date: row.get(0)?,
+            open_count: row.get(1)?,
+            closed_count: row.get(2)?,
+            taken_at: row.get(3)?,
+        })
+    })?;
+
+    let mut result = Vec::new();
+    for snapshot in snapshots {
+        result.push(snapshot?);
+    }
+    Ok(result)
+}
+
+/// A parsed CSV file waiting for its column mapping before it is imported.
+#[derive(Debug, Clone)]
+pub struct ImportUpload {
+    pub id: String,
+    /// Header row
+    pub columns: Vec<String>,
+    /// Data rows, padded or cut to the number of columns
+    pub rows: Vec<Vec<String>>,
+    pub created_at: String,
+}
+
+pub fn create_import_upload(
+    pool: &DbPool,
+    columns: Vec<String>,
+    rows: Vec<Vec<String>>,
+) -> Result<ImportUpload, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let upload = ImportUpload {
+        id: uuid::Uuid::new_v4().to_string(),
+        columns,
+        rows,
+        created_at: now_timestamp(),
+    };
+    conn.execute(
+        "INSERT INTO import_uploads (id, columns, rows, created_at) VALUES (?1, ?2, ?3, ?4)",
+        rusqlite::params![
+            upload.id,
+            serde_json::to_string(&upload.columns)?,
+            serde_json::to_string(&upload.rows)?,
+            upload.created_at,
+        ],
+    )?;
+    Ok(upload)
+}
+
+// Runs a statement yielding an upload's columns, rows and creation time
+fn query_import_upload(
+    pool: &DbPool,
+    sql: &str,
+    id: &str,
+) -> Result<Option<ImportUpload>, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let row = conn
+        .query_row(sql, [id], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+            ))
+        })
+        .optional()?;
+    let Some((columns, rows, created_at)) = row else {
+        return Ok(None);
+    };
+    Ok(Some(ImportUpload {
+        id: id.to_string(),
+        columns: serde_json::from_str(&columns)?,
+        rows: serde_json::from_str(&rows)?,
+        created_at,
+    }))
+}
+
+pub fn get_import_upload(
+    pool: &DbPool,
+    id: &str,
+) -> Result<Option<ImportUpload>, Box<dyn std::error::Error + Send + Sync>> {
+    query_import_upload(
+        pool,
+        "SELECT columns, rows, created_at FROM import_uploads WHERE id = ?1",
+        id,
+    )
+}
+
+/// Removes an upload and returns it, so only one caller gets to import it.
+pub fn take_import_upload(
+    pool: &DbPool,
+    id: &str,
+) -> Result<Option<ImportUpload>, Box<dyn std::error::Error + Send + Sync>> {
+    query_import_upload(
+        pool,
+        "DELETE FROM import_uploads WHERE id = ?1 RETURNING columns, rows, created_at",
+        id,
+    )
+}
+
+pub fn delete_import_upload(
+    pool: &DbPool,
+    id: &str,
+) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let rows_affected = conn.execute("DELETE FROM import_uploads WHERE id = ?1", [id])?;
+    Ok(rows_affected > 0)
+}
+
+/// Drops uploads that were never imported.
+pub fn prune_import_uploads(
+    pool: &DbPool,
+    hours: u32,
+) -> Result<usize, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let rows_affected = conn.execute(
+        "DELETE FROM import_uploads WHERE (julianday(?1) - julianday(created_at)) * 24 >= ?2",
+        rusqlite::params![now_timestamp(), hours],
+    )?;
+    Ok(rows_affected)
+}
+
+/// Who made a change, as far as the server can tell. There are no accounts, so `actor` is
+/// whatever the client sent in `X-Actor`.
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct ActivityActor {
+    pub actor: Option<String>,
+    pub client_ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub request_id: Option<String>,
+}
+
+/// One entry of the activity log.
+#[derive(Debug, Serialize, Clone)]
+pub struct Activity {
+    pub id: i64,
+    /// `create`, `update`, `complete`, `delete` or `bulk_delete`
+    pub action: String,
+    /// Not set for bulk actions
+    pub todo_id: Option<String>,
+    #[serde(flatten)]
+    pub actor: ActivityActor,
+    /// The todo after the change (before it, for deletes)
+    pub payload: Value,
+    /// For updates, the old and new value of each field kept in the todo's history that changed
+    pub changes: Option<Map<String, Value>>,
+    pub created_at: String,
+}
+
+pub fn record_activity(
+    pool: &DbPool,
+    action: &str,
+    todo_id: Option<&str>,
+    actor: &ActivityActor,
+    payload: &Value,
+    changes: Option<&Map<String, Value>>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let changes = changes.map(serde_json::to_string).transpose()?;
+    conn.execute(
+        "INSERT INTO activity
+         (action, todo_id, actor, client_ip, user_agent, request_id, payload, changes, created_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+        rusqlite::params![
+            action,
+            todo_id,
+            actor.actor,
+            actor.client_ip,
+            actor.user_agent,
+            actor.request_id,
+            payload.to_string(),
+            changes,
+            now_timestamp()
+        ],
+    )?;
+    Ok(())
+}
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct Todo {
+#[derive(Debug, Default)]
+pub struct ActivityFilter {
+    /// Only entries with a higher id
+    pub after: i64,
+    /// Only entries at or after this time
+    pub since: Option<String>,
+    pub todo_id: Option<String>,
+    pub action: Option<String>,
+}
+
+/// Activity matching the filter, oldest first.
+pub fn get_activity(
+    pool: &DbPool,
+    filter: &ActivityFilter,
+    limit: u32,
+) -> Result<Vec<Activity>, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let mut stmt = conn.prepare(
+        "SELECT id, action, todo_id, actor, client_ip, user_agent, request_id, payload, created_at,
+                changes
+         FROM activity
+         WHERE id > ?1
+           AND (?2 IS NULL OR julianday(created_at) >= julianday(?2))
+           AND (?3 IS NULL OR todo_id = ?3)
+           AND (?4 IS NULL OR action = ?4)
+         ORDER BY id LIMIT ?5",
+    )?;
+    let entries = stmt.query_map(
+        rusqlite::params![
+            filter.after,
+            filter.since,
+            filter.todo_id,
+            filter.action,
+            limit
+        ],
+        |row| {
+            let payload: String = row.get(7)?;
+            Ok(Activity {
+                id: row.get(0)?,
+                action: row.get(1)?,
+                todo_id: row.get(2)?,
+                actor: ActivityActor {
+                    actor: row.get(3)?,
+                    client_ip: row.get(4)?,
+                    user_agent: row.get(5)?,
+                    request_id: row.get(6)?,
+                },
+                payload: serde_json::from_str(&payload).unwrap_or(Value::Null),
+                changes: row
+                    .get::<_, Option<String>>(9)?
+                    .and_then(|changes| serde_json::from_str(&changes).ok()),
+                created_at: row.get(8)?,
+            })
+        },
+    )?;
+
+    let mut result = Vec::new();
+    for entry in entries {
+        result.push(entry?);
+    }
+    Ok(result)
+}
+
+/// Deletes activity older than `days`.
+pub fn prune_activity(
+    pool: &DbPool,
+    days: u32,
+) -> Result<usize, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let rows_affected = conn.execute(
+        "DELETE FROM activity WHERE julianday(?1) - julianday(created_at) >= ?2",
+        rusqlite::params![now_timestamp(), days],
+    )?;
+    Ok(rows_affected)
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct Attachment {
     pub id: String,
-    pub title: String,
-    pub description: Option<String>,
-    pub completed: bool,
+    pub todo_id: String,
+    pub filename: String,
+    pub content_type: String,
+    /// In bytes
+    pub size: i64,
+    /// Hex digest of the contents; `None` for files uploaded before contents were deduplicated
+    pub sha256: Option<String>,
     pub created_at: String,
 }
 
-#[derive(Debug, Deserialize)]
-pub struct CreateTodo {
-    pub title: String,
-    pub description: Option<String>,
+impl Attachment {
+    /// Name of the file holding the contents, shared by every attachment with the same contents.
+    pub fn blob_name(&self) -> &str {
+        self.sha256.as_deref().unwrap_or(&self.id)
+    }
 }
 
-#[derive(Debug, Deserialize)]
-pub struct UpdateTodo {
-    pub title: Option<String>,
-    pub description: Option<String>,
-    pub completed: Option<bool>,
+const ATTACHMENT_COLUMNS: &str =
+    "a.id, a.todo_id, a.filename, a.content_type, a.size, a.sha256, a.created_at";
+
+fn row_to_attachment(row: &rusqlite::Row) -> Result<Attachment> {
+    Ok(Attachment {
+        id: row.get(0)?,
+        todo_id: row.get(1)?,
+        filename: row.get(2)?,
+        content_type: row.get(3)?,
+        size: row.get(4)?,
+        sha256: row.get(5)?,
+        created_at: row.get(6)?,
+    })
 }
 
-// WANTED EXAMPLE: Poolの使い方、genericの使い方
-pub type DbPool = Pool<SqliteConnectionManager>;
+/// Attachments of a todo, oldest first.
+pub fn get_attachments(
+    pool: &DbPool,
+    todo_id: &str,
+) -> Result<Vec<Attachment>, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {ATTACHMENT_COLUMNS} FROM attachments a
+         WHERE a.todo_id = ?1 ORDER BY a.created_at, a.rowid"
+    ))?;
+    let attachments = stmt.query_map([todo_id], row_to_attachment)?;
 
-pub fn create_pool() -> Result<DbPool, r2d2::Error> {
-    let manager = SqliteConnectionManager::file("todos.db");
-    let pool = Pool::new(manager)?;
+    let mut result = Vec::new();
+    for attachment in attachments {
+        result.push(attachment?);
+    }
+    Ok(result)
+}
 
-    // Initialize database schema
-    let conn = pool.get().unwrap();
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS todos (
-            id TEXT PRIMARY KEY,
-            title TEXT NOT NULL,
-            description TEXT,
-            completed BOOLEAN NOT NULL DEFAULT 0,
-            created_at TEXT NOT NULL
-        )",
-        [],
-    )
-    .unwrap();
+/// Attachments of the todos in a list that are not in the trash, with the title of their
+/// todo, todo by todo in creation order.
+pub fn get_list_attachments(
+    pool: &DbPool,
+    list_id: &str,
+) -> Result<Vec<(Attachment, String)>, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {ATTACHMENT_COLUMNS}, t.title FROM attachments a JOIN todos t ON t.id = a.todo_id
+         WHERE t.list_id = ?1 AND t.deleted_at IS NULL
+         ORDER BY t.seq, a.created_at, a.rowid"
+    ))?;
+    let attachments =
+        stmt.query_map([list_id], |row| Ok((row_to_attachment(row)?, row.get(7)?)))?;
 
-    Ok(pool)
+    let mut result = Vec::new();
+    for attachment in attachments {
+        result.push(attachment?);
+    }
+    Ok(result)
 }
 
-// This topic is explained in `.copilot/explanation/rust-error-types.md`
-pub fn create_todo(
+/// An attachment of a todo that is not in the trash.
+pub fn get_attachment(
     pool: &DbPool,
-    create_todo: CreateTodo,
-) -> Result<Todo, Box<dyn std::error::Error + Send + Sync>> {
+    id: &str,
+) -> Result<Option<Attachment>, Box<dyn std::error::Error + Send + Sync>> {
     let conn = pool.get()?;
-    let id = uuid::Uuid::new_v4().to_string();
-    let created_at = chrono::Utc::now().to_rfc3339();
-    let description = create_todo.description.clone().unwrap_or_default();
+    let attachment = conn
+        .query_row(
+            &format!(
+                "SELECT {ATTACHMENT_COLUMNS} FROM attachments a JOIN todos t ON t.id = a.todo_id
+                 WHERE a.id = ?1 AND t.deleted_at IS NULL"
+            ),
+            [id],
+            row_to_attachment,
+        )
+        .optional()?;
+    Ok(attachment)
+}
 
+pub fn create_attachment(
+    pool: &DbPool,
+    attachment: &Attachment,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
     conn.execute(
-        "INSERT INTO todos (id, title, description, completed, created_at) VALUES (?1, ?2, ?3, ?4, ?5)",
-        [&id, &create_todo.title, &description, &"0".to_string(), &created_at],
+        "INSERT INTO attachments (id, todo_id, filename, content_type, size, sha256, created_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        rusqlite::params![
+            attachment.id,
+            attachment.todo_id,
+            attachment.filename,
+            attachment.content_type,
+            attachment.size,
+            attachment.sha256,
+            attachment.created_at,
+        ],
     )?;
+    Ok(())
+}
 
-    Ok(Todo {
-        id,
-        title: create_todo.title,
-        description: create_todo.description,
-        completed: false,
-        created_at,
-    })
+pub fn delete_attachment(
+    pool: &DbPool,
+    id: &str,
+) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let rows_affected = conn.execute("DELETE FROM attachments WHERE id = ?1", [id])?;
+    Ok(rows_affected > 0)
 }
 
-pub fn get_todos(pool: &DbPool) -> Result<Vec<Todo>, Box<dyn std::error::Error + Send + Sync>> {
+/// How many attachments, including ones of trashed todos, are stored in the file `blob_name`.
+pub fn count_blob_references(
+    pool: &DbPool,
+    blob_name: &str,
+) -> Result<i64, Box<dyn std::error::Error + Send + Sync>> {
     let conn = pool.get()?;
-    let mut stmt = conn.prepare(
-        "SELECT id, title, description, completed, created_at FROM todos ORDER BY created_at DESC",
+    let count = conn.query_row(
+        "SELECT COUNT(*) FROM attachments
+         WHERE sha256 = ?1 OR (sha256 IS NULL AND id = ?1)",
+        [blob_name],
+        |row| row.get(0),
     )?;
+    Ok(count)
+}
 
-    // WANTED EXAMPLE:　stmt.query_mapの使い方
-    let todos = stmt.query_map([], |row| {
-        Ok(Todo {
-            id: row.get(0)?,
-            title: row.get(1)?,
-            description: {
-                let desc: String = row.get(2)?;
-                if desc.is_empty() {
-                    None
-                } else {
-                    Some(desc)
-                }
-            },
-            completed: row.get::<_, i32>(3)? != 0,
-            created_at: row.get(4)?,
-        })
-    })?;
+// Longest accepted checklist item text, in characters
+pub const CHECKLIST_TEXT_MAX_LEN: usize = 200;
+/// Most checklist items a todo can have; longer lists are what subtasks are for
+pub const CHECKLIST_MAX_ITEMS: usize = 100;
 
-    let mut result = Vec::new();
-    for todo in todos {
-        result.push(todo?);
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ChecklistItem {
+    pub id: String,
+    pub text: String,
+    pub checked: bool,
+    /// Zero-based index in the checklist
+    pub position: i64,
+}
+
+fn row_to_checklist_item(row: &rusqlite::Row) -> Result<ChecklistItem> {
+    Ok(ChecklistItem {
+        id: row.get(0)?,
+        text: row.get(1)?,
+        checked: row.get(2)?,
+        position: row.get(3)?,
+    })
+}
+
+pub fn normalize_checklist_text(text: &str) -> Result<String, String> {
+    let text = text.trim();
+    if text.is_empty() || text.chars().count() > CHECKLIST_TEXT_MAX_LEN {
+        return Err(format!(
+            "Checklist items must be 1-{CHECKLIST_TEXT_MAX_LEN} characters"
+        ));
     }
-    Ok(result)
+    Ok(text.to_string())
 }
 
-pub fn get_todo(
+pub fn get_checklist(
     pool: &DbPool,
-    id: &str,
-) -> Result<Option<Todo>, Box<dyn std::error::Error + Send + Sync>> {
+    todo_id: &str,
+) -> Result<Vec<ChecklistItem>, Box<dyn std::error::Error + Send + Sync>> {
     let conn = pool.get()?;
-    let mut stmt = conn
-        .prepare("SELECT id, title, description, completed, created_at FROM todos WHERE id = ?1")?;
-
-    let mut todos = stmt.query_map([id], |row| {
-        Ok(Todo {
-            id: row.get(0)?,
-            title: row.get(1)?,
-            description: {
-                let desc: String = row.get(2)?;
-                if desc.is_empty() {
-                    None
-                } else {
-                    Some(desc)
-                }
-            },
-            completed: row.get::<_, i32>(3)? != 0,
-            created_at: row.get(4)?,
-        })
-    })?;
+    let mut stmt = conn.prepare(
+        "SELECT id, text, checked, position FROM checklist_items
+         WHERE todo_id = ?1 ORDER BY position",
+    )?;
+    let items = stmt.query_map([todo_id], row_to_checklist_item)?;
 
-    match todos.next() {
-        Some(todo) => Ok(Some(todo?)),
-        None => Ok(None),
+    let mut result = Vec::new();
+    for item in items {
+        result.push(item?);
     }
+    Ok(result)
 }
 
-pub fn update_todo(
-    pool: &DbPool,
+fn get_checklist_item(
+    conn: &rusqlite::Connection,
+    todo_id: &str,
     id: &str,
-    update: UpdateTodo,
-) -> Result<Option<Todo>, Box<dyn std::error::Error + Send + Sync>> {
-    let conn = pool.get()?;
+) -> Result<Option<ChecklistItem>> {
+    conn.query_row(
+        "SELECT id, text, checked, position FROM checklist_items WHERE todo_id = ?1 AND id = ?2",
+        [todo_id, id],
+        row_to_checklist_item,
+    )
+    .optional()
+}
 
-    // Check if todo exists first
-    if get_todo(pool, id)?.is_none() {
+/// Appends an item to the end of the checklist. Returns `None` when the checklist is full.
+pub fn add_checklist_item(
+    pool: &DbPool,
+    todo_id: &str,
+    text: &str,
+    checked: bool,
+) -> Result<Option<ChecklistItem>, Box<dyn std::error::Error + Send + Sync>> {
+    let mut conn = pool.get()?;
+    let tx = conn.transaction()?;
+    let count: i64 = tx.query_row(
+        "SELECT COUNT(*) FROM checklist_items WHERE todo_id = ?1",
+        [todo_id],
+        |row| row.get(0),
+    )?;
+    if count as usize >= CHECKLIST_MAX_ITEMS {
         return Ok(None);
     }
+    let item = ChecklistItem {
+        id: uuid::Uuid::new_v4().to_string(),
+        text: text.to_string(),
+        checked,
+        position: count,
+    };
+    tx.execute(
+        "INSERT INTO checklist_items (id, todo_id, text, checked, position, created_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        rusqlite::params![
+            item.id,
+            todo_id,
+            item.text,
+            item.checked,
+            item.position,
+            chrono::Utc::now().to_rfc3339(),
+        ],
+    )?;
+    tx.commit()?;
+    Ok(Some(item))
+}
 
-    // Build dynamic update query
-    let mut updates = Vec::new();
-    let mut params: Vec<String> = Vec::new();
-
-    if let Some(title) = update.title {
-        updates.push("title = ?");
-        params.push(title);
+/// Changes an item's text or checked state, or moves it to another zero-based `position`
+/// (clamped to the end).
+pub fn update_checklist_item(
+    pool: &DbPool,
+    todo_id: &str,
+    id: &str,
+    text: Option<&str>,
+    checked: Option<bool>,
+    position: Option<u32>,
+) -> Result<Option<ChecklistItem>, Box<dyn std::error::Error + Send + Sync>> {
+    let mut conn = pool.get()?;
+    let tx = conn.transaction()?;
+    let Some(item) = get_checklist_item(&tx, todo_id, id)? else {
+        return Ok(None);
+    };
+    if let Some(text) = text {
+        tx.execute(
+            "UPDATE checklist_items SET text = ?1 WHERE id = ?2",
+            [text, id],
+        )?;
     }
-    if let Some(description) = update.description {
-        updates.push("description = ?");
-        params.push(description);
+    if let Some(checked) = checked {
+        tx.execute(
+            "UPDATE checklist_items SET checked = ?1 WHERE id = ?2",
+            rusqlite::params![checked, id],
+        )?;
     }
-    if let Some(completed) = update.completed {
-        updates.push("completed = ?");
-        params.push(if completed {
-            "1".to_string()
-        } else {
-            "0".to_string()
-        });
+    if let Some(position) = position {
+        let last: i64 = tx.query_row(
+            "SELECT COUNT(*) - 1 FROM checklist_items WHERE todo_id = ?1",
+            [todo_id],
+            |row| row.get(0),
+        )?;
+        let target = (position as i64).min(last);
+        // Close the gap the item leaves, then open one where it goes
+        tx.execute(
+            "UPDATE checklist_items SET position = position - 1 WHERE todo_id = ?1 AND position > ?2",
+            rusqlite::params![todo_id, item.position],
+        )?;
+        tx.execute(
+            "UPDATE checklist_items SET position = position + 1
+             WHERE todo_id = ?1 AND position >= ?2 AND id != ?3",
+            rusqlite::params![todo_id, target, id],
+        )?;
+        tx.execute(
+            "UPDATE checklist_items SET position = ?1 WHERE id = ?2",
+            rusqlite::params![target, id],
+        )?;
     }
+    let item = get_checklist_item(&tx, todo_id, id)?;
+    tx.commit()?;
+    Ok(item)
+}
 
-    if updates.is_empty() {
-        return get_todo(pool, id);
+pub fn delete_checklist_item(
+    pool: &DbPool,
+    todo_id: &str,
+    id: &str,
+) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+    let mut conn = pool.get()?;
+    let tx = conn.transaction()?;
+    let Some(item) = get_checklist_item(&tx, todo_id, id)? else {
+        return Ok(false);
+    };
+    tx.execute("DELETE FROM checklist_items WHERE id = ?1", [id])?;
+    tx.execute(
+        "UPDATE checklist_items SET position = position - 1 WHERE todo_id = ?1 AND position > ?2",
+        rusqlite::params![todo_id, item.position],
+    )?;
+    tx.commit()?;
+    Ok(true)
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TodoLock {
+    /// Who is editing the todo, as named by their client
+    pub holder: String,
+    pub acquired_at: String,
+    pub expires_at: String,
+}
+
+fn active_lock(conn: &rusqlite::Connection, todo_id: &str, now: &str) -> Result<Option<TodoLock>> {
+    conn.query_row(
+        "SELECT holder, acquired_at, expires_at FROM todo_locks
+         WHERE todo_id = ?1 AND expires_at > ?2",
+        [todo_id, now],
+        |row| {
+            Ok(TodoLock {
+                holder: row.get(0)?,
+                acquired_at: row.get(1)?,
+                expires_at: row.get(2)?,
+            })
+        },
+    )
+    .optional()
+}
+
+/// The todo's lock, unless it has expired.
+pub fn get_lock(
+    pool: &DbPool,
+    todo_id: &str,
+) -> Result<Option<TodoLock>, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    Ok(active_lock(&conn, todo_id, &now_timestamp())?)
+}
+
+/// Locks a todo for `holder` for `ttl_seconds`, or extends their lock. Returns `Err` with
+/// the current lock when someone else holds it.
+pub fn acquire_lock(
+    pool: &DbPool,
+    todo_id: &str,
+    holder: &str,
+    ttl_seconds: u32,
+) -> Result<Result<TodoLock, TodoLock>, Box<dyn std::error::Error + Send + Sync>> {
+    let mut conn = pool.get()?;
+    let tx = conn.transaction()?;
+    let now = chrono::Utc::now();
+    let now_text = now.to_rfc3339_opts(chrono::SecondsFormat::Secs, true);
+    let acquired_at = match active_lock(&tx, todo_id, &now_text)? {
+        Some(lock) if lock.holder != holder => return Ok(Err(lock)),
+        Some(lock) => lock.acquired_at,
+        None => now_text,
+    };
+    let lock = TodoLock {
+        holder: holder.to_string(),
+        acquired_at,
+        expires_at: (now + chrono::Duration::seconds(ttl_seconds as i64))
+            .to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+    };
+    tx.execute(
+        "INSERT INTO todo_locks (todo_id, holder, acquired_at, expires_at) VALUES (?1, ?2, ?3, ?4)
+         ON CONFLICT(todo_id) DO UPDATE SET
+             holder = excluded.holder,
+             acquired_at = excluded.acquired_at,
+             expires_at = excluded.expires_at",
+        [todo_id, &lock.holder, &lock.acquired_at, &lock.expires_at],
+    )?;
+    tx.commit()?;
+    Ok(Ok(lock))
+}
+
+pub fn release_lock(
+    pool: &DbPool,
+    todo_id: &str,
+) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let rows_affected = conn.execute("DELETE FROM todo_locks WHERE todo_id = ?1", [todo_id])?;
+    Ok(rows_affected > 0)
+}
+
+/// Records that `blocker_id` has to be done before `blocked_id`. Returns false, without
+/// adding anything, if `blocked_id` already blocks `blocker_id`, directly or through other
+/// todos, since that would leave neither of them doable.
+pub fn add_dependency(
+    pool: &DbPool,
+    blocker_id: &str,
+    blocked_id: &str,
+) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+    let mut conn = pool.get()?;
+    let tx = conn.transaction()?;
+    let creates_cycle: bool = tx.query_row(
+        "WITH RECURSIVE downstream(id) AS (
+            SELECT ?1
+            UNION
+            SELECT d.blocked_id FROM todo_dependencies d JOIN downstream ON d.blocker_id = downstream.id
+        )
+        SELECT EXISTS (SELECT 1 FROM downstream WHERE id = ?2)",
+        [blocked_id, blocker_id],
+        |row| row.get(0),
+    )?;
+    if creates_cycle {
+        return Ok(false);
     }
+    tx.execute(
+        "INSERT OR IGNORE INTO todo_dependencies (blocker_id, blocked_id, created_at)
+         VALUES (?1, ?2, ?3)",
+        [blocker_id, blocked_id, &chrono::Utc::now().to_rfc3339()],
+    )?;
+    tx.commit()?;
+    Ok(true)
+}
 
-    params.push(id.to_string());
-    let query = format!("UPDATE todos SET {} WHERE id = ?", updates.join(", "));
+/// Returns false if there was no such dependency.
+pub fn remove_dependency(
+    pool: &DbPool,
+    blocker_id: &str,
+    blocked_id: &str,
+) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let rows_affected = conn.execute(
+        "DELETE FROM todo_dependencies WHERE blocker_id = ?1 AND blocked_id = ?2",
+        [blocker_id, blocked_id],
+    )?;
+    Ok(rows_affected > 0)
+}
 
-    let param_refs: Vec<&dyn rusqlite::ToSql> =
-        params.iter().map(|p| p as &dyn rusqlite::ToSql).collect();
-    conn.execute(&query, param_refs.as_slice())?;
+/// Makes a todo a habit with a weekly target, or with `None` a plain todo again. Its
+/// check-ins are kept either way.
+pub fn set_habit_target(
+    pool: &DbPool,
+    todo_id: &str,
+    target_per_week: Option<u8>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    conn.execute(
+        "UPDATE todos SET habit_target_per_week = ?1 WHERE id = ?2",
+        rusqlite::params![target_per_week, todo_id],
+    )?;
+    Ok(())
+}
 
-    get_todo(pool, id)
+/// Records that a habit was done on `date` (`YYYY-MM-DD`). Returns false if it already was.
+pub fn add_checkin(
+    pool: &DbPool,
+    todo_id: &str,
+    date: &str,
+) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let rows_affected = conn.execute(
+        "INSERT OR IGNORE INTO habit_checkins (todo_id, date, created_at) VALUES (?1, ?2, ?3)",
+        [todo_id, date, &chrono::Utc::now().to_rfc3339()],
+    )?;
+    Ok(rows_affected > 0)
 }
 
-pub fn delete_todo(
+pub fn remove_checkin(
     pool: &DbPool,
-    id: &str,
+    todo_id: &str,
+    date: &str,
 ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
     let conn = pool.get()?;
-    let rows_affected = conn.execute("DELETE FROM todos WHERE id = ?1", [id])?;
+    let rows_affected = conn.execute(
+        "DELETE FROM habit_checkins WHERE todo_id = ?1 AND date = ?2",
+        [todo_id, date],
+    )?;
     Ok(rows_affected > 0)
 }
+
+/// The days a habit was done, oldest first.
+pub fn get_checkin_dates(
+    pool: &DbPool,
+    todo_id: &str,
+) -> Result<Vec<String>, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let mut stmt =
+        conn.prepare("SELECT date FROM habit_checkins WHERE todo_id = ?1 ORDER BY date")?;
+    let dates = stmt.query_map([todo_id], |row| row.get(0))?;
+
+    let mut result = Vec::new();
+    for date in dates {
+        result.push(date?);
+    }
+    Ok(result)
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct TimeEntry {
+    pub id: String,
+    pub todo_id: String,
+    pub started_at: String,
+    /// `None` while the timer runs
+    pub stopped_at: Option<String>,
+}
+
+const TIME_ENTRY_COLUMNS: &str = "id, todo_id, started_at, stopped_at";
+
+fn row_to_time_entry(row: &rusqlite::Row) -> Result<TimeEntry> {
+    Ok(TimeEntry {
+        id: row.get(0)?,
+        todo_id: row.get(1)?,
+        started_at: row.get(2)?,
+        stopped_at: row.get(3)?,
+    })
+}
+
+/// A todo's time entries, most recent first.
+pub fn get_time_entries(
+    pool: &DbPool,
+    todo_id: &str,
+) -> Result<Vec<TimeEntry>, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {TIME_ENTRY_COLUMNS} FROM time_entries WHERE todo_id = ?1
+         ORDER BY started_at DESC"
+    ))?;
+    let entries = stmt.query_map([todo_id], row_to_time_entry)?;
+
+    let mut result = Vec::new();
+    for entry in entries {
+        result.push(entry?);
+    }
+    Ok(result)
+}
+
+/// Starts the timer of a todo. Returns `None` if it is already running.
+pub fn start_timer(
+    pool: &DbPool,
+    todo_id: &str,
+) -> Result<Option<TimeEntry>, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let entry = TimeEntry {
+        id: uuid::Uuid::new_v4().to_string(),
+        todo_id: todo_id.to_string(),
+        started_at: now_timestamp(),
+        stopped_at: None,
+    };
+    // The partial unique index turns a second running timer into a no-op
+    let rows_affected = conn.execute(
+        "INSERT OR IGNORE INTO time_entries (id, todo_id, started_at) VALUES (?1, ?2, ?3)",
+        [&entry.id, &entry.todo_id, &entry.started_at],
+    )?;
+    Ok((rows_affected > 0).then_some(entry))
+}
+
+/// Stops the running timer of a todo, if there is one.
+pub fn stop_timer(
+    pool: &DbPool,
+    todo_id: &str,
+) -> Result<Option<TimeEntry>, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let entry = conn
+        .query_row(
+            &format!(
+                "UPDATE time_entries SET stopped_at = ?1
+                 WHERE todo_id = ?2 AND stopped_at IS NULL
+                 RETURNING {TIME_ENTRY_COLUMNS}"
+            ),
+            [&now_timestamp(), todo_id],
+            row_to_time_entry,
+        )
+        .optional()?;
+    Ok(entry)
+}
+
+// A value as an SQL literal; text quotes are doubled and blobs are written as hex
+fn sql_literal(value: rusqlite::types::ValueRef) -> String {
+    use rusqlite::types::ValueRef;
+    match value {
+        ValueRef::Null => "NULL".to_string(),
+        ValueRef::Integer(value) => value.to_string(),
+        // `{:?}` keeps the decimal point, so the value stays a REAL
+        ValueRef::Real(value) => format!("{value:?}"),
+        ValueRef::Text(text) => format!("'{}'", String::from_utf8_lossy(text).replace('\'', "''")),
+        ValueRef::Blob(blob) => format!("X'{}'", hex::encode(blob)),
+    }
+}
+
+/// Writes the whole database as SQL statements: the tables, one `INSERT` per row, then the
+/// full-text indexes are rebuilt and the indexes and triggers created, so triggers do not
+/// fire while the rows are loaded. It ends by setting `user_version` to the schema version,
+/// so a restored database is not migrated again. Everything is read from one snapshot.
+/// Stops early when `emit` returns false.
+pub fn dump_sql(
+    pool: &DbPool,
+    mut emit: impl FnMut(String) -> bool,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    with_snapshot(pool, |conn| {
+        let mut stmt = conn.prepare(
+            "SELECT type, name, sql FROM sqlite_master
+             WHERE sql IS NOT NULL AND name NOT LIKE 'sqlite_%'
+             ORDER BY CASE type WHEN 'table' THEN 0 WHEN 'index' THEN 1 ELSE 2 END, rowid",
+        )?;
+        let mut schema = stmt
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>>>()?;
+
+        if !emit(format!(
+            "-- Logical dump of {DATABASE_FILE}, schema version {SCHEMA_VERSION}\n\
+             BEGIN TRANSACTION;\n"
+        )) {
+            return Ok(());
+        }
+        // Full-text indexes keep their data in shadow tables named after them. Only their
+        // definition is dumped; they are rebuilt from the tables they index once those are loaded.
+        let virtual_tables: Vec<String> = schema
+            .iter()
+            .filter(|(kind, _, sql)| kind == "table" && sql.starts_with("CREATE VIRTUAL TABLE"))
+            .map(|(_, name, _)| name.clone())
+            .collect();
+        schema.retain(|(_, name, _)| {
+            !virtual_tables
+                .iter()
+                .any(|table| name.starts_with(&format!("{table}_")))
+        });
+        let tables: Vec<&str> = schema
+            .iter()
+            .filter(|(kind, name, _)| kind == "table" && !virtual_tables.contains(name))
+            .map(|(_, name, _)| name.as_str())
+            .collect();
+        for (kind, _, sql) in &schema {
+            if kind == "table" && !emit(format!("{sql};\n")) {
+                return Ok(());
+            }
+        }
+        for table in tables {
+            let quoted = format!("\"{}\"", table.replace('"', "\"\""));
+            let mut rows = conn.prepare(&format!("SELECT * FROM {quoted}"))?;
+            let columns = rows
+                .column_names()
+                .iter()
+                .map(|column| format!("\"{}\"", column.replace('"', "\"\"")))
+                .collect::<Vec<_>>()
+                .join(", ");
+            let column_count = rows.column_count();
+            let mut result = rows.query([])?;
+            while let Some(row) = result.next()? {
+                let values = (0..column_count)
+                    .map(|index| row.get_ref(index).map(sql_literal))
+                    .collect::<Result<Vec<_>>>()?
+                    .join(", ");
+                if !emit(format!(
+                    "INSERT INTO {quoted} ({columns}) VALUES ({values});\n"
+                )) {
+                    return Ok(());
+                }
+            }
+        }
+        for table in &virtual_tables {
+            let quoted = format!("\"{}\"", table.replace('"', "\"\""));
+            if !emit(format!(
+                "INSERT INTO {quoted} ({quoted}) VALUES ('rebuild');\n"
+            )) {
+                return Ok(());
+            }
+        }
+        for (kind, _, sql) in &schema {
+            if kind != "table" && !emit(format!("{sql};\n")) {
+                return Ok(());
+            }
+        }
+        if !emit(format!("PRAGMA user_version = {SCHEMA_VERSION};\n")) {
+            return Ok(());
+        }
+        emit("COMMIT;\n".to_string());
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A fresh in-memory database; a single connection, since each opens a database of its own
+    fn test_pool() -> DbPool {
+        let manager = SqliteConnectionManager::memory()
+            .with_init(|conn| conn.execute_batch("PRAGMA foreign_keys = ON;"));
+        let pool = Pool::builder().max_size(1).build(manager).unwrap();
+        migrate(&pool.get().unwrap()).unwrap();
+        pool
+    }
+
+    fn todo(pool: &DbPool, title: &str) -> String {
+        let create = CreateTodo {
+            title: title.to_string(),
+            description: None,
+            metadata: None,
+            custom_fields: None,
+            due_date: None,
+            start_date: None,
+            remind_at: None,
+            estimate_minutes: None,
+            priority: None,
+            parent_id: None,
+            icon: None,
+            recurrence: None,
+            list_id: None,
+            tags: None,
+        };
+        create_todo(pool, create).unwrap().id
+    }
+
+    #[test]
+    fn rejects_a_dependency_that_closes_a_cycle() {
+        let pool = test_pool();
+        let [a, b, c] = ["a", "b", "c"].map(|title| todo(&pool, title));
+        assert!(add_dependency(&pool, &a, &b).unwrap());
+        assert!(add_dependency(&pool, &b, &c).unwrap());
+        // c waits for a through b, so a cannot wait for c, nor b for a
+        assert!(!add_dependency(&pool, &c, &a).unwrap());
+        assert!(!add_dependency(&pool, &b, &a).unwrap());
+        assert!(!add_dependency(&pool, &a, &a).unwrap());
+        // Other todos that do not lead back stay fine, as does a repeated dependency
+        assert!(add_dependency(&pool, &a, &c).unwrap());
+        assert!(add_dependency(&pool, &a, &b).unwrap());
+        let mut expected = vec![a, b];
+        expected.sort();
+        assert_eq!(get_todo(&pool, &c).unwrap().unwrap().blocked_by, expected);
+    }
+}
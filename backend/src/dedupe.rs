@@ -0,0 +1,74 @@
+use std::collections::HashSet;
+
+// Titles at or above this trigram similarity are treated as duplicates
+pub const SIMILARITY_THRESHOLD: f64 = 0.8;
+
+/// Lowercases, drops punctuation and collapses whitespace so that
+/// "Buy milk!" and "buy  milk" compare equal.
+pub fn normalize_title(title: &str) -> String {
+    title
+        .to_lowercase()
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { ' ' })
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn trigrams(normalized: &str) -> HashSet<String> {
+    // Padding lets short words still produce trigrams for their edges
+    let padded: Vec<char> = format!("  {normalized} ").chars().collect();
+    padded
+        .windows(3)
+        .map(|window| window.iter().collect())
+        .collect()
+}
+
+/// Jaccard similarity of the two titles' trigram sets, from 0.0 to 1.0.
+pub fn title_similarity(a: &str, b: &str) -> f64 {
+    let (a, b) = (normalize_title(a), normalize_title(b));
+    if a == b {
+        return 1.0;
+    }
+    let (a, b) = (trigrams(&a), trigrams(&b));
+    let union = a.union(&b).count();
+    if union == 0 {
+        return 0.0;
+    }
+    a.intersection(&b).count() as f64 / union as f64
+}
+
+pub fn is_duplicate_title(a: &str, b: &str) -> bool {
+    title_similarity(a, b) >= SIMILARITY_THRESHOLD
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalizes_case_punctuation_and_spacing() {
+        assert_eq!(normalize_title("  Buy   MILK!! "), "buy milk");
+        assert_eq!(normalize_title("Café-au-lait"), "café au lait");
+    }
+
+    #[test]
+    fn scores_titles_by_shared_trigrams() {
+        assert_eq!(title_similarity("Buy milk!", "buy  milk"), 1.0);
+        assert_eq!(title_similarity("abc", "xyz"), 0.0);
+        assert_eq!(title_similarity("", "!!"), 1.0);
+        let similarity = title_similarity("Write the report", "Write the reports");
+        assert!((0.8..1.0).contains(&similarity), "{similarity}");
+    }
+
+    #[test]
+    fn treats_near_identical_titles_as_duplicates() {
+        assert!(is_duplicate_title(
+            "Write the implementation",
+            "write the implementations"
+        ));
+        assert!(!is_duplicate_title("Buy milk", "Buy bread"));
+        assert!(!is_duplicate_title("Call Alice", "Call Bob"));
+    }
+}
@@ -0,0 +1,64 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::Json,
+};
+use serde_json::{json, Value};
+
+use crate::database::{self, DbPool, Todo};
+
+fn error(status: StatusCode, message: &str) -> (StatusCode, Json<Value>) {
+    (status, Json(json!({"error": message})))
+}
+
+fn find_todo(pool: &DbPool, id: &str, missing: &str) -> Result<Todo, (StatusCode, Json<Value>)> {
+    match database::get_todo(pool, id) {
+        Ok(Some(todo)) => Ok(todo),
+        Ok(None) => Err(error(StatusCode::NOT_FOUND, missing)),
+        Err(_) => Err(error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to get todo",
+        )),
+    }
+}
+
+/// Declares that `blocker_id` has to be done before `id`.
+pub async fn add_blocker_handler(
+    State(pool): State<DbPool>,
+    Path((id, blocker_id)): Path<(String, String)>,
+) -> Result<Json<Todo>, (StatusCode, Json<Value>)> {
+    if id == blocker_id {
+        return Err(error(StatusCode::BAD_REQUEST, "A todo cannot block itself"));
+    }
+    find_todo(&pool, &id, "Todo not found")?;
+    find_todo(&pool, &blocker_id, "Blocking todo not found")?;
+    match database::add_dependency(&pool, &blocker_id, &id) {
+        Ok(true) => find_todo(&pool, &id, "Todo not found").map(Json),
+        Ok(false) => Err(error(
+            StatusCode::CONFLICT,
+            "The todo already blocks the other one, directly or through other todos",
+        )),
+        Err(_) => Err(error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to add dependency",
+        )),
+    }
+}
+
+pub async fn remove_blocker_handler(
+    State(pool): State<DbPool>,
+    Path((id, blocker_id)): Path<(String, String)>,
+) -> Result<Json<Todo>, (StatusCode, Json<Value>)> {
+    find_todo(&pool, &id, "Todo not found")?;
+    match database::remove_dependency(&pool, &blocker_id, &id) {
+        Ok(true) => find_todo(&pool, &id, "Todo not found").map(Json),
+        Ok(false) => Err(error(
+            StatusCode::NOT_FOUND,
+            "The todo is not blocked by this todo",
+        )),
+        Err(_) => Err(error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to remove dependency",
+        )),
+    }
+}
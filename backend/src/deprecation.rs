@@ -0,0 +1,99 @@
+use axum::{
+    extract::{MatchedPath, Request},
+    http::{header, HeaderMap, HeaderValue, Method},
+    middleware::Next,
+    response::Response,
+};
+use serde::Serialize;
+
+use crate::access_log;
+
+/// A route that still works but is scheduled for removal.
+#[derive(Debug, Serialize)]
+pub struct DeprecatedRoute {
+    pub method: &'static str,
+    /// Route template as registered in the router, e.g. `/api/todos/:id`
+    pub path: &'static str,
+    /// RFC3339 date from which the route is deprecated
+    pub since: &'static str,
+    /// RFC3339 date after which the route may be removed
+    pub sunset: Option<&'static str>,
+    /// Where clients should move to, sent as the successor link
+    pub successor: Option<&'static str>,
+}
+
+/// Add an entry here to deprecate a route; responses then carry `Deprecation`, `Sunset`
+/// and `Link` headers and every use is logged so remaining clients can be contacted.
+/// Removed response fields are announced the same way, on the routes that return them.
+pub const DEPRECATED_ROUTES: &[DeprecatedRoute] = &[];
+
+fn find(method: &Method, path: &str) -> Option<&'static DeprecatedRoute> {
+    DEPRECATED_ROUTES
+        .iter()
+        .find(|route| route.method == method.as_str() && route.path == path)
+}
+
+fn timestamp(date: &str) -> Option<chrono::DateTime<chrono::Utc>> {
+    chrono::DateTime::parse_from_rfc3339(date)
+        .ok()
+        .map(|date| date.with_timezone(&chrono::Utc))
+}
+
+fn insert_headers(route: &DeprecatedRoute, headers: &mut HeaderMap) {
+    // RFC 9745: `@` followed by a Unix timestamp
+    if let Some(since) = timestamp(route.since) {
+        if let Ok(value) = HeaderValue::from_str(&format!("@{}", since.timestamp())) {
+            headers.insert("deprecation", value);
+        }
+    }
+    // RFC 8594: an HTTP-date
+    if let Some(sunset) = route.sunset.and_then(timestamp) {
+        let date = sunset.format("%a, %d %b %Y %H:%M:%S GMT").to_string();
+        if let Ok(value) = HeaderValue::from_str(&date) {
+            headers.insert("sunset", value);
+        }
+    }
+    if let Some(successor) = route.successor {
+        if let Ok(value) =
+            HeaderValue::from_str(&format!("<{successor}>; rel=\"successor-version\""))
+        {
+            headers.append(header::LINK, value);
+        }
+    }
+}
+
+fn client_identity(headers: &HeaderMap) -> (String, String) {
+    let header = |name| {
+        headers
+            .get(name)
+            .and_then(|value: &HeaderValue| value.to_str().ok())
+            .unwrap_or("-")
+            .to_string()
+    };
+    (
+        header("x-forwarded-for"),
+        header(header::USER_AGENT.as_str()),
+    )
+}
+
+/// Marks responses of deprecated routes and logs who is still calling them.
+pub async fn deprecation_layer(request: Request, next: Next) -> Response {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .and_then(|path| find(request.method(), path.as_str()));
+    let Some(route) = route else {
+        return next.run(request).await;
+    };
+
+    let (client_ip, user_agent) = client_identity(request.headers());
+    eprintln!(
+        "⚠️ Deprecated route {} {} called by ip={client_ip} user_agent={user_agent:?} req={}",
+        route.method,
+        route.path,
+        access_log::current_request_id().unwrap_or_else(|| "-".to_string()),
+    );
+    let mut response = next.run(request).await;
+    insert_headers(route, response.headers_mut());
+    response
+}
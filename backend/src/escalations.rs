@@ -0,0 +1,195 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::{
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    response::Json,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::activity;
+use crate::database::{
+    self, DbPool, EscalationRule, PendingEscalation, Priority, Todo, UpdateTodo,
+};
+use crate::json_body::JsonBody;
+use crate::lists;
+use crate::reminders::Notifier;
+
+pub const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+/// Rules can wait up to a year past the due date
+pub const MAX_AFTER_HOURS: u32 = 24 * 365;
+
+#[derive(Debug, Deserialize)]
+pub struct CreateEscalationRule {
+    pub after_hours: u32,
+    pub priority: Option<Priority>,
+    #[serde(default = "default_notify")]
+    pub notify: bool,
+}
+
+fn default_notify() -> bool {
+    true
+}
+
+fn error(status: StatusCode, message: &str) -> (StatusCode, Json<Value>) {
+    (status, Json(json!({"error": message})))
+}
+
+/// One line describing an escalation, for notifications.
+pub fn describe(todo: &Todo, rule: &EscalationRule) -> String {
+    let due = todo
+        .due_date
+        .map(database::format_timestamp)
+        .unwrap_or_default();
+    format!(
+        "Escalation: '{}' is still open {}h after it was due ({due}), priority {}",
+        todo.title,
+        rule.after_hours,
+        todo.priority.as_str()
+    )
+}
+
+// Raises the todo's priority and notifies as the rule says. Returns whether it was escalated;
+// a todo that changed since it was found is left for the next check.
+fn escalate(
+    pool: &DbPool,
+    notifier: &dyn Notifier,
+    escalation: &PendingEscalation,
+) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+    let Some(todo) = database::get_todo(pool, &escalation.todo_id)? else {
+        return Ok(false);
+    };
+    let still_due =
+        todo.due_date.map(database::format_timestamp).as_ref() == Some(&escalation.due_date);
+    if todo.completed || !still_due {
+        return Ok(false);
+    }
+    let rule = &escalation.rule;
+    // Rules only ever raise the priority
+    let raise = rule
+        .priority
+        .filter(|priority| *priority as i64 > todo.priority as i64);
+    let todo = match raise {
+        Some(priority) => {
+            let update = UpdateTodo {
+                priority: Some(priority),
+                ..Default::default()
+            };
+            database::update_todo(pool, &todo.id, update)?.unwrap_or(todo)
+        }
+        None => todo,
+    };
+    if rule.notify {
+        // Not recorded on failure, so the next check tries again; the priority stays raised
+        notifier.escalate(&todo, rule)?;
+    }
+    database::record_escalation(pool, escalation, raise)?;
+    activity::record(
+        pool,
+        &HeaderMap::new(),
+        "escalate",
+        Some(&todo.id),
+        json!({"rule": rule, "todo": todo}),
+    );
+    Ok(true)
+}
+
+fn check_escalations(
+    pool: &DbPool,
+    notifier: &dyn Notifier,
+) -> Result<usize, Box<dyn std::error::Error + Send + Sync>> {
+    let mut escalated = 0;
+    for escalation in database::get_pending_escalations(pool)? {
+        match escalate(pool, notifier, &escalation) {
+            Ok(done) => escalated += usize::from(done),
+            Err(e) => eprintln!(
+                "Escalating todo {} by rule {} failed: {e}",
+                escalation.todo_id, escalation.rule.id
+            ),
+        }
+    }
+    Ok(escalated)
+}
+
+/// Background task that applies the escalation rules of every list once a minute.
+pub async fn run_escalation_checker(pool: DbPool, notifier: Arc<dyn Notifier>) {
+    let mut interval = tokio::time::interval(CHECK_INTERVAL);
+    loop {
+        interval.tick().await;
+        let pool = pool.clone();
+        let notifier = notifier.clone();
+        match tokio::task::spawn_blocking(move || check_escalations(&pool, notifier.as_ref())).await
+        {
+            Ok(Ok(_)) => {}
+            Ok(Err(e)) => eprintln!("Escalation check failed: {e}"),
+            Err(e) => eprintln!("Escalation check panicked: {e}"),
+        }
+    }
+}
+
+pub async fn get_escalation_rules_handler(
+    State(pool): State<DbPool>,
+    Path(list_id): Path<String>,
+) -> Result<Json<Vec<EscalationRule>>, (StatusCode, Json<Value>)> {
+    lists::ensure_list_exists(&pool, &list_id)?;
+    database::get_escalation_rules(&pool, &list_id)
+        .map(Json)
+        .map_err(|_| {
+            error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to get escalation rules",
+            )
+        })
+}
+
+pub async fn create_escalation_rule_handler(
+    State(pool): State<DbPool>,
+    Path(list_id): Path<String>,
+    JsonBody(body): JsonBody<CreateEscalationRule>,
+) -> Result<(StatusCode, Json<EscalationRule>), (StatusCode, Json<Value>)> {
+    if !(1..=MAX_AFTER_HOURS).contains(&body.after_hours) {
+        return Err(error(
+            StatusCode::BAD_REQUEST,
+            &format!("`after_hours` must be between 1 and {MAX_AFTER_HOURS}"),
+        ));
+    }
+    if body.priority.is_none() && !body.notify {
+        return Err(error(
+            StatusCode::BAD_REQUEST,
+            "A rule needs a `priority` to raise todos to, or `notify`",
+        ));
+    }
+    lists::ensure_list_exists(&pool, &list_id)?;
+    database::create_escalation_rule(
+        &pool,
+        &list_id,
+        body.after_hours,
+        body.priority,
+        body.notify,
+    )
+    .map(|rule| (StatusCode::CREATED, Json(rule)))
+    .map_err(|_| {
+        error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to create escalation rule",
+        )
+    })
+}
+
+pub async fn delete_escalation_rule_handler(
+    State(pool): State<DbPool>,
+    Path((list_id, id)): Path<(String, String)>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    match database::delete_escalation_rule(&pool, &list_id, &id) {
+        Ok(true) => Ok(Json(
+            json!({"message": "Escalation rule deleted successfully"}),
+        )),
+        Ok(false) => Err(error(StatusCode::NOT_FOUND, "Escalation rule not found")),
+        Err(_) => Err(error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to delete escalation rule",
+        )),
+    }
+}
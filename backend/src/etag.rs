@@ -0,0 +1,169 @@
+use axum::{
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Json, Response},
+};
+use serde_json::{json, Map, Value};
+use sha2::{Digest, Sha256};
+
+use crate::database::Todo;
+
+// What a todo's tag covers: what is stored for it. Values derived when it is read and that
+// move with the clock alone are left out (the age, the tracked time, an edit lock that
+// expires and the habit's check-ins this week), so the tag of a todo nobody touched stays
+// good for `If-Match`.
+const STORED_FIELDS: [&str; 32] = [
+    "id",
+    "title",
+    "description",
+    "completed",
+    "created_at",
+    "metadata",
+    "due_date",
+    "start_date",
+    "priority",
+    "tags",
+    "parent_id",
+    "subtask_count",
+    "completed_subtask_count",
+    "icon",
+    "recurrence",
+    "list_id",
+    "position",
+    "completed_at",
+    "archived_at",
+    "deleted_at",
+    "checklist",
+    "review",
+    "blocked_by",
+    "blocks",
+    "series_id",
+    "estimate_minutes",
+    "timer_started_at",
+    "pinned",
+    "custom_fields",
+    "remind_at",
+    "reminded_at",
+    "updated_at",
+];
+
+/// The routes that check `If-Match`, and require it with `REQUIRE_IF_MATCH`. Bulk routes have
+/// no single tag to check.
+pub const IF_MATCH_ROUTES: [&str; 6] = [
+    "PUT /api/todos/:id",
+    "DELETE /api/todos/:id",
+    "POST /api/todos/:id/toggle",
+    "POST /api/todos/:id/move",
+    "POST /api/todos/:id/pin",
+    "POST /api/todos/:id/revert",
+];
+
+fn error(status: StatusCode, message: &str) -> (StatusCode, Json<Value>) {
+    (status, Json(json!({"error": message})))
+}
+
+/// A strong entity tag over the given parts of a response.
+pub fn of(parts: &[&[u8]]) -> String {
+    let mut hasher = Sha256::new();
+    for part in parts {
+        // Length-prefixed, so moving bytes between parts changes the tag
+        hasher.update((part.len() as u64).to_le_bytes());
+        hasher.update(part);
+    }
+    format!("\"{}\"", hex::encode(&hasher.finalize()[..16]))
+}
+
+/// The tag of a todo, as sent with `GET` and `PUT /api/todos/:id` and checked by `If-Match`.
+pub fn of_todo(todo: &Todo) -> String {
+    let mut fields = match serde_json::to_value(todo) {
+        Ok(Value::Object(fields)) => fields,
+        _ => Map::new(),
+    };
+    let mut stored: Map<String, Value> = STORED_FIELDS
+        .iter()
+        .filter_map(|field| Some((field.to_string(), fields.remove(*field)?)))
+        .collect();
+    stored.insert(
+        "habit_target_per_week".to_string(),
+        json!(todo.habit.as_ref().map(|habit| habit.target_per_week)),
+    );
+    of(&[Value::Object(stored).to_string().as_bytes()])
+}
+
+// Whether a list of tags like `"a", W/"b"` or `*` names `etag`. Weak tags only match when
+// `weak` comparison is allowed, as for `If-None-Match`.
+fn list_matches(list: &str, etag: &str, weak: bool) -> bool {
+    list.split(',').map(str::trim).any(|candidate| {
+        if candidate == "*" {
+            return true;
+        }
+        match candidate.strip_prefix("W/") {
+            Some(candidate) => weak && candidate == etag,
+            None => candidate == etag,
+        }
+    })
+}
+
+/// Whether the client's `If-None-Match` already names `etag`, so `304` can be sent instead.
+pub fn not_modified(request: &HeaderMap, etag: &str) -> bool {
+    request
+        .get_all(header::IF_NONE_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .any(|list| list_matches(list, etag, true))
+}
+
+/// An empty `304 Not Modified` with the tag the client has.
+pub fn not_modified_response(etag: &str) -> Response {
+    let mut response = StatusCode::NOT_MODIFIED.into_response();
+    insert(response.headers_mut(), etag);
+    response
+}
+
+/// Sets the `ETag` header of a response.
+pub fn insert(headers: &mut HeaderMap, etag: &str) {
+    if let Ok(value) = HeaderValue::from_str(etag) {
+        headers.insert(header::ETAG, value);
+    }
+}
+
+/// The client's `If-Match` header. A missing one is `428` when the deployment requires it.
+pub fn if_match(
+    request: &HeaderMap,
+    required: bool,
+) -> Result<Option<String>, (StatusCode, Json<Value>)> {
+    match request.get(header::IF_MATCH) {
+        Some(value) => value
+            .to_str()
+            .map(|value| Some(value.to_string()))
+            .map_err(|_| error(StatusCode::BAD_REQUEST, "Invalid If-Match header")),
+        None if required => Err(error(
+            StatusCode::PRECONDITION_REQUIRED,
+            "Changes to a todo must send If-Match with its ETag",
+        )),
+        None => Ok(None),
+    }
+}
+
+/// The check for a change to a todo: the client's `If-Match`, if it sent one, names the todo
+/// as it is.
+pub fn precondition(if_match: Option<&str>) -> impl FnOnce(&Todo) -> bool + '_ {
+    move |todo| if_match.is_none_or(|if_match| matches_todo(if_match, todo))
+}
+
+/// Whether an `If-Match` header names the current tag of `todo`.
+pub fn matches_todo(if_match: &str, todo: &Todo) -> bool {
+    list_matches(if_match, &of_todo(todo), false)
+}
+
+/// `412` for an update made against an outdated todo, with the current one and its tag so the
+/// client can merge without another request.
+pub fn precondition_failed(current: &Todo) -> (StatusCode, Json<Value>) {
+    (
+        StatusCode::PRECONDITION_FAILED,
+        Json(json!({
+            "error": "The todo has changed since it was read",
+            "etag": of_todo(current),
+            "todo": current,
+        })),
+    )
+}
@@ -0,0 +1,87 @@
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::Json,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::database::{self, DbPool};
+
+/// How long events are kept; consumers that are away longer have to resync
+pub const RETENTION_DAYS: u32 = 30;
+const DEFAULT_LIMIT: u32 = 100;
+const MAX_LIMIT: u32 = 1000;
+
+#[derive(Debug, Deserialize)]
+pub struct EventParams {
+    pub consumer: Option<String>,
+    /// The last event the consumer has processed; defaults to its stored cursor
+    pub after: Option<i64>,
+    pub limit: Option<u32>,
+}
+
+fn error(status: StatusCode, message: &str) -> (StatusCode, Json<Value>) {
+    (status, Json(json!({"error": message})))
+}
+
+fn internal_error() -> (StatusCode, Json<Value>) {
+    error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to get events")
+}
+
+/// Events after `after` for a named consumer. Passing `after` also acknowledges every event
+/// up to it, so a consumer that crashes before acknowledging gets the same events again.
+pub async fn get_events_handler(
+    State(pool): State<DbPool>,
+    Query(params): Query<EventParams>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let consumer = params
+        .consumer
+        .as_deref()
+        .ok_or_else(|| error(StatusCode::BAD_REQUEST, "`consumer` is required"))?;
+    let consumer = database::normalize_consumer_name(consumer)
+        .map_err(|message| error(StatusCode::BAD_REQUEST, &message))?;
+    let limit = params.limit.unwrap_or(DEFAULT_LIMIT);
+    if !(1..=MAX_LIMIT).contains(&limit) {
+        return Err(error(
+            StatusCode::BAD_REQUEST,
+            &format!("limit must be between 1 and {MAX_LIMIT}"),
+        ));
+    }
+
+    let (oldest, latest) = database::get_event_bounds(&pool).map_err(|_| internal_error())?;
+    match params.after {
+        Some(after) if after < 0 || after > latest => {
+            return Err(error(
+                StatusCode::BAD_REQUEST,
+                &format!("`after` must be between 0 and the latest event, {latest}"),
+            ))
+        }
+        _ => {}
+    }
+    let acked =
+        database::ack_events(&pool, &consumer, params.after).map_err(|_| internal_error())?;
+    let after = params.after.unwrap_or(acked);
+
+    // Events are pruned after a while; a consumer that was away longer has missed some
+    let first_available = if oldest > 0 { oldest } else { latest + 1 };
+    if after + 1 < first_available {
+        return Err((
+            StatusCode::GONE,
+            Json(json!({
+                "error": "Events after `after` have been pruned; resync, then continue from `resume_after`",
+                "resume_after": first_available - 1,
+            })),
+        ));
+    }
+
+    let events = database::get_events(&pool, after, limit).map_err(|_| internal_error())?;
+    let next_after = events.last().map_or(after, |event| event.seq);
+    Ok(Json(json!({
+        "consumer": consumer,
+        "acked": acked,
+        "events": events,
+        "next_after": next_after,
+        "has_more": next_after < latest,
+    })))
+}
@@ -0,0 +1,200 @@
+use std::io::Write;
+
+use axum::{
+    body::{Body, Bytes},
+    http::header,
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, SecondsFormat, Utc};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+
+use crate::database::{self, DbPool, Todo, TodoFilter};
+
+// Rows buffered between the database thread and the client; bounds memory on slow readers
+const CHANNEL_CAPACITY: usize = 64;
+
+pub const NDJSON_CONTENT_TYPE: &str = "application/x-ndjson";
+
+// Nested fields like the checklist are left out; tags are joined with commas
+const CSV_COLUMNS: [&str; 11] = [
+    "id",
+    "title",
+    "description",
+    "completed",
+    "priority",
+    "due_date",
+    "start_date",
+    "created_at",
+    "completed_at",
+    "list_id",
+    "tags",
+];
+
+#[derive(Debug, Clone, Copy)]
+pub enum ExportFormat {
+    /// One todo per line
+    Ndjson,
+    /// A single JSON array, written element by element
+    Json,
+    /// A header row, then one row per todo
+    Csv,
+}
+
+impl ExportFormat {
+    pub const ALL: [&'static str; 3] = ["ndjson", "json", "csv"];
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "ndjson" => Some(ExportFormat::Ndjson),
+            "json" => Some(ExportFormat::Json),
+            "csv" => Some(ExportFormat::Csv),
+            _ => None,
+        }
+    }
+
+    /// Also the file extension of scheduled exports
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ExportFormat::Ndjson => "ndjson",
+            ExportFormat::Json => "json",
+            ExportFormat::Csv => "csv",
+        }
+    }
+
+    fn content_type(self) -> &'static str {
+        match self {
+            ExportFormat::Ndjson => NDJSON_CONTENT_TYPE,
+            ExportFormat::Json => "application/json",
+            ExportFormat::Csv => "text/csv",
+        }
+    }
+
+    fn header(self) -> Vec<u8> {
+        match self {
+            ExportFormat::Ndjson => Vec::new(),
+            ExportFormat::Json => b"[".to_vec(),
+            ExportFormat::Csv => csv_row(&CSV_COLUMNS).unwrap_or_default(),
+        }
+    }
+
+    fn footer(self) -> Vec<u8> {
+        match self {
+            ExportFormat::Json => b"]".to_vec(),
+            ExportFormat::Ndjson | ExportFormat::Csv => Vec::new(),
+        }
+    }
+
+    // `first` is whether this is the first todo of the export
+    fn encode(self, todo: &Todo, first: bool) -> Option<Vec<u8>> {
+        match self {
+            ExportFormat::Ndjson => {
+                let mut chunk = serde_json::to_vec(todo).ok()?;
+                chunk.push(b'\n');
+                Some(chunk)
+            }
+            ExportFormat::Json => {
+                let mut chunk = if first { Vec::new() } else { vec![b','] };
+                serde_json::to_writer(&mut chunk, todo).ok()?;
+                Some(chunk)
+            }
+            ExportFormat::Csv => {
+                // Same text as in the JSON formats
+                let time = |time: Option<DateTime<Utc>>| {
+                    time.map(|time| time.to_rfc3339_opts(SecondsFormat::AutoSi, true))
+                        .unwrap_or_default()
+                };
+                csv_row(&[
+                    todo.id.as_str(),
+                    todo.title.as_str(),
+                    todo.description.as_deref().unwrap_or_default(),
+                    if todo.completed { "true" } else { "false" },
+                    todo.priority.as_str(),
+                    &time(todo.due_date),
+                    &time(todo.start_date),
+                    &time(Some(todo.created_at)),
+                    &time(todo.completed_at),
+                    todo.list_id.as_deref().unwrap_or_default(),
+                    todo.tags.join(",").as_str(),
+                ])
+            }
+        }
+    }
+}
+
+fn csv_row(fields: &[&str]) -> Option<Vec<u8>> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record(fields).ok()?;
+    writer.into_inner().ok()
+}
+
+/// Writes every todo matching `filter` to `out`, one at a time. Returns how many were written.
+pub fn write_todos(
+    pool: &DbPool,
+    filter: &TodoFilter,
+    format: ExportFormat,
+    out: &mut impl Write,
+) -> Result<usize, Box<dyn std::error::Error + Send + Sync>> {
+    out.write_all(&format.header())?;
+    let mut written = 0;
+    let mut failure = None;
+    database::for_each_todo(pool, filter, |todo| {
+        let result = format
+            .encode(&todo, written == 0)
+            .ok_or_else(|| std::io::Error::other("failed to encode todo"))
+            .and_then(|chunk| out.write_all(&chunk));
+        match result {
+            Ok(()) => {
+                written += 1;
+                true
+            }
+            Err(e) => {
+                failure = Some(e);
+                false
+            }
+        }
+    })?;
+    if let Some(e) = failure {
+        return Err(e.into());
+    }
+    out.write_all(&format.footer())?;
+    Ok(written)
+}
+
+/// Streams every todo matching `filter` without buffering the full result in memory.
+pub fn stream_todos(pool: DbPool, filter: TodoFilter, format: ExportFormat) -> Response {
+    let (tx, rx) = mpsc::channel::<Result<Bytes, std::io::Error>>(CHANNEL_CAPACITY);
+
+    tokio::task::spawn_blocking(move || {
+        let mut first = true;
+        let send = |chunk: Vec<u8>| tx.blocking_send(Ok(Bytes::from(chunk))).is_ok();
+
+        if !send(format.header()) {
+            return;
+        }
+        let result = database::for_each_todo(&pool, &filter, |todo| {
+            let Some(chunk) = format.encode(&todo, first) else {
+                return false;
+            };
+            first = false;
+            // A failed send means the client went away
+            send(chunk)
+        });
+
+        match result {
+            Ok(()) => {
+                send(format.footer());
+            }
+            // Headers are already sent, so the best we can do is abort the body
+            Err(e) => {
+                let _ = tx.blocking_send(Err(std::io::Error::other(e.to_string())));
+            }
+        }
+    });
+
+    (
+        [(header::CONTENT_TYPE, format.content_type())],
+        Body::from_stream(ReceiverStream::new(rx)),
+    )
+        .into_response()
+}
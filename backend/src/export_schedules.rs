@@ -0,0 +1,309 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path as FsPath, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::Json,
+};
+use chrono::{DateTime, Timelike, Utc};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::config::Config;
+use crate::database::{self, DbPool, ExportSchedule, METADATA_MAX_KEY_LEN};
+use crate::export::{self, ExportFormat};
+use crate::handlers;
+use crate::json_body::JsonBody;
+
+pub const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+pub const DESTINATIONS: [&str; 1] = ["local"];
+
+const DEFAULT_HOUR_UTC: u8 = 2;
+
+#[derive(Debug, Deserialize)]
+pub struct CreateExportSchedule {
+    pub name: String,
+    #[serde(default = "default_format")]
+    pub format: String,
+    /// Query parameters of `GET /api/todos`
+    #[serde(default)]
+    pub filter: HashMap<String, String>,
+    #[serde(default = "default_destination")]
+    pub destination: String,
+    #[serde(default = "default_hour_utc")]
+    pub hour_utc: u8,
+}
+
+fn default_format() -> String {
+    ExportFormat::Ndjson.as_str().to_string()
+}
+
+fn default_destination() -> String {
+    DESTINATIONS[0].to_string()
+}
+
+fn default_hour_utc() -> u8 {
+    DEFAULT_HOUR_UTC
+}
+
+fn error(status: StatusCode, message: &str) -> (StatusCode, Json<Value>) {
+    (status, Json(json!({"error": message})))
+}
+
+fn parse_time(value: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(value)
+        .ok()
+        .map(|time| time.with_timezone(&Utc))
+}
+
+// Due once the latest `hour_utc` before `now` is after the last run (or the creation)
+fn is_due(schedule: &ExportSchedule, now: DateTime<Utc>) -> bool {
+    let Some(today) = now
+        .with_hour(u32::from(schedule.hour_utc))
+        .and_then(|time| time.with_minute(0))
+        .and_then(|time| time.with_second(0))
+        .and_then(|time| time.with_nanosecond(0))
+    else {
+        return false;
+    };
+    let latest = if today <= now {
+        today
+    } else {
+        today - chrono::Duration::days(1)
+    };
+    let since = schedule
+        .last_run_at
+        .as_deref()
+        .unwrap_or(&schedule.created_at);
+    parse_time(since).is_some_and(|since| latest > since)
+}
+
+// Writes to a temporary file first so a partial export is never left under the final name
+fn write_export(
+    pool: &DbPool,
+    dir: &FsPath,
+    schedule: &ExportSchedule,
+) -> Result<(String, usize), Box<dyn std::error::Error + Send + Sync>> {
+    let format = ExportFormat::parse(&schedule.format)
+        .ok_or_else(|| format!("unknown format '{}'", schedule.format))?;
+    let filter = handlers::parse_todo_filter(&schedule.filter)?;
+    std::fs::create_dir_all(dir)?;
+
+    let file_name = format!(
+        "{}-{}.{}",
+        schedule.name,
+        Utc::now().format("%Y%m%dT%H%M%SZ"),
+        format.as_str()
+    );
+    let path = dir.join(&file_name);
+    let temp_path = dir.join(format!(".{file_name}.tmp"));
+    let written = (|| {
+        let mut out = BufWriter::new(File::create(&temp_path)?);
+        let written = export::write_todos(pool, &filter, format, &mut out)?;
+        out.flush()?;
+        std::fs::rename(&temp_path, &path)?;
+        Ok::<_, Box<dyn std::error::Error + Send + Sync>>(written)
+    })();
+    if written.is_err() {
+        let _ = std::fs::remove_file(&temp_path);
+    }
+    Ok((file_name, written?))
+}
+
+// Runs a schedule and records the outcome on it
+fn run_export(
+    pool: &DbPool,
+    dir: &FsPath,
+    schedule: &ExportSchedule,
+) -> Result<(String, usize), String> {
+    let result = write_export(pool, dir, schedule).map_err(|e| e.to_string());
+    let outcome = match &result {
+        Ok((file_name, _)) => Ok(file_name.as_str()),
+        Err(message) => Err(message.as_str()),
+    };
+    if let Err(e) = database::record_export_run(pool, &schedule.id, outcome) {
+        eprintln!(
+            "Failed to record the run of export schedule {}: {e}",
+            schedule.id
+        );
+    }
+    result
+}
+
+fn run_due_exports(
+    pool: &DbPool,
+    dir: &FsPath,
+) -> Result<usize, Box<dyn std::error::Error + Send + Sync>> {
+    let now = Utc::now();
+    let mut ran = 0;
+    for schedule in database::get_export_schedules(pool)? {
+        if !is_due(&schedule, now) {
+            continue;
+        }
+        match run_export(pool, dir, &schedule) {
+            Ok((file_name, written)) => {
+                println!("📦 Exported {written} todos to {file_name}")
+            }
+            Err(message) => eprintln!("Export schedule '{}' failed: {message}", schedule.name),
+        }
+        ran += 1;
+    }
+    Ok(ran)
+}
+
+/// Background task that runs each export schedule once a day at its `hour_utc`.
+pub async fn run_export_scheduler(pool: DbPool, dir: PathBuf) {
+    let mut interval = tokio::time::interval(CHECK_INTERVAL);
+    loop {
+        interval.tick().await;
+        let pool = pool.clone();
+        let dir = dir.clone();
+        match tokio::task::spawn_blocking(move || run_due_exports(&pool, &dir)).await {
+            Ok(Ok(_)) => {}
+            Ok(Err(e)) => eprintln!("Export schedule check failed: {e}"),
+            Err(e) => eprintln!("Export schedule check panicked: {e}"),
+        }
+    }
+}
+
+pub async fn get_export_schedules_handler(
+    State(pool): State<DbPool>,
+) -> Result<Json<Vec<ExportSchedule>>, (StatusCode, Json<Value>)> {
+    database::get_export_schedules(&pool)
+        .map(Json)
+        .map_err(|_| {
+            error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to get export schedules",
+            )
+        })
+}
+
+pub async fn create_export_schedule_handler(
+    State(pool): State<DbPool>,
+    JsonBody(body): JsonBody<CreateExportSchedule>,
+) -> Result<(StatusCode, Json<ExportSchedule>), (StatusCode, Json<Value>)> {
+    let name = body.name.trim();
+    if !database::is_valid_metadata_key(name) {
+        return Err(error(
+            StatusCode::BAD_REQUEST,
+            &format!(
+                "Invalid schedule name '{name}': use 1-{METADATA_MAX_KEY_LEN} characters of [A-Za-z0-9_-]"
+            ),
+        ));
+    }
+    if ExportFormat::parse(&body.format).is_none() {
+        return Err(error(
+            StatusCode::BAD_REQUEST,
+            &format!("`format` must be one of: {}", ExportFormat::ALL.join(", ")),
+        ));
+    }
+    if !DESTINATIONS.contains(&body.destination.as_str()) {
+        return Err(error(
+            StatusCode::BAD_REQUEST,
+            &format!(
+                "Unsupported destination '{}': only `local` is available",
+                body.destination
+            ),
+        ));
+    }
+    if body.hour_utc > 23 {
+        return Err(error(
+            StatusCode::BAD_REQUEST,
+            "`hour_utc` must be between 0 and 23",
+        ));
+    }
+    handlers::parse_todo_filter(&body.filter)
+        .map_err(|message| error(StatusCode::BAD_REQUEST, &message))?;
+
+    match database::find_export_schedule_by_name(&pool, name) {
+        Ok(Some(existing)) => {
+            return Err((
+                StatusCode::CONFLICT,
+                Json(
+                    json!({"error": "An export schedule with this name already exists", "existing": existing}),
+                ),
+            ))
+        }
+        Ok(None) => {}
+        Err(_) => {
+            return Err(error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to check schedule name",
+            ))
+        }
+    }
+
+    database::create_export_schedule(
+        &pool,
+        name,
+        &body.format,
+        body.filter,
+        &body.destination,
+        body.hour_utc,
+    )
+    .map(|schedule| (StatusCode::CREATED, Json(schedule)))
+    .map_err(|_| {
+        error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to create export schedule",
+        )
+    })
+}
+
+/// Deletes a schedule. Files it already wrote are kept.
+pub async fn delete_export_schedule_handler(
+    State(pool): State<DbPool>,
+    Path(id): Path<String>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    match database::delete_export_schedule(&pool, &id) {
+        Ok(true) => Ok(Json(
+            json!({"message": "Export schedule deleted successfully"}),
+        )),
+        Ok(false) => Err(error(StatusCode::NOT_FOUND, "Export schedule not found")),
+        Err(_) => Err(error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to delete export schedule",
+        )),
+    }
+}
+
+/// Runs a schedule right away, without waiting for its hour.
+pub async fn run_export_schedule_handler(
+    State(pool): State<DbPool>,
+    State(config): State<Arc<Config>>,
+    Path(id): Path<String>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let schedule = match database::get_export_schedule(&pool, &id) {
+        Ok(Some(schedule)) => schedule,
+        Ok(None) => return Err(error(StatusCode::NOT_FOUND, "Export schedule not found")),
+        Err(_) => {
+            return Err(error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to get export schedule",
+            ))
+        }
+    };
+    let dir = config.export_dir.clone();
+    let (file_name, written) =
+        tokio::task::spawn_blocking(move || run_export(&pool, &dir, &schedule))
+            .await
+            .map_err(|_| error(StatusCode::INTERNAL_SERVER_ERROR, "Export failed"))?
+            .map_err(|message| {
+                error(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    &format!("Export failed: {message}"),
+                )
+            })?;
+    Ok(Json(json!({
+        "schedule_id": id,
+        "file": file_name,
+        "exported": written,
+    })))
+}
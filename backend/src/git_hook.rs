@@ -0,0 +1,122 @@
+use std::sync::Arc;
+
+use axum::{
+    body::Bytes,
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::Json,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::config::Config;
+use crate::database::{self, DbPool, UpdateTodo};
+use crate::github;
+
+const CLOSING_KEYWORDS: &[&str] = &[
+    "close", "closes", "closed", "fix", "fixes", "fixed", "resolve", "resolves", "resolved",
+];
+
+/// The part of a GitHub/GitLab/Gitea push payload we need.
+#[derive(Debug, Deserialize)]
+pub struct PushPayload {
+    #[serde(default)]
+    pub commits: Vec<PushCommit>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PushCommit {
+    pub id: String,
+    pub message: String,
+}
+
+/// Finds `closes T-<ref>` style references; `<ref>` is an id prefix of the todo.
+pub fn closing_references(message: &str) -> Vec<String> {
+    let words: Vec<&str> = message.split_whitespace().collect();
+    words
+        .windows(2)
+        .filter(|pair| {
+            let keyword = pair[0].trim_end_matches(':').to_ascii_lowercase();
+            CLOSING_KEYWORDS.contains(&keyword.as_str())
+        })
+        .filter_map(|pair| {
+            let reference = pair[1].trim_end_matches([',', '.', ';', ')']);
+            reference
+                .strip_prefix("T-")
+                .or_else(|| reference.strip_prefix("t-"))
+                .map(str::to_string)
+        })
+        .collect()
+}
+
+// GitHub and Gitea sign the body, GitLab sends the secret itself as a token
+fn is_authorized(secret: &str, headers: &HeaderMap, body: &[u8]) -> bool {
+    let header = |name: &str| headers.get(name).and_then(|value| value.to_str().ok());
+    if let Some(token) = header("x-gitlab-token") {
+        return token == secret;
+    }
+    github::verify_signature(secret, body, header("x-hub-signature-256"))
+}
+
+fn close_reference(
+    pool: &DbPool,
+    reference: &str,
+    commit: &str,
+) -> Result<Option<String>, Box<dyn std::error::Error + Send + Sync>> {
+    let mut matches = database::find_todos_by_id_prefix(pool, reference)?;
+    if matches.len() != 1 {
+        return Ok(None);
+    }
+    let todo = matches.remove(0);
+    let mut metadata = todo.metadata;
+    metadata.insert("closed_by_commit".to_string(), json!(commit));
+    let update = UpdateTodo {
+        completed: Some(true),
+        metadata: Some(metadata),
+        ..Default::default()
+    };
+    Ok(database::update_todo(pool, &todo.id, update)?.map(|todo| todo.id))
+}
+
+/// Push hook that completes todos referenced from commit messages.
+pub async fn git_push_handler(
+    State(pool): State<DbPool>,
+    State(config): State<Arc<Config>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let secret = config.git_webhook_secret.as_deref().ok_or((
+        StatusCode::NOT_FOUND,
+        Json(json!({"error": "Git integration is not enabled"})),
+    ))?;
+    if !is_authorized(secret, &headers, &body) {
+        return Err((
+            StatusCode::UNAUTHORIZED,
+            Json(json!({"error": "Invalid webhook signature"})),
+        ));
+    }
+    let payload: PushPayload = serde_json::from_slice(&body).map_err(|_| {
+        (
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "Invalid push payload"})),
+        )
+    })?;
+
+    let mut closed = Vec::new();
+    let mut unresolved = Vec::new();
+    for commit in &payload.commits {
+        for reference in closing_references(&commit.message) {
+            match close_reference(&pool, &reference, &commit.id) {
+                Ok(Some(id)) => closed.push(id),
+                Ok(None) => unresolved.push(format!("T-{reference}")),
+                Err(_) => {
+                    return Err((
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        Json(json!({"error": "Failed to complete referenced todos"})),
+                    ))
+                }
+            }
+        }
+    }
+    Ok(Json(json!({"closed": closed, "unresolved": unresolved})))
+}
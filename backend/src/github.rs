@@ -0,0 +1,361 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::{
+    body::Bytes,
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    response::Json,
+};
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use sha2::Sha256;
+
+use crate::config::Config;
+use crate::database::{self, DbPool, Todo, TodoFilter, UpdateTodo};
+use crate::json_body::JsonBody;
+
+// Links are stored in the todo's metadata so no extra table is needed
+const REPO_KEY: &str = "github_repo";
+const ISSUE_KEY: &str = "github_issue";
+const STATE_KEY: &str = "github_state";
+
+// Cursor of the issue sync in the event feed
+const SYNC_CONSUMER: &str = "github-issue-sync";
+const SYNC_INTERVAL: Duration = Duration::from_secs(10);
+const SYNC_BATCH: u32 = 100;
+
+#[derive(Debug, Deserialize)]
+pub struct GithubLink {
+    /// `owner/name`
+    pub repo: String,
+    pub number: u64,
+    pub state: Option<String>,
+}
+
+fn is_valid_repo(repo: &str) -> bool {
+    let mut parts = repo.split('/');
+    let valid_part = |part: Option<&str>| {
+        part.is_some_and(|part| {
+            !part.is_empty()
+                && part
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+        })
+    };
+    valid_part(parts.next()) && valid_part(parts.next()) && parts.next().is_none()
+}
+
+fn internal_error(message: &str) -> (StatusCode, Json<Value>) {
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Json(json!({"error": message})),
+    )
+}
+
+fn todo_not_found() -> (StatusCode, Json<Value>) {
+    (
+        StatusCode::NOT_FOUND,
+        Json(json!({"error": "Todo not found"})),
+    )
+}
+
+fn metadata_update(metadata: database::Metadata) -> UpdateTodo {
+    UpdateTodo {
+        metadata: Some(metadata),
+        ..Default::default()
+    }
+}
+
+pub async fn link_issue_handler(
+    State(pool): State<DbPool>,
+    Path(id): Path<String>,
+    JsonBody(link): JsonBody<GithubLink>,
+) -> Result<Json<Todo>, (StatusCode, Json<Value>)> {
+    if !is_valid_repo(&link.repo) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "repo must look like `owner/name`"})),
+        ));
+    }
+    let todo = database::get_todo(&pool, &id)
+        .map_err(|_| internal_error("Failed to get todo"))?
+        .ok_or_else(todo_not_found)?;
+
+    let mut metadata = todo.metadata;
+    metadata.insert(REPO_KEY.to_string(), json!(link.repo));
+    metadata.insert(ISSUE_KEY.to_string(), json!(link.number));
+    metadata.insert(
+        STATE_KEY.to_string(),
+        json!(link.state.unwrap_or_else(|| "open".to_string())),
+    );
+    database::validate_metadata(&metadata)
+        .map_err(|message| (StatusCode::BAD_REQUEST, Json(json!({"error": message}))))?;
+
+    match database::update_todo(&pool, &id, metadata_update(metadata)) {
+        Ok(Some(todo)) => Ok(Json(todo)),
+        Ok(None) => Err(todo_not_found()),
+        Err(_) => Err(internal_error("Failed to link issue")),
+    }
+}
+
+pub async fn unlink_issue_handler(
+    State(pool): State<DbPool>,
+    Path(id): Path<String>,
+) -> Result<Json<Todo>, (StatusCode, Json<Value>)> {
+    let todo = database::get_todo(&pool, &id)
+        .map_err(|_| internal_error("Failed to get todo"))?
+        .ok_or_else(todo_not_found)?;
+
+    let mut metadata = todo.metadata;
+    for key in [REPO_KEY, ISSUE_KEY, STATE_KEY] {
+        metadata.remove(key);
+    }
+    match database::update_todo(&pool, &id, metadata_update(metadata)) {
+        Ok(Some(todo)) => Ok(Json(todo)),
+        Ok(None) => Err(todo_not_found()),
+        Err(_) => Err(internal_error("Failed to unlink issue")),
+    }
+}
+
+// GitHub signs the raw body with HMAC-SHA256 and sends `sha256=<hex>`
+pub fn verify_signature(secret: &str, body: &[u8], signature: Option<&str>) -> bool {
+    let Some(expected) = signature
+        .and_then(|signature| signature.strip_prefix("sha256="))
+        .and_then(|hex_digest| hex::decode(hex_digest).ok())
+    else {
+        return false;
+    };
+    let Ok(mut mac) = Hmac::<Sha256>::new_from_slice(secret.as_bytes()) else {
+        return false;
+    };
+    mac.update(body);
+    mac.verify_slice(&expected).is_ok()
+}
+
+/// Applies an issue's state to every todo linked to it.
+fn sync_issue_state(
+    pool: &DbPool,
+    repo: &str,
+    number: u64,
+    state: &str,
+) -> Result<Vec<Todo>, Box<dyn std::error::Error + Send + Sync>> {
+    let linked = database::get_todos(
+        pool,
+        &TodoFilter {
+            metadata: vec![
+                (REPO_KEY.to_string(), repo.to_string()),
+                (ISSUE_KEY.to_string(), number.to_string()),
+            ],
+            ..Default::default()
+        },
+    )?;
+
+    let mut updated = Vec::new();
+    for todo in linked {
+        let mut metadata = todo.metadata;
+        metadata.insert(STATE_KEY.to_string(), json!(state));
+        let update = UpdateTodo {
+            completed: Some(state == "closed"),
+            ..metadata_update(metadata)
+        };
+        updated.extend(database::update_todo(pool, &todo.id, update)?);
+    }
+    Ok(updated)
+}
+
+/// Receives GitHub `issues` webhooks and completes/reopens the linked todos.
+pub async fn github_webhook_handler(
+    State(pool): State<DbPool>,
+    State(config): State<Arc<Config>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let secret = config.github_webhook_secret.as_deref().ok_or((
+        StatusCode::NOT_FOUND,
+        Json(json!({"error": "GitHub integration is not enabled"})),
+    ))?;
+    let signature = headers
+        .get("x-hub-signature-256")
+        .and_then(|value| value.to_str().ok());
+    if !verify_signature(secret, &body, signature) {
+        return Err((
+            StatusCode::UNAUTHORIZED,
+            Json(json!({"error": "Invalid webhook signature"})),
+        ));
+    }
+
+    let event = headers
+        .get("x-github-event")
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    if event != "issues" {
+        return Ok(Json(json!({"ignored": event})));
+    }
+
+    let payload: Value = serde_json::from_slice(&body).map_err(|_| {
+        (
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "Invalid webhook payload"})),
+        )
+    })?;
+    let (Some(repo), Some(number), Some(state)) = (
+        payload["repository"]["full_name"].as_str(),
+        payload["issue"]["number"].as_u64(),
+        payload["issue"]["state"].as_str(),
+    ) else {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "Webhook payload is missing the issue or repository"})),
+        ));
+    };
+
+    match sync_issue_state(&pool, repo, number, state) {
+        Ok(updated) => Ok(Json(json!({"updated": updated.len()}))),
+        Err(_) => Err(internal_error("Failed to update linked todos")),
+    }
+}
+
+// The issue state a todo asks for, when it is linked and its issue is not in that state yet
+fn pending_issue_state(todo: &Todo) -> Option<(String, u64, &'static str)> {
+    let repo = todo.metadata.get(REPO_KEY)?.as_str()?;
+    let number = todo.metadata.get(ISSUE_KEY)?.as_u64()?;
+    let wanted = if todo.completed { "closed" } else { "open" };
+    let state = todo.metadata.get(STATE_KEY).and_then(Value::as_str);
+    (state != Some(wanted)).then(|| (repo.to_string(), number, wanted))
+}
+
+enum IssueUpdate {
+    Done,
+    /// GitHub refused it for good, e.g. the issue is gone or the token may not write to it
+    Refused(String),
+}
+
+async fn set_issue_state(
+    client: &reqwest::Client,
+    api_url: &str,
+    token: &str,
+    repo: &str,
+    number: u64,
+    state: &str,
+) -> Result<IssueUpdate, Box<dyn std::error::Error + Send + Sync>> {
+    let response = client
+        .patch(format!("{api_url}/repos/{repo}/issues/{number}"))
+        .bearer_auth(token)
+        .header("accept", "application/vnd.github+json")
+        .header("x-github-api-version", "2022-11-28")
+        // GitHub rejects requests without a user agent
+        .header("user-agent", "rust_todo")
+        .json(&json!({"state": state}))
+        .send()
+        .await?;
+    let status = response.status();
+    if status.is_success() {
+        return Ok(IssueUpdate::Done);
+    }
+    let retry_later = status.is_server_error()
+        || status == reqwest::StatusCode::TOO_MANY_REQUESTS
+        // Rate limits are sent as 403 with no requests remaining
+        || status == reqwest::StatusCode::FORBIDDEN
+            && response
+                .headers()
+                .get("x-ratelimit-remaining")
+                .is_some_and(|remaining| remaining == "0");
+    if retry_later {
+        return Err(format!("GitHub answered {status}").into());
+    }
+    Ok(IssueUpdate::Refused(format!("GitHub answered {status}")))
+}
+
+// Closes or reopens the issue of a todo that was completed or reopened here, then records
+// the new state on the todo
+async fn sync_todo(
+    pool: &DbPool,
+    client: &reqwest::Client,
+    api_url: &str,
+    token: &str,
+    todo_id: String,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let todo = {
+        let pool = pool.clone();
+        tokio::task::spawn_blocking(move || database::get_todo(&pool, &todo_id)).await??
+    };
+    let Some(todo) = todo else {
+        return Ok(());
+    };
+    let Some((repo, number, state)) = pending_issue_state(&todo) else {
+        return Ok(());
+    };
+    if let IssueUpdate::Refused(reason) =
+        set_issue_state(client, api_url, token, &repo, number, state).await?
+    {
+        eprintln!(
+            "Could not set {repo}#{number} to {state} for todo {}: {reason}",
+            todo.id
+        );
+        return Ok(());
+    }
+    let mut metadata = todo.metadata.clone();
+    metadata.insert(STATE_KEY.to_string(), json!(state));
+    let pool = pool.clone();
+    // A todo changed in the meantime is synced again for the event of that change
+    tokio::task::spawn_blocking(move || {
+        database::update_todo_if(&pool, &todo.id, metadata_update(metadata), |current| {
+            current.completed == todo.completed && current.metadata == todo.metadata
+        })
+    })
+    .await??;
+    Ok(())
+}
+
+// Works through the new events in batches. Returns how many there were.
+async fn sync_issues(
+    pool: &DbPool,
+    client: &reqwest::Client,
+    api_url: &str,
+    token: &str,
+) -> Result<usize, Box<dyn std::error::Error + Send + Sync>> {
+    let events = {
+        let pool = pool.clone();
+        tokio::task::spawn_blocking(move || {
+            let after = database::ack_events(&pool, SYNC_CONSUMER, None)?;
+            database::get_events(&pool, after, SYNC_BATCH)
+        })
+        .await??
+    };
+    let count = events.len();
+    for event in events {
+        if event.kind == "todo.updated" {
+            // Stops at the first failure, so the rest is tried again on the next run
+            sync_todo(pool, client, api_url, token, event.todo_id).await?;
+        }
+        let pool = pool.clone();
+        tokio::task::spawn_blocking(move || {
+            database::ack_events(&pool, SYNC_CONSUMER, Some(event.seq))
+        })
+        .await??;
+    }
+    Ok(count)
+}
+
+/// Background task that closes the linked GitHub issue when a todo is completed here, and
+/// reopens it when the todo is reopened. `api_url` is the base URL of the GitHub REST API.
+pub async fn run_issue_sync(pool: DbPool, api_url: String, token: String) {
+    let client = reqwest::Client::new();
+    let mut interval = tokio::time::interval(SYNC_INTERVAL);
+    loop {
+        interval.tick().await;
+        loop {
+            match sync_issues(&pool, &client, &api_url, &token).await {
+                // Catch up without waiting when there was a full batch
+                Ok(count) if count == SYNC_BATCH as usize => continue,
+                Ok(_) => break,
+                Err(e) => {
+                    eprintln!("GitHub issue sync failed: {e}");
+                    break;
+                }
+            }
+        }
+    }
+}
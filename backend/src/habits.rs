@@ -0,0 +1,217 @@
+use std::collections::BTreeMap;
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::Json,
+};
+use chrono::{Datelike, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::database::{self, DbPool, Todo};
+use crate::json_body::JsonBody;
+use crate::recurrence::{self, StreakStats};
+
+const DATE_FORMAT: &str = "%Y-%m-%d";
+
+#[derive(Debug, Deserialize)]
+pub struct SetHabit {
+    pub target_per_week: u8,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateCheckin {
+    /// `YYYY-MM-DD` in the user's time zone; defaults to today in UTC
+    pub date: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct HabitStats {
+    pub todo_id: String,
+    pub target_per_week: u8,
+    pub checkins_this_week: usize,
+    pub total_checkins: usize,
+    pub last_checkin: Option<String>,
+    /// Days in a row with a check-in, up to today or yesterday
+    pub day_streak: usize,
+    /// In weeks, from the week of the first check-in: a week is done once it reaches the
+    /// target. The current week only counts once it is done.
+    #[serde(flatten)]
+    pub weeks: StreakStats,
+}
+
+fn error(status: StatusCode, message: &str) -> (StatusCode, Json<Value>) {
+    (status, Json(json!({"error": message})))
+}
+
+fn find_todo(pool: &DbPool, id: &str) -> Result<Todo, (StatusCode, Json<Value>)> {
+    match database::get_todo(pool, id) {
+        Ok(Some(todo)) => Ok(todo),
+        Ok(None) => Err(error(StatusCode::NOT_FOUND, "Todo not found")),
+        Err(_) => Err(error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to get todo",
+        )),
+    }
+}
+
+fn find_habit(pool: &DbPool, id: &str) -> Result<(Todo, u8), (StatusCode, Json<Value>)> {
+    let todo = find_todo(pool, id)?;
+    match todo.habit.as_ref().map(|habit| habit.target_per_week) {
+        Some(target) => Ok((todo, target)),
+        None => Err(error(StatusCode::CONFLICT, "The todo is not a habit")),
+    }
+}
+
+// Time zones ahead of UTC may already be on tomorrow
+fn parse_checkin_date(value: &str, today: NaiveDate) -> Result<NaiveDate, String> {
+    let date = NaiveDate::parse_from_str(value, DATE_FORMAT)
+        .map_err(|_| format!("Invalid date '{value}': expected YYYY-MM-DD"))?;
+    if date > today + chrono::Duration::days(1) {
+        return Err("Check-ins cannot be in the future".to_string());
+    }
+    Ok(date)
+}
+
+fn week_start(date: NaiveDate) -> NaiveDate {
+    date - chrono::Duration::days(date.weekday().num_days_from_monday() as i64)
+}
+
+pub fn compute_stats(
+    todo_id: String,
+    target_per_week: u8,
+    dates: &[NaiveDate],
+    today: NaiveDate,
+) -> HabitStats {
+    let mut per_week: BTreeMap<NaiveDate, usize> = BTreeMap::new();
+    for date in dates {
+        *per_week.entry(week_start(*date)).or_default() += 1;
+    }
+    let this_week = week_start(today);
+    let checkins_this_week = per_week.get(&this_week).copied().unwrap_or(0);
+    let target = target_per_week as usize;
+    let weeks = per_week.keys().next().map(|first| {
+        let mut week = *first;
+        let mut outcomes = Vec::new();
+        while week < this_week {
+            outcomes.push(per_week.get(&week).copied().unwrap_or(0) >= target);
+            week += chrono::Duration::weeks(1);
+        }
+        if checkins_this_week >= target {
+            outcomes.push(true);
+        }
+        outcomes
+    });
+
+    // Today not being done yet does not break the streak
+    let mut day = today;
+    if dates.last() != Some(&today) {
+        day -= chrono::Duration::days(1);
+    }
+    let day_streak = dates
+        .iter()
+        .rev()
+        .skip_while(|date| **date > day)
+        .zip(0..)
+        .take_while(|(date, offset)| **date == day - chrono::Duration::days(*offset))
+        .count();
+
+    HabitStats {
+        todo_id,
+        target_per_week,
+        checkins_this_week,
+        total_checkins: dates.len(),
+        last_checkin: dates
+            .last()
+            .map(|date| date.format(DATE_FORMAT).to_string()),
+        day_streak,
+        weeks: recurrence::streak_stats(weeks.unwrap_or_default()),
+    }
+}
+
+/// Makes a todo a habit, or changes its weekly target.
+pub async fn set_habit_handler(
+    State(pool): State<DbPool>,
+    Path(id): Path<String>,
+    JsonBody(body): JsonBody<SetHabit>,
+) -> Result<Json<Todo>, (StatusCode, Json<Value>)> {
+    if !(1..=7).contains(&body.target_per_week) {
+        return Err(error(
+            StatusCode::BAD_REQUEST,
+            "target_per_week must be between 1 and 7",
+        ));
+    }
+    find_todo(&pool, &id)?;
+    database::set_habit_target(&pool, &id, Some(body.target_per_week))
+        .map_err(|_| error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to set habit"))?;
+    find_todo(&pool, &id).map(Json)
+}
+
+/// Turns a habit back into a plain todo; its check-ins are kept.
+pub async fn delete_habit_handler(
+    State(pool): State<DbPool>,
+    Path(id): Path<String>,
+) -> Result<Json<Todo>, (StatusCode, Json<Value>)> {
+    find_habit(&pool, &id)?;
+    database::set_habit_target(&pool, &id, None)
+        .map_err(|_| error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to remove habit"))?;
+    find_todo(&pool, &id).map(Json)
+}
+
+/// Records that the habit was done on a day. Checking in twice on the same day is a no-op.
+pub async fn create_checkin_handler(
+    State(pool): State<DbPool>,
+    Path(id): Path<String>,
+    JsonBody(body): JsonBody<CreateCheckin>,
+) -> Result<(StatusCode, Json<Todo>), (StatusCode, Json<Value>)> {
+    let today = Utc::now().date_naive();
+    let date = match body.date.as_deref() {
+        Some(date) => parse_checkin_date(date, today)
+            .map_err(|message| error(StatusCode::BAD_REQUEST, &message))?,
+        None => today,
+    };
+    find_habit(&pool, &id)?;
+    let created = database::add_checkin(&pool, &id, &date.format(DATE_FORMAT).to_string())
+        .map_err(|_| error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to check in"))?;
+    let status = if created {
+        StatusCode::CREATED
+    } else {
+        StatusCode::OK
+    };
+    find_todo(&pool, &id).map(|todo| (status, Json(todo)))
+}
+
+pub async fn delete_checkin_handler(
+    State(pool): State<DbPool>,
+    Path((id, date)): Path<(String, String)>,
+) -> Result<Json<Todo>, (StatusCode, Json<Value>)> {
+    find_todo(&pool, &id)?;
+    match database::remove_checkin(&pool, &id, &date) {
+        Ok(true) => find_todo(&pool, &id).map(Json),
+        Ok(false) => Err(error(StatusCode::NOT_FOUND, "No check-in on this day")),
+        Err(_) => Err(error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to remove check-in",
+        )),
+    }
+}
+
+pub async fn get_habit_stats_handler(
+    State(pool): State<DbPool>,
+    Path(id): Path<String>,
+) -> Result<Json<HabitStats>, (StatusCode, Json<Value>)> {
+    let (todo, target) = find_habit(&pool, &id)?;
+    let dates = database::get_checkin_dates(&pool, &id)
+        .map_err(|_| error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to get check-ins"))?;
+    let dates: Vec<NaiveDate> = dates
+        .iter()
+        .filter_map(|date| NaiveDate::parse_from_str(date, DATE_FORMAT).ok())
+        .collect();
+    Ok(Json(compute_stats(
+        todo.id,
+        target,
+        &dates,
+        Utc::now().date_naive(),
+    )))
+}
@@ -1,46 +1,1038 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Instant;
+
 use axum::{
-    extract::{Path, State},
-    http::StatusCode,
-    response::Json,
-    routing::get,
+    body::Bytes,
+    extract::{DefaultBodyLimit, FromRef, Path, Query, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    middleware,
+    response::{IntoResponse, Json, Response},
+    routing::{delete, get, patch, post, put},
     Router,
 };
+use serde::Deserialize;
 use serde_json::{json, Value};
-use tower_http::{cors::CorsLayer, services::ServeDir};
+use tower_http::{catch_panic::CatchPanicLayer, cors::CorsLayer, services::ServeDir};
+
+use crate::access_log;
+use crate::activity;
+use crate::agenda;
+use crate::alerts;
+use crate::attachments;
+use crate::attachments_zip;
+use crate::autocomplete::{self, AutocompleteIndex};
+use crate::bulk;
+use crate::capabilities;
+use crate::checklist;
+use crate::config::Config;
+use crate::confirmation::{self, ConfirmationTokens};
+use crate::csv_import;
+use crate::custom_fields;
+use crate::database::{
+    self, Conditional, CreateTodo, DbPool, Deduplicated, Keyset, MoveTarget, Priority,
+    ReviewStatus, SortDirection, SortKey, Todo, TodoFilter, TodoStats, UpdateTodo,
+};
+use crate::dependencies;
+use crate::deprecation;
+use crate::escalations;
+use crate::etag;
+use crate::events;
+use crate::export::{self, ExportFormat};
+use crate::export_schedules;
+use crate::git_hook;
+use crate::github;
+use crate::habits;
+use crate::history;
+use crate::humanize::{self, Language};
+use crate::icons;
+use crate::ics;
+use crate::idempotency;
+use crate::inbound_email;
+use crate::inbound_hooks;
+use crate::ingest;
+use crate::ip_filter;
+use crate::jira;
+use crate::json_body::{JsonBody, OptionalJsonBody};
+use crate::list_snapshots;
+use crate::lists;
+use crate::locks;
+use crate::macros;
+use crate::maintenance::{self, MaintenanceMode};
+use crate::palette;
+use crate::panic_handler;
+use crate::planning;
+use crate::pomodoro;
+use crate::realtime::{self, Realtime};
+use crate::recurrence;
+use crate::reviews;
+use crate::search;
+use crate::security_headers;
+use crate::server_info;
+use crate::signed_urls;
+use crate::single_flight::{self, SingleFlight};
+use crate::sql_dump;
+use crate::stale;
+use crate::subtasks;
+use crate::tags;
+use crate::templates;
+use crate::timer;
+use crate::trash;
+use crate::views;
+
+#[derive(Clone)]
+pub struct AppState {
+    pub db_pool: DbPool,
+    pub maintenance: MaintenanceMode,
+    pub config: Arc<Config>,
+    pub confirmations: ConfirmationTokens,
+    pub started_at: Instant,
+    pub todo_list_flights: TodoListFlights,
+    pub realtime: Realtime,
+    pub autocomplete: AutocompleteIndex,
+}
+
+impl FromRef<AppState> for DbPool {
+    fn from_ref(state: &AppState) -> Self {
+        state.db_pool.clone()
+    }
+}
+
+impl FromRef<AppState> for MaintenanceMode {
+    fn from_ref(state: &AppState) -> Self {
+        state.maintenance.clone()
+    }
+}
 
-use crate::database::{CreateTodo, DbPool, Todo, UpdateTodo};
+impl FromRef<AppState> for ConfirmationTokens {
+    fn from_ref(state: &AppState) -> Self {
+        state.confirmations.clone()
+    }
+}
+
+impl FromRef<AppState> for TodoListFlights {
+    fn from_ref(state: &AppState) -> Self {
+        state.todo_list_flights.clone()
+    }
+}
+
+impl FromRef<AppState> for Realtime {
+    fn from_ref(state: &AppState) -> Self {
+        state.realtime.clone()
+    }
+}
+
+impl FromRef<AppState> for AutocompleteIndex {
+    fn from_ref(state: &AppState) -> Self {
+        state.autocomplete.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<Config> {
+    fn from_ref(state: &AppState) -> Self {
+        state.config.clone()
+    }
+}
+
+pub fn create_router(
+    db_pool: DbPool,
+    config: Config,
+    realtime: Realtime,
+    autocomplete: AutocompleteIndex,
+) -> Router {
+    let access_log_format = config.access_log_format;
+    let state = AppState {
+        db_pool,
+        maintenance: MaintenanceMode::default(),
+        config: Arc::new(config),
+        confirmations: ConfirmationTokens::default(),
+        started_at: Instant::now(),
+        todo_list_flights: TodoListFlights::default(),
+        realtime,
+        autocomplete,
+    };
 
-pub fn create_router(db_pool: DbPool) -> Router {
     // This topic is explained in `.copilot/explanation/axum-routing.md`
     let api_routes = Router::new()
-        .route("/", get(get_todos_handler).post(create_todo_handler))
+        .route(
+            "/",
+            get(get_todos_handler)
+                .post(create_todo_handler)
+                .delete(delete_todos_handler),
+        )
+        .route("/autocomplete", get(autocomplete::autocomplete_handler))
+        .route(
+            "/bulk",
+            post(bulk::bulk_create_handler).patch(bulk::bulk_update_handler),
+        )
+        .route("/count", get(count_todos_handler))
+        .route("/stats", get(get_todo_stats_handler))
+        .route("/export", get(export_todos_handler))
+        .route("/ingest", post(ingest::ingest_handler))
+        .route("/stale", get(stale::get_stale_todos_handler))
+        .route("/scheduled", get(get_scheduled_todos_handler))
+        .route("/search", get(search::search_todos_handler))
+        .route("/bulk-tag", post(tags::bulk_tag_handler))
         .route(
             "/:id",
             get(get_todo_handler)
                 .put(update_todo_handler)
                 .delete(delete_todo_handler),
         )
-        .with_state(db_pool);
+        .route("/:id/move", post(move_todo_handler))
+        .route("/:id/pin", post(toggle_pin_handler))
+        .route("/:id/toggle", post(toggle_completed_handler))
+        .route("/:id/duplicate", post(duplicate_todo_handler))
+        .route(
+            "/:id/signed-url",
+            post(signed_urls::create_signed_url_handler),
+        )
+        .route("/:id/history", get(history::get_todo_history_handler))
+        .route(
+            "/:id/history/:revision/diff",
+            get(history::get_revision_diff_handler),
+        )
+        .route("/:id/revert", post(history::revert_todo_handler))
+        .route(
+            "/:id/lock",
+            post(locks::acquire_lock_handler).delete(locks::release_lock_handler),
+        )
+        .route("/:id/restore", post(trash::restore_todo_handler))
+        .route(
+            "/:id/attachments",
+            // Uploads are streamed to disk; the handler enforces ATTACHMENT_MAX_BYTES
+            post(attachments::upload_attachment_handler)
+                .layer(DefaultBodyLimit::disable())
+                .get(attachments::get_attachments_handler),
+        )
+        .route("/:id/pomodoro", get(pomodoro::get_pomodoros_handler))
+        .route(
+            "/:id/pomodoro/start",
+            post(pomodoro::start_pomodoro_handler),
+        )
+        .route("/:id/pomodoro/stop", post(pomodoro::stop_pomodoro_handler))
+        .route("/:id/timer", get(timer::get_time_entries_handler))
+        .route("/:id/timer/start", post(timer::start_timer_handler))
+        .route("/:id/timer/stop", post(timer::stop_timer_handler))
+        .route(
+            "/:id/github",
+            put(github::link_issue_handler).delete(github::unlink_issue_handler),
+        )
+        .route(
+            "/:id/children",
+            get(subtasks::get_children_handler).post(subtasks::create_child_handler),
+        )
+        .route(
+            "/:id/checklist",
+            get(checklist::get_checklist_handler).post(checklist::create_checklist_item_handler),
+        )
+        .route(
+            "/:id/checklist/:item_id",
+            put(checklist::update_checklist_item_handler)
+                .delete(checklist::delete_checklist_item_handler),
+        )
+        .route(
+            "/:id/habit",
+            put(habits::set_habit_handler).delete(habits::delete_habit_handler),
+        )
+        .route("/:id/habit/stats", get(habits::get_habit_stats_handler))
+        .route("/:id/checkins", post(habits::create_checkin_handler))
+        .route(
+            "/:id/checkins/:date",
+            delete(habits::delete_checkin_handler),
+        )
+        .route(
+            "/:id/recurrence/stats",
+            get(recurrence::get_recurrence_stats_handler),
+        )
+        .route(
+            "/:id/blockers/:blocker_id",
+            put(dependencies::add_blocker_handler).delete(dependencies::remove_blocker_handler),
+        )
+        .route(
+            "/:id/tags/:tag",
+            put(tags::attach_tag_handler).delete(tags::detach_tag_handler),
+        )
+        .with_state(state.clone());
+
+    let tag_routes = Router::new()
+        .route(
+            "/",
+            get(tags::get_tags_handler).post(tags::create_tag_handler),
+        )
+        .route(
+            "/:id",
+            get(tags::get_tag_handler)
+                .put(tags::update_tag_handler)
+                .delete(tags::delete_tag_handler),
+        )
+        .with_state(state.clone());
+
+    let list_routes = Router::new()
+        .route(
+            "/",
+            get(lists::get_lists_handler).post(lists::create_list_handler),
+        )
+        .route(
+            "/:id",
+            get(lists::get_list_handler)
+                .put(lists::update_list_handler)
+                .delete(lists::delete_list_handler),
+        )
+        .route(
+            "/:id/todos",
+            get(lists::get_list_todos_handler).post(lists::create_list_todo_handler),
+        )
+        .route("/:id/defaults", patch(lists::update_list_defaults_handler))
+        .route(
+            "/:id/attachments.zip",
+            get(attachments_zip::download_list_attachments_handler),
+        )
+        .route("/:id/presence", get(realtime::get_presence_handler))
+        .route(
+            "/:id/snapshots",
+            get(list_snapshots::get_list_snapshots_handler),
+        )
+        .route(
+            "/:id/escalations",
+            get(escalations::get_escalation_rules_handler)
+                .post(escalations::create_escalation_rule_handler),
+        )
+        .route(
+            "/:id/escalations/:rule_id",
+            delete(escalations::delete_escalation_rule_handler),
+        )
+        .with_state(state.clone());
+
+    let attachment_routes = Router::new()
+        .route(
+            "/:id",
+            get(attachments::download_attachment_handler)
+                .delete(attachments::delete_attachment_handler),
+        )
+        .with_state(state.clone());
+
+    // No credentials needed here: the query signature is the authorization
+    let signed_routes = Router::new()
+        .route("/todos/:id", get(signed_urls::signed_todo_handler))
+        .route(
+            "/attachments/:id",
+            get(signed_urls::signed_attachment_handler),
+        )
+        .with_state(state.clone());
+
+    let trash_routes = Router::new()
+        .route("/", get(trash::get_trash_handler))
+        .route("/:id", delete(trash::purge_todo_handler))
+        .with_state(state.clone());
+
+    let review_routes = Router::new()
+        .route("/pending", get(reviews::get_pending_reviews_handler))
+        .route("/:id/approve", post(reviews::approve_review_handler))
+        .route("/:id/reject", post(reviews::reject_review_handler))
+        .with_state(state.clone());
+
+    let view_routes = Router::new()
+        .route(
+            "/",
+            get(views::get_views_handler).post(views::create_view_handler),
+        )
+        .route("/default", get(views::get_default_view_handler))
+        .route(
+            "/:id",
+            get(views::get_view_handler)
+                .put(views::update_view_handler)
+                .delete(views::delete_view_handler),
+        )
+        .route("/:id/todos", get(views::get_view_todos_handler))
+        .with_state(state.clone());
+
+    let macro_routes = Router::new()
+        .route(
+            "/",
+            get(macros::get_macros_handler).post(macros::create_macro_handler),
+        )
+        .route(
+            "/:id",
+            get(macros::get_macro_handler)
+                .put(macros::update_macro_handler)
+                .delete(macros::delete_macro_handler),
+        )
+        .route("/:id/run", post(macros::run_macro_handler))
+        .with_state(state.clone());
+
+    let template_routes = Router::new()
+        .route(
+            "/",
+            get(templates::get_templates_handler).post(templates::create_template_handler),
+        )
+        .route(
+            "/:id",
+            get(templates::get_template_handler)
+                .put(templates::update_template_handler)
+                .delete(templates::delete_template_handler),
+        )
+        .route(
+            "/:id/instantiate",
+            post(templates::instantiate_template_handler),
+        )
+        .with_state(state.clone());
+
+    let palette_routes = Router::new()
+        .route(
+            "/",
+            get(palette::get_palette_handler).post(palette::create_palette_color_handler),
+        )
+        .route(
+            "/:name",
+            put(palette::update_palette_color_handler)
+                .delete(palette::delete_palette_color_handler),
+        )
+        .with_state(state.clone());
+
+    let custom_field_routes = Router::new()
+        .route(
+            "/",
+            get(custom_fields::get_custom_fields_handler)
+                .post(custom_fields::create_custom_field_handler),
+        )
+        .route("/:name", delete(custom_fields::delete_custom_field_handler))
+        .with_state(state.clone());
+
+    let admin_routes = Router::new()
+        .route(
+            "/maintenance",
+            get(maintenance::get_maintenance_handler).post(maintenance::set_maintenance_handler),
+        )
+        .route(
+            "/export-schedules",
+            get(export_schedules::get_export_schedules_handler)
+                .post(export_schedules::create_export_schedule_handler),
+        )
+        .route(
+            "/export-schedules/:id",
+            delete(export_schedules::delete_export_schedule_handler),
+        )
+        .route(
+            "/export-schedules/:id/run",
+            post(export_schedules::run_export_schedule_handler),
+        )
+        .route(
+            "/alerts",
+            get(alerts::get_alert_rules_handler).post(alerts::create_alert_rule_handler),
+        )
+        .route("/alerts/:id", delete(alerts::delete_alert_rule_handler))
+        .route(
+            "/inbound-hooks",
+            get(inbound_hooks::get_inbound_hooks_handler)
+                .post(inbound_hooks::create_inbound_hook_handler),
+        )
+        .route(
+            "/inbound-hooks/:id",
+            get(inbound_hooks::get_inbound_hook_handler)
+                .put(inbound_hooks::update_inbound_hook_handler)
+                .delete(inbound_hooks::delete_inbound_hook_handler),
+        )
+        .route("/dump", get(sql_dump::dump_handler))
+        .with_state(state.clone());
+
+    let pomodoro_routes = Router::new()
+        .route("/focus", get(pomodoro::get_focus_report_handler))
+        .with_state(state.clone());
+
+    let agenda_routes = Router::new()
+        .route("/print", get(agenda::print_agenda_handler))
+        .route("/plan", post(planning::plan_handler))
+        .with_state(state.clone());
+
+    let import_routes = Router::new()
+        .route("/ics", post(ics::import_ics_handler))
+        .route("/jira", post(jira::import_jira_handler))
+        .route("/csv", post(csv_import::upload_csv_handler))
+        .route(
+            "/csv/:id",
+            get(csv_import::get_upload_handler)
+                .post(csv_import::import_upload_handler)
+                .delete(csv_import::delete_upload_handler),
+        )
+        .with_state(state.clone());
+
+    let integration_routes = Router::new()
+        .route("/github", post(github::github_webhook_handler))
+        .route("/git", post(git_hook::git_push_handler))
+        .with_state(state.clone());
+
+    let inbound_routes = Router::new()
+        .route(
+            "/email",
+            post(inbound_email::inbound_email_handler)
+                .layer(DefaultBodyLimit::max(inbound_email::MAX_EMAIL_BYTES)),
+        )
+        .route("/:token", post(inbound_hooks::inbound_hook_handler))
+        .with_state(state.clone());
 
     Router::new()
+        .route(
+            "/api/capabilities",
+            get(capabilities::get_capabilities_handler).with_state(state.clone()),
+        )
+        .route(
+            "/api/server-info",
+            get(server_info::get_server_info_handler).with_state(state.clone()),
+        )
+        .route(
+            "/api/events",
+            get(events::get_events_handler).with_state(state.clone()),
+        )
+        .route(
+            "/api/activity",
+            get(activity::get_activity_handler).with_state(state.clone()),
+        )
+        .route(
+            "/api/ws",
+            get(realtime::websocket_handler).with_state(state.clone()),
+        )
         .nest("/api/todos", api_routes)
+        .nest("/api/tags", tag_routes)
+        .nest("/api/lists", list_routes)
+        .nest("/api/attachments", attachment_routes)
+        .nest("/api/signed", signed_routes)
+        .nest("/api/trash", trash_routes)
+        .nest("/api/reviews", review_routes)
+        .nest("/api/palette", palette_routes)
+        .nest("/api/custom-fields", custom_field_routes)
+        .nest("/api/macros", macro_routes)
+        .nest("/api/views", view_routes)
+        .nest("/api/templates", template_routes)
+        .nest("/api/admin", admin_routes)
+        .nest("/api/agenda", agenda_routes)
+        .nest("/api/pomodoro", pomodoro_routes)
+        .nest("/api/import", import_routes)
+        .nest("/api/inbound", inbound_routes)
+        .nest("/api/integrations", integration_routes)
         .nest_service("/", ServeDir::new("static"))
+        .layer(middleware::from_fn(deprecation::deprecation_layer))
+        .layer(middleware::from_fn_with_state(
+            state.todo_list_flights,
+            single_flight::invalidate_after_writes,
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.maintenance,
+            maintenance::maintenance_layer,
+        ))
         .layer(CorsLayer::permissive())
+        .layer(CatchPanicLayer::custom(panic_handler::panic_response))
+        .layer(middleware::from_fn_with_state(
+            state.config.clone(),
+            ip_filter::ip_filter_layer,
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.config,
+            security_headers::security_headers_layer,
+        ))
+        .layer(middleware::from_fn_with_state(
+            access_log_format,
+            access_log::access_log_layer,
+        ))
+}
+
+pub const MAX_PAGE_SIZE: u32 = 1000;
+
+/// Page size for `?cursor=` when no `limit` is given
+pub const DEFAULT_CURSOR_PAGE_SIZE: u32 = 100;
+
+fn bad_request(message: String) -> (StatusCode, Json<Value>) {
+    (StatusCode::BAD_REQUEST, Json(json!({"error": message})))
+}
+
+fn parse_bool(name: &str, value: &str) -> Result<bool, String> {
+    value
+        .parse()
+        .map_err(|_| format!("Invalid value for '{name}': expected true or false"))
+}
+
+// Formatted the way `created_at` is stored, so they compare correctly as text
+fn parse_created_at(name: &str, value: &str) -> Result<String, String> {
+    chrono::DateTime::parse_from_rfc3339(value)
+        .map(|time| time.with_timezone(&chrono::Utc).to_rfc3339())
+        .map_err(|_| format!("Invalid value for '{name}': expected an RFC3339 timestamp"))
+}
+
+fn parse_direction(direction: &str) -> Result<SortDirection, String> {
+    match direction {
+        "asc" => Ok(SortDirection::Asc),
+        "desc" => Ok(SortDirection::Desc),
+        _ => Err(format!("Invalid sort direction '{direction}'")),
+    }
+}
+
+// `?sort=title:asc,created_at:desc` - keys without a direction take `?order=`, which
+// defaults to ascending
+fn parse_sort(value: &str, order: SortDirection) -> Result<Vec<SortKey>, String> {
+    value
+        .split(',')
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            let (field, direction) = match part.split_once(':') {
+                Some((field, direction)) => (field, parse_direction(direction)?),
+                None => (part, order),
+            };
+            let column =
+                database::sort_column(field).ok_or_else(|| format!("Cannot sort by '{field}'"))?;
+            Ok(SortKey { column, direction })
+        })
+        .collect()
+}
+
+// Cursors are opaque to clients; they are hex so they survive a query string unescaped
+fn encode_cursor(todo: &Todo) -> String {
+    hex::encode(format!("{}\n{}", todo.created_at.to_rfc3339(), todo.id))
+}
+
+fn decode_cursor(cursor: &str) -> Result<(String, String), String> {
+    hex::decode(cursor)
+        .ok()
+        .and_then(|bytes| String::from_utf8(bytes).ok())
+        .and_then(|decoded| {
+            let (created_at, id) = decoded.split_once('\n')?;
+            Some((created_at.to_string(), id.to_string()))
+        })
+        .ok_or_else(|| "Invalid cursor".to_string())
+}
+
+// `?cursor=` (empty) starts keyset pagination, later pages pass the `X-Next-Cursor` they got
+fn parse_keyset(filter: &mut TodoFilter, cursor: &str) -> Result<(), String> {
+    if filter.offset != 0 {
+        return Err("cursor cannot be combined with offset".to_string());
+    }
+    let direction = match filter.sort.as_slice() {
+        [] => SortDirection::Asc,
+        [key] if Some(key.column) == database::sort_column("created_at") => key.direction,
+        _ => return Err("cursor only supports sort=created_at:asc or :desc".to_string()),
+    };
+    filter.sort.clear();
+    filter.limit.get_or_insert(DEFAULT_CURSOR_PAGE_SIZE);
+    filter.keyset = Some(Keyset {
+        direction,
+        after: (!cursor.is_empty())
+            .then(|| decode_cursor(cursor))
+            .transpose()?,
+    });
+    Ok(())
 }
 
-async fn get_todos_handler(State(pool): State<DbPool>) -> Result<Json<Vec<Todo>>, StatusCode> {
-    match crate::database::get_todos(&pool) {
-        Ok(todos) => Ok(Json(todos)),
-        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
+// All filters are ANDed; `?metadata.<key>=<value>` pairs become metadata equality filters
+pub fn parse_todo_filter(params: &HashMap<String, String>) -> Result<TodoFilter, String> {
+    let mut filter = TodoFilter::default();
+    for (name, value) in params {
+        if let Some(key) = name.strip_prefix("metadata.") {
+            if !database::is_valid_metadata_key(key) {
+                return Err(format!("Invalid metadata key '{key}'"));
+            }
+            filter.metadata.push((key.to_string(), value.clone()));
+        } else if let Some(field) = name.strip_prefix("field.") {
+            if !database::is_valid_metadata_key(field) {
+                return Err(format!("Invalid custom field name '{field}'"));
+            }
+            filter
+                .custom_fields
+                .push((field.to_string(), value.clone()));
+        }
     }
+    if let Some(completed) = params.get("completed") {
+        filter.completed = Some(parse_bool("completed", completed)?);
+    }
+    if let Some(list_id) = params.get("list") {
+        filter.list_id = Some(list_id.clone());
+    }
+    if let Some(created_before) = params.get("created_before") {
+        filter.created_before = Some(parse_created_at("created_before", created_before)?);
+    }
+    if let Some(created_after) = params.get("created_after") {
+        filter.created_after = Some(parse_created_at("created_after", created_after)?);
+    }
+    filter.title_contains = params
+        .get("q")
+        .map(|q| q.trim())
+        .filter(|q| !q.is_empty())
+        .map(str::to_string);
+    // Archived todos only show up when asked for
+    filter.archived = match params.get("archived").map(String::as_str) {
+        Some("all") => None,
+        Some(archived) => Some(parse_bool("archived", archived)?),
+        None => Some(false),
+    };
+    // So are todos deferred to a future start date
+    filter.scheduled = match params.get("scheduled").map(String::as_str) {
+        Some("all") => None,
+        Some(scheduled) => Some(parse_bool("scheduled", scheduled)?),
+        None => Some(false),
+    };
+    if let Some(review) = params.get("review") {
+        filter.review = Some(ReviewStatus::parse(review).ok_or_else(|| {
+            format!("Invalid review status '{review}': expected pending, approved or rejected")
+        })?);
+    }
+    if let Some(tags) = params.get("tag") {
+        filter.tags = tags
+            .split(',')
+            .map(str::trim)
+            .filter(|tag| !tag.is_empty())
+            .map(str::to_string)
+            .collect();
+    }
+    if let Some(priorities) = params.get("priority") {
+        filter.priorities = priorities
+            .split(',')
+            .filter(|part| !part.is_empty())
+            .map(|part| {
+                Priority::parse(part).ok_or_else(|| {
+                    format!("Invalid priority '{part}': expected low, medium, high or urgent")
+                })
+            })
+            .collect::<Result<_, _>>()?;
+    }
+    if let Some(due_before) = params.get("due_before") {
+        filter.due_before = Some(database::normalize_due_date(due_before)?);
+    }
+    if let Some(due_after) = params.get("due_after") {
+        filter.due_after = Some(database::normalize_due_date(due_after)?);
+    }
+    let order = params
+        .get("order")
+        .map(|order| parse_direction(order))
+        .transpose()?;
+    match (params.get("sort"), order) {
+        (Some(sort), order) => filter.sort = parse_sort(sort, order.unwrap_or(SortDirection::Asc))?,
+        (None, Some(_)) => return Err("order needs a sort field, e.g. sort=due_date".to_string()),
+        (None, None) => {}
+    }
+    if let Some(limit) = params.get("limit") {
+        filter.limit = Some(
+            limit
+                .parse()
+                .ok()
+                .filter(|limit| (1..=MAX_PAGE_SIZE).contains(limit))
+                .ok_or(format!("limit must be between 1 and {MAX_PAGE_SIZE}"))?,
+        );
+    }
+    if let Some(offset) = params.get("offset") {
+        filter.offset = offset
+            .parse()
+            .map_err(|_| "offset must be a non-negative integer".to_string())?;
+    }
+    if let Some(cursor) = params.get("cursor") {
+        parse_keyset(&mut filter, cursor)?;
+    }
+    Ok(filter)
+}
+
+/// Lists todos. When paginated with `limit`, the total number of matches is sent in `X-Total-Count`.
+/// With `?cursor=`, a full page also sends the cursor of the next one in `X-Next-Cursor`.
+/// Clients that send `Accept: application/x-ndjson` get the rows streamed as NDJSON instead.
+async fn get_todos_handler(
+    State(pool): State<DbPool>,
+    State(flights): State<TodoListFlights>,
+    headers: HeaderMap,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<Response, (StatusCode, Json<Value>)> {
+    let filter = parse_todo_filter(&params).map_err(bad_request)?;
+    let language = humanize_language(&params, &headers).map_err(bad_request)?;
+    let wants_ndjson = headers
+        .get(header::ACCEPT)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|accept| accept.contains(export::NDJSON_CONTENT_TYPE));
+    if wants_ndjson {
+        return Ok(export::stream_todos(pool, filter, ExportFormat::Ndjson));
+    }
+
+    // Identical concurrent requests (e.g. a wall of dashboards) share a single query
+    let mut key = operation_fingerprint("GET /api/todos", &params);
+    if let Some(language) = language {
+        key.push_str(&format!(" lang={}", language.as_str()));
+    }
+    // The query runs on the blocking pool so the runtime stays free to accept the followers
+    let page = flights
+        .run(key, async move {
+            tokio::task::spawn_blocking(move || load_todo_list(&pool, &filter, language))
+                .await
+                .unwrap_or(Err(()))
+        })
+        .await
+        .map_err(|_| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": "Failed to get todos"})),
+            )
+        })?;
+
+    if etag::not_modified(&headers, &page.etag) {
+        return Ok(etag::not_modified_response(&page.etag));
+    }
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/json"),
+    );
+    etag::insert(&mut headers, &page.etag);
+    if let Some(total) = page.total {
+        headers.insert("x-total-count", HeaderValue::from(total));
+    }
+    if let Some(cursor) = page
+        .next_cursor
+        .and_then(|cursor| HeaderValue::try_from(cursor).ok())
+    {
+        headers.insert("x-next-cursor", cursor);
+    }
+    if let Some(language) = language {
+        insert_language_headers(&mut headers, language);
+    }
+    Ok((headers, page.body).into_response())
+}
+
+/// `?humanize=true` adds relative times in the client's `Accept-Language`; `None` when off.
+fn humanize_language(
+    params: &HashMap<String, String>,
+    headers: &HeaderMap,
+) -> Result<Option<Language>, String> {
+    let humanize = match params.get("humanize") {
+        Some(value) => parse_bool("humanize", value)?,
+        None => false,
+    };
+    let accept_language = headers
+        .get(header::ACCEPT_LANGUAGE)
+        .and_then(|value| value.to_str().ok());
+    Ok(humanize.then(|| Language::from_accept_language(accept_language)))
+}
+
+fn insert_language_headers(headers: &mut HeaderMap, language: Language) {
+    headers.insert(
+        header::CONTENT_LANGUAGE,
+        HeaderValue::from_static(language.as_str()),
+    );
+    headers.insert(header::VARY, HeaderValue::from_static("accept-language"));
+}
+
+/// A serialized list response, cheap to clone for every coalesced request.
+#[derive(Clone)]
+pub struct TodoListPage {
+    body: Bytes,
+    total: Option<usize>,
+    next_cursor: Option<String>,
+    etag: String,
+}
+
+pub type TodoListFlights = SingleFlight<Result<TodoListPage, ()>>;
+
+fn load_todo_list(
+    pool: &DbPool,
+    filter: &TodoFilter,
+    language: Option<Language>,
+) -> Result<TodoListPage, ()> {
+    let (todos, total) = if filter.limit.is_some() {
+        let (todos, total) = database::get_todos_page(pool, filter).map_err(|_| ())?;
+        (todos, Some(total))
+    } else {
+        (database::get_todos(pool, filter).map_err(|_| ())?, None)
+    };
+    // A short page is the last one
+    let next_cursor = filter
+        .keyset
+        .as_ref()
+        .filter(|_| {
+            filter
+                .limit
+                .is_some_and(|limit| todos.len() >= limit as usize)
+        })
+        .and(todos.last())
+        .map(encode_cursor);
+    let body = match language {
+        Some(language) => {
+            let now = chrono::Utc::now();
+            let todos: Vec<Value> = todos
+                .iter()
+                .map(|todo| humanize::humanize_todo(todo, language, now))
+                .collect();
+            serde_json::to_vec(&todos)
+        }
+        None => serde_json::to_vec(&todos),
+    }
+    .map_err(|_| ())?;
+    let total_text = total.map(|total| total.to_string()).unwrap_or_default();
+    let etag = etag::of(&[
+        &body,
+        total_text.as_bytes(),
+        next_cursor.as_deref().unwrap_or_default().as_bytes(),
+    ]);
+    Ok(TodoListPage {
+        body: Bytes::from(body),
+        total,
+        next_cursor,
+        etag,
+    })
+}
+
+/// Streams all matching todos as NDJSON (default) or `?format=json` without buffering them.
+async fn export_todos_handler(
+    State(pool): State<DbPool>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<Response, (StatusCode, Json<Value>)> {
+    let filter = parse_todo_filter(&params).map_err(bad_request)?;
+    let format = match params.get("format") {
+        None => ExportFormat::Ndjson,
+        Some(format) => ExportFormat::parse(format)
+            .ok_or_else(|| bad_request(format!("Unknown export format '{format}'")))?,
+    };
+    Ok(export::stream_todos(pool, filter, format))
+}
+
+/// Todos deferred to a future start date, the soonest to start first; takes the same query
+/// parameters as `GET /api/todos`.
+async fn get_scheduled_todos_handler(
+    State(pool): State<DbPool>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<Json<Vec<Todo>>, (StatusCode, Json<Value>)> {
+    let mut filter = parse_todo_filter(&params).map_err(bad_request)?;
+    filter.scheduled = Some(true);
+    if filter.sort.is_empty() {
+        filter.sort = vec![SortKey {
+            column: "start_date",
+            direction: SortDirection::Asc,
+        }];
+    }
+    database::get_todos(&pool, &filter).map(Json).map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Failed to get todos"})),
+        )
+    })
 }
 
+/// Totals, counts per tag and list, and recent completions for dashboards, over the todos
+/// matching the same filters as `GET /api/todos`.
+async fn get_todo_stats_handler(
+    State(pool): State<DbPool>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<Json<TodoStats>, (StatusCode, Json<Value>)> {
+    let filter = parse_todo_filter(&params).map_err(bad_request)?;
+    match database::get_todo_stats(&pool, &filter) {
+        Ok(stats) => Ok(Json(stats)),
+        Err(_) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Failed to compute todo stats"})),
+        )),
+    }
+}
+
+async fn count_todos_handler(
+    State(pool): State<DbPool>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let filter = parse_todo_filter(&params).map_err(bad_request)?;
+    match database::count_todos(&pool, &filter) {
+        Ok(count) => Ok(Json(json!({"count": count}))),
+        Err(_) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Failed to count todos"})),
+        )),
+    }
+}
+
+/// Checks a new todo's fields and normalizes its dates in place.
+pub fn validate_create_todo(create_todo: &mut CreateTodo) -> Result<(), String> {
+    if let Some(metadata) = &create_todo.metadata {
+        database::validate_metadata(metadata)?;
+    }
+    if let Some(due_date) = &create_todo.due_date {
+        create_todo.due_date = Some(database::normalize_due_date(due_date)?);
+    }
+    if let Some(start_date) = &create_todo.start_date {
+        create_todo.start_date = Some(database::normalize_start_date(start_date)?);
+    }
+    if let Some(remind_at) = &create_todo.remind_at {
+        create_todo.remind_at = Some(database::normalize_remind_at(remind_at)?);
+    }
+    if let Some(icon) = &create_todo.icon {
+        create_todo.icon = Some(icons::validate_icon(icon)?);
+    }
+    if let Some(recurrence) = &create_todo.recurrence {
+        create_todo.recurrence = Some(recurrence::normalize_recurrence(recurrence)?);
+    }
+    if let Some(estimate) = create_todo.estimate_minutes {
+        database::validate_estimate(estimate)?;
+    }
+    if let Some(tags) = &create_todo.tags {
+        create_todo.tags = Some(database::normalize_tag_names(tags)?);
+    }
+    Ok(())
+}
+
+#[derive(Debug, Deserialize)]
+struct CreateTodoParams {
+    #[serde(default)]
+    dedupe: bool,
+}
+
+/// Creates a todo. With an `Idempotency-Key`, a retry of a request that already created one
+/// gets the original response instead of a second todo.
 async fn create_todo_handler(
     State(pool): State<DbPool>,
-    Json(create_todo): Json<CreateTodo>,
-) -> Result<(StatusCode, Json<Todo>), (StatusCode, Json<Value>)> {
-    match crate::database::create_todo(&pool, create_todo) {
-        Ok(todo) => Ok((StatusCode::CREATED, Json(todo))),
+    Query(params): Query<CreateTodoParams>,
+    headers: HeaderMap,
+    JsonBody(mut create_todo): JsonBody<CreateTodo>,
+) -> Result<Response, (StatusCode, Json<Value>)> {
+    validate_create_todo(&mut create_todo).map_err(bad_request)?;
+    let list = match &create_todo.list_id {
+        Some(list_id) => Some(lists::ensure_list_exists(&pool, list_id)?),
+        None => None,
+    };
+    if let Some(values) = &create_todo.custom_fields {
+        custom_fields::check_values(&pool, values)?;
+    }
+    let idempotency_key = idempotency::key(&headers)?;
+    if let Some(key) = &idempotency_key {
+        // Hashed after validation, so requests that only differ in how a date is written match
+        let body = serde_json::to_vec(&create_todo).unwrap_or_default();
+        let dedupe = [u8::from(params.dedupe)];
+        let hash = idempotency::request_hash(&[b"POST /api/todos", &body, &dedupe]);
+        if let Some(replay) = idempotency::claim(&pool, key, &hash)? {
+            return Ok(replay);
+        }
+    }
+    // Applied after hashing, so a retry still matches when the list's defaults changed since
+    if let Some(list) = &list {
+        lists::apply_defaults(list, &mut create_todo);
+    }
+    let created = insert_todo(&pool, params.dedupe, &headers, create_todo);
+    if let Some(key) = &idempotency_key {
+        match &created {
+            Ok(todo) => idempotency::complete(&pool, key, StatusCode::CREATED, todo),
+            Err(_) => idempotency::release(&pool, key),
+        }
+    }
+    created.map(|todo| (StatusCode::CREATED, Json(todo)).into_response())
+}
+
+fn insert_todo(
+    pool: &DbPool,
+    dedupe: bool,
+    headers: &HeaderMap,
+    create_todo: CreateTodo,
+) -> Result<Todo, (StatusCode, Json<Value>)> {
+    let created = if dedupe {
+        database::create_todo_unless_duplicate(pool, create_todo)
+    } else {
+        database::create_todo(pool, create_todo).map(Deduplicated::Created)
+    };
+    match created {
+        Ok(Deduplicated::Created(todo)) => {
+            activity::record_todo(pool, headers, "create", &todo);
+            Ok(todo)
+        }
+        Ok(Deduplicated::Duplicate(existing)) => Err((
+            StatusCode::CONFLICT,
+            Json(json!({"error": "A similar open todo already exists", "existing": existing})),
+        )),
         Err(_) => Err((
             StatusCode::INTERNAL_SERVER_ERROR,
             Json(json!({"error": "Failed to create todo"})),
@@ -51,9 +1043,31 @@ async fn create_todo_handler(
 async fn get_todo_handler(
     State(pool): State<DbPool>,
     Path(id): Path<String>,
-) -> Result<Json<Todo>, (StatusCode, Json<Value>)> {
+    headers: HeaderMap,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<Response, (StatusCode, Json<Value>)> {
+    let language = humanize_language(&params, &headers).map_err(bad_request)?;
     match crate::database::get_todo(&pool, &id) {
-        Ok(Some(todo)) => Ok(Json(todo)),
+        Ok(Some(todo)) => {
+            let mut etag = etag::of_todo(&todo);
+            // Each language is a representation of its own
+            if let Some(language) = language {
+                etag = etag::of(&[etag.as_bytes(), language.as_str().as_bytes()]);
+            }
+            if etag::not_modified(&headers, &etag) {
+                return Ok(etag::not_modified_response(&etag));
+            }
+            let mut response_headers = HeaderMap::new();
+            etag::insert(&mut response_headers, &etag);
+            match language {
+                Some(language) => {
+                    insert_language_headers(&mut response_headers, language);
+                    let todo = humanize::humanize_todo(&todo, language, chrono::Utc::now());
+                    Ok((response_headers, Json(todo)).into_response())
+                }
+                None => Ok((response_headers, Json(todo)).into_response()),
+            }
+        }
         Ok(None) => Err((
             StatusCode::NOT_FOUND,
             Json(json!({"error": "Todo not found"})),
@@ -65,13 +1079,153 @@ async fn get_todo_handler(
     }
 }
 
+#[derive(Debug, Deserialize)]
+struct UpdateTodoParams {
+    /// Completing a todo also completes all of its subtasks
+    #[serde(default)]
+    cascade: bool,
+    /// Who is saving, so their own edit lock does not get in the way
+    holder: Option<String>,
+    /// Save even though someone else holds the edit lock
+    #[serde(default)]
+    force: bool,
+}
+
+/// Validates an update and normalizes its dates, icon and recurrence rule in place.
+pub fn prepare_update(
+    pool: &DbPool,
+    update: &mut UpdateTodo,
+) -> Result<(), (StatusCode, Json<Value>)> {
+    if let Some(metadata) = &update.metadata {
+        database::validate_metadata(metadata).map_err(bad_request)?;
+    }
+    if let Some(values) = &update.custom_fields {
+        custom_fields::check_values(pool, values)?;
+    }
+    if let Some(due_date) = update.due_date.as_deref().filter(|due| !due.is_empty()) {
+        update.due_date = Some(database::normalize_due_date(due_date).map_err(bad_request)?);
+    }
+    if let Some(start_date) = update
+        .start_date
+        .as_deref()
+        .filter(|start| !start.is_empty())
+    {
+        update.start_date = Some(database::normalize_start_date(start_date).map_err(bad_request)?);
+    }
+    if let Some(remind_at) = update
+        .remind_at
+        .as_deref()
+        .filter(|remind_at| !remind_at.is_empty())
+    {
+        update.remind_at = Some(database::normalize_remind_at(remind_at).map_err(bad_request)?);
+    }
+    if let Some(icon) = update.icon.as_deref().filter(|icon| !icon.is_empty()) {
+        update.icon = Some(icons::validate_icon(icon).map_err(bad_request)?);
+    }
+    if let Some(estimate) = update.estimate_minutes.filter(|estimate| *estimate > 0) {
+        database::validate_estimate(estimate).map_err(bad_request)?;
+    }
+    if let Some(rule) = update.recurrence.as_deref().filter(|rule| !rule.is_empty()) {
+        update.recurrence = Some(recurrence::normalize_recurrence(rule).map_err(bad_request)?);
+    }
+    if let Some(list_id) = update
+        .list_id
+        .as_deref()
+        .filter(|list_id| !list_id.is_empty())
+    {
+        lists::ensure_list_exists(pool, list_id)?;
+    }
+    Ok(())
+}
+
 async fn update_todo_handler(
     State(pool): State<DbPool>,
+    State(config): State<Arc<Config>>,
+    Path(id): Path<String>,
+    Query(params): Query<UpdateTodoParams>,
+    headers: HeaderMap,
+    JsonBody(mut update): JsonBody<UpdateTodo>,
+) -> Result<(HeaderMap, Json<Todo>), (StatusCode, Json<Value>)> {
+    prepare_update(&pool, &mut update)?;
+    let if_match = etag::if_match(&headers, config.require_if_match)?;
+    locks::check_lock(&pool, &id, params.holder.as_deref(), params.force)?;
+    let completed = update.completed == Some(true);
+    let cascade = params.cascade && completed;
+    let precondition = etag::precondition(if_match.as_deref());
+    let updated = match database::update_todo_if(&pool, &id, update, precondition) {
+        Ok(Conditional::Changed(change)) => Ok(Some(change)),
+        Ok(Conditional::Failed(current)) => return Err(etag::precondition_failed(&current)),
+        Ok(Conditional::NotFound) => Ok(None),
+        Err(e) => Err(e),
+    };
+    let updated = updated.and_then(|change| match change {
+        Some((before, _)) if cascade => {
+            database::complete_descendants(&pool, &id)?;
+            Ok(database::get_todo(&pool, &id)?.map(|after| (before, after)))
+        }
+        change => Ok(change),
+    });
+    match updated {
+        Ok(Some((before, todo))) => {
+            if completed {
+                recurrence::notify_completed();
+            }
+            // Only closing an open todo counts as completing it in the activity log
+            let action = if !before.completed && todo.completed {
+                "complete"
+            } else {
+                "update"
+            };
+            activity::record_todo_change(&pool, &headers, action, &before, &todo);
+            Ok(with_etag(todo))
+        }
+        Ok(None) => Err((
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": "Todo not found"})),
+        )),
+        Err(_) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Failed to update todo"})),
+        )),
+    }
+}
+
+/// Completes an open todo or reopens a completed one, without the client having to read it
+/// first.
+async fn toggle_completed_handler(
+    State(pool): State<DbPool>,
+    State(config): State<Arc<Config>>,
     Path(id): Path<String>,
-    Json(update): Json<UpdateTodo>,
-) -> Result<Json<Todo>, (StatusCode, Json<Value>)> {
-    match crate::database::update_todo(&pool, &id, update) {
-        Ok(Some(todo)) => Ok(Json(todo)),
+    Query(params): Query<UpdateTodoParams>,
+    headers: HeaderMap,
+) -> Result<(HeaderMap, Json<Todo>), (StatusCode, Json<Value>)> {
+    let if_match = etag::if_match(&headers, config.require_if_match)?;
+    locks::check_lock(&pool, &id, params.holder.as_deref(), params.force)?;
+    let toggled =
+        match database::toggle_completed(&pool, &id, etag::precondition(if_match.as_deref())) {
+            Ok(Conditional::Changed(change)) => Ok(Some(change)),
+            Ok(Conditional::Failed(current)) => return Err(etag::precondition_failed(&current)),
+            Ok(Conditional::NotFound) => Ok(None),
+            Err(e) => Err(e),
+        };
+    let toggled = toggled.and_then(|change| match change {
+        Some((before, after)) if params.cascade && after.completed => {
+            database::complete_descendants(&pool, &id)?;
+            Ok(database::get_todo(&pool, &id)?.map(|after| (before, after)))
+        }
+        change => Ok(change),
+    });
+    match toggled {
+        Ok(Some((before, todo))) => {
+            let action = if todo.completed {
+                recurrence::notify_completed();
+                "complete"
+            } else {
+                "update"
+            };
+            activity::record_todo_change(&pool, &headers, action, &before, &todo);
+            Ok(with_etag(todo))
+        }
         Ok(None) => Err((
             StatusCode::NOT_FOUND,
             Json(json!({"error": "Todo not found"})),
@@ -83,13 +1237,121 @@ async fn update_todo_handler(
     }
 }
 
+// The todo with its `ETag`, for the next change to send in `If-Match`
+pub fn with_etag(todo: Todo) -> (HeaderMap, Json<Todo>) {
+    let mut headers = HeaderMap::new();
+    etag::insert(&mut headers, &etag::of_todo(&todo));
+    (headers, Json(todo))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MoveTodo {
+    pub after: Option<String>,
+    pub before: Option<String>,
+    pub index: Option<u32>,
+}
+
+/// Persists drag-and-drop ordering: places the todo right after or before another todo,
+/// or at an index of the full list. Todo lists are returned in this order by default.
+async fn move_todo_handler(
+    State(pool): State<DbPool>,
+    State(config): State<Arc<Config>>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+    JsonBody(body): JsonBody<MoveTodo>,
+) -> Result<(HeaderMap, Json<Todo>), (StatusCode, Json<Value>)> {
+    let if_match = etag::if_match(&headers, config.require_if_match)?;
+    let target = match (body.after, body.before, body.index) {
+        (Some(after), None, None) => MoveTarget::After(after),
+        (None, Some(before), None) => MoveTarget::Before(before),
+        (None, None, Some(index)) => MoveTarget::Index(index),
+        _ => {
+            return Err(bad_request(
+                "Specify exactly one of `after`, `before` or `index`".to_string(),
+            ))
+        }
+    };
+    if matches!(&target, MoveTarget::After(other) | MoveTarget::Before(other) if *other == id) {
+        return Err(bad_request(
+            "A todo cannot be moved next to itself".to_string(),
+        ));
+    }
+    match database::move_todo(&pool, &id, &target, etag::precondition(if_match.as_deref())) {
+        Ok(Conditional::Changed(Some(todo))) => Ok(with_etag(todo)),
+        Ok(Conditional::Changed(None)) => Err(bad_request(
+            "The todo to move next to does not exist".to_string(),
+        )),
+        Ok(Conditional::Failed(current)) => Err(etag::precondition_failed(&current)),
+        Ok(Conditional::NotFound) => Err((
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": "Todo not found"})),
+        )),
+        Err(_) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Failed to move todo"})),
+        )),
+    }
+}
+
+/// Pins the todo to the top of every listing, or unpins it if it already is.
+async fn toggle_pin_handler(
+    State(pool): State<DbPool>,
+    State(config): State<Arc<Config>>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+) -> Result<(HeaderMap, Json<Todo>), (StatusCode, Json<Value>)> {
+    let if_match = etag::if_match(&headers, config.require_if_match)?;
+    match database::toggle_pin(&pool, &id, etag::precondition(if_match.as_deref())) {
+        Ok(Conditional::Changed(todo)) => Ok(with_etag(todo)),
+        Ok(Conditional::Failed(current)) => Err(etag::precondition_failed(&current)),
+        Ok(Conditional::NotFound) => Err((
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": "Todo not found"})),
+        )),
+        Err(_) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Failed to pin todo"})),
+        )),
+    }
+}
+
+/// Copies a todo with its subtasks, tags, checklist and dependencies as a new open todo.
+async fn duplicate_todo_handler(
+    State(pool): State<DbPool>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+) -> Result<(StatusCode, Json<Todo>), (StatusCode, Json<Value>)> {
+    match database::duplicate_todo(&pool, &id) {
+        Ok(Some(todo)) => {
+            activity::record_todo(&pool, &headers, "create", &todo);
+            Ok((StatusCode::CREATED, Json(todo)))
+        }
+        Ok(None) => Err((
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": "Todo not found"})),
+        )),
+        Err(_) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Failed to duplicate todo"})),
+        )),
+    }
+}
+
 async fn delete_todo_handler(
     State(pool): State<DbPool>,
+    State(config): State<Arc<Config>>,
     Path(id): Path<String>,
+    headers: HeaderMap,
 ) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
-    match crate::database::delete_todo(&pool, &id) {
-        Ok(true) => Ok(Json(json!({"message": "Todo deleted successfully"}))),
-        Ok(false) => Err((
+    let if_match = etag::if_match(&headers, config.require_if_match)?;
+    match database::delete_todo(&pool, &id, etag::precondition(if_match.as_deref())) {
+        Ok(Conditional::Changed(todo)) => {
+            // The activity log records the todo as it was
+            activity::record_todo(&pool, &headers, "delete", &todo);
+            Ok(Json(json!({"message": "Todo deleted successfully"})))
+        }
+        Ok(Conditional::Failed(current)) => Err(etag::precondition_failed(&current)),
+        Ok(Conditional::NotFound) => Err((
             StatusCode::NOT_FOUND,
             Json(json!({"error": "Todo not found"})),
         )),
@@ -99,3 +1361,122 @@ async fn delete_todo_handler(
         )),
     }
 }
+
+fn delete_todos_by_id(
+    pool: &DbPool,
+    params: &HashMap<String, String>,
+    headers: &HeaderMap,
+    ids: Vec<String>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    if !params.is_empty() {
+        return Err(bad_request(
+            "Send either filters in the query or `ids` in the body, not both".to_string(),
+        ));
+    }
+    if ids.is_empty() {
+        return Err(bad_request("`ids` must not be empty".to_string()));
+    }
+    if ids.len() > bulk::MAX_ITEMS {
+        return Err((
+            StatusCode::PAYLOAD_TOO_LARGE,
+            Json(
+                json!({"error": format!("A bulk request can delete at most {} todos", bulk::MAX_ITEMS)}),
+            ),
+        ));
+    }
+    let filter = TodoFilter {
+        ids: Some(ids.clone()),
+        ..Default::default()
+    };
+    match database::delete_todos(pool, &filter) {
+        Ok(deleted) => {
+            activity::record(
+                pool,
+                headers,
+                "bulk_delete",
+                None,
+                json!({"ids": ids, "deleted": deleted}),
+            );
+            Ok(Json(json!({"deleted": deleted})))
+        }
+        Err(_) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Failed to delete todos"})),
+        )),
+    }
+}
+
+// Identifies a bulk operation by its (sorted) query, so a token only confirms what was previewed
+fn operation_fingerprint(method: &str, params: &HashMap<String, String>) -> String {
+    let mut pairs: Vec<_> = params
+        .iter()
+        .filter(|(name, _)| name.as_str() != "confirm")
+        .map(|(name, value)| format!("{name}={value}"))
+        .collect();
+    pairs.sort();
+    format!("{method} {}", pairs.join("&"))
+}
+
+#[derive(Debug, Deserialize)]
+struct DeleteTodos {
+    ids: Vec<String>,
+}
+
+/// Bulk delete by filter. Without `?confirm=` this is a dry run that reports how many
+/// todos match and issues a token; repeating the same request with that token deletes them.
+/// A body listing `ids` deletes exactly those todos right away instead.
+async fn delete_todos_handler(
+    State(pool): State<DbPool>,
+    State(confirmations): State<ConfirmationTokens>,
+    Query(params): Query<HashMap<String, String>>,
+    headers: HeaderMap,
+    OptionalJsonBody(body): OptionalJsonBody<DeleteTodos>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    if let Some(body) = body {
+        return delete_todos_by_id(&pool, &params, &headers, body.ids);
+    }
+    let filter = parse_todo_filter(&params).map_err(bad_request)?;
+    let fingerprint = operation_fingerprint("DELETE /api/todos", &params);
+
+    let Some(token) = params.get("confirm") else {
+        return match database::count_todos(&pool, &filter) {
+            Ok(matched) => Ok(Json(json!({
+                "dry_run": true,
+                "matched": matched,
+                "confirmation_token": confirmations.issue(fingerprint),
+                "expires_in_seconds": confirmation::TOKEN_TTL.as_secs(),
+            }))),
+            Err(_) => Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": "Failed to count todos"})),
+            )),
+        };
+    };
+
+    if !confirmations.consume(token, &fingerprint) {
+        return Err((
+            StatusCode::PRECONDITION_FAILED,
+            Json(
+                json!({"error": "Invalid or expired confirmation token; run the request without `confirm` first"}),
+            ),
+        ));
+    }
+    match database::delete_todos(&pool, &filter) {
+        Ok(deleted) => {
+            let mut filter_params = params.clone();
+            filter_params.remove("confirm");
+            activity::record(
+                &pool,
+                &headers,
+                "bulk_delete",
+                None,
+                json!({"filter": filter_params, "deleted": deleted}),
+            );
+            Ok(Json(json!({"deleted": deleted})))
+        }
+        Err(_) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Failed to delete todos"})),
+        )),
+    }
+}
@@ -0,0 +1,330 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode},
+    response::Json,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+
+use crate::activity;
+use crate::config::Config;
+use crate::database::{self, Conditional, DbPool, Todo, TodoRevision, UpdateTodo};
+use crate::etag;
+use crate::handlers;
+use crate::locks;
+
+const DEFAULT_LIMIT: u32 = 50;
+pub const MAX_LIMIT: u32 = 500;
+// Largest table of line pairs compared for a description diff; longer descriptions show the
+// differing part as removed and added as a whole
+const MAX_DIFF_CELLS: usize = 1_000_000;
+
+#[derive(Debug, Deserialize)]
+pub struct HistoryParams {
+    pub limit: Option<u32>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RevertParams {
+    /// The revision to go back to
+    pub to: Option<i64>,
+    /// Who is reverting, so their own edit lock does not get in the way
+    pub holder: Option<String>,
+    /// Revert even though someone else holds the edit lock
+    #[serde(default)]
+    pub force: bool,
+}
+
+fn error(status: StatusCode, message: &str) -> (StatusCode, Json<Value>) {
+    (status, Json(json!({"error": message})))
+}
+
+// `404` unless the todo exists, in the trash or not
+fn ensure_todo_exists(pool: &DbPool, id: &str) -> Result<(), (StatusCode, Json<Value>)> {
+    let failed = |_| error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to get todo");
+    let exists = database::get_todo(pool, id).map_err(failed)?.is_some()
+        || database::get_trashed_todo(pool, id)
+            .map_err(failed)?
+            .is_some();
+    if !exists {
+        return Err(error(StatusCode::NOT_FOUND, "Todo not found"));
+    }
+    Ok(())
+}
+
+/// The changes made to a todo and the escalations applied to it, newest first. Also works for
+/// todos in the trash.
+pub async fn get_todo_history_handler(
+    State(pool): State<DbPool>,
+    Path(id): Path<String>,
+    Query(params): Query<HistoryParams>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let limit = params.limit.unwrap_or(DEFAULT_LIMIT);
+    if !(1..=MAX_LIMIT).contains(&limit) {
+        return Err(error(
+            StatusCode::BAD_REQUEST,
+            &format!("`limit` must be between 1 and {MAX_LIMIT}"),
+        ));
+    }
+    ensure_todo_exists(&pool, &id)?;
+    let revisions = database::get_todo_revisions(&pool, &id, limit)
+        .map_err(|_| error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to get history"))?;
+    let escalations = database::get_todo_escalations(&pool, &id, limit)
+        .map_err(|_| error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to get history"))?;
+    Ok(Json(
+        json!({"todo_id": id, "revisions": revisions, "escalations": escalations}),
+    ))
+}
+
+/// One revision of a todo as a diff: the fields it changed, in the order of
+/// `REVISION_FIELDS`, and for the description the lines that were removed and added.
+pub async fn get_revision_diff_handler(
+    State(pool): State<DbPool>,
+    Path((id, revision)): Path<(String, i64)>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    ensure_todo_exists(&pool, &id)?;
+    let revision = database::get_todo_revision(&pool, &id, revision)
+        .map_err(|_| error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to get history"))?
+        .ok_or_else(|| error(StatusCode::NOT_FOUND, "Revision not found"))?;
+    let fields: Vec<Value> = database::REVISION_FIELDS
+        .iter()
+        .filter_map(|field| {
+            let change = revision.changes.get(*field)?;
+            let (from, to) = (&change["from"], &change["to"]);
+            let mut diff = json!({"field": field, "from": from, "to": to});
+            if *field == "description" {
+                let text = |value: &Value| value.as_str().unwrap_or_default().to_string();
+                diff["lines"] = json!(line_diff(&text(from), &text(to)));
+            }
+            Some(diff)
+        })
+        .collect();
+    Ok(Json(json!({
+        "todo_id": id,
+        "revision": revision.id,
+        "changed_at": revision.changed_at,
+        "fields": fields,
+    })))
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum DiffOp {
+    Equal,
+    Delete,
+    Insert,
+}
+
+/// A line of a text diff
+#[derive(Debug, Serialize, PartialEq)]
+pub struct DiffLine {
+    pub op: DiffOp,
+    pub text: String,
+}
+
+/// The lines of `to` compared with those of `from`: unchanged lines are `equal`, lines only in
+/// `from` are `delete` and lines only in `to` are `insert`, in reading order.
+pub fn line_diff(from: &str, to: &str) -> Vec<DiffLine> {
+    let from: Vec<&str> = from.lines().collect();
+    let to: Vec<&str> = to.lines().collect();
+    let line = |op, text: &str| DiffLine {
+        op,
+        text: text.to_string(),
+    };
+    // Edits are usually local, so only the part between the common ends is compared
+    let prefix = from.iter().zip(&to).take_while(|(a, b)| a == b).count();
+    let suffix = from[prefix..]
+        .iter()
+        .rev()
+        .zip(to[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let old = &from[prefix..from.len() - suffix];
+    let new = &to[prefix..to.len() - suffix];
+
+    let mut diff: Vec<DiffLine> = from[..prefix]
+        .iter()
+        .map(|text| line(DiffOp::Equal, text))
+        .collect();
+    let (mut i, mut j) = (0, 0);
+    if old.len() * new.len() <= MAX_DIFF_CELLS {
+        // `lengths[i * width + j]`: the longest common subsequence of `old[i..]` and `new[j..]`
+        let width = new.len() + 1;
+        let mut lengths = vec![0u32; (old.len() + 1) * width];
+        for i in (0..old.len()).rev() {
+            for j in (0..new.len()).rev() {
+                lengths[i * width + j] = if old[i] == new[j] {
+                    lengths[(i + 1) * width + j + 1] + 1
+                } else {
+                    lengths[(i + 1) * width + j].max(lengths[i * width + j + 1])
+                };
+            }
+        }
+        while i < old.len() && j < new.len() {
+            if old[i] == new[j] {
+                diff.push(line(DiffOp::Equal, old[i]));
+                i += 1;
+                j += 1;
+            } else if lengths[(i + 1) * width + j] >= lengths[i * width + j + 1] {
+                diff.push(line(DiffOp::Delete, old[i]));
+                i += 1;
+            } else {
+                diff.push(line(DiffOp::Insert, new[j]));
+                j += 1;
+            }
+        }
+    }
+    diff.extend(old[i..].iter().map(|text| line(DiffOp::Delete, text)));
+    diff.extend(new[j..].iter().map(|text| line(DiffOp::Insert, text)));
+    diff.extend(
+        from[from.len() - suffix..]
+            .iter()
+            .map(|text| line(DiffOp::Equal, text)),
+    );
+    diff
+}
+
+/// Puts the fields kept in a todo's history back to how they were right after revision `to`.
+/// The revert is an update like any other, so it is recorded as a new revision and can itself
+/// be reverted.
+pub async fn revert_todo_handler(
+    State(pool): State<DbPool>,
+    State(config): State<Arc<Config>>,
+    Path(id): Path<String>,
+    Query(params): Query<RevertParams>,
+    headers: HeaderMap,
+) -> Result<(HeaderMap, Json<Todo>), (StatusCode, Json<Value>)> {
+    let Some(to) = params.to else {
+        return Err(error(
+            StatusCode::BAD_REQUEST,
+            "`to` must name the revision to revert to",
+        ));
+    };
+    let if_match = etag::if_match(&headers, config.require_if_match)?;
+    locks::check_lock(&pool, &id, params.holder.as_deref(), params.force)?;
+    let failed = |_| error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to revert todo");
+    let current = database::get_todo(&pool, &id)
+        .map_err(failed)?
+        .ok_or_else(|| error(StatusCode::NOT_FOUND, "Todo not found"))?;
+    if !etag::precondition(if_match.as_deref())(&current) {
+        return Err(etag::precondition_failed(&current));
+    }
+    let newer = database::get_todo_revisions_after(&pool, &id, to)
+        .map_err(failed)?
+        .ok_or_else(|| error(StatusCode::NOT_FOUND, "Revision not found"))?;
+    let mut update = revert_update(&current, &newer).map_err(failed)?;
+    // Values from back then may no longer be valid, e.g. a list that was deleted since
+    handlers::prepare_update(&pool, &mut update)?;
+
+    // The update was worked out from the todo as read, so it only applies to that state
+    let read = etag::of_todo(&current);
+    match database::update_todo_if(&pool, &id, update, |todo| etag::of_todo(todo) == read) {
+        Ok(Conditional::Changed((before, after))) => {
+            let action = if !before.completed && after.completed {
+                "complete"
+            } else {
+                "update"
+            };
+            activity::record_todo_change(&pool, &headers, action, &before, &after);
+            Ok(handlers::with_etag(after))
+        }
+        Ok(Conditional::Failed(current)) if if_match.is_some() => {
+            Err(etag::precondition_failed(&current))
+        }
+        Ok(Conditional::Failed(_)) => Err(error(
+            StatusCode::CONFLICT,
+            "The todo changed while it was being reverted; try again",
+        )),
+        Ok(Conditional::NotFound) => Err(error(StatusCode::NOT_FOUND, "Todo not found")),
+        Err(e) => Err(failed(e)),
+    }
+}
+
+// The update that undoes `newer`, the revisions made after the one reverted to, newest first
+fn revert_update(
+    current: &Todo,
+    newer: &[TodoRevision],
+) -> Result<UpdateTodo, Box<dyn std::error::Error + Send + Sync>> {
+    let Value::Object(now) = serde_json::to_value(current)? else {
+        return Ok(UpdateTodo::default());
+    };
+    let mut then = now.clone();
+    for revision in newer {
+        for (field, change) in &revision.changes {
+            then.insert(field.clone(), change["from"].clone());
+        }
+    }
+    let mut patch = Map::new();
+    for field in database::REVISION_FIELDS {
+        let (from, to) = (&now[field], &then[field]);
+        if from == to {
+            continue;
+        }
+        let value = match (field, to) {
+            // Custom fields are merged, so values added since are removed explicitly
+            ("custom_fields", Value::Object(values)) => {
+                let mut values = values.clone();
+                for key in from.as_object().into_iter().flat_map(Map::keys) {
+                    values.entry(key.clone()).or_insert(Value::Null);
+                }
+                Value::Object(values)
+            }
+            ("description", to) => to.clone(),
+            ("estimate_minutes", Value::Null) => json!(0),
+            (_, Value::Null) => json!(""),
+            (_, to) => to.clone(),
+        };
+        patch.insert(field.to_string(), value);
+    }
+    Ok(serde_json::from_value(Value::Object(patch))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ops(diff: &[DiffLine]) -> Vec<(&DiffOp, &str)> {
+        diff.iter()
+            .map(|line| (&line.op, line.text.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn diffs_changed_lines_between_common_ones() {
+        let diff = line_diff("a\nb\nc\nd", "a\nx\nc\nd\ne");
+        assert_eq!(
+            ops(&diff),
+            [
+                (&DiffOp::Equal, "a"),
+                (&DiffOp::Delete, "b"),
+                (&DiffOp::Insert, "x"),
+                (&DiffOp::Equal, "c"),
+                (&DiffOp::Equal, "d"),
+                (&DiffOp::Insert, "e"),
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_the_longest_common_lines() {
+        let diff = line_diff("x\na\nb", "a\nb\nx");
+        assert_eq!(
+            ops(&diff),
+            [
+                (&DiffOp::Delete, "x"),
+                (&DiffOp::Equal, "a"),
+                (&DiffOp::Equal, "b"),
+                (&DiffOp::Insert, "x"),
+            ]
+        );
+    }
+
+    #[test]
+    fn diffs_against_no_text() {
+        assert_eq!(ops(&line_diff("", "a")), [(&DiffOp::Insert, "a")]);
+        assert_eq!(ops(&line_diff("a", "")), [(&DiffOp::Delete, "a")]);
+        assert!(line_diff("", "").is_empty());
+    }
+}
@@ -0,0 +1,152 @@
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+
+use crate::database::Todo;
+
+/// Languages relative times can be rendered in.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Language {
+    #[default]
+    En,
+    Ja,
+    De,
+}
+
+impl Language {
+    fn parse(tag: &str) -> Option<Self> {
+        // Only the primary subtag matters, `en-GB` and `en` read the same
+        let primary = tag.split(['-', '_']).next()?.trim().to_ascii_lowercase();
+        match primary.as_str() {
+            "en" => Some(Language::En),
+            "ja" => Some(Language::Ja),
+            "de" => Some(Language::De),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Language::En => "en",
+            Language::Ja => "ja",
+            Language::De => "de",
+        }
+    }
+
+    /// Picks the supported language the client prefers most, e.g. from
+    /// `ja,en-US;q=0.9`. Falls back to English.
+    pub fn from_accept_language(header: Option<&str>) -> Self {
+        let mut ranges: Vec<(f32, &str)> = header
+            .unwrap_or_default()
+            .split(',')
+            .filter_map(|range| {
+                let mut parts = range.split(';');
+                let tag = parts.next()?.trim();
+                let quality = parts
+                    .find_map(|param| param.trim().strip_prefix("q="))
+                    .and_then(|q| q.parse().ok())
+                    .unwrap_or(1.0);
+                Some((quality, tag))
+            })
+            .collect();
+        // Stable, so equally weighted languages keep the client's order
+        ranges.sort_by(|a, b| b.0.total_cmp(&a.0));
+        ranges
+            .into_iter()
+            .filter(|(quality, _)| *quality > 0.0)
+            .find_map(|(_, tag)| Language::parse(tag))
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Unit {
+    Minute,
+    Hour,
+    Day,
+    Week,
+    Month,
+    Year,
+}
+
+fn largest_unit(seconds: i64) -> Option<(i64, Unit)> {
+    let minutes = seconds as f64 / 60.0;
+    let (value, unit) = match minutes {
+        m if m < 0.75 => return None,
+        m if m < 45.0 => (m, Unit::Minute),
+        m if m < 22.0 * 60.0 => (m / 60.0, Unit::Hour),
+        m if m < 7.0 * 1440.0 => (m / 1440.0, Unit::Day),
+        m if m < 30.0 * 1440.0 => (m / (7.0 * 1440.0), Unit::Week),
+        m if m < 365.0 * 1440.0 => (m / (30.0 * 1440.0), Unit::Month),
+        m => (m / (365.0 * 1440.0), Unit::Year),
+    };
+    Some(((value.round() as i64).max(1), unit))
+}
+
+fn unit_name(language: Language, unit: Unit, count: i64) -> &'static str {
+    let (one, many) = match (language, unit) {
+        (Language::En, Unit::Minute) => ("minute", "minutes"),
+        (Language::En, Unit::Hour) => ("hour", "hours"),
+        (Language::En, Unit::Day) => ("day", "days"),
+        (Language::En, Unit::Week) => ("week", "weeks"),
+        (Language::En, Unit::Month) => ("month", "months"),
+        (Language::En, Unit::Year) => ("year", "years"),
+        (Language::Ja, Unit::Minute) => ("分", "分"),
+        (Language::Ja, Unit::Hour) => ("時間", "時間"),
+        (Language::Ja, Unit::Day) => ("日", "日"),
+        (Language::Ja, Unit::Week) => ("週間", "週間"),
+        (Language::Ja, Unit::Month) => ("か月", "か月"),
+        (Language::Ja, Unit::Year) => ("年", "年"),
+        // Dative, as used after "in" and "vor"
+        (Language::De, Unit::Minute) => ("Minute", "Minuten"),
+        (Language::De, Unit::Hour) => ("Stunde", "Stunden"),
+        (Language::De, Unit::Day) => ("Tag", "Tagen"),
+        (Language::De, Unit::Week) => ("Woche", "Wochen"),
+        (Language::De, Unit::Month) => ("Monat", "Monaten"),
+        (Language::De, Unit::Year) => ("Jahr", "Jahren"),
+    };
+    if count == 1 {
+        one
+    } else {
+        many
+    }
+}
+
+/// "in 3 days" / "3 days ago" for `time` as seen from `now`.
+pub fn relative_time(time: DateTime<Utc>, now: DateTime<Utc>, language: Language) -> String {
+    let seconds = (time - now).num_seconds();
+    let Some((count, unit)) = largest_unit(seconds.abs()) else {
+        return match language {
+            Language::En => "just now",
+            Language::Ja => "たった今",
+            Language::De => "gerade eben",
+        }
+        .to_string();
+    };
+    let unit = unit_name(language, unit, count);
+    match (language, seconds > 0) {
+        (Language::En, true) => format!("in {count} {unit}"),
+        (Language::En, false) => format!("{count} {unit} ago"),
+        (Language::Ja, true) => format!("{count}{unit}後"),
+        (Language::Ja, false) => format!("{count}{unit}前"),
+        (Language::De, true) => format!("in {count} {unit}"),
+        (Language::De, false) => format!("vor {count} {unit}"),
+    }
+}
+
+/// The todo's JSON with `created_ago` and, when it has a due date, `due_in` added.
+pub fn humanize_todo(todo: &Todo, language: Language, now: DateTime<Utc>) -> Value {
+    let mut value = serde_json::to_value(todo).unwrap_or_default();
+    if let Value::Object(fields) = &mut value {
+        fields.insert(
+            "created_ago".to_string(),
+            relative_time(todo.created_at, now, language).into(),
+        );
+        if let Some(due) = todo.due_date {
+            fields.insert(
+                "due_in".to_string(),
+                relative_time(due, now, language).into(),
+            );
+        }
+    }
+    value
+}
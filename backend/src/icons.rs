@@ -0,0 +1,30 @@
+/// Icons clients know how to draw by name.
+pub const NAMED_ICONS: &[&str] = &[
+    "book", "bug", "calendar", "car", "cart", "code", "flag", "folder", "gift", "health", "heart",
+    "home", "idea", "inbox", "list", "mail", "money", "music", "phone", "star", "travel", "work",
+];
+
+/// Emoji accepted as icons; anything else is rejected so icons stay renderable everywhere.
+pub const EMOJI_ICONS: &[&str] = &[
+    "✅", "📌", "🏠", "💼", "🛒", "📚", "💡", "🔥", "⭐", "❤️", "🐛", "📞", "✉️", "📅", "💰", "✈️",
+    "🏃", "🍳", "🧹", "🎉", "🎯", "🔧", "📝", "🚗", "🎁", "🎵", "💊", "🌱",
+];
+
+/// Trims the icon and checks it against the allowlists.
+pub fn validate_icon(icon: &str) -> Result<String, String> {
+    let icon = icon.trim();
+    // Emoji are also accepted without the variation selector, e.g. a bare "❤"
+    let emoji = EMOJI_ICONS
+        .iter()
+        .find(|emoji| **emoji == icon || emoji.trim_end_matches('\u{fe0f}') == icon);
+    if let Some(emoji) = emoji {
+        return Ok(emoji.to_string());
+    }
+    let name = icon.to_ascii_lowercase();
+    if NAMED_ICONS.contains(&name.as_str()) {
+        return Ok(name);
+    }
+    Err(format!(
+        "Unknown icon '{icon}': use one of the icons listed under `icons` in /api/capabilities"
+    ))
+}
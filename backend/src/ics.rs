@@ -0,0 +1,372 @@
+use axum::{extract::State, http::StatusCode, response::Json};
+use chrono::{DateTime, Duration, FixedOffset, NaiveDate, NaiveDateTime, Utc};
+use serde_json::{json, Value};
+
+use crate::database::{self, CreateTodo, DbPool, Metadata, Priority};
+use crate::import;
+
+/// A VTODO component reduced to the properties we map onto todos.
+#[derive(Debug, Default)]
+pub struct VTodo {
+    pub uid: Option<String>,
+    pub summary: Option<String>,
+    pub description: Option<String>,
+    pub due: Option<String>,
+    pub priority: Option<u8>,
+    pub completed: bool,
+}
+
+// RFC 5545 3.1: long lines are folded with CRLF followed by a space or tab
+fn unfold(input: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for line in input.lines() {
+        let line = line.trim_end_matches('\r');
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(continuation), Some(last)) => last.push_str(continuation),
+            _ => lines.push(line.to_string()),
+        }
+    }
+    lines
+}
+
+fn unescape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') | Some('N') => out.push('\n'),
+            Some(other) => out.push(other),
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+/// Extracts every VTODO from an iCalendar document; other components are ignored.
+pub fn parse_vtodos(input: &str) -> Vec<VTodo> {
+    let mut todos = Vec::new();
+    let mut current: Option<VTodo> = None;
+    // Components open inside the current VTODO, such as a VALARM, whose properties are not
+    // the todo's
+    let mut nested = 0usize;
+
+    for line in unfold(input) {
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        // Drop property parameters such as `DUE;VALUE=DATE`
+        let name = name.split(';').next().unwrap_or(name).to_ascii_uppercase();
+
+        match (name.as_str(), current.as_mut()) {
+            ("BEGIN", None) if value.eq_ignore_ascii_case("VTODO") => {
+                current = Some(VTodo::default());
+                nested = 0;
+            }
+            ("BEGIN", Some(_)) => nested += 1,
+            ("END", Some(_)) if nested > 0 => nested -= 1,
+            ("END", Some(_)) if value.eq_ignore_ascii_case("VTODO") => {
+                todos.extend(current.take());
+            }
+            (_, Some(_)) if nested > 0 => {}
+            ("UID", Some(todo)) => todo.uid = Some(value.to_string()),
+            ("SUMMARY", Some(todo)) => todo.summary = Some(unescape(value)),
+            ("DESCRIPTION", Some(todo)) => todo.description = Some(unescape(value)),
+            ("DUE", Some(todo)) => todo.due = Some(value.to_string()),
+            ("PRIORITY", Some(todo)) => todo.priority = value.trim().parse().ok(),
+            ("STATUS", Some(todo)) => todo.completed |= value.eq_ignore_ascii_case("COMPLETED"),
+            ("COMPLETED", Some(todo)) => todo.completed = true,
+            _ => {}
+        }
+    }
+    todos
+}
+
+/// A span of time an uploaded calendar marks as busy.
+#[derive(Debug, Clone, Copy)]
+pub struct BusyBlock {
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+}
+
+// `20261015T090000Z`, a floating `20261015T090000` (read in `offset`), or a date for an
+// all-day event. Returns the time and whether it was a date.
+fn parse_time(value: &str, offset: FixedOffset) -> Option<(DateTime<Utc>, bool)> {
+    let value = value.trim();
+    if let Some(utc) = value.strip_suffix(['Z', 'z']) {
+        let time = NaiveDateTime::parse_from_str(utc, "%Y%m%dT%H%M%S").ok()?;
+        return Some((time.and_utc(), false));
+    }
+    if let Ok(time) = NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S") {
+        let time = time.and_local_timezone(offset).single()?;
+        return Some((time.with_timezone(&Utc), false));
+    }
+    let date = NaiveDate::parse_from_str(value, "%Y%m%d").ok()?;
+    let time = date
+        .and_hms_opt(0, 0, 0)?
+        .and_local_timezone(offset)
+        .single()?;
+    Some((time.with_timezone(&Utc), true))
+}
+
+// RFC 5545 3.3.6, e.g. `PT1H30M` or `P1D`; negative durations are not busy time
+fn parse_duration(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    let mut rest = value.strip_prefix('+').unwrap_or(value).strip_prefix('P')?;
+    let mut total = Duration::zero();
+    let mut in_time = false;
+    while !rest.is_empty() {
+        if let Some(time) = rest.strip_prefix('T') {
+            in_time = true;
+            rest = time;
+            continue;
+        }
+        let digits = rest.find(|c: char| !c.is_ascii_digit())?;
+        let amount: i64 = rest[..digits].parse().ok()?;
+        let unit = rest[digits..].chars().next()?;
+        let part = match (unit, in_time) {
+            ('W', false) => Duration::try_weeks(amount)?,
+            ('D', false) => Duration::try_days(amount)?,
+            ('H', true) => Duration::try_hours(amount)?,
+            ('M', true) => Duration::try_minutes(amount)?,
+            ('S', true) => Duration::try_seconds(amount)?,
+            _ => return None,
+        };
+        total = total.checked_add(&part)?;
+        rest = &rest[digits + unit.len_utf8()..];
+    }
+    Some(total)
+}
+
+/// Extracts the busy time from an iCalendar document: the events (except transparent and
+/// cancelled ones) and the busy periods of VFREEBUSY components. Times without a zone are
+/// read in `offset`; `TZID` parameters are not resolved and count as such times.
+pub fn parse_busy_blocks(input: &str, offset: FixedOffset) -> Vec<BusyBlock> {
+    #[derive(Default)]
+    struct Event {
+        start: Option<(DateTime<Utc>, bool)>,
+        end: Option<DateTime<Utc>>,
+        duration: Option<Duration>,
+        free: bool,
+    }
+
+    let mut blocks = Vec::new();
+    let mut event: Option<Event> = None;
+    let mut in_freebusy = false;
+    // Components open inside the current event or VFREEBUSY, such as a VALARM
+    let mut nested = 0usize;
+
+    for line in unfold(input) {
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        let mut params = name.split(';');
+        let name = params.next().unwrap_or(name).to_ascii_uppercase();
+        let mut params = params.map(str::to_ascii_uppercase);
+
+        let in_component = event.is_some() || in_freebusy;
+        match (name.as_str(), event.as_mut()) {
+            ("BEGIN", _) if in_component => nested += 1,
+            ("END", _) if nested > 0 => nested -= 1,
+            _ if nested > 0 => {}
+            ("BEGIN", None) if value.eq_ignore_ascii_case("VEVENT") => {
+                event = Some(Event::default())
+            }
+            ("BEGIN", None) if value.eq_ignore_ascii_case("VFREEBUSY") => in_freebusy = true,
+            ("END", None) if value.eq_ignore_ascii_case("VFREEBUSY") => in_freebusy = false,
+            ("END", Some(_)) if value.eq_ignore_ascii_case("VEVENT") => {
+                let Some(Event {
+                    start: Some((start, all_day)),
+                    end,
+                    duration,
+                    free: false,
+                }) = event.take()
+                else {
+                    continue;
+                };
+                // Without an end, an all-day event lasts the day and others are instants
+                let end = end
+                    .or(duration.and_then(|duration| start.checked_add_signed(duration)))
+                    .unwrap_or(if all_day {
+                        start + Duration::days(1)
+                    } else {
+                        start
+                    });
+                if end > start {
+                    blocks.push(BusyBlock { start, end });
+                }
+            }
+            ("DTSTART", Some(event)) => event.start = parse_time(value, offset),
+            ("DTEND", Some(event)) => event.end = parse_time(value, offset).map(|(end, _)| end),
+            ("DURATION", Some(event)) => event.duration = parse_duration(value),
+            ("TRANSP", Some(event)) => event.free |= value.eq_ignore_ascii_case("TRANSPARENT"),
+            ("STATUS", Some(event)) => event.free |= value.eq_ignore_ascii_case("CANCELLED"),
+            ("FREEBUSY", None) if in_freebusy => {
+                if params.any(|param| param == "FBTYPE=FREE") {
+                    continue;
+                }
+                for period in value.split(',') {
+                    let Some((start, until)) = period.split_once('/') else {
+                        continue;
+                    };
+                    let Some((start, _)) = parse_time(start, offset) else {
+                        continue;
+                    };
+                    let end = match parse_time(until, offset) {
+                        Some((end, _)) => Some(end),
+                        None => parse_duration(until)
+                            .and_then(|duration| start.checked_add_signed(duration)),
+                    };
+                    if let Some(end) = end.filter(|end| *end > start) {
+                        blocks.push(BusyBlock { start, end });
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    blocks
+}
+
+// A due date or time, as a todo's `due_date`. A date is due at the end of that day, and
+// floating times are read in UTC since `TZID` is not resolved.
+fn due_date(value: &str) -> Option<String> {
+    let utc = FixedOffset::east_opt(0)?;
+    let (due, all_day) = parse_time(value, utc)?;
+    let due = if all_day {
+        due.checked_add_signed(Duration::days(1) - Duration::seconds(1))?
+    } else {
+        due
+    };
+    database::normalize_due_date(&due.to_rfc3339()).ok()
+}
+
+// RFC 5545 3.8.1.9: 1 is the highest priority, 9 the lowest and 0 undefined
+fn priority(value: u8) -> Option<Priority> {
+    match value {
+        1..=2 => Some(Priority::Urgent),
+        3..=4 => Some(Priority::High),
+        5 => Some(Priority::Medium),
+        6..=9 => Some(Priority::Low),
+        _ => None,
+    }
+}
+
+fn vtodo_to_create(vtodo: &VTodo) -> CreateTodo {
+    // Keep the UID so the todo can be traced back to its calendar
+    let mut metadata = Metadata::new();
+    metadata.insert("source".to_string(), json!("ics"));
+    if let Some(uid) = &vtodo.uid {
+        metadata.insert("ics_uid".to_string(), json!(uid));
+    }
+    let due = vtodo.due.as_deref().map(|due| (due, due_date(due)));
+    if let Some((due, None)) = due {
+        // Not a form we can read, so keep it as it was rather than drop it
+        metadata.insert("ics_due".to_string(), json!(due));
+    }
+
+    CreateTodo {
+        title: vtodo
+            .summary
+            .clone()
+            .filter(|summary| !summary.trim().is_empty())
+            .unwrap_or_else(|| "(untitled)".to_string()),
+        description: vtodo.description.clone().filter(|d| !d.is_empty()),
+        metadata: Some(metadata),
+        due_date: due.and_then(|(_, due_date)| due_date),
+        start_date: None,
+        remind_at: None,
+        estimate_minutes: None,
+        custom_fields: None,
+        priority: vtodo.priority.and_then(priority),
+        parent_id: None,
+        icon: None,
+        recurrence: None,
+        list_id: None,
+        tags: None,
+    }
+}
+
+pub async fn import_ics_handler(
+    State(pool): State<DbPool>,
+    body: String,
+) -> Result<(StatusCode, Json<Value>), (StatusCode, Json<Value>)> {
+    let vtodos = parse_vtodos(&body);
+    if vtodos.is_empty() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "No VTODO components found in the uploaded calendar"})),
+        ));
+    }
+
+    let todos = vtodos
+        .iter()
+        .map(|vtodo| import::SourceTodo {
+            create: vtodo_to_create(vtodo),
+            completed: vtodo.completed,
+            labels: Vec::new(),
+        })
+        .collect();
+    let imported = import::import_todos(&pool, todos).map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Failed to import todos"})),
+        )
+    })?;
+    Ok((
+        StatusCode::CREATED,
+        Json(json!({"imported": imported.len(), "todos": imported})),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unfolds_continuation_lines() {
+        // Only the one whitespace character that marks the fold is dropped
+        let lines = unfold("SUMMARY:Buy\r\n  milk and\r\n\t eggs\r\nDUE:20261015\r\n");
+        assert_eq!(lines, ["SUMMARY:Buy milk and eggs", "DUE:20261015"]);
+    }
+
+    #[test]
+    fn unescapes_text_values() {
+        assert_eq!(unescape(r"a\, b\; c\\d\nnext\N"), "a, b; c\\d\nnext\n");
+        assert_eq!(unescape(r"trailing\"), "trailing\\");
+    }
+
+    #[test]
+    fn parses_vtodos_and_ignores_nested_components() {
+        let calendar = "BEGIN:VCALENDAR\n\
+            BEGIN:VEVENT\nSUMMARY:Not a todo\nEND:VEVENT\n\
+            BEGIN:VTODO\nUID:1\nSUMMARY:Write\n report\nDUE;VALUE=DATE:20261020\nPRIORITY:1\n\
+            BEGIN:VALARM\nDESCRIPTION:Reminder\nEND:VALARM\n\
+            STATUS:COMPLETED\nEND:VTODO\n\
+            BEGIN:VTODO\nSUMMARY:Second\nEND:VTODO\n\
+            END:VCALENDAR\n";
+        let todos = parse_vtodos(calendar);
+        assert_eq!(todos.len(), 2);
+        assert_eq!(todos[0].uid.as_deref(), Some("1"));
+        assert_eq!(todos[0].summary.as_deref(), Some("Writereport"));
+        assert_eq!(todos[0].description, None);
+        assert_eq!(todos[0].due.as_deref(), Some("20261020"));
+        assert_eq!(todos[0].priority, Some(1));
+        assert!(todos[0].completed);
+        assert_eq!(todos[1].summary.as_deref(), Some("Second"));
+        assert!(!todos[1].completed);
+    }
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("PT1H30M"), Some(Duration::minutes(90)));
+        assert_eq!(parse_duration("P1W2D"), Some(Duration::days(9)));
+        assert_eq!(parse_duration("+P1DT1S"), Some(Duration::seconds(86_401)));
+        assert_eq!(parse_duration("-PT1H"), None);
+        assert_eq!(parse_duration("P1H"), None);
+        assert_eq!(parse_duration("PT"), Some(Duration::zero()));
+    }
+}
@@ -0,0 +1,100 @@
+use axum::{
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Json, Response},
+};
+use serde::Serialize;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+use crate::database::{self, DbPool, IdempotencyClaim};
+
+pub const HEADER: &str = "idempotency-key";
+pub const KEY_MAX_LEN: usize = 255;
+/// How long a key is remembered
+pub const TTL_HOURS: u32 = 24;
+// A request holding a key this long without finishing is taken to have died with the server
+const ABANDON_AFTER_SECONDS: u32 = 60;
+
+fn error(status: StatusCode, message: &str) -> (StatusCode, Json<Value>) {
+    (status, Json(json!({"error": message})))
+}
+
+/// The request's `Idempotency-Key`, if it sent one.
+pub fn key(headers: &HeaderMap) -> Result<Option<String>, (StatusCode, Json<Value>)> {
+    let Some(value) = headers.get(HEADER) else {
+        return Ok(None);
+    };
+    match value.to_str() {
+        Ok(key) if !key.is_empty() && key.len() <= KEY_MAX_LEN => Ok(Some(key.to_string())),
+        _ => Err(error(
+            StatusCode::BAD_REQUEST,
+            &format!("`Idempotency-Key` must be 1-{KEY_MAX_LEN} visible ASCII characters"),
+        )),
+    }
+}
+
+/// Fingerprint of a request, to tell a retry from another request reusing the key.
+pub fn request_hash(parts: &[&[u8]]) -> String {
+    let mut hasher = Sha256::new();
+    for part in parts {
+        hasher.update((part.len() as u64).to_le_bytes());
+        hasher.update(part);
+    }
+    hex::encode(hasher.finalize())
+}
+
+/// Claims `key` for this request. Returns the stored response when the request is a retry of
+/// one that already finished, or `None` when this request should run.
+pub fn claim(
+    pool: &DbPool,
+    key: &str,
+    request_hash: &str,
+) -> Result<Option<Response>, (StatusCode, Json<Value>)> {
+    let claim =
+        database::claim_idempotency_key(pool, key, request_hash, TTL_HOURS, ABANDON_AFTER_SECONDS)
+            .map_err(|_| {
+                error(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "Failed to check the Idempotency-Key",
+                )
+            })?;
+    match claim {
+        IdempotencyClaim::New => Ok(None),
+        IdempotencyClaim::Replay(status, body) => {
+            let status = StatusCode::from_u16(status).unwrap_or(StatusCode::OK);
+            let mut response = (status, body).into_response();
+            let headers = response.headers_mut();
+            headers.insert(
+                header::CONTENT_TYPE,
+                HeaderValue::from_static("application/json"),
+            );
+            headers.insert("idempotent-replayed", HeaderValue::from_static("true"));
+            Ok(Some(response))
+        }
+        IdempotencyClaim::InProgress => Err(error(
+            StatusCode::CONFLICT,
+            "A request with this Idempotency-Key is still being processed; retry shortly",
+        )),
+        IdempotencyClaim::Mismatch => Err(error(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "This Idempotency-Key was already used for a different request",
+        )),
+    }
+}
+
+/// Stores the response of the request that claimed `key`, for its retries.
+pub fn complete(pool: &DbPool, key: &str, status: StatusCode, body: &impl Serialize) {
+    let stored = serde_json::to_string(body)
+        .map_err(|e| e.into())
+        .and_then(|body| database::complete_idempotency_key(pool, key, status.as_u16(), &body));
+    if let Err(e) = stored {
+        eprintln!("Failed to store the response for Idempotency-Key {key}: {e}");
+    }
+}
+
+/// Frees `key` after its request failed, so a retry runs it again.
+pub fn release(pool: &DbPool, key: &str) {
+    if let Err(e) = database::release_idempotency_key(pool, key) {
+        eprintln!("Failed to release Idempotency-Key {key}: {e}");
+    }
+}
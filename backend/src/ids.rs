@@ -0,0 +1,41 @@
+use std::sync::OnceLock;
+
+/// Format of newly generated todo ids. Existing ids keep working whatever the setting.
+#[derive(Debug, Clone, Copy, Default)]
+pub enum IdFormat {
+    #[default]
+    Uuid,
+    /// Time-sortable, so ids also reflect rough creation order and index better
+    Ulid,
+}
+
+impl IdFormat {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "uuid" => Some(IdFormat::Uuid),
+            "ulid" => Some(IdFormat::Ulid),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            IdFormat::Uuid => "uuid",
+            IdFormat::Ulid => "ulid",
+        }
+    }
+}
+
+static TODO_ID_FORMAT: OnceLock<IdFormat> = OnceLock::new();
+
+/// Sets the format for the lifetime of the process; call once at startup.
+pub fn init(format: IdFormat) {
+    let _ = TODO_ID_FORMAT.set(format);
+}
+
+pub fn new_todo_id() -> String {
+    match TODO_ID_FORMAT.get().copied().unwrap_or_default() {
+        IdFormat::Uuid => uuid::Uuid::new_v4().to_string(),
+        IdFormat::Ulid => ulid::Ulid::new().to_string(),
+    }
+}
@@ -0,0 +1,34 @@
+use crate::database::{self, CreateTodo, DbPool, ImportedTodo, Todo};
+
+/// A todo from an external source, in the state it has there.
+pub struct SourceTodo {
+    pub create: CreateTodo,
+    pub completed: bool,
+    /// Attached as tags; labels that cannot be tag names, e.g. with a comma, are skipped
+    pub labels: Vec<String>,
+}
+
+/// Creates the todos of one import in a single transaction, so a failure leaves none of
+/// them behind. Completed todos are created completed rather than completed afterwards, so
+/// nothing that completing a todo sets off, such as a review or the next occurrence, runs.
+pub fn import_todos(
+    pool: &DbPool,
+    todos: Vec<SourceTodo>,
+) -> Result<Vec<Todo>, Box<dyn std::error::Error + Send + Sync>> {
+    let todos = todos
+        .into_iter()
+        .map(|todo| ImportedTodo {
+            create: CreateTodo {
+                tags: Some(
+                    todo.labels
+                        .iter()
+                        .filter_map(|label| database::normalize_tag_name(label).ok())
+                        .collect(),
+                ),
+                ..todo.create
+            },
+            completed: todo.completed,
+        })
+        .collect();
+    database::create_imported_todos(pool, todos)
+}
@@ -0,0 +1,212 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use axum::{
+    extract::{FromRequest, Multipart, Request, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Json},
+    Form,
+};
+use serde_json::{json, Value};
+
+use crate::attachments::{self, Upload};
+use crate::config::Config;
+use crate::database::{self, CreateTodo, DbPool, Metadata, Todo};
+
+/// Largest email accepted with its attachments, as Mailgun's own limit
+pub const MAX_EMAIL_BYTES: usize = 25 * 1024 * 1024;
+
+/// The subset of Mailgun's inbound route payload that we use. It is form-encoded, or
+/// `multipart/form-data` when the email has attachments.
+#[derive(Debug)]
+pub struct InboundEmail {
+    pub recipient: String,
+    pub sender: Option<String>,
+    pub subject: Option<String>,
+    pub body_plain: Option<String>,
+    pub message_id: Option<String>,
+    pub attachment_count: Option<u32>,
+}
+
+impl InboundEmail {
+    fn from_fields(mut fields: HashMap<String, String>) -> Result<Self, (StatusCode, Json<Value>)> {
+        let recipient = fields.remove("recipient").ok_or((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            Json(json!({"error": "Missing field `recipient`"})),
+        ))?;
+        Ok(InboundEmail {
+            recipient,
+            sender: fields.remove("sender"),
+            subject: fields.remove("subject"),
+            body_plain: fields.remove("body-plain"),
+            message_id: fields.remove("Message-Id"),
+            attachment_count: fields
+                .remove("attachment-count")
+                .and_then(|count| count.trim().parse().ok()),
+        })
+    }
+}
+
+// `todo+<token>@example.com` -> `<token>`
+fn address_token(recipient: &str) -> Option<&str> {
+    let local_part = recipient.trim().split('@').next()?;
+    local_part.split_once('+').map(|(_, token)| token)
+}
+
+fn email_to_todo(email: InboundEmail) -> CreateTodo {
+    let title = email
+        .subject
+        .map(|subject| subject.trim().to_string())
+        .filter(|subject| !subject.is_empty())
+        .unwrap_or_else(|| "(no subject)".to_string());
+    let description = email
+        .body_plain
+        .map(|body| body.trim().to_string())
+        .filter(|body| !body.is_empty());
+
+    // Keep the email's identity so the todo can be traced back to its message
+    let mut metadata = Metadata::new();
+    metadata.insert("source".to_string(), json!("email"));
+    if let Some(sender) = email.sender {
+        metadata.insert("email_sender".to_string(), json!(sender));
+    }
+    if let Some(message_id) = email.message_id {
+        metadata.insert("email_message_id".to_string(), json!(message_id));
+    }
+    if let Some(count) = email.attachment_count.filter(|count| *count > 0) {
+        metadata.insert("email_attachment_count".to_string(), json!(count));
+    }
+
+    CreateTodo {
+        title,
+        description,
+        metadata: Some(metadata),
+        due_date: None,
+        start_date: None,
+        remind_at: None,
+        estimate_minutes: None,
+        custom_fields: None,
+        priority: None,
+        parent_id: None,
+        icon: None,
+        recurrence: None,
+        list_id: None,
+        tags: None,
+    }
+}
+
+// Mailgun names the files `attachment-1`, `attachment-2`, ...; any field with a file name is
+// taken as one. Files are received before the todo exists, since fields come in any order.
+async fn read_multipart(
+    config: &Config,
+    mut multipart: Multipart,
+) -> Result<(HashMap<String, String>, Vec<Upload>), (StatusCode, Json<Value>)> {
+    let mut fields = HashMap::new();
+    let mut uploads = Vec::new();
+    let failed = loop {
+        let mut field = match multipart.next_field().await {
+            Ok(Some(field)) => field,
+            Ok(None) => break None,
+            Err(e) => {
+                break Some((
+                    StatusCode::BAD_REQUEST,
+                    Json(json!({"error": format!("Invalid email: {e}")})),
+                ))
+            }
+        };
+        let Some(name) = field.name().map(str::to_string) else {
+            continue;
+        };
+        if field.file_name().is_some() {
+            match attachments::receive_field(config, &mut field).await {
+                Ok(upload) => uploads.push(upload),
+                Err(e) => break Some(e),
+            }
+            continue;
+        }
+        match field.text().await {
+            Ok(value) => {
+                fields.insert(name, value);
+            }
+            Err(e) => {
+                break Some((
+                    StatusCode::BAD_REQUEST,
+                    Json(json!({"error": format!("Invalid email: {e}")})),
+                ))
+            }
+        }
+    };
+    match failed {
+        None => Ok((fields, uploads)),
+        Some(e) => {
+            for upload in uploads {
+                attachments::discard_upload(upload).await;
+            }
+            Err(e)
+        }
+    }
+}
+
+/// Creates a todo from an email, with the email's files as its attachments.
+pub async fn inbound_email_handler(
+    State(pool): State<DbPool>,
+    State(config): State<Arc<Config>>,
+    request: Request,
+) -> Result<(StatusCode, Json<Todo>), (StatusCode, Json<Value>)> {
+    let expected = config.inbound_email_token.as_deref().ok_or((
+        StatusCode::NOT_FOUND,
+        Json(json!({"error": "Inbound email is not enabled"})),
+    ))?;
+    let multipart = request
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|content_type| content_type.starts_with("multipart/form-data"));
+    let rejection = |e: axum::response::Response| {
+        let status = e.status();
+        (status, Json(json!({"error": "Invalid email payload"})))
+    };
+    let (fields, uploads) = if multipart {
+        let multipart = Multipart::from_request(request, &())
+            .await
+            .map_err(|e| rejection(e.into_response()))?;
+        read_multipart(&config, multipart).await?
+    } else {
+        let Form(fields) = Form::<HashMap<String, String>>::from_request(request, &())
+            .await
+            .map_err(|e| rejection(e.into_response()))?;
+        (fields, Vec::new())
+    };
+
+    let created = InboundEmail::from_fields(fields).and_then(|email| {
+        if address_token(&email.recipient) != Some(expected) {
+            return Err((
+                StatusCode::FORBIDDEN,
+                Json(json!({"error": "Unknown recipient address"})),
+            ));
+        }
+        database::create_todo(&pool, email_to_todo(email)).map_err(|_| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": "Failed to create todo"})),
+            )
+        })
+    });
+    let todo = match created {
+        Ok(todo) => todo,
+        Err(e) => {
+            for upload in uploads {
+                attachments::discard_upload(upload).await;
+            }
+            return Err(e);
+        }
+    };
+    // The todo is kept even if a file cannot be stored, so a retried webhook does not create it
+    // twice
+    for upload in uploads {
+        if let Err((_, Json(e))) = attachments::store_upload(&pool, &config, &todo.id, upload) {
+            eprintln!("Failed to attach an emailed file to todo {}: {e}", todo.id);
+        }
+    }
+    Ok((StatusCode::CREATED, Json(todo)))
+}
@@ -0,0 +1,576 @@
+use std::collections::BTreeMap;
+
+use axum::{
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode},
+    response::Json,
+};
+use chrono::{Duration, Utc};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::activity;
+use crate::database::{
+    self, CreateTodo, DbPool, InboundHook, InboundHookDefinition, Metadata, Priority,
+    SortDirection, SortKey, Todo, TodoFilter, UpdateTodo, Upserted, METADATA_MAX_KEY_LEN,
+};
+use crate::handlers;
+use crate::json_body::JsonBody;
+use crate::lists;
+
+/// Todo fields a hook can fill in; `metadata.<key>` sets a metadata value as well.
+pub const FIELDS: [&str; 9] = [
+    "title",
+    "description",
+    "due_date",
+    "start_date",
+    "remind_at",
+    "priority",
+    "icon",
+    "estimate_minutes",
+    "recurrence",
+];
+const METADATA_PREFIX: &str = "metadata.";
+pub const DEFAULT_DEDUPE_WINDOW_MINUTES: u32 = 60;
+pub const MAX_DEDUPE_WINDOW_MINUTES: u32 = 7 * 24 * 60;
+// Metadata of todos created by a hook that deduplicates
+const DEDUPE_KEY: &str = "inbound_dedupe_key";
+const OCCURRENCES: &str = "inbound_occurrences";
+const LAST_SEEN: &str = "inbound_last_seen";
+
+#[derive(Debug, Deserialize)]
+pub struct CreateInboundHook {
+    pub name: String,
+    pub mapping: BTreeMap<String, String>,
+    pub list_id: Option<String>,
+    pub dedupe_key: Option<String>,
+    pub dedupe_window_minutes: Option<u32>,
+}
+
+/// Fields left out keep their value; an empty `list_id` or `dedupe_key` removes it.
+#[derive(Debug, Deserialize)]
+pub struct UpdateInboundHook {
+    pub name: Option<String>,
+    pub mapping: Option<BTreeMap<String, String>>,
+    pub list_id: Option<String>,
+    pub dedupe_key: Option<String>,
+    pub dedupe_window_minutes: Option<u32>,
+    /// Give the hook a new token, so the old URL stops working
+    #[serde(default)]
+    pub rotate_token: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct InboundParams {
+    /// Return the todo the payload maps to without creating it
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Key(String),
+    Index(usize),
+}
+
+// How one field is taken from a payload
+#[derive(Debug)]
+enum Source {
+    /// `$.alert.labels[0]`: the value found there, keeping its JSON type
+    Path(Vec<Segment>),
+    /// `[{{ $.status }}] {{ $.service }}`: text with the values found at each path
+    Template(Vec<(String, Option<Vec<Segment>>)>),
+    Literal(String),
+}
+
+fn error(status: StatusCode, message: &str) -> (StatusCode, Json<Value>) {
+    (status, Json(json!({"error": message})))
+}
+
+fn bad_request(message: String) -> (StatusCode, Json<Value>) {
+    error(StatusCode::BAD_REQUEST, &message)
+}
+
+// `$`, then any of `.key`, `["key"]`, `['key']` and `[0]`
+fn parse_path(path: &str) -> Result<Vec<Segment>, String> {
+    let invalid = || format!("Invalid path '{path}'");
+    let mut rest = path.strip_prefix('$').ok_or_else(invalid)?;
+    let mut segments = Vec::new();
+    while !rest.is_empty() {
+        if let Some(after) = rest.strip_prefix('.') {
+            let end = after.find(['.', '[']).unwrap_or(after.len());
+            if end == 0 {
+                return Err(invalid());
+            }
+            segments.push(Segment::Key(after[..end].to_string()));
+            rest = &after[end..];
+        } else if let Some(after) = rest.strip_prefix('[') {
+            let end = after.find(']').ok_or_else(invalid)?;
+            let inner = &after[..end];
+            let quoted = [('"', '"'), ('\'', '\'')].iter().find_map(|(open, close)| {
+                inner
+                    .strip_prefix(*open)
+                    .and_then(|inner| inner.strip_suffix(*close))
+            });
+            segments.push(match quoted {
+                Some(key) => Segment::Key(key.to_string()),
+                None => Segment::Index(inner.parse().map_err(|_| invalid())?),
+            });
+            rest = &after[end + 1..];
+        } else {
+            return Err(invalid());
+        }
+    }
+    Ok(segments)
+}
+
+fn parse_source(source: &str) -> Result<Source, String> {
+    if source.starts_with('$') {
+        return parse_path(source).map(Source::Path);
+    }
+    if !source.contains("{{") {
+        return Ok(Source::Literal(source.to_string()));
+    }
+    let mut parts = Vec::new();
+    let mut rest = source;
+    while let Some(start) = rest.find("{{") {
+        let end = rest[start..]
+            .find("}}")
+            .ok_or_else(|| format!("Unclosed '{{{{' in '{source}'"))?;
+        let path = parse_path(rest[start + 2..start + end].trim())?;
+        parts.push((rest[..start].to_string(), Some(path)));
+        rest = &rest[start + end + 2..];
+    }
+    parts.push((rest.to_string(), None));
+    Ok(Source::Template(parts))
+}
+
+fn lookup<'a>(payload: &'a Value, path: &[Segment]) -> Option<&'a Value> {
+    path.iter()
+        .try_fold(payload, |value, segment| match segment {
+            Segment::Key(key) => value.get(key),
+            Segment::Index(index) => value.get(index),
+        })
+        .filter(|value| !value.is_null())
+}
+
+// Strings as they are, anything else as compact JSON
+fn text(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        other => other.to_string(),
+    }
+}
+
+// The value of a field for a payload; nothing when the payload does not have it
+fn resolve(source: &Source, payload: &Value) -> Option<Value> {
+    let value = match source {
+        Source::Path(path) => lookup(payload, path)?.clone(),
+        Source::Template(parts) => {
+            let mut rendered = String::new();
+            let mut found = false;
+            for (literal, path) in parts {
+                rendered.push_str(literal);
+                if let Some(value) = path.as_ref().and_then(|path| lookup(payload, path)) {
+                    rendered.push_str(&text(value));
+                    found = true;
+                }
+            }
+            // Only the surrounding text is left when none of the paths is in the payload
+            if !found {
+                return None;
+            }
+            Value::String(rendered)
+        }
+        Source::Literal(literal) => Value::String(literal.clone()),
+    };
+    match value {
+        Value::String(text) if text.trim().is_empty() => None,
+        Value::String(text) => Some(Value::String(text.trim().to_string())),
+        value => Some(value),
+    }
+}
+
+/// Checks every entry of a mapping names a known field and has a valid source.
+pub fn validate_mapping(mapping: &BTreeMap<String, String>) -> Result<(), String> {
+    if !mapping.contains_key("title") {
+        return Err("The mapping needs a source for `title`".to_string());
+    }
+    for (field, source) in mapping {
+        match field.strip_prefix(METADATA_PREFIX) {
+            Some(key) if !database::is_valid_metadata_key(key) => {
+                return Err(format!(
+                    "Invalid metadata key '{key}': use 1-{METADATA_MAX_KEY_LEN} characters of [A-Za-z0-9_-]"
+                ))
+            }
+            Some(_) => {}
+            None if !FIELDS.contains(&field.as_str()) => {
+                return Err(format!(
+                    "Unknown field '{field}': expected one of {} or metadata.<key>",
+                    FIELDS.join(", ")
+                ))
+            }
+            None => {}
+        }
+        parse_source(source).map_err(|message| format!("`{field}`: {message}"))?;
+    }
+    Ok(())
+}
+
+// Checks a definition can be applied to payloads and normalizes its name
+fn validate(definition: &mut InboundHookDefinition) -> Result<(), String> {
+    definition.name = database::normalize_inbound_hook_name(&definition.name)?;
+    validate_mapping(&definition.mapping)?;
+    if let Some(key) = &definition.dedupe_key {
+        // A fixed key would fold every payload into the same todo
+        match parse_source(key).map_err(|message| format!("`dedupe_key`: {message}"))? {
+            Source::Literal(_) => {
+                return Err(
+                    "`dedupe_key` must read from the payload: use a path or a template".to_string(),
+                )
+            }
+            Source::Path(_) | Source::Template(_) => {}
+        }
+    }
+    if !(1..=MAX_DEDUPE_WINDOW_MINUTES).contains(&definition.dedupe_window_minutes) {
+        return Err(format!(
+            "`dedupe_window_minutes` must be between 1 and {MAX_DEDUPE_WINDOW_MINUTES}"
+        ));
+    }
+    Ok(())
+}
+
+/// Which alert a payload is about, when the hook deduplicates.
+pub fn dedupe_key(hook: &InboundHook, payload: &Value) -> Result<Option<String>, String> {
+    let Some(key) = &hook.dedupe_key else {
+        return Ok(None);
+    };
+    let source = parse_source(key).map_err(|message| format!("`dedupe_key`: {message}"))?;
+    Ok(resolve(&source, payload).map(|value| text(&value)))
+}
+
+/// The todo a payload posted to `hook` stands for, validated like any other new todo.
+pub fn payload_to_todo(
+    hook: &InboundHook,
+    payload: &Value,
+    dedupe_key: Option<&str>,
+) -> Result<CreateTodo, String> {
+    let mut values = BTreeMap::new();
+    for (field, source) in &hook.mapping {
+        let source = parse_source(source).map_err(|message| format!("`{field}`: {message}"))?;
+        if let Some(value) = resolve(&source, payload) {
+            values.insert(field.as_str(), value);
+        }
+    }
+    let mut take = |field: &str| values.remove(field).map(|value| text(&value));
+
+    let title = take("title").ok_or("The payload has no value for `title`")?;
+    let priority = take("priority")
+        .map(|priority| {
+            Priority::parse(&priority).ok_or_else(|| {
+                format!("Invalid priority '{priority}': expected low, medium, high or urgent")
+            })
+        })
+        .transpose()?;
+    let estimate_minutes = take("estimate_minutes")
+        .map(|minutes| {
+            minutes.parse().map_err(|_| {
+                format!("Invalid estimate_minutes '{minutes}': expected a whole number")
+            })
+        })
+        .transpose()?;
+
+    // Keep where the todo came from, like the other integrations do
+    let mut metadata = Metadata::new();
+    metadata.insert("source".to_string(), json!("inbound"));
+    metadata.insert("inbound_hook".to_string(), json!(hook.name));
+    if let Some(key) = dedupe_key {
+        metadata.insert(DEDUPE_KEY.to_string(), json!(key));
+        metadata.insert(OCCURRENCES.to_string(), json!(1));
+        metadata.insert(LAST_SEEN.to_string(), json!(database::now_timestamp()));
+    }
+    let mut create = CreateTodo {
+        title,
+        description: take("description"),
+        metadata: None,
+        due_date: take("due_date"),
+        start_date: take("start_date"),
+        remind_at: take("remind_at"),
+        estimate_minutes,
+        custom_fields: None,
+        priority,
+        parent_id: None,
+        icon: take("icon"),
+        recurrence: take("recurrence"),
+        list_id: hook.list_id.clone(),
+        tags: None,
+    };
+    for (field, value) in values {
+        if let Some(key) = field.strip_prefix(METADATA_PREFIX) {
+            metadata.insert(key.to_string(), value);
+        }
+    }
+    create.metadata = Some(metadata);
+    handlers::validate_create_todo(&mut create)?;
+    Ok(create)
+}
+
+// The newest open todo of `hook` with this dedupe key
+fn duplicate_filter(hook: &InboundHook, key: &str) -> TodoFilter {
+    TodoFilter {
+        completed: Some(false),
+        archived: Some(false),
+        metadata: vec![
+            ("inbound_hook".to_string(), hook.name.clone()),
+            (DEDUPE_KEY.to_string(), key.to_string()),
+        ],
+        sort: vec![SortKey {
+            column: "seq",
+            direction: SortDirection::Desc,
+        }],
+        limit: Some(1),
+        ..Default::default()
+    }
+}
+
+// Whether the alert a todo stands for was last seen within the hook's window
+fn within_window(hook: &InboundHook, todo: &Todo) -> bool {
+    let cutoff = Utc::now() - Duration::minutes(i64::from(hook.dedupe_window_minutes));
+    let last_seen = todo
+        .metadata
+        .get(LAST_SEEN)
+        .and_then(Value::as_str)
+        .and_then(|last_seen| database::parse_timestamp(last_seen).ok())
+        .unwrap_or(todo.created_at);
+    last_seen >= cutoff
+}
+
+/// The open todo the hook created for `key` that was last seen within its window.
+pub fn find_duplicate(
+    pool: &DbPool,
+    hook: &InboundHook,
+    key: &str,
+) -> Result<Option<Todo>, Box<dyn std::error::Error + Send + Sync>> {
+    Ok(database::get_todos(pool, &duplicate_filter(hook, key))?
+        .into_iter()
+        .next()
+        .filter(|todo| within_window(hook, todo)))
+}
+
+// Counts another occurrence of the alert a todo stands for
+fn record_occurrence(todo: Todo) -> UpdateTodo {
+    let mut metadata = todo.metadata;
+    let occurrences = metadata
+        .get(OCCURRENCES)
+        .and_then(Value::as_u64)
+        .unwrap_or(1);
+    metadata.insert(OCCURRENCES.to_string(), json!(occurrences + 1));
+    metadata.insert(LAST_SEEN.to_string(), json!(database::now_timestamp()));
+    UpdateTodo {
+        metadata: Some(metadata),
+        ..Default::default()
+    }
+}
+
+// Names are unique ignoring case; `except` is the hook being renamed
+fn ensure_name_free(
+    pool: &DbPool,
+    name: &str,
+    except: Option<&str>,
+) -> Result<(), (StatusCode, Json<Value>)> {
+    match database::find_inbound_hook_by_name(pool, name) {
+        Ok(Some(existing)) if Some(existing.id.as_str()) != except => Err((
+            StatusCode::CONFLICT,
+            Json(json!({"error": "A hook with this name already exists", "existing": existing})),
+        )),
+        Ok(_) => Ok(()),
+        Err(_) => Err(error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to check hook name",
+        )),
+    }
+}
+
+fn find_hook(pool: &DbPool, id: &str) -> Result<InboundHook, (StatusCode, Json<Value>)> {
+    match database::get_inbound_hook(pool, id) {
+        Ok(Some(hook)) => Ok(hook),
+        Ok(None) => Err(error(StatusCode::NOT_FOUND, "Inbound hook not found")),
+        Err(_) => Err(error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to get inbound hook",
+        )),
+    }
+}
+
+pub async fn get_inbound_hooks_handler(
+    State(pool): State<DbPool>,
+) -> Result<Json<Vec<InboundHook>>, (StatusCode, Json<Value>)> {
+    database::get_inbound_hooks(&pool).map(Json).map_err(|_| {
+        error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to get inbound hooks",
+        )
+    })
+}
+
+pub async fn get_inbound_hook_handler(
+    State(pool): State<DbPool>,
+    Path(id): Path<String>,
+) -> Result<Json<InboundHook>, (StatusCode, Json<Value>)> {
+    find_hook(&pool, &id).map(Json)
+}
+
+pub async fn create_inbound_hook_handler(
+    State(pool): State<DbPool>,
+    JsonBody(body): JsonBody<CreateInboundHook>,
+) -> Result<(StatusCode, Json<InboundHook>), (StatusCode, Json<Value>)> {
+    let mut definition = InboundHookDefinition {
+        name: body.name,
+        mapping: body.mapping,
+        list_id: body.list_id.filter(|list_id| !list_id.is_empty()),
+        dedupe_key: body.dedupe_key.filter(|key| !key.is_empty()),
+        dedupe_window_minutes: body
+            .dedupe_window_minutes
+            .unwrap_or(DEFAULT_DEDUPE_WINDOW_MINUTES),
+    };
+    validate(&mut definition).map_err(bad_request)?;
+    if let Some(list_id) = &definition.list_id {
+        lists::ensure_list_exists(&pool, list_id)?;
+    }
+    ensure_name_free(&pool, &definition.name, None)?;
+    database::create_inbound_hook(&pool, &definition)
+        .map(|hook| (StatusCode::CREATED, Json(hook)))
+        .map_err(|_| {
+            error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to create inbound hook",
+            )
+        })
+}
+
+pub async fn update_inbound_hook_handler(
+    State(pool): State<DbPool>,
+    Path(id): Path<String>,
+    JsonBody(body): JsonBody<UpdateInboundHook>,
+) -> Result<Json<InboundHook>, (StatusCode, Json<Value>)> {
+    let hook = find_hook(&pool, &id)?;
+    let mut definition = InboundHookDefinition {
+        name: body.name.unwrap_or(hook.name),
+        mapping: body.mapping.unwrap_or(hook.mapping),
+        list_id: match body.list_id {
+            Some(list_id) => Some(list_id).filter(|list_id| !list_id.is_empty()),
+            None => hook.list_id,
+        },
+        dedupe_key: match body.dedupe_key {
+            Some(key) => Some(key).filter(|key| !key.is_empty()),
+            None => hook.dedupe_key,
+        },
+        dedupe_window_minutes: body
+            .dedupe_window_minutes
+            .unwrap_or(hook.dedupe_window_minutes),
+    };
+    validate(&mut definition).map_err(bad_request)?;
+    if let Some(list_id) = &definition.list_id {
+        lists::ensure_list_exists(&pool, list_id)?;
+    }
+    ensure_name_free(&pool, &definition.name, Some(&id))?;
+    match database::update_inbound_hook(&pool, &id, &definition, body.rotate_token) {
+        Ok(Some(hook)) => Ok(Json(hook)),
+        Ok(None) => Err(error(StatusCode::NOT_FOUND, "Inbound hook not found")),
+        Err(_) => Err(error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to update inbound hook",
+        )),
+    }
+}
+
+pub async fn delete_inbound_hook_handler(
+    State(pool): State<DbPool>,
+    Path(id): Path<String>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    match database::delete_inbound_hook(&pool, &id) {
+        Ok(true) => Ok(Json(
+            json!({"message": "Inbound hook deleted successfully"}),
+        )),
+        Ok(false) => Err(error(StatusCode::NOT_FOUND, "Inbound hook not found")),
+        Err(_) => Err(error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to delete inbound hook",
+        )),
+    }
+}
+
+/// Creates a todo from any JSON payload, mapped by the hook the token belongs to. A payload
+/// about an alert that already has an open todo within the hook's window updates that todo
+/// instead (`200`).
+pub async fn inbound_hook_handler(
+    State(pool): State<DbPool>,
+    Path(token): Path<String>,
+    Query(params): Query<InboundParams>,
+    headers: HeaderMap,
+    JsonBody(payload): JsonBody<Value>,
+) -> Result<(StatusCode, Json<Value>), (StatusCode, Json<Value>)> {
+    let hook = match database::find_inbound_hook_by_token(&pool, &token) {
+        Ok(Some(hook)) => hook,
+        Ok(None) => return Err(error(StatusCode::NOT_FOUND, "Unknown inbound hook")),
+        Err(_) => {
+            return Err(error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to get inbound hook",
+            ))
+        }
+    };
+    let key = dedupe_key(&hook, &payload)
+        .map_err(|message| error(StatusCode::UNPROCESSABLE_ENTITY, &message))?;
+    let create = payload_to_todo(&hook, &payload, key.as_deref())
+        .map_err(|message| error(StatusCode::UNPROCESSABLE_ENTITY, &message))?;
+    if params.dry_run {
+        let duplicate = match &key {
+            Some(key) => find_duplicate(&pool, &hook, key)
+                .map_err(|_| error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to get todos"))?,
+            None => None,
+        };
+        return Ok((
+            StatusCode::OK,
+            Json(json!({
+                "todo": create,
+                "dedupe_key": key,
+                "duplicate_of": duplicate.map(|todo| todo.id),
+            })),
+        ));
+    }
+
+    let upserted = match &key {
+        // The lookup and the create or update are one transaction, so concurrent deliveries
+        // of an alert make one todo and count every occurrence
+        Some(key) => {
+            database::create_or_update_todo(&pool, &duplicate_filter(&hook, key), create, |todo| {
+                within_window(&hook, &todo).then(|| record_occurrence(todo))
+            })
+        }
+        None => database::create_todo(&pool, create).map(Upserted::Created),
+    };
+    let (status, todo) = match upserted {
+        Ok(Upserted::Updated(todo)) => {
+            activity::record_todo(&pool, &headers, "update", &todo);
+            (StatusCode::OK, todo)
+        }
+        Ok(Upserted::Created(todo)) => {
+            activity::record_todo(&pool, &headers, "create", &todo);
+            (StatusCode::CREATED, todo)
+        }
+        Err(_) => {
+            return Err(error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to save todo",
+            ))
+        }
+    };
+    if let Err(e) = database::record_inbound_delivery(&pool, &hook.id) {
+        eprintln!(
+            "Failed to record delivery to inbound hook '{}': {e}",
+            hook.name
+        );
+    }
+    Ok((status, Json(json!(todo))))
+}
@@ -0,0 +1,172 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use axum::{
+    body::Body,
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::Json,
+};
+use serde::Serialize;
+use serde_json::{json, Value};
+use tokio_stream::StreamExt;
+
+use crate::config::Config;
+use crate::database::{self, CreateTodo, CustomField, DbPool};
+use crate::handlers;
+use crate::json_body;
+
+// Rows per transaction
+const BATCH_SIZE: usize = 500;
+// A single line longer than this is rejected instead of being buffered forever
+const MAX_LINE_BYTES: usize = 64 * 1024;
+// Only the first few errors are reported back
+const MAX_REPORTED_ERRORS: usize = 100;
+
+#[derive(Debug, Serialize)]
+pub struct LineError {
+    pub line: usize,
+    pub error: String,
+}
+
+#[derive(Debug, Serialize, Default)]
+pub struct IngestReport {
+    pub inserted: usize,
+    pub failed: usize,
+    pub errors: Vec<LineError>,
+}
+
+impl IngestReport {
+    fn fail(&mut self, line: usize, error: String) {
+        self.failed += 1;
+        if self.errors.len() < MAX_REPORTED_ERRORS {
+            self.errors.push(LineError { line, error });
+        }
+    }
+}
+
+/// Parses and validates one `CreateTodo` against the known lists and custom fields.
+pub fn parse_todo(
+    line: &[u8],
+    strict: bool,
+    list_ids: &HashSet<String>,
+    fields: &[CustomField],
+) -> Result<CreateTodo, String> {
+    let mut todo: CreateTodo = json_body::parse(line, strict).map_err(|e| e.message)?;
+    handlers::validate_create_todo(&mut todo)?;
+    if let Some(list_id) = todo.list_id.as_ref().filter(|id| !list_ids.contains(*id)) {
+        return Err(format!("Unknown list '{list_id}'"));
+    }
+    if let Some(values) = &todo.custom_fields {
+        database::validate_custom_field_values(fields, values)?;
+    }
+    Ok(todo)
+}
+
+async fn flush(
+    pool: &DbPool,
+    batch: &mut Vec<CreateTodo>,
+    report: &mut IngestReport,
+) -> Result<(), (StatusCode, Json<Value>)> {
+    if batch.is_empty() {
+        return Ok(());
+    }
+    let todos = std::mem::take(batch);
+    let pool = pool.clone();
+    match tokio::task::spawn_blocking(move || database::create_todos(&pool, todos)).await {
+        Ok(Ok(created)) => {
+            report.inserted += created.len();
+            Ok(())
+        }
+        _ => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Failed to insert batch", "inserted": report.inserted})),
+        )),
+    }
+}
+
+/// Reads newline-delimited `CreateTodo` objects from the request body and inserts them
+/// in batched transactions. The body is only read further once the previous batch is
+/// committed, so a fast client is slowed down to the speed of the database.
+pub async fn ingest_handler(
+    State(pool): State<DbPool>,
+    State(config): State<Arc<Config>>,
+    headers: HeaderMap,
+    body: Body,
+) -> Result<Json<IngestReport>, (StatusCode, Json<Value>)> {
+    let strict = json_body::is_strict(&config, &headers);
+    // Looked up once; a list deleted mid-ingest fails its batch instead of single lines
+    let list_ids: HashSet<String> = database::get_lists(&pool)
+        .map_err(|_| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": "Failed to get lists"})),
+            )
+        })?
+        .into_iter()
+        .map(|list| list.id)
+        .collect();
+    let fields = database::get_custom_fields(&pool).map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Failed to get custom fields"})),
+        )
+    })?;
+    let mut stream = body.into_data_stream();
+    let mut report = IngestReport::default();
+    let mut batch = Vec::with_capacity(BATCH_SIZE);
+    let mut buffer: Vec<u8> = Vec::new();
+    let mut line_number = 0;
+
+    loop {
+        let chunk = match stream.next().await {
+            Some(Ok(chunk)) => Some(chunk),
+            Some(Err(_)) => {
+                return Err((
+                    StatusCode::BAD_REQUEST,
+                    Json(
+                        json!({"error": "Failed to read request body", "inserted": report.inserted}),
+                    ),
+                ))
+            }
+            None => None,
+        };
+        let finished = chunk.is_none();
+        if let Some(chunk) = chunk {
+            buffer.extend_from_slice(&chunk);
+        } else if !buffer.is_empty() {
+            // The last line does not need a trailing newline
+            buffer.push(b'\n');
+        }
+
+        while let Some(end) = buffer.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = buffer.drain(..=end).collect();
+            line_number += 1;
+            let line = line.trim_ascii();
+            if line.is_empty() {
+                continue;
+            }
+            match parse_todo(line, strict, &list_ids, &fields) {
+                Ok(todo) => batch.push(todo),
+                Err(error) => report.fail(line_number, error),
+            }
+            if batch.len() >= BATCH_SIZE {
+                flush(&pool, &mut batch, &mut report).await?;
+            }
+        }
+        if buffer.len() > MAX_LINE_BYTES {
+            return Err((
+                StatusCode::PAYLOAD_TOO_LARGE,
+                Json(json!({
+                    "error": format!("Line {} exceeds {MAX_LINE_BYTES} bytes", line_number + 1),
+                    "inserted": report.inserted,
+                })),
+            ));
+        }
+        if finished {
+            break;
+        }
+    }
+    flush(&pool, &mut batch, &mut report).await?;
+    Ok(Json(report))
+}
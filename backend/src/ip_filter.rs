@@ -0,0 +1,134 @@
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::{IntoResponse, Json, Response},
+};
+use serde_json::json;
+
+use crate::access_log;
+use crate::config::Config;
+
+/// An address range in CIDR notation, e.g. `10.0.0.0/8`. A bare address is a range of one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IpNet {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl IpNet {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let invalid = || format!("Invalid address range '{value}': expected e.g. 10.0.0.0/8");
+        let (addr, prefix) = match value.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (value, None),
+        };
+        let addr: IpAddr = addr.trim().parse().map_err(|_| invalid())?;
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => prefix
+                .trim()
+                .parse()
+                .ok()
+                .filter(|prefix| *prefix <= max)
+                .ok_or_else(invalid)?,
+            None => max,
+        };
+        Ok(IpNet { addr, prefix })
+    }
+
+    /// IPv4 clients connecting over IPv6 (`::ffff:a.b.c.d`) match IPv4 ranges.
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.addr, ip.to_canonical()) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = u32::MAX
+                    .checked_shl(32 - u32::from(self.prefix))
+                    .unwrap_or(0);
+                u32::from(net) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = u128::MAX
+                    .checked_shl(128 - u32::from(self.prefix))
+                    .unwrap_or(0);
+                u128::from(net) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Parses a comma-separated list of ranges, as in `ADMIN_ALLOWLIST`.
+pub fn parse_list(value: &str) -> Result<Vec<IpNet>, String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|range| !range.is_empty())
+        .map(IpNet::parse)
+        .collect()
+}
+
+// The peer address, or the last `X-Forwarded-For` entry (the one the proxy added) when the
+// server runs behind a trusted proxy
+fn client_ip(request: &Request, trust_forwarded_for: bool) -> Option<IpAddr> {
+    let forwarded = request
+        .headers()
+        .get("x-forwarded-for")
+        .and_then(|value| value.to_str().ok());
+    match forwarded {
+        Some(forwarded) if trust_forwarded_for => forwarded.rsplit(',').next()?.trim().parse().ok(),
+        _ => request
+            .extensions()
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip()),
+    }
+}
+
+fn blocked(request: &Request, ip: Option<IpAddr>, reason: &str) -> Response {
+    eprintln!(
+        "🚫 Blocked {} {} from ip={} ({reason}) req={}",
+        request.method(),
+        request.uri().path(),
+        ip.map_or_else(|| "unknown".to_string(), |ip| ip.to_string()),
+        access_log::current_request_id().unwrap_or_else(|| "-".to_string()),
+    );
+    (
+        StatusCode::FORBIDDEN,
+        Json(json!({"error": "Requests from your address are not allowed"})),
+    )
+        .into_response()
+}
+
+/// Rejects clients on `IP_DENYLIST`, and clients outside `ADMIN_ALLOWLIST` on the admin
+/// routes, with 403. Clients whose address cannot be told are only let through where no
+/// allowlist applies.
+pub async fn ip_filter_layer(
+    State(config): State<Arc<Config>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if config.ip_denylist.is_empty() && config.admin_allowlist.is_empty() {
+        return next.run(request).await;
+    }
+    let ip = client_ip(&request, config.trust_forwarded_for);
+    if let Some(ip) = ip {
+        if config.ip_denylist.iter().any(|range| range.contains(ip)) {
+            return blocked(&request, Some(ip), "denylisted");
+        }
+    }
+    let is_admin = request.uri().path().starts_with("/api/admin");
+    if is_admin
+        && !config.admin_allowlist.is_empty()
+        && !ip.is_some_and(|ip| {
+            config
+                .admin_allowlist
+                .iter()
+                .any(|range| range.contains(ip))
+        })
+    {
+        return blocked(&request, ip, "not on the admin allowlist");
+    }
+    next.run(request).await
+}
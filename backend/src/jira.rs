@@ -0,0 +1,233 @@
+use axum::{
+    extract::State,
+    http::{header, HeaderMap, StatusCode},
+    response::Json,
+};
+use chrono::{NaiveDate, NaiveDateTime};
+use serde_json::{json, Value};
+
+use crate::database::{self, CreateTodo, DbPool, Metadata};
+use crate::import;
+
+/// A Jira issue reduced to the fields we map onto todos.
+#[derive(Debug, Default)]
+pub struct JiraIssue {
+    pub key: Option<String>,
+    pub summary: String,
+    pub description: Option<String>,
+    pub status: Option<String>,
+    pub status_category: Option<String>,
+    pub labels: Vec<String>,
+    pub due: Option<String>,
+}
+
+// Jira's `duedate` is a date (`2026-10-20`), the CSV export writes e.g. `20/Oct/26 12:00 AM`
+// and some exports use RFC3339. A date is due at the end of that day.
+fn due_date(value: &str) -> Option<String> {
+    let value = value.trim();
+    if let Ok(due) = database::normalize_due_date(value) {
+        return Some(due);
+    }
+    let date = NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .or_else(|_| {
+            NaiveDateTime::parse_from_str(value, "%d/%b/%y %I:%M %p").map(|time| time.date())
+        })
+        .ok()?;
+    let due = date.and_hms_opt(23, 59, 59)?.and_utc();
+    database::normalize_due_date(&due.to_rfc3339()).ok()
+}
+
+impl JiraIssue {
+    fn is_done(&self) -> bool {
+        if let Some(category) = &self.status_category {
+            return category.eq_ignore_ascii_case("done");
+        }
+        self.status.as_deref().is_some_and(|status| {
+            ["done", "closed", "resolved"]
+                .iter()
+                .any(|done| status.eq_ignore_ascii_case(done))
+        })
+    }
+
+    fn to_create(&self) -> CreateTodo {
+        // Keep the issue key so the todo can be traced back to Jira
+        let mut metadata = Metadata::new();
+        metadata.insert("source".to_string(), json!("jira"));
+        if let Some(key) = &self.key {
+            metadata.insert("jira_key".to_string(), json!(key));
+        }
+        if let Some(status) = &self.status {
+            metadata.insert("jira_status".to_string(), json!(status));
+        }
+        if !self.labels.is_empty() {
+            metadata.insert("jira_labels".to_string(), json!(self.labels));
+        }
+        let due = self.due.as_deref().map(|due| (due, due_date(due)));
+        if let Some((due, None)) = due {
+            // Not a form we can read, so keep it as it was rather than drop it
+            metadata.insert("jira_due".to_string(), json!(due));
+        }
+
+        let title = match (&self.key, self.summary.trim()) {
+            (Some(key), "") => key.clone(),
+            (_, "") => "(untitled)".to_string(),
+            (_, summary) => summary.to_string(),
+        };
+        CreateTodo {
+            title,
+            description: self.description.clone().filter(|d| !d.trim().is_empty()),
+            metadata: Some(metadata),
+            due_date: due.and_then(|(_, due_date)| due_date),
+            start_date: None,
+            remind_at: None,
+            estimate_minutes: None,
+            custom_fields: None,
+            priority: None,
+            parent_id: None,
+            icon: None,
+            recurrence: None,
+            list_id: None,
+            tags: None,
+        }
+    }
+}
+
+// Jira Cloud returns descriptions as Atlassian Document Format; flatten it to plain text
+fn adf_to_text(node: &Value, out: &mut String) {
+    if let Some(text) = node.get("text").and_then(Value::as_str) {
+        out.push_str(text);
+    }
+    if let Some(children) = node.get("content").and_then(Value::as_array) {
+        for child in children {
+            adf_to_text(child, out);
+        }
+    }
+    if matches!(
+        node.get("type").and_then(Value::as_str),
+        Some("paragraph" | "heading" | "listItem" | "hardBreak")
+    ) && !out.ends_with('\n')
+    {
+        out.push('\n');
+    }
+}
+
+fn description_text(value: &Value) -> Option<String> {
+    match value {
+        Value::String(text) => Some(text.clone()),
+        Value::Object(_) => {
+            let mut text = String::new();
+            adf_to_text(value, &mut text);
+            Some(text.trim_end().to_string())
+        }
+        _ => None,
+    }
+}
+
+/// Parses the `issues` array of a Jira search / JSON export.
+pub fn parse_json(body: &str) -> Result<Vec<JiraIssue>, String> {
+    let value: Value = serde_json::from_str(body).map_err(|e| format!("Invalid JSON: {e}"))?;
+    let issues = value
+        .get("issues")
+        .and_then(Value::as_array)
+        .ok_or("Expected an object with an `issues` array")?;
+
+    Ok(issues
+        .iter()
+        .map(|issue| {
+            let fields = &issue["fields"];
+            JiraIssue {
+                key: issue["key"].as_str().map(str::to_string),
+                summary: fields["summary"].as_str().unwrap_or_default().to_string(),
+                description: description_text(&fields["description"]),
+                status: fields["status"]["name"].as_str().map(str::to_string),
+                status_category: fields["status"]["statusCategory"]["key"]
+                    .as_str()
+                    .map(str::to_string),
+                labels: fields["labels"]
+                    .as_array()
+                    .map(|labels| {
+                        labels
+                            .iter()
+                            .filter_map(|label| label.as_str().map(str::to_string))
+                            .collect()
+                    })
+                    .unwrap_or_default(),
+                due: fields["duedate"].as_str().map(str::to_string),
+            }
+        })
+        .collect())
+}
+
+/// Parses Jira's CSV export. Jira repeats the `Labels` column once per label.
+pub fn parse_csv(body: &str) -> Result<Vec<JiraIssue>, String> {
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .from_reader(body.as_bytes());
+    let headers = reader
+        .headers()
+        .map_err(|e| format!("Invalid CSV: {e}"))?
+        .clone();
+    if !headers.iter().any(|h| h == "Summary") {
+        return Err("CSV export must contain a `Summary` column".to_string());
+    }
+
+    let mut issues = Vec::new();
+    for record in reader.records() {
+        let record = record.map_err(|e| format!("Invalid CSV: {e}"))?;
+        let mut issue = JiraIssue::default();
+        for (name, value) in headers.iter().zip(record.iter()) {
+            let value = value.trim();
+            if value.is_empty() {
+                continue;
+            }
+            match name {
+                "Summary" => issue.summary = value.to_string(),
+                "Issue key" => issue.key = Some(value.to_string()),
+                "Description" => issue.description = Some(value.to_string()),
+                "Status" => issue.status = Some(value.to_string()),
+                "Status Category" => issue.status_category = Some(value.to_string()),
+                "Labels" => issue.labels.push(value.to_string()),
+                "Due Date" | "Due date" => issue.due = Some(value.to_string()),
+                _ => {}
+            }
+        }
+        issues.push(issue);
+    }
+    Ok(issues)
+}
+
+pub async fn import_jira_handler(
+    State(pool): State<DbPool>,
+    headers: HeaderMap,
+    body: String,
+) -> Result<(StatusCode, Json<Value>), (StatusCode, Json<Value>)> {
+    let is_csv = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("text/csv"));
+    let issues = if is_csv {
+        parse_csv(&body)
+    } else {
+        parse_json(&body)
+    }
+    .map_err(|message| (StatusCode::BAD_REQUEST, Json(json!({"error": message}))))?;
+
+    let todos = issues
+        .iter()
+        .map(|issue| import::SourceTodo {
+            create: issue.to_create(),
+            completed: issue.is_done(),
+            labels: issue.labels.clone(),
+        })
+        .collect();
+    let imported = import::import_todos(&pool, todos).map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Failed to import todos"})),
+        )
+    })?;
+    Ok((
+        StatusCode::CREATED,
+        Json(json!({"imported": imported.len(), "todos": imported})),
+    ))
+}
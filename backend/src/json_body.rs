@@ -0,0 +1,172 @@
+use std::sync::Arc;
+
+use axum::{
+    async_trait,
+    body::Bytes,
+    extract::{FromRef, FromRequest, Request},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Json, Response},
+};
+use serde::de::DeserializeOwned;
+use serde_json::json;
+
+use crate::config::Config;
+
+/// Per-request override of the `STRICT_JSON` setting (`true` or `false`)
+pub const STRICT_HEADER: &str = "x-strict-json";
+
+/// Drop-in replacement for axum's `Json` extractor whose rejections say which field
+/// was wrong and what type was expected, in the usual `{"error": ...}` format.
+/// In strict mode, fields the target type does not know are rejected instead of dropped.
+pub struct JsonBody<T>(pub T);
+
+/// `JsonBody` for endpoints whose body may be left out: an empty body is `None`, and any
+/// other body must be valid JSON, so a mistyped body is rejected rather than ignored.
+pub struct OptionalJsonBody<T>(pub Option<T>);
+
+#[derive(Debug)]
+pub struct JsonBodyError {
+    status: StatusCode,
+    pub message: String,
+    /// Dotted path of the offending field, e.g. `metadata.priority`
+    pub field: Option<String>,
+}
+
+impl IntoResponse for JsonBodyError {
+    fn into_response(self) -> Response {
+        (
+            self.status,
+            Json(json!({"error": self.message, "field": self.field})),
+        )
+            .into_response()
+    }
+}
+
+fn malformed(error: serde_json::Error) -> JsonBodyError {
+    JsonBodyError {
+        status: StatusCode::BAD_REQUEST,
+        message: format!("Malformed JSON: {error}"),
+        field: None,
+    }
+}
+
+/// Whether unknown fields are rejected for this request.
+pub fn is_strict(config: &Config, headers: &HeaderMap) -> bool {
+    headers
+        .get(STRICT_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(config.strict_json)
+}
+
+/// Deserializes a JSON document, reporting the path of the field that failed.
+/// With `strict`, fields that `T` would silently ignore are an error.
+pub fn parse<T: DeserializeOwned>(bytes: &[u8], strict: bool) -> Result<T, JsonBodyError> {
+    let mut unknown_fields = Vec::new();
+    let deserializer = &mut serde_json::Deserializer::from_slice(bytes);
+    let mut track = |path: serde_ignored::Path| unknown_fields.push(path.to_string());
+    let tracked = serde_ignored::Deserializer::new(&mut *deserializer, &mut track);
+    let value = serde_path_to_error::deserialize(tracked).map_err(|e| {
+        let path = e.path().to_string();
+        let inner = e.into_inner();
+        if !inner.is_data() {
+            return malformed(inner);
+        }
+        // `.` is the document itself, e.g. for a missing field or a wrong top-level type
+        let field = (path != ".").then_some(path);
+        let message = match &field {
+            Some(field) => format!("Invalid value for `{field}`: {inner}"),
+            None => format!("Invalid request body: {inner}"),
+        };
+        JsonBodyError {
+            status: StatusCode::UNPROCESSABLE_ENTITY,
+            message,
+            field,
+        }
+    })?;
+    // Anything after the document, e.g. a second object, is a client bug too
+    deserializer.end().map_err(malformed)?;
+
+    if strict && !unknown_fields.is_empty() {
+        let names = unknown_fields
+            .iter()
+            .map(|field| format!("`{field}`"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        return Err(JsonBodyError {
+            status: StatusCode::UNPROCESSABLE_ENTITY,
+            message: format!(
+                "Unknown field(s) {names}; check the spelling against the API documentation"
+            ),
+            field: unknown_fields.into_iter().next(),
+        });
+    }
+    Ok(value)
+}
+
+fn has_json_content_type(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(';').next())
+        .map(|mime| mime.trim().to_ascii_lowercase())
+        .is_some_and(|mime| mime == "application/json" || mime.ends_with("+json"))
+}
+
+fn unsupported_media_type() -> JsonBodyError {
+    JsonBodyError {
+        status: StatusCode::UNSUPPORTED_MEDIA_TYPE,
+        message: "Expected a request body with `Content-Type: application/json`".to_string(),
+        field: None,
+    }
+}
+
+#[async_trait]
+impl<T, S> FromRequest<S> for JsonBody<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+    Arc<Config>: FromRef<S>,
+{
+    type Rejection = Response;
+
+    async fn from_request(request: Request, state: &S) -> Result<Self, Self::Rejection> {
+        if !has_json_content_type(request.headers()) {
+            return Err(unsupported_media_type().into_response());
+        }
+        let strict = is_strict(&Arc::<Config>::from_ref(state), request.headers());
+        let bytes = Bytes::from_request(request, state)
+            .await
+            .map_err(IntoResponse::into_response)?;
+        parse(&bytes, strict)
+            .map(JsonBody)
+            .map_err(IntoResponse::into_response)
+    }
+}
+
+#[async_trait]
+impl<T, S> FromRequest<S> for OptionalJsonBody<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+    Arc<Config>: FromRef<S>,
+{
+    type Rejection = Response;
+
+    async fn from_request(request: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let json = has_json_content_type(request.headers());
+        let strict = is_strict(&Arc::<Config>::from_ref(state), request.headers());
+        let bytes = Bytes::from_request(request, state)
+            .await
+            .map_err(IntoResponse::into_response)?;
+        if bytes.trim_ascii().is_empty() {
+            return Ok(OptionalJsonBody(None));
+        }
+        if !json {
+            return Err(unsupported_media_type().into_response());
+        }
+        parse(&bytes, strict)
+            .map(|value| OptionalJsonBody(Some(value)))
+            .map_err(IntoResponse::into_response)
+    }
+}
@@ -0,0 +1,91 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::Json,
+};
+use chrono::{NaiveDate, Utc};
+use serde_json::{json, Value};
+
+use crate::database::{self, DbPool, ListSnapshot};
+use crate::lists;
+
+// Today's snapshot is refreshed on every check, so it ends up holding the day's final counts
+pub const CHECK_INTERVAL: Duration = Duration::from_secs(60 * 60);
+const DATE_FORMAT: &str = "%Y-%m-%d";
+const DEFAULT_DAYS: i64 = 90;
+/// Longest range one request can ask for
+pub const MAX_DAYS: i64 = 366 * 2;
+
+fn error(status: StatusCode, message: &str) -> (StatusCode, Json<Value>) {
+    (status, Json(json!({"error": message})))
+}
+
+fn parse_date(params: &HashMap<String, String>, name: &str) -> Result<Option<NaiveDate>, String> {
+    params
+        .get(name)
+        .map(|value| {
+            NaiveDate::parse_from_str(value, DATE_FORMAT)
+                .map_err(|_| format!("Invalid {name} '{value}': expected YYYY-MM-DD"))
+        })
+        .transpose()
+}
+
+/// Background task that records the open and closed counts of every list once a day.
+pub async fn run_snapshot_job(pool: DbPool) {
+    let mut interval = tokio::time::interval(CHECK_INTERVAL);
+    loop {
+        interval.tick().await;
+        let pool = pool.clone();
+        let today = Utc::now().format(DATE_FORMAT).to_string();
+        match tokio::task::spawn_blocking(move || database::snapshot_lists(&pool, &today)).await {
+            Ok(Ok(_)) => {}
+            Ok(Err(e)) => eprintln!("List snapshot failed: {e}"),
+            Err(e) => eprintln!("List snapshot panicked: {e}"),
+        }
+    }
+}
+
+/// Daily counts of a list for `?from=` to `?to=` (`YYYY-MM-DD`, inclusive). Defaults to the
+/// last 90 days. Days before the list existed or while the server was down have no entry.
+pub async fn get_list_snapshots_handler(
+    State(pool): State<DbPool>,
+    Path(id): Path<String>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<Json<Vec<ListSnapshot>>, (StatusCode, Json<Value>)> {
+    lists::find_list(&pool, &id)?;
+    let bad_request = |message: String| error(StatusCode::BAD_REQUEST, &message);
+    let to = parse_date(&params, "to")
+        .map_err(bad_request)?
+        .unwrap_or_else(|| Utc::now().date_naive());
+    let from = parse_date(&params, "from")
+        .map_err(bad_request)?
+        .unwrap_or(to - chrono::Duration::days(DEFAULT_DAYS - 1));
+    if from > to {
+        return Err(error(
+            StatusCode::BAD_REQUEST,
+            "`from` must not be after `to`",
+        ));
+    }
+    if (to - from).num_days() >= MAX_DAYS {
+        return Err(error(
+            StatusCode::BAD_REQUEST,
+            &format!("At most {MAX_DAYS} days can be requested at once"),
+        ));
+    }
+    database::get_list_snapshots(
+        &pool,
+        &id,
+        &from.format(DATE_FORMAT).to_string(),
+        &to.format(DATE_FORMAT).to_string(),
+    )
+    .map(Json)
+    .map_err(|_| {
+        error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to get list snapshots",
+        )
+    })
+}
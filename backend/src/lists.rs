@@ -0,0 +1,379 @@
+use std::collections::HashMap;
+
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Json, Response},
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::activity;
+use crate::custom_fields;
+use crate::database::{self, CreateTodo, DbPool, List, Priority, Todo, INBOX_LIST_ID};
+use crate::etag;
+use crate::handlers;
+use crate::icons;
+use crate::json_body::JsonBody;
+use crate::palette;
+
+// Ten years; longer policies are almost certainly a unit mistake
+const MAX_RETENTION_DAYS: u32 = 3650;
+/// Longest default reminder lead time: 30 days
+pub const MAX_REMIND_BEFORE_MINUTES: u32 = 43_200;
+
+#[derive(Debug, Deserialize)]
+pub struct CreateList {
+    pub name: String,
+    pub icon: Option<String>,
+    /// Name of a palette color
+    pub color: Option<String>,
+    pub archive_completed_after_days: Option<u32>,
+    pub purge_trash_after_days: Option<u32>,
+    #[serde(default)]
+    pub require_review: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateList {
+    pub name: Option<String>,
+    /// An empty string removes the icon
+    pub icon: Option<String>,
+    /// An empty string removes the color
+    pub color: Option<String>,
+    /// `0` turns the policy off
+    pub archive_completed_after_days: Option<u32>,
+    /// `0` turns the policy off
+    pub purge_trash_after_days: Option<u32>,
+    /// Todos already waiting for review stay in the queue when this is turned off
+    pub require_review: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateListDefaults {
+    /// `null` removes the default priority
+    #[serde(default, deserialize_with = "database::nullable")]
+    pub priority: Option<Option<Priority>>,
+    /// Replaces the default tags; `[]` removes them
+    pub tags: Option<Vec<String>>,
+    /// `null` removes the default reminder
+    #[serde(default, deserialize_with = "database::nullable")]
+    pub remind_before_minutes: Option<Option<u32>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeleteListParams {
+    /// Delete the list's todos instead of moving them to the Inbox
+    #[serde(default)]
+    pub delete_todos: bool,
+}
+
+fn error(status: StatusCode, message: &str) -> (StatusCode, Json<Value>) {
+    (status, Json(json!({"error": message})))
+}
+
+fn list_name(name: &str) -> Result<String, (StatusCode, Json<Value>)> {
+    database::normalize_list_name(name).map_err(|message| error(StatusCode::BAD_REQUEST, &message))
+}
+
+fn list_icon(icon: &str) -> Result<String, (StatusCode, Json<Value>)> {
+    icons::validate_icon(icon).map_err(|message| error(StatusCode::BAD_REQUEST, &message))
+}
+
+// `0` means keep forever
+fn retention_days(
+    field: &str,
+    days: Option<u32>,
+) -> Result<Option<Option<u32>>, (StatusCode, Json<Value>)> {
+    match days {
+        Some(days) if days > MAX_RETENTION_DAYS => Err(error(
+            StatusCode::BAD_REQUEST,
+            &format!("`{field}` must be between 0 and {MAX_RETENTION_DAYS} days"),
+        )),
+        Some(days) => Ok(Some((days > 0).then_some(days))),
+        None => Ok(None),
+    }
+}
+
+// Names are unique ignoring case; `except` is the list being renamed
+fn ensure_name_free(
+    pool: &DbPool,
+    name: &str,
+    except: Option<&str>,
+) -> Result<(), (StatusCode, Json<Value>)> {
+    match database::find_list_by_name(pool, name) {
+        Ok(Some(existing)) if Some(existing.id.as_str()) != except => Err((
+            StatusCode::CONFLICT,
+            Json(json!({"error": "A list with this name already exists", "existing": existing})),
+        )),
+        Ok(_) => Ok(()),
+        Err(_) => Err(error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to check list name",
+        )),
+    }
+}
+
+pub fn find_list(pool: &DbPool, id: &str) -> Result<List, (StatusCode, Json<Value>)> {
+    match database::get_list(pool, id) {
+        Ok(Some(list)) => Ok(list),
+        Ok(None) => Err(error(StatusCode::NOT_FOUND, "List not found")),
+        Err(_) => Err(error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to get list",
+        )),
+    }
+}
+
+/// Checks a `list_id` sent in a todo body refers to an existing list, and returns the list.
+pub fn ensure_list_exists(pool: &DbPool, id: &str) -> Result<List, (StatusCode, Json<Value>)> {
+    match database::get_list(pool, id) {
+        Ok(Some(list)) => Ok(list),
+        Ok(None) => Err(error(
+            StatusCode::BAD_REQUEST,
+            &format!("Unknown list '{id}'"),
+        )),
+        Err(_) => Err(error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to get list",
+        )),
+    }
+}
+
+pub async fn get_lists_handler(
+    State(pool): State<DbPool>,
+) -> Result<Json<Vec<List>>, (StatusCode, Json<Value>)> {
+    database::get_lists(&pool)
+        .map(Json)
+        .map_err(|_| error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to get lists"))
+}
+
+pub async fn get_list_handler(
+    State(pool): State<DbPool>,
+    Path(id): Path<String>,
+) -> Result<Json<List>, (StatusCode, Json<Value>)> {
+    find_list(&pool, &id).map(Json)
+}
+
+pub async fn create_list_handler(
+    State(pool): State<DbPool>,
+    JsonBody(body): JsonBody<CreateList>,
+) -> Result<(StatusCode, Json<List>), (StatusCode, Json<Value>)> {
+    let name = list_name(&body.name)?;
+    ensure_name_free(&pool, &name, None)?;
+    let icon = body.icon.as_deref().map(list_icon).transpose()?;
+    let color = match &body.color {
+        Some(color) => Some(palette::resolve_color(&pool, color)?),
+        None => None,
+    };
+    let archive_after = retention_days(
+        "archive_completed_after_days",
+        body.archive_completed_after_days,
+    )?;
+    let purge_after = retention_days("purge_trash_after_days", body.purge_trash_after_days)?;
+    let list = database::create_list(&pool, &name, icon.as_deref(), color.as_deref())
+        .and_then(|list| {
+            database::set_list_retention(&pool, &list.id, archive_after, purge_after)?;
+            database::set_list_require_review(&pool, &list.id, body.require_review)?;
+            database::get_list(&pool, &list.id)
+        })
+        .map_err(|_| error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to create list"))?;
+    list.map(|list| (StatusCode::CREATED, Json(list)))
+        .ok_or_else(|| error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to create list"))
+}
+
+pub async fn update_list_handler(
+    State(pool): State<DbPool>,
+    Path(id): Path<String>,
+    JsonBody(body): JsonBody<UpdateList>,
+) -> Result<Json<List>, (StatusCode, Json<Value>)> {
+    let name = match &body.name {
+        Some(name) => {
+            let name = list_name(name)?;
+            ensure_name_free(&pool, &name, Some(&id))?;
+            Some(name)
+        }
+        None => None,
+    };
+    let icon = match body.icon.as_deref() {
+        Some("") => Some(String::new()),
+        Some(icon) => Some(list_icon(icon)?),
+        None => None,
+    };
+    let color = match body.color.as_deref() {
+        Some("") => Some(String::new()),
+        Some(color) => Some(palette::resolve_color(&pool, color)?),
+        None => None,
+    };
+    let archive_after = retention_days(
+        "archive_completed_after_days",
+        body.archive_completed_after_days,
+    )?;
+    let purge_after = retention_days("purge_trash_after_days", body.purge_trash_after_days)?;
+    let updated = database::update_list(
+        &pool,
+        &id,
+        name.as_deref(),
+        icon.as_deref(),
+        color.as_deref(),
+    )
+    .and_then(|list| match list {
+        Some(_) => {
+            database::set_list_retention(&pool, &id, archive_after, purge_after)?;
+            if let Some(require_review) = body.require_review {
+                database::set_list_require_review(&pool, &id, require_review)?;
+            }
+            database::get_list(&pool, &id)
+        }
+        None => Ok(None),
+    });
+    match updated {
+        Ok(Some(list)) => Ok(Json(list)),
+        Ok(None) => Err(error(StatusCode::NOT_FOUND, "List not found")),
+        Err(_) => Err(error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to update list",
+        )),
+    }
+}
+
+/// Changes the defaults applied to todos created in a list. Fields left out keep their value.
+pub async fn update_list_defaults_handler(
+    State(pool): State<DbPool>,
+    Path(id): Path<String>,
+    JsonBody(body): JsonBody<UpdateListDefaults>,
+) -> Result<Json<List>, (StatusCode, Json<Value>)> {
+    let tags = body
+        .tags
+        .as_deref()
+        .map(database::normalize_tag_names)
+        .transpose()
+        .map_err(|message| error(StatusCode::BAD_REQUEST, &message))?;
+    if let Some(Some(minutes)) = body.remind_before_minutes {
+        if minutes > MAX_REMIND_BEFORE_MINUTES {
+            return Err(error(
+                StatusCode::BAD_REQUEST,
+                &format!(
+                    "`remind_before_minutes` must be between 0 and {MAX_REMIND_BEFORE_MINUTES}"
+                ),
+            ));
+        }
+    }
+    match database::set_list_defaults(
+        &pool,
+        &id,
+        body.priority,
+        tags.as_deref(),
+        body.remind_before_minutes,
+    ) {
+        Ok(Some(list)) => Ok(Json(list)),
+        Ok(None) => Err(error(StatusCode::NOT_FOUND, "List not found")),
+        Err(_) => Err(error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to update list defaults",
+        )),
+    }
+}
+
+/// Fills the fields a new todo left out with the defaults of `list`, the list it goes to.
+/// Expects a validated todo, whose due date is normalized.
+pub fn apply_defaults(list: &List, create_todo: &mut CreateTodo) {
+    let defaults = &list.defaults;
+    if create_todo.priority.is_none() {
+        create_todo.priority = defaults.priority;
+    }
+    if create_todo.tags.is_none() && !defaults.tags.is_empty() {
+        create_todo.tags = Some(defaults.tags.clone());
+    }
+    if let (None, Some(due_date), Some(minutes)) = (
+        &create_todo.remind_at,
+        &create_todo.due_date,
+        defaults.remind_before_minutes,
+    ) {
+        // A reminder that would already be due is not set
+        create_todo.remind_at = database::parse_timestamp(due_date)
+            .ok()
+            .map(|due| due - chrono::Duration::minutes(i64::from(minutes)))
+            .filter(|remind_at| *remind_at > chrono::Utc::now())
+            .map(|remind_at| remind_at.to_rfc3339_opts(chrono::SecondsFormat::Secs, true));
+    }
+}
+
+/// Deletes a list. Its todos move to the Inbox unless `?delete_todos=true` is given.
+pub async fn delete_list_handler(
+    State(pool): State<DbPool>,
+    Path(id): Path<String>,
+    Query(params): Query<DeleteListParams>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    if id == INBOX_LIST_ID {
+        return Err(error(
+            StatusCode::CONFLICT,
+            "The Inbox list cannot be deleted",
+        ));
+    }
+    match database::delete_list(&pool, &id, params.delete_todos) {
+        Ok(Some(count)) if params.delete_todos => Ok(Json(
+            json!({"message": "List deleted successfully", "deleted_todos": count}),
+        )),
+        Ok(Some(count)) => Ok(Json(
+            json!({"message": "List deleted successfully", "moved_todos": count}),
+        )),
+        Ok(None) => Err(error(StatusCode::NOT_FOUND, "List not found")),
+        Err(_) => Err(error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to delete list",
+        )),
+    }
+}
+
+/// Todos in a list; takes the same query parameters as `GET /api/todos`, and answers
+/// `If-None-Match` the same way.
+pub async fn get_list_todos_handler(
+    State(pool): State<DbPool>,
+    Path(id): Path<String>,
+    Query(params): Query<HashMap<String, String>>,
+    headers: HeaderMap,
+) -> Result<Response, (StatusCode, Json<Value>)> {
+    find_list(&pool, &id)?;
+    let mut filter = handlers::parse_todo_filter(&params)
+        .map_err(|message| error(StatusCode::BAD_REQUEST, &message))?;
+    filter.list_id = Some(id);
+    let todos = database::get_todos(&pool, &filter)
+        .map_err(|_| error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to get todos"))?;
+    let body = serde_json::to_vec(&todos)
+        .map_err(|_| error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to get todos"))?;
+    let etag = etag::of(&[&body]);
+    if etag::not_modified(&headers, &etag) {
+        return Ok(etag::not_modified_response(&etag));
+    }
+    let mut response_headers = HeaderMap::new();
+    response_headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/json"),
+    );
+    etag::insert(&mut response_headers, &etag);
+    Ok((response_headers, body).into_response())
+}
+
+/// Creates a todo in a list; the body is the same as for `POST /api/todos`.
+pub async fn create_list_todo_handler(
+    State(pool): State<DbPool>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+    JsonBody(mut create_todo): JsonBody<CreateTodo>,
+) -> Result<(StatusCode, Json<Todo>), (StatusCode, Json<Value>)> {
+    handlers::validate_create_todo(&mut create_todo)
+        .map_err(|message| error(StatusCode::BAD_REQUEST, &message))?;
+    let list = find_list(&pool, &id)?;
+    if let Some(values) = &create_todo.custom_fields {
+        custom_fields::check_values(&pool, values)?;
+    }
+    create_todo.list_id = Some(id);
+    apply_defaults(&list, &mut create_todo);
+    database::create_todo(&pool, create_todo)
+        .map(|todo| {
+            activity::record_todo(&pool, &headers, "create", &todo);
+            (StatusCode::CREATED, Json(todo))
+        })
+        .map_err(|_| error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to create todo"))
+}
@@ -0,0 +1,125 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::Json,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::database::{self, DbPool, TodoLock};
+use crate::json_body::JsonBody;
+use crate::realtime;
+
+const DEFAULT_TTL_SECONDS: u32 = 120;
+/// Longest a lock can be taken for; clients editing for longer renew it
+pub const MAX_TTL_SECONDS: u32 = 900;
+
+#[derive(Debug, Deserialize)]
+pub struct AcquireLock {
+    pub holder: String,
+    pub ttl_seconds: Option<u32>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReleaseLockParams {
+    pub holder: Option<String>,
+    /// Release someone else's lock
+    #[serde(default)]
+    pub force: bool,
+}
+
+fn error(status: StatusCode, message: &str) -> (StatusCode, Json<Value>) {
+    (status, Json(json!({"error": message})))
+}
+
+fn locked_by(lock: TodoLock) -> (StatusCode, Json<Value>) {
+    (
+        StatusCode::CONFLICT,
+        Json(json!({
+            "error": format!("The todo is being edited by {}", lock.holder),
+            "lock": lock,
+        })),
+    )
+}
+
+fn ensure_todo_exists(pool: &DbPool, id: &str) -> Result<(), (StatusCode, Json<Value>)> {
+    match database::get_todo(pool, id) {
+        Ok(Some(_)) => Ok(()),
+        Ok(None) => Err(error(StatusCode::NOT_FOUND, "Todo not found")),
+        Err(_) => Err(error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to get todo",
+        )),
+    }
+}
+
+/// Refuses a write while someone other than `holder` has the todo locked, unless `force`
+/// is set. Locks are advisory: this only protects clients that say who they are.
+pub fn check_lock(
+    pool: &DbPool,
+    id: &str,
+    holder: Option<&str>,
+    force: bool,
+) -> Result<(), (StatusCode, Json<Value>)> {
+    if force {
+        return Ok(());
+    }
+    match database::get_lock(pool, id) {
+        Ok(Some(lock)) if Some(lock.holder.as_str()) != holder.map(str::trim) => {
+            Err(locked_by(lock))
+        }
+        Ok(_) => Ok(()),
+        Err(_) => Err(error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to check lock",
+        )),
+    }
+}
+
+/// Locks a todo for editing, or renews the caller's lock.
+pub async fn acquire_lock_handler(
+    State(pool): State<DbPool>,
+    Path(id): Path<String>,
+    JsonBody(body): JsonBody<AcquireLock>,
+) -> Result<Json<TodoLock>, (StatusCode, Json<Value>)> {
+    let holder = realtime::normalize_user_name(&body.holder)
+        .map_err(|message| error(StatusCode::BAD_REQUEST, &message))?;
+    let ttl = body.ttl_seconds.unwrap_or(DEFAULT_TTL_SECONDS);
+    if !(1..=MAX_TTL_SECONDS).contains(&ttl) {
+        return Err(error(
+            StatusCode::BAD_REQUEST,
+            &format!("ttl_seconds must be between 1 and {MAX_TTL_SECONDS}"),
+        ));
+    }
+    ensure_todo_exists(&pool, &id)?;
+    match database::acquire_lock(&pool, &id, &holder, ttl) {
+        Ok(Ok(lock)) => Ok(Json(lock)),
+        Ok(Err(lock)) => Err(locked_by(lock)),
+        Err(_) => Err(error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to lock todo",
+        )),
+    }
+}
+
+pub async fn release_lock_handler(
+    State(pool): State<DbPool>,
+    Path(id): Path<String>,
+    Query(params): Query<ReleaseLockParams>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    ensure_todo_exists(&pool, &id)?;
+    match database::get_lock(&pool, &id) {
+        Ok(Some(_)) => {}
+        Ok(None) => return Err(error(StatusCode::NOT_FOUND, "The todo is not locked")),
+        Err(_) => {
+            return Err(error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to check lock",
+            ))
+        }
+    }
+    check_lock(&pool, &id, params.holder.as_deref(), params.force)?;
+    database::release_lock(&pool, &id)
+        .map(|_| Json(json!({"message": "Lock released"})))
+        .map_err(|_| error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to release lock"))
+}
@@ -0,0 +1,261 @@
+use std::collections::HashMap;
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::Json,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::database::{self, BulkAction, DbPool, Macro};
+use crate::handlers;
+use crate::json_body::JsonBody;
+
+pub const MAX_STEPS: usize = 20;
+
+/// One saved action. `filter` takes the query parameters of `GET /api/todos`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(tag = "action", rename_all = "lowercase")]
+pub enum MacroStep {
+    /// Archives the completed todos matching the filter
+    Archive {
+        #[serde(default)]
+        filter: HashMap<String, String>,
+    },
+    Tag {
+        #[serde(default)]
+        filter: HashMap<String, String>,
+        #[serde(default)]
+        add: Vec<String>,
+        #[serde(default)]
+        remove: Vec<String>,
+    },
+    /// Moves the todos matching the filter to the trash
+    Delete {
+        #[serde(default)]
+        filter: HashMap<String, String>,
+    },
+}
+
+impl MacroStep {
+    fn name(&self) -> &'static str {
+        match self {
+            MacroStep::Archive { .. } => "archive",
+            MacroStep::Tag { .. } => "tag",
+            MacroStep::Delete { .. } => "delete",
+        }
+    }
+
+    fn to_action(&self) -> Result<BulkAction, String> {
+        Ok(match self {
+            MacroStep::Archive { filter } => {
+                BulkAction::Archive(handlers::parse_todo_filter(filter)?)
+            }
+            MacroStep::Tag {
+                filter,
+                add,
+                remove,
+            } => {
+                if add.is_empty() && remove.is_empty() {
+                    return Err("Tag steps need tags to `add` and/or `remove`".to_string());
+                }
+                let normalize = |names: &[String]| {
+                    names
+                        .iter()
+                        .map(|name| database::normalize_tag_name(name))
+                        .collect::<Result<Vec<_>, _>>()
+                };
+                BulkAction::Tag {
+                    filter: handlers::parse_todo_filter(filter)?,
+                    add: normalize(add)?,
+                    remove: normalize(remove)?,
+                }
+            }
+            MacroStep::Delete { filter } => {
+                BulkAction::Delete(handlers::parse_todo_filter(filter)?)
+            }
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateMacro {
+    pub name: String,
+    pub steps: Vec<MacroStep>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateMacro {
+    pub name: Option<String>,
+    pub steps: Option<Vec<MacroStep>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StepOutcome {
+    pub action: &'static str,
+    /// Todos archived, tagged or deleted by the step
+    pub affected: usize,
+}
+
+fn error(status: StatusCode, message: &str) -> (StatusCode, Json<Value>) {
+    (status, Json(json!({"error": message})))
+}
+
+fn macro_name(name: &str) -> Result<String, (StatusCode, Json<Value>)> {
+    database::normalize_macro_name(name).map_err(|message| error(StatusCode::BAD_REQUEST, &message))
+}
+
+// Checks the steps can be run and returns them as they are saved
+fn macro_steps(steps: &[MacroStep]) -> Result<Value, (StatusCode, Json<Value>)> {
+    if steps.is_empty() || steps.len() > MAX_STEPS {
+        return Err(error(
+            StatusCode::BAD_REQUEST,
+            &format!("Macros need 1-{MAX_STEPS} steps"),
+        ));
+    }
+    for (index, step) in steps.iter().enumerate() {
+        step.to_action().map_err(|message| {
+            error(
+                StatusCode::BAD_REQUEST,
+                &format!("Step {}: {message}", index + 1),
+            )
+        })?;
+    }
+    serde_json::to_value(steps)
+        .map_err(|_| error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to save macro"))
+}
+
+// Names are unique ignoring case; `except` is the macro being renamed
+fn ensure_name_free(
+    pool: &DbPool,
+    name: &str,
+    except: Option<&str>,
+) -> Result<(), (StatusCode, Json<Value>)> {
+    match database::find_macro_by_name(pool, name) {
+        Ok(Some(existing)) if Some(existing.id.as_str()) != except => Err((
+            StatusCode::CONFLICT,
+            Json(json!({"error": "A macro with this name already exists", "existing": existing})),
+        )),
+        Ok(_) => Ok(()),
+        Err(_) => Err(error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to check macro name",
+        )),
+    }
+}
+
+fn find_macro(pool: &DbPool, id: &str) -> Result<Macro, (StatusCode, Json<Value>)> {
+    match database::get_macro(pool, id) {
+        Ok(Some(macro_)) => Ok(macro_),
+        Ok(None) => Err(error(StatusCode::NOT_FOUND, "Macro not found")),
+        Err(_) => Err(error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to get macro",
+        )),
+    }
+}
+
+pub async fn get_macros_handler(
+    State(pool): State<DbPool>,
+) -> Result<Json<Vec<Macro>>, (StatusCode, Json<Value>)> {
+    database::get_macros(&pool)
+        .map(Json)
+        .map_err(|_| error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to get macros"))
+}
+
+pub async fn get_macro_handler(
+    State(pool): State<DbPool>,
+    Path(id): Path<String>,
+) -> Result<Json<Macro>, (StatusCode, Json<Value>)> {
+    find_macro(&pool, &id).map(Json)
+}
+
+pub async fn create_macro_handler(
+    State(pool): State<DbPool>,
+    JsonBody(body): JsonBody<CreateMacro>,
+) -> Result<(StatusCode, Json<Macro>), (StatusCode, Json<Value>)> {
+    let name = macro_name(&body.name)?;
+    ensure_name_free(&pool, &name, None)?;
+    let steps = macro_steps(&body.steps)?;
+    database::create_macro(&pool, &name, steps)
+        .map(|macro_| (StatusCode::CREATED, Json(macro_)))
+        .map_err(|_| error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to create macro"))
+}
+
+pub async fn update_macro_handler(
+    State(pool): State<DbPool>,
+    Path(id): Path<String>,
+    JsonBody(body): JsonBody<UpdateMacro>,
+) -> Result<Json<Macro>, (StatusCode, Json<Value>)> {
+    let name = match &body.name {
+        Some(name) => {
+            let name = macro_name(name)?;
+            ensure_name_free(&pool, &name, Some(&id))?;
+            Some(name)
+        }
+        None => None,
+    };
+    let steps = body.steps.as_deref().map(macro_steps).transpose()?;
+    match database::update_macro(&pool, &id, name.as_deref(), steps.as_ref()) {
+        Ok(Some(macro_)) => Ok(Json(macro_)),
+        Ok(None) => Err(error(StatusCode::NOT_FOUND, "Macro not found")),
+        Err(_) => Err(error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to update macro",
+        )),
+    }
+}
+
+pub async fn delete_macro_handler(
+    State(pool): State<DbPool>,
+    Path(id): Path<String>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    match database::delete_macro(&pool, &id) {
+        Ok(true) => Ok(Json(json!({"message": "Macro deleted successfully"}))),
+        Ok(false) => Err(error(StatusCode::NOT_FOUND, "Macro not found")),
+        Err(_) => Err(error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to delete macro",
+        )),
+    }
+}
+
+/// Runs a macro's steps in order, all in one transaction.
+pub async fn run_macro_handler(
+    State(pool): State<DbPool>,
+    Path(id): Path<String>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let macro_ = find_macro(&pool, &id)?;
+    let invalid = |message: String| {
+        error(
+            StatusCode::CONFLICT,
+            &format!("The macro's steps can no longer be run: {message}"),
+        )
+    };
+    let steps: Vec<MacroStep> =
+        serde_json::from_value(macro_.steps.clone()).map_err(|e| invalid(e.to_string()))?;
+    let actions = steps
+        .iter()
+        .map(MacroStep::to_action)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(invalid)?;
+    let affected = database::run_bulk_actions(&pool, &actions)
+        .map_err(|_| error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to run macro"))?;
+    if database::record_macro_run(&pool, &id).is_err() {
+        eprintln!("Failed to record the run of macro {id}");
+    }
+    let outcomes: Vec<StepOutcome> = steps
+        .iter()
+        .zip(affected)
+        .map(|(step, affected)| StepOutcome {
+            action: step.name(),
+            affected,
+        })
+        .collect();
+    Ok(Json(json!({
+        "macro_id": macro_.id,
+        "name": macro_.name,
+        "steps": outcomes,
+    })))
+}
@@ -1,30 +1,175 @@
+// The capabilities document is one large `json!` literal
+#![recursion_limit = "256"]
+
+mod access_log;
+mod activity;
+mod agenda;
+mod alerts;
+mod attachments;
+mod attachments_zip;
+mod autocomplete;
+mod bulk;
+mod capabilities;
+mod checklist;
+mod config;
+mod confirmation;
+mod csv_import;
+mod custom_fields;
 mod database;
+mod dedupe;
+mod dependencies;
+mod deprecation;
+mod escalations;
+mod etag;
+mod events;
+mod export;
+mod export_schedules;
+mod git_hook;
+mod github;
+mod habits;
 mod handlers;
+mod history;
+mod humanize;
+mod icons;
+mod ics;
+mod idempotency;
+mod ids;
+mod import;
+mod inbound_email;
+mod inbound_hooks;
+mod ingest;
+mod ip_filter;
+mod jira;
+mod json_body;
+mod list_snapshots;
+mod lists;
+mod locks;
+mod macros;
+mod maintenance;
+mod palette;
+mod panic_handler;
+mod planning;
+mod pomodoro;
+mod realtime;
+mod recurrence;
+mod reminders;
+mod request_stats;
+mod retention;
+mod reviews;
+mod search;
+mod security_headers;
+mod server_info;
+mod signed_urls;
+mod single_flight;
+mod slow_query;
+mod sql_dump;
+mod stale;
+mod subtasks;
+mod tags;
+mod templates;
+mod timer;
+mod trash;
+mod views;
+
+use std::net::SocketAddr;
+use std::sync::Arc;
 
+use config::Config;
 use database::create_pool;
 
 #[tokio::main]
 async fn main() {
+    panic_handler::install_hook();
+
+    let config = Config::from_env();
+    ids::init(config.id_format);
+    slow_query::init(config.slow_query_ms);
+
     // Initialize database pool
     let db_pool = match create_pool() {
         Ok(pool) => pool,
         Err(e) => {
             eprintln!("Failed to initialize database pool: {e}");
-            return;
+            std::process::exit(1);
+        }
+    };
+
+    if std::env::args().nth(1).as_deref() == Some("dump") {
+        if let Err(e) = sql_dump::dump_to_stdout(&db_pool) {
+            eprintln!("Failed to dump the database: {e}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if let Some(days) = config.stale_after_days {
+        tokio::spawn(stale::run_stale_notifier(db_pool.clone(), days));
+    }
+    tokio::spawn(recurrence::run_materializer(db_pool.clone()));
+    let notifier: Arc<dyn reminders::Notifier> = Arc::new(reminders::LogNotifier);
+    tokio::spawn(reminders::run_reminder_scheduler(
+        db_pool.clone(),
+        notifier.clone(),
+    ));
+    tokio::spawn(alerts::run_alert_evaluator(
+        db_pool.clone(),
+        notifier.clone(),
+    ));
+    tokio::spawn(escalations::run_escalation_checker(
+        db_pool.clone(),
+        notifier,
+    ));
+    tokio::spawn(list_snapshots::run_snapshot_job(db_pool.clone()));
+    tokio::spawn(export_schedules::run_export_scheduler(
+        db_pool.clone(),
+        config.export_dir.clone(),
+    ));
+    tokio::spawn(retention::run_retention_jobs(
+        db_pool.clone(),
+        config.attachments_dir.clone(),
+    ));
+    if let Some(token) = config.github_token.clone() {
+        tokio::spawn(github::run_issue_sync(
+            db_pool.clone(),
+            config.github_api_url.clone(),
+            token,
+        ));
+    }
+    let realtime = realtime::Realtime::default();
+    tokio::spawn(realtime::run_event_relay(db_pool.clone(), realtime.clone()));
+
+    let (autocomplete, after) = match autocomplete::AutocompleteIndex::load(&db_pool) {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            eprintln!("Failed to build the autocomplete index: {e}");
+            std::process::exit(1);
         }
     };
+    tokio::spawn(autocomplete::run_index_updater(
+        db_pool.clone(),
+        autocomplete.clone(),
+        after,
+    ));
 
     // Create router
-    let app = handlers::create_router(db_pool);
+    let app = handlers::create_router(db_pool, config, realtime, autocomplete);
 
-    println!("🚀 TODO Server starting at http://localhost:3030");
+    println!(
+        "🚀 TODO Server v{} ({}) starting at http://localhost:3030",
+        server_info::VERSION,
+        server_info::GIT_COMMIT
+    );
 
     // Start server
     let listener = tokio::net::TcpListener::bind("127.0.0.1:3030")
         .await
         .expect("Failed to bind to port 3030");
 
-    axum::serve(listener, app)
-        .await
-        .expect("Failed to start server");
+    // The peer address is needed by the IP allowlist and denylist
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await
+    .expect("Failed to start server");
 }
@@ -0,0 +1,97 @@
+use std::sync::{Arc, RwLock};
+
+use axum::{
+    extract::{Request, State},
+    http::{Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Json, Response},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::json_body::JsonBody;
+
+const DEFAULT_MESSAGE: &str = "The service is in maintenance mode. Please try again later.";
+
+#[derive(Debug, Serialize, Clone)]
+pub struct MaintenanceStatus {
+    pub enabled: bool,
+    pub message: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetMaintenance {
+    pub enabled: bool,
+    pub message: Option<String>,
+}
+
+// Shared between the toggle handler and the middleware; cloning only bumps the Arc
+#[derive(Clone)]
+pub struct MaintenanceMode(Arc<RwLock<MaintenanceStatus>>);
+
+impl Default for MaintenanceMode {
+    fn default() -> Self {
+        MaintenanceMode(Arc::new(RwLock::new(MaintenanceStatus {
+            enabled: false,
+            message: DEFAULT_MESSAGE.to_string(),
+        })))
+    }
+}
+
+impl MaintenanceMode {
+    pub fn status(&self) -> MaintenanceStatus {
+        self.0.read().unwrap().clone()
+    }
+
+    pub fn set(&self, update: SetMaintenance) -> MaintenanceStatus {
+        let mut status = self.0.write().unwrap();
+        status.enabled = update.enabled;
+        status.message = update
+            .message
+            .unwrap_or_else(|| DEFAULT_MESSAGE.to_string());
+        status.clone()
+    }
+}
+
+// The only mutating route left open during maintenance
+const TOGGLE_PATH: &str = "/api/admin/maintenance";
+
+fn is_read_only(method: &Method) -> bool {
+    matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS)
+}
+
+/// Rejects mutating requests with 503 while maintenance mode is enabled.
+/// The maintenance route itself is never blocked so the mode can always be switched off again.
+pub async fn maintenance_layer(
+    State(mode): State<MaintenanceMode>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if is_read_only(request.method()) || request.uri().path() == TOGGLE_PATH {
+        return next.run(request).await;
+    }
+
+    let status = mode.status();
+    if !status.enabled {
+        return next.run(request).await;
+    }
+
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        Json(json!({"error": status.message})),
+    )
+        .into_response()
+}
+
+pub async fn get_maintenance_handler(
+    State(mode): State<MaintenanceMode>,
+) -> Json<MaintenanceStatus> {
+    Json(mode.status())
+}
+
+pub async fn set_maintenance_handler(
+    State(mode): State<MaintenanceMode>,
+    JsonBody(update): JsonBody<SetMaintenance>,
+) -> Json<MaintenanceStatus> {
+    Json(mode.set(update))
+}
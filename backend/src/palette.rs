@@ -0,0 +1,112 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::Json,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::database::{self, DbPool, PaletteColor};
+use crate::json_body::JsonBody;
+
+#[derive(Debug, Deserialize)]
+pub struct CreatePaletteColor {
+    pub name: String,
+    pub hex: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdatePaletteColor {
+    pub hex: String,
+}
+
+fn error(status: StatusCode, message: &str) -> (StatusCode, Json<Value>) {
+    (status, Json(json!({"error": message})))
+}
+
+fn hex_color(hex: &str) -> Result<String, (StatusCode, Json<Value>)> {
+    database::normalize_hex_color(hex).map_err(|message| error(StatusCode::BAD_REQUEST, &message))
+}
+
+/// Checks `name` is in the palette and returns it as spelled there, for storing on tags.
+pub fn resolve_color(pool: &DbPool, name: &str) -> Result<String, (StatusCode, Json<Value>)> {
+    match database::get_palette_color(pool, name.trim()) {
+        Ok(Some(color)) => Ok(color.name),
+        Ok(None) => Err(error(
+            StatusCode::BAD_REQUEST,
+            &format!("Unknown color '{name}': use one of the colors from /api/palette"),
+        )),
+        Err(_) => Err(error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to check color",
+        )),
+    }
+}
+
+pub async fn get_palette_handler(
+    State(pool): State<DbPool>,
+) -> Result<Json<Vec<PaletteColor>>, (StatusCode, Json<Value>)> {
+    database::get_palette(&pool)
+        .map(Json)
+        .map_err(|_| error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to get palette"))
+}
+
+pub async fn create_palette_color_handler(
+    State(pool): State<DbPool>,
+    JsonBody(body): JsonBody<CreatePaletteColor>,
+) -> Result<(StatusCode, Json<PaletteColor>), (StatusCode, Json<Value>)> {
+    let name = database::normalize_color_name(&body.name)
+        .map_err(|message| error(StatusCode::BAD_REQUEST, &message))?;
+    let hex = hex_color(&body.hex)?;
+    match database::get_palette_color(&pool, &name) {
+        Ok(Some(existing)) => {
+            return Err((
+                StatusCode::CONFLICT,
+                Json(
+                    json!({"error": "A color with this name already exists", "existing": existing}),
+                ),
+            ))
+        }
+        Ok(None) => {}
+        Err(_) => {
+            return Err(error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to check color name",
+            ))
+        }
+    }
+    database::create_palette_color(&pool, &name, &hex)
+        .map(|color| (StatusCode::CREATED, Json(color)))
+        .map_err(|_| error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to create color"))
+}
+
+/// Changes the hex value; tags refer to colors by name, so they pick the change up.
+pub async fn update_palette_color_handler(
+    State(pool): State<DbPool>,
+    Path(name): Path<String>,
+    JsonBody(body): JsonBody<UpdatePaletteColor>,
+) -> Result<Json<PaletteColor>, (StatusCode, Json<Value>)> {
+    let hex = hex_color(&body.hex)?;
+    match database::update_palette_color(&pool, &name, &hex) {
+        Ok(Some(color)) => Ok(Json(color)),
+        Ok(None) => Err(error(StatusCode::NOT_FOUND, "Color not found")),
+        Err(_) => Err(error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to update color",
+        )),
+    }
+}
+
+pub async fn delete_palette_color_handler(
+    State(pool): State<DbPool>,
+    Path(name): Path<String>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    match database::delete_palette_color(&pool, &name) {
+        Ok(true) => Ok(Json(json!({"message": "Color deleted successfully"}))),
+        Ok(false) => Err(error(StatusCode::NOT_FOUND, "Color not found")),
+        Err(_) => Err(error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to delete color",
+        )),
+    }
+}
@@ -0,0 +1,39 @@
+use std::any::Any;
+use std::backtrace::Backtrace;
+
+use axum::{
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+};
+use serde_json::json;
+
+use crate::access_log;
+
+/// Logs every panic with its backtrace and, when it happened inside a request, the request id.
+pub fn install_hook() {
+    std::panic::set_hook(Box::new(|info| {
+        let request_id = access_log::current_request_id().unwrap_or_else(|| "-".to_string());
+        eprintln!(
+            "💥 Panic (req={request_id}): {info}\n{}",
+            Backtrace::force_capture()
+        );
+    }));
+}
+
+/// Turns a panicked handler into a 500 problem+json response instead of a dropped connection.
+/// The panic message is only logged, never sent to the client.
+pub fn panic_response(_payload: Box<dyn Any + Send + 'static>) -> Response {
+    let body = json!({
+        "type": "about:blank",
+        "title": "Internal Server Error",
+        "status": 500,
+        "detail": "The server hit an unexpected error while handling the request",
+        "request_id": access_log::current_request_id(),
+    });
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        [(header::CONTENT_TYPE, "application/problem+json")],
+        body.to_string(),
+    )
+        .into_response()
+}
@@ -0,0 +1,291 @@
+use std::collections::HashMap;
+
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::Json,
+};
+use chrono::{DateTime, Datelike, Duration, DurationRound, FixedOffset, NaiveTime, Utc, Weekday};
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::database::{self, DbPool, Todo};
+use crate::handlers;
+use crate::ics::{self, BusyBlock};
+
+pub const DEFAULT_DAYS: u32 = 14;
+pub const MAX_DAYS: u32 = 90;
+pub const DEFAULT_HOURS: &str = "09:00-17:00";
+
+#[derive(Debug, Serialize)]
+pub struct Suggestion {
+    pub todo_id: String,
+    pub title: String,
+    pub due_date: Option<DateTime<Utc>>,
+    /// The estimate less the time already tracked
+    pub remaining_minutes: i64,
+    pub start_date: DateTime<Utc>,
+    /// When the work is done if it starts at `start_date`
+    pub finish: DateTime<Utc>,
+    /// Whether it is done by the due date
+    pub fits: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Unplanned {
+    pub todo_id: String,
+    pub title: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Plan {
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+    pub suggestions: Vec<Suggestion>,
+    pub unplanned: Vec<Unplanned>,
+}
+
+struct Settings {
+    days: u32,
+    work_start: NaiveTime,
+    work_end: NaiveTime,
+    offset: FixedOffset,
+    weekends: bool,
+}
+
+fn error(status: StatusCode, message: &str) -> (StatusCode, Json<Value>) {
+    (status, Json(json!({"error": message})))
+}
+
+fn parse_settings(params: &HashMap<String, String>) -> Result<Settings, String> {
+    let days = match params.get("days") {
+        Some(days) => days
+            .parse()
+            .ok()
+            .filter(|days| (1..=MAX_DAYS).contains(days))
+            .ok_or_else(|| format!("`days` must be between 1 and {MAX_DAYS}"))?,
+        None => DEFAULT_DAYS,
+    };
+    let hours = params.get("hours").map_or(DEFAULT_HOURS, String::as_str);
+    let (work_start, work_end) = hours
+        .split_once('-')
+        .and_then(|(start, end)| {
+            let start = NaiveTime::parse_from_str(start.trim(), "%H:%M").ok()?;
+            let end = NaiveTime::parse_from_str(end.trim(), "%H:%M").ok()?;
+            (start < end).then_some((start, end))
+        })
+        .ok_or_else(|| format!("Invalid `hours` '{hours}': expected e.g. {DEFAULT_HOURS}"))?;
+    let offset = match params.get("utc_offset") {
+        // `+` arrives as a space when the query string is not encoded
+        Some(offset) => offset
+            .replace(' ', "+")
+            .trim()
+            .parse()
+            .map_err(|_| format!("Invalid `utc_offset` '{offset}': expected e.g. +02:00"))?,
+        None => FixedOffset::east_opt(0).expect("zero offset is valid"),
+    };
+    let weekends = match params.get("weekends").map(String::as_str) {
+        Some("true") => true,
+        Some("false") | None => false,
+        Some(_) => return Err("`weekends` must be true or false".to_string()),
+    };
+    Ok(Settings {
+        days,
+        work_start,
+        work_end,
+        offset,
+        weekends,
+    })
+}
+
+// Working hours between `from` and `to`, less the busy blocks, in order
+fn free_time(
+    settings: &Settings,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    busy: &mut [BusyBlock],
+) -> Vec<(DateTime<Utc>, DateTime<Utc>)> {
+    busy.sort_by_key(|block| block.start);
+    let mut free = Vec::new();
+    let mut day = from.with_timezone(&settings.offset).date_naive();
+    while let Some(start) = day
+        .and_time(settings.work_start)
+        .and_local_timezone(settings.offset)
+        .single()
+    {
+        let start = start.with_timezone(&Utc);
+        if start >= to {
+            break;
+        }
+        let weekend = matches!(day.weekday(), Weekday::Sat | Weekday::Sun);
+        let end = day
+            .and_time(settings.work_end)
+            .and_local_timezone(settings.offset)
+            .single()
+            .map(|end| end.with_timezone(&Utc));
+        if let (false, Some(end)) = (weekend && !settings.weekends, end) {
+            let mut cursor = start.max(from);
+            let end = end.min(to);
+            for block in busy.iter() {
+                if block.start >= end {
+                    break;
+                }
+                if block.end <= cursor {
+                    continue;
+                }
+                if block.start > cursor {
+                    free.push((cursor, block.start));
+                }
+                cursor = cursor.max(block.end);
+            }
+            if cursor < end {
+                free.push((cursor, end));
+            }
+        }
+        let Some(next) = day.succ_opt() else {
+            break;
+        };
+        day = next;
+    }
+    free
+}
+
+/// Lays the todos out one after another in the free time, earliest due first, then by
+/// priority. A todo may be split across several stretches of free time, and never starts
+/// before its own start date.
+fn plan(
+    todos: Vec<Todo>,
+    settings: &Settings,
+    from: DateTime<Utc>,
+    busy: &mut [BusyBlock],
+) -> Plan {
+    let to = from + Duration::days(i64::from(settings.days));
+    // Used up from the front of each stretch; stretches before a deferred todo's start date
+    // are left for the todos after it
+    let mut slots = free_time(settings, from, to, busy);
+
+    let mut todos = todos;
+    todos.sort_by(|a, b| {
+        (a.due_date.is_none(), a.due_date)
+            .cmp(&(b.due_date.is_none(), b.due_date))
+            .then((b.priority as i64).cmp(&(a.priority as i64)))
+            .then(a.created_at.cmp(&b.created_at))
+    });
+
+    let mut suggestions = Vec::new();
+    let mut unplanned = Vec::new();
+    for todo in todos {
+        let Some(estimate) = todo.estimate_minutes else {
+            unplanned.push(Unplanned {
+                todo_id: todo.id,
+                title: todo.title,
+                reason: "no estimate".to_string(),
+            });
+            continue;
+        };
+        let remaining = i64::from(estimate) * 60 - todo.time_spent;
+        if remaining <= 0 {
+            unplanned.push(Unplanned {
+                todo_id: todo.id,
+                title: todo.title,
+                reason: "the estimate is used up".to_string(),
+            });
+            continue;
+        }
+
+        let earliest = todo
+            .start_date
+            .map_or(from, |start_date| start_date.max(from));
+        let mut needed = Duration::seconds(remaining);
+        let mut start_date = None;
+        let mut finish = None;
+        let mut taken = Vec::new();
+        for (index, (slot_start, slot_end)) in slots.iter().enumerate() {
+            let begin = (*slot_start).max(earliest);
+            if begin >= *slot_end {
+                continue;
+            }
+            start_date.get_or_insert(begin);
+            let available = *slot_end - begin;
+            if available >= needed {
+                taken.push((index, begin, begin + needed));
+                finish = Some(begin + needed);
+                break;
+            }
+            needed -= available;
+            taken.push((index, begin, *slot_end));
+        }
+        if finish.is_some() {
+            // Splits each used stretch into the free time left before and after the work
+            for (index, begin, end) in taken.into_iter().rev() {
+                let (slot_start, slot_end) = slots[index];
+                let mut rest = Vec::new();
+                if slot_start < begin {
+                    rest.push((slot_start, begin));
+                }
+                if end < slot_end {
+                    rest.push((end, slot_end));
+                }
+                slots.splice(index..=index, rest);
+            }
+        }
+        match (start_date, finish) {
+            (Some(start_date), Some(finish)) => suggestions.push(Suggestion {
+                fits: todo.due_date.is_none_or(|due| finish <= due),
+                todo_id: todo.id,
+                title: todo.title,
+                due_date: todo.due_date,
+                remaining_minutes: (remaining + 59) / 60,
+                start_date,
+                finish,
+            }),
+            _ => unplanned.push(Unplanned {
+                todo_id: todo.id,
+                title: todo.title,
+                reason: format!("not enough free time in the next {} days", settings.days),
+            }),
+        }
+    }
+    Plan {
+        from,
+        to,
+        suggestions,
+        unplanned,
+    }
+}
+
+/// Suggests start dates for open todos so each fits in the working hours left around the
+/// busy blocks of an optional uploaded calendar. Takes the same query parameters as
+/// `GET /api/todos`; nothing is changed.
+pub async fn plan_handler(
+    State(pool): State<DbPool>,
+    Query(params): Query<HashMap<String, String>>,
+    body: String,
+) -> Result<Json<Plan>, (StatusCode, Json<Value>)> {
+    let settings =
+        parse_settings(&params).map_err(|message| error(StatusCode::BAD_REQUEST, &message))?;
+    let mut filter = handlers::parse_todo_filter(&params)
+        .map_err(|message| error(StatusCode::BAD_REQUEST, &message))?;
+    filter.completed = Some(false);
+    // Deferred todos are planned too, unless asked otherwise
+    if !params.contains_key("scheduled") {
+        filter.scheduled = None;
+    }
+    let mut busy = if body.trim().is_empty() {
+        Vec::new()
+    } else if body.to_ascii_uppercase().contains("BEGIN:VCALENDAR") {
+        ics::parse_busy_blocks(&body, settings.offset)
+    } else {
+        return Err(error(
+            StatusCode::BAD_REQUEST,
+            "The body must be an iCalendar document with the busy time, or empty",
+        ));
+    };
+    let todos = database::get_todos(&pool, &filter)
+        .map_err(|_| error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to get todos"))?;
+    let from = Utc::now()
+        .duration_trunc(Duration::minutes(1))
+        .unwrap_or_else(|_| Utc::now());
+    Ok(Json(plan(todos, &settings, from, &mut busy)))
+}
@@ -0,0 +1,196 @@
+use std::collections::BTreeMap;
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::Json,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::database::{self, DbPool, PomodoroSession};
+use crate::json_body::OptionalJsonBody;
+
+const DEFAULT_MINUTES: i64 = 25;
+const DEFAULT_REPORT_DAYS: i64 = 7;
+
+#[derive(Debug, Deserialize, Default)]
+pub struct StartPomodoro {
+    pub minutes: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FocusParams {
+    pub days: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Default)]
+pub struct TodoFocus {
+    pub todo_id: String,
+    pub title: String,
+    pub minutes: i64,
+}
+
+#[derive(Debug, Serialize, Default)]
+pub struct TagFocus {
+    pub tag: String,
+    pub minutes: i64,
+}
+
+#[derive(Debug, Serialize, Default)]
+pub struct ListFocus {
+    /// `None` for todos outside any list
+    pub list_id: Option<String>,
+    pub name: Option<String>,
+    pub minutes: i64,
+}
+
+#[derive(Debug, Serialize, Default)]
+pub struct DailyFocus {
+    pub date: String,
+    pub minutes: i64,
+    pub sessions: usize,
+    pub todos: Vec<TodoFocus>,
+    /// A session counts for every tag of its todo, so these can add up to more than `minutes`
+    pub tags: Vec<TagFocus>,
+    pub lists: Vec<ListFocus>,
+}
+
+fn error(status: StatusCode, message: &str) -> (StatusCode, Json<Value>) {
+    (status, Json(json!({"error": message})))
+}
+
+fn duration_minutes(session: &PomodoroSession) -> i64 {
+    let parse = |value: &str| chrono::DateTime::parse_from_rfc3339(value).ok();
+    match (
+        parse(&session.started_at),
+        session.ended_at.as_deref().and_then(parse),
+    ) {
+        (Some(start), Some(end)) => (end - start).num_minutes().max(0),
+        _ => 0,
+    }
+}
+
+fn ensure_todo_exists(pool: &DbPool, id: &str) -> Result<(), (StatusCode, Json<Value>)> {
+    match database::get_todo(pool, id) {
+        Ok(Some(_)) => Ok(()),
+        Ok(None) => Err(error(StatusCode::NOT_FOUND, "Todo not found")),
+        Err(_) => Err(error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to get todo",
+        )),
+    }
+}
+
+pub async fn start_pomodoro_handler(
+    State(pool): State<DbPool>,
+    Path(id): Path<String>,
+    OptionalJsonBody(body): OptionalJsonBody<StartPomodoro>,
+) -> Result<(StatusCode, Json<PomodoroSession>), (StatusCode, Json<Value>)> {
+    let minutes = body
+        .and_then(|start| start.minutes)
+        .unwrap_or(DEFAULT_MINUTES);
+    if !(1..=180).contains(&minutes) {
+        return Err(error(
+            StatusCode::BAD_REQUEST,
+            "minutes must be between 1 and 180",
+        ));
+    }
+    ensure_todo_exists(&pool, &id)?;
+
+    match database::start_pomodoro(&pool, &id, minutes) {
+        Ok(Some(session)) => Ok((StatusCode::CREATED, Json(session))),
+        Ok(None) => Err(error(
+            StatusCode::CONFLICT,
+            "A pomodoro is already running for this todo",
+        )),
+        Err(_) => Err(error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to start pomodoro",
+        )),
+    }
+}
+
+pub async fn stop_pomodoro_handler(
+    State(pool): State<DbPool>,
+    Path(id): Path<String>,
+) -> Result<Json<PomodoroSession>, (StatusCode, Json<Value>)> {
+    match database::stop_pomodoro(&pool, &id) {
+        Ok(Some(session)) => Ok(Json(session)),
+        Ok(None) => Err(error(
+            StatusCode::NOT_FOUND,
+            "No pomodoro is running for this todo",
+        )),
+        Err(_) => Err(error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to stop pomodoro",
+        )),
+    }
+}
+
+pub async fn get_pomodoros_handler(
+    State(pool): State<DbPool>,
+    Path(id): Path<String>,
+) -> Result<Json<Vec<PomodoroSession>>, (StatusCode, Json<Value>)> {
+    ensure_todo_exists(&pool, &id)?;
+    database::get_pomodoros(&pool, &id)
+        .map(Json)
+        .map_err(|_| error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to get pomodoros"))
+}
+
+/// Focus time per day (UTC) over the last `days` days, broken down by todo, tag and list.
+pub async fn get_focus_report_handler(
+    State(pool): State<DbPool>,
+    Query(params): Query<FocusParams>,
+) -> Result<Json<Vec<DailyFocus>>, (StatusCode, Json<Value>)> {
+    let days = params.days.unwrap_or(DEFAULT_REPORT_DAYS);
+    if !(1..=366).contains(&days) {
+        return Err(error(
+            StatusCode::BAD_REQUEST,
+            "days must be between 1 and 366",
+        ));
+    }
+    let since = (chrono::Utc::now() - chrono::Duration::days(days)).to_rfc3339();
+    let sessions = database::get_finished_pomodoros_since(&pool, &since).map_err(|_| {
+        error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to get focus report",
+        )
+    })?;
+
+    let mut by_day: BTreeMap<String, DailyFocus> = BTreeMap::new();
+    for finished in sessions {
+        let session = finished.session;
+        let date = session.started_at.get(..10).unwrap_or_default().to_string();
+        let minutes = duration_minutes(&session);
+        let day = by_day.entry(date.clone()).or_insert_with(|| DailyFocus {
+            date,
+            ..Default::default()
+        });
+        day.minutes += minutes;
+        day.sessions += 1;
+        match day.todos.iter_mut().find(|t| t.todo_id == session.todo_id) {
+            Some(todo) => todo.minutes += minutes,
+            None => day.todos.push(TodoFocus {
+                todo_id: session.todo_id,
+                title: finished.title,
+                minutes,
+            }),
+        }
+        for tag in finished.tags {
+            match day.tags.iter_mut().find(|t| t.tag == tag) {
+                Some(focus) => focus.minutes += minutes,
+                None => day.tags.push(TagFocus { tag, minutes }),
+            }
+        }
+        match day.lists.iter_mut().find(|l| l.list_id == finished.list_id) {
+            Some(list) => list.minutes += minutes,
+            None => day.lists.push(ListFocus {
+                list_id: finished.list_id,
+                name: finished.list_name,
+                minutes,
+            }),
+        }
+    }
+    Ok(Json(by_day.into_values().collect()))
+}
@@ -0,0 +1,399 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        Path, State,
+    },
+    http::StatusCode,
+    response::{Json, Response},
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::time::Instant;
+
+use crate::database::{self, DbPool, Event};
+
+// Events are written by database triggers, so the relay picks them up by polling
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+const RELAY_BATCH: u32 = 1000;
+// Sockets that fall further behind are told to catch up through `GET /api/events`
+const CHANNEL_CAPACITY: usize = 1024;
+/// A socket that sends nothing for this long no longer counts as present
+pub const PRESENCE_TIMEOUT: Duration = Duration::from_secs(30);
+const USER_NAME_MAX_LEN: usize = 50;
+
+// Presence and typing notices, sent to the sockets subscribed to `list_id`
+#[derive(Debug, Clone)]
+struct Signal {
+    list_id: String,
+    /// The connection it came from, which does not get its own typing notices back
+    connection: u64,
+    message: Value,
+}
+
+/// Fans new events and presence changes out to every connected WebSocket.
+#[derive(Clone)]
+pub struct Realtime {
+    sender: broadcast::Sender<Event>,
+    signals: broadcast::Sender<Signal>,
+    /// Per list, the user name of each present connection
+    presence: Arc<Mutex<HashMap<String, BTreeMap<u64, String>>>>,
+    next_connection: Arc<AtomicU64>,
+}
+
+impl Default for Realtime {
+    fn default() -> Self {
+        let (sender, _) = broadcast::channel(CHANNEL_CAPACITY);
+        let (signals, _) = broadcast::channel(CHANNEL_CAPACITY);
+        Realtime {
+            sender,
+            signals,
+            presence: Arc::default(),
+            next_connection: Arc::default(),
+        }
+    }
+}
+
+fn users_of(connections: Option<&BTreeMap<u64, String>>) -> Vec<String> {
+    let users: BTreeSet<&String> = connections.into_iter().flat_map(|c| c.values()).collect();
+    users.into_iter().cloned().collect()
+}
+
+impl Realtime {
+    /// Users with the list open, sorted by name. A user with several tabs is listed once.
+    pub fn present_users(&self, list_id: &str) -> Vec<String> {
+        users_of(self.presence.lock().unwrap().get(list_id))
+    }
+
+    /// Makes `connection` present as `user` in exactly `lists`, or nowhere with `None`, and
+    /// tells the subscribers of every list whose users changed.
+    fn set_presence(&self, connection: u64, present: Option<(&str, &BTreeSet<String>)>) {
+        let mut changed = Vec::new();
+        {
+            let mut presence = self.presence.lock().unwrap();
+            let mut affected: BTreeSet<String> = presence
+                .iter()
+                .filter(|(_, connections)| connections.contains_key(&connection))
+                .map(|(list_id, _)| list_id.clone())
+                .collect();
+            if let Some((_, lists)) = present {
+                affected.extend(lists.iter().cloned());
+            }
+            for list_id in affected {
+                let connections = presence.entry(list_id.clone()).or_default();
+                let before = users_of(Some(connections));
+                match present {
+                    Some((user, lists)) if lists.contains(&list_id) => {
+                        connections.insert(connection, user.to_string());
+                    }
+                    _ => {
+                        connections.remove(&connection);
+                    }
+                }
+                let after = users_of(Some(connections));
+                if connections.is_empty() {
+                    presence.remove(&list_id);
+                }
+                if before != after {
+                    changed.push((list_id, after));
+                }
+            }
+        }
+        for (list_id, users) in changed {
+            let message = json!({"type": "presence", "list_id": list_id, "users": users});
+            // Fails only when no socket is connected
+            let _ = self.signals.send(Signal {
+                list_id,
+                connection,
+                message,
+            });
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ClientMessage {
+    /// Adds lists to the subscription; a non-empty `types` replaces the event type filter
+    Subscribe {
+        #[serde(default)]
+        lists: Vec<String>,
+        #[serde(default)]
+        types: Vec<String>,
+    },
+    /// Removes lists from the subscription, or all of them when `lists` is empty
+    Unsubscribe {
+        #[serde(default)]
+        lists: Vec<String>,
+    },
+    /// Names the user, who then shows up as present in the subscribed lists
+    Identify { user: String },
+    /// Keeps the user present; any other message does too
+    Heartbeat,
+    /// Tells the list's other subscribers the user is editing a todo, or has stopped
+    Typing {
+        list_id: String,
+        todo_id: String,
+        #[serde(default = "default_active")]
+        active: bool,
+    },
+}
+
+fn default_active() -> bool {
+    true
+}
+
+/// Trims a user name sent by a client; there are no accounts, so any printable name goes.
+pub fn normalize_user_name(name: &str) -> Result<String, String> {
+    let name = name.trim();
+    if name.is_empty()
+        || name.chars().count() > USER_NAME_MAX_LEN
+        || name.chars().any(char::is_control)
+    {
+        return Err(format!(
+            "User names must be 1-{USER_NAME_MAX_LEN} printable characters"
+        ));
+    }
+    Ok(name.to_string())
+}
+
+// Nothing is sent until a list is subscribed, so clients never get the firehose
+#[derive(Debug, Default)]
+struct Subscription {
+    lists: BTreeSet<String>,
+    types: BTreeSet<String>,
+    user: Option<String>,
+}
+
+impl Subscription {
+    fn matches(&self, event: &Event) -> bool {
+        let in_list = [&event.list_id, &event.previous_list_id]
+            .into_iter()
+            .flatten()
+            .any(|list_id| self.lists.contains(list_id));
+        in_list && (self.types.is_empty() || self.types.contains(&event.kind))
+    }
+
+    fn to_message(&self) -> Value {
+        json!({
+            "type": "subscribed",
+            "lists": self.lists,
+            "types": self.types,
+            "user": self.user,
+        })
+    }
+}
+
+/// Background task that reads new events and broadcasts them to the connected sockets.
+pub async fn run_event_relay(pool: DbPool, realtime: Realtime) {
+    let start = {
+        let pool = pool.clone();
+        tokio::task::spawn_blocking(move || database::get_event_bounds(&pool)).await
+    };
+    // Sockets only see events from after they connected
+    let mut after = match start {
+        Ok(Ok((_, latest))) => latest,
+        Ok(Err(e)) => {
+            eprintln!("Realtime relay failed to start: {e}");
+            return;
+        }
+        Err(e) => {
+            eprintln!("Realtime relay panicked: {e}");
+            return;
+        }
+    };
+    let mut interval = tokio::time::interval(POLL_INTERVAL);
+    loop {
+        interval.tick().await;
+        let pool = pool.clone();
+        match tokio::task::spawn_blocking(move || database::get_events(&pool, after, RELAY_BATCH))
+            .await
+        {
+            Ok(Ok(events)) => {
+                for event in events {
+                    after = event.seq;
+                    // Fails only when no socket is connected
+                    let _ = realtime.sender.send(event);
+                }
+            }
+            Ok(Err(e)) => eprintln!("Realtime relay failed: {e}"),
+            Err(e) => eprintln!("Realtime relay panicked: {e}"),
+        }
+    }
+}
+
+/// WebSocket with per-list subscriptions, e.g. `{"type": "subscribe", "lists": ["inbox"]}`.
+pub async fn websocket_handler(ws: WebSocketUpgrade, State(realtime): State<Realtime>) -> Response {
+    ws.on_upgrade(move |socket| handle_socket(socket, realtime))
+}
+
+/// Users who currently have the list open over the WebSocket.
+pub async fn get_presence_handler(
+    State(pool): State<DbPool>,
+    State(realtime): State<Realtime>,
+    Path(list_id): Path<String>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    match database::get_list(&pool, &list_id) {
+        Ok(Some(_)) => Ok(Json(json!({
+            "list_id": list_id,
+            "users": realtime.present_users(&list_id),
+        }))),
+        Ok(None) => Err((
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": "List not found"})),
+        )),
+        Err(_) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Failed to get list"})),
+        )),
+    }
+}
+
+async fn send(socket: &mut WebSocket, message: Value) -> bool {
+    socket
+        .send(Message::Text(message.to_string()))
+        .await
+        .is_ok()
+}
+
+// Applies a client message and returns the reply, if any
+fn handle_message(
+    realtime: &Realtime,
+    connection: u64,
+    subscription: &mut Subscription,
+    message: ClientMessage,
+) -> Option<Value> {
+    match message {
+        ClientMessage::Subscribe { lists, types } => {
+            subscription.lists.extend(lists);
+            if !types.is_empty() {
+                subscription.types = types.into_iter().collect();
+            }
+            Some(subscription.to_message())
+        }
+        ClientMessage::Unsubscribe { lists } => {
+            if lists.is_empty() {
+                subscription.lists.clear();
+            }
+            for list in &lists {
+                subscription.lists.remove(list);
+            }
+            Some(subscription.to_message())
+        }
+        ClientMessage::Identify { user } => match normalize_user_name(&user) {
+            Ok(user) => {
+                subscription.user = Some(user);
+                Some(subscription.to_message())
+            }
+            Err(message) => Some(json!({"type": "error", "error": message})),
+        },
+        ClientMessage::Heartbeat => None,
+        ClientMessage::Typing {
+            list_id,
+            todo_id,
+            active,
+        } => {
+            let Some(user) = &subscription.user else {
+                return Some(
+                    json!({"type": "error", "error": "Identify before sending typing notices"}),
+                );
+            };
+            if !subscription.lists.contains(&list_id) {
+                return Some(json!({"type": "error", "error": "Subscribe to the list first"}));
+            }
+            let message = json!({
+                "type": "typing",
+                "list_id": list_id,
+                "todo_id": todo_id,
+                "user": user,
+                "active": active,
+            });
+            let _ = realtime.signals.send(Signal {
+                list_id,
+                connection,
+                message,
+            });
+            None
+        }
+    }
+}
+
+async fn handle_socket(mut socket: WebSocket, realtime: Realtime) {
+    let connection = realtime.next_connection.fetch_add(1, Ordering::Relaxed);
+    let mut events = realtime.sender.subscribe();
+    let mut signals = realtime.signals.subscribe();
+    let mut subscription = Subscription::default();
+    // The last event this socket saw, whether or not it was sent
+    let mut last_seq = None;
+    let mut active_until = Instant::now() + PRESENCE_TIMEOUT;
+    let mut announced = None;
+    loop {
+        let present = subscription
+            .user
+            .clone()
+            .filter(|_| Instant::now() < active_until)
+            .map(|user| (user, subscription.lists.clone()));
+        if present != announced {
+            let entry = present.as_ref().map(|(user, lists)| (user.as_str(), lists));
+            realtime.set_presence(connection, entry);
+            announced = present;
+        }
+
+        let message = tokio::select! {
+            message = socket.recv() => match message {
+                Some(Ok(Message::Text(text))) => {
+                    active_until = Instant::now() + PRESENCE_TIMEOUT;
+                    match serde_json::from_str(&text) {
+                        Ok(message) => {
+                            match handle_message(&realtime, connection, &mut subscription, message) {
+                                Some(reply) => reply,
+                                None => continue,
+                            }
+                        }
+                        Err(e) => json!({"type": "error", "error": format!("Invalid message: {e}")}),
+                    }
+                }
+                // Pings are answered by axum; binary frames are ignored
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => continue,
+            },
+            event = events.recv() => match event {
+                Ok(event) => {
+                    last_seq = Some(event.seq);
+                    if !subscription.matches(&event) {
+                        continue;
+                    }
+                    json!({"type": "event", "event": event})
+                }
+                Err(RecvError::Lagged(_)) => {
+                    json!({"type": "lagged", "resume_after": last_seq})
+                }
+                Err(RecvError::Closed) => break,
+            },
+            signal = signals.recv() => match signal {
+                Ok(signal) => {
+                    let own_typing = signal.connection == connection
+                        && signal.message["type"] == "typing";
+                    if own_typing || !subscription.lists.contains(&signal.list_id) {
+                        continue;
+                    }
+                    signal.message
+                }
+                // Presence is resent on the next change; missed typing notices are harmless
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => break,
+            },
+            // Wakes the loop so the presence above expires
+            _ = tokio::time::sleep_until(active_until), if Instant::now() < active_until => continue,
+        };
+        if !send(&mut socket, message).await {
+            break;
+        }
+    }
+    realtime.set_presence(connection, None);
+}
@@ -0,0 +1,570 @@
+use std::fmt;
+use std::time::Duration;
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::Json,
+};
+use chrono::{DateTime, Datelike, NaiveDate, Utc, Weekday};
+use serde::Serialize;
+use serde_json::{json, Value};
+use tokio::sync::Notify;
+
+use crate::database::{self, DbPool, RecurrenceCompletion, Todo};
+
+// Completions made outside the todo handlers (integrations, bulk updates) are picked up here
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+// Stops a rule whose next occurrence lies far in the future from spinning forever
+const MAX_STEPS: usize = 10_000;
+
+static COMPLETED: Notify = Notify::const_new();
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Frequency {
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+/// The supported subset of an iCalendar RRULE (RFC 5545): `FREQ`, `INTERVAL`,
+/// `BYDAY` (weekly rules only), `COUNT` and `UNTIL`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rule {
+    pub frequency: Frequency,
+    pub interval: u32,
+    pub by_day: Vec<Weekday>,
+    /// Occurrences left, including the current one
+    pub count: Option<u32>,
+    pub until: Option<DateTime<Utc>>,
+}
+
+const WEEKDAYS: &[(&str, Weekday)] = &[
+    ("MO", Weekday::Mon),
+    ("TU", Weekday::Tue),
+    ("WE", Weekday::Wed),
+    ("TH", Weekday::Thu),
+    ("FR", Weekday::Fri),
+    ("SA", Weekday::Sat),
+    ("SU", Weekday::Sun),
+];
+
+fn parse_until(value: &str) -> Option<DateTime<Utc>> {
+    // Either a UTC date-time (`20261231T235959Z`) or a date, meaning the end of that day
+    if let Ok(time) = chrono::NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%SZ") {
+        return Some(time.and_utc());
+    }
+    NaiveDate::parse_from_str(value, "%Y%m%d")
+        .ok()
+        .and_then(|date| date.and_hms_opt(23, 59, 59))
+        .map(|time| time.and_utc())
+}
+
+impl Rule {
+    /// Parses e.g. `FREQ=WEEKLY;BYDAY=MO` or `RRULE:FREQ=DAILY;INTERVAL=2`.
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let value = value.trim();
+        let value = match value.split_once(':') {
+            Some((prefix, rule)) if prefix.eq_ignore_ascii_case("RRULE") => rule,
+            _ => value,
+        };
+        let mut frequency = None;
+        let mut interval = 1;
+        let mut by_day = Vec::new();
+        let mut count = None;
+        let mut until = None;
+
+        for part in value.split(';').filter(|part| !part.is_empty()) {
+            let (name, value) = part
+                .split_once('=')
+                .ok_or_else(|| format!("Invalid recurrence part '{part}'"))?;
+            match name.to_ascii_uppercase().as_str() {
+                "FREQ" => {
+                    frequency = Some(match value.to_ascii_uppercase().as_str() {
+                        "DAILY" => Frequency::Daily,
+                        "WEEKLY" => Frequency::Weekly,
+                        "MONTHLY" => Frequency::Monthly,
+                        "YEARLY" => Frequency::Yearly,
+                        _ => return Err(format!("Unsupported FREQ '{value}'")),
+                    })
+                }
+                "INTERVAL" => {
+                    interval = value
+                        .parse()
+                        .ok()
+                        .filter(|interval| *interval > 0)
+                        .ok_or_else(|| format!("Invalid INTERVAL '{value}'"))?
+                }
+                "BYDAY" => {
+                    for day in value.split(',') {
+                        let weekday = WEEKDAYS
+                            .iter()
+                            .find(|(name, _)| name.eq_ignore_ascii_case(day.trim()))
+                            .map(|(_, weekday)| *weekday)
+                            .ok_or_else(|| format!("Invalid BYDAY day '{day}'"))?;
+                        if !by_day.contains(&weekday) {
+                            by_day.push(weekday);
+                        }
+                    }
+                }
+                "COUNT" => {
+                    count = Some(
+                        value
+                            .parse()
+                            .ok()
+                            .filter(|count| *count > 0)
+                            .ok_or_else(|| format!("Invalid COUNT '{value}'"))?,
+                    )
+                }
+                "UNTIL" => {
+                    until =
+                        Some(parse_until(value).ok_or_else(|| format!("Invalid UNTIL '{value}'"))?)
+                }
+                other => return Err(format!("Unsupported recurrence part '{other}'")),
+            }
+        }
+
+        let frequency = frequency.ok_or("Recurrence needs a FREQ, e.g. FREQ=WEEKLY;BYDAY=MO")?;
+        if !by_day.is_empty() && frequency != Frequency::Weekly {
+            return Err("BYDAY is only supported with FREQ=WEEKLY".to_string());
+        }
+        if count.is_some() && until.is_some() {
+            return Err("COUNT and UNTIL cannot be combined".to_string());
+        }
+        by_day.sort_by_key(|day| day.num_days_from_monday());
+        Ok(Rule {
+            frequency,
+            interval,
+            by_day,
+            count,
+            until,
+        })
+    }
+
+    // The first occurrence strictly after `after`, keeping its time of day
+    fn step(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let interval = self.interval as i64;
+        match self.frequency {
+            Frequency::Daily => Some(after + chrono::Duration::days(interval)),
+            Frequency::Weekly if self.by_day.is_empty() => {
+                Some(after + chrono::Duration::weeks(interval))
+            }
+            Frequency::Weekly => {
+                let week_start = |time: DateTime<Utc>| {
+                    time.date_naive()
+                        - chrono::Duration::days(time.weekday().num_days_from_monday() as i64)
+                };
+                (1..=7 * interval)
+                    .map(|days| after + chrono::Duration::days(days))
+                    .find(|candidate| {
+                        let weeks = (week_start(*candidate) - week_start(after)).num_weeks();
+                        weeks % interval == 0 && self.by_day.contains(&candidate.weekday())
+                    })
+            }
+            // Months and years without the anchor's day (the 31st, Feb 29) are skipped, as in RFC 5545
+            Frequency::Monthly | Frequency::Yearly => {
+                let months = match self.frequency {
+                    Frequency::Monthly => interval,
+                    _ => interval * 12,
+                };
+                let start = after.year() as i64 * 12 + after.month0() as i64;
+                (1..=48).find_map(|n| {
+                    let month = start + n * months;
+                    let date = NaiveDate::from_ymd_opt(
+                        (month / 12) as i32,
+                        (month % 12) as u32 + 1,
+                        after.day(),
+                    )?;
+                    Some(date.and_time(after.time()).and_utc())
+                })
+            }
+        }
+    }
+
+    /// The next occurrence after `anchor` that is still in the future, or `None` when the
+    /// rule has run out.
+    pub fn next_occurrence(
+        &self,
+        anchor: DateTime<Utc>,
+        now: DateTime<Utc>,
+    ) -> Option<DateTime<Utc>> {
+        if self.count.is_some_and(|count| count <= 1) {
+            return None;
+        }
+        let mut next = self.step(anchor)?;
+        for _ in 0..MAX_STEPS {
+            if next > now {
+                break;
+            }
+            next = self.step(next)?;
+        }
+        match self.until {
+            Some(until) if next > until => None,
+            _ => Some(next),
+        }
+    }
+
+    /// How many occurrences after `anchor` came and went before `end` without being done.
+    pub fn missed_between(&self, anchor: DateTime<Utc>, end: DateTime<Utc>) -> u32 {
+        let mut missed = 0;
+        let mut next = self.step(anchor);
+        while let Some(occurrence) = next {
+            let ran_out = self.until.is_some_and(|until| occurrence > until)
+                || self.count.is_some_and(|count| missed + 1 >= count);
+            if occurrence >= end || ran_out || missed as usize >= MAX_STEPS {
+                break;
+            }
+            missed += 1;
+            next = self.step(occurrence);
+        }
+        missed
+    }
+
+    /// The rule carried by the next occurrence, with one occurrence used up.
+    pub fn advanced(&self) -> Rule {
+        Rule {
+            count: self.count.map(|count| count - 1),
+            ..self.clone()
+        }
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let frequency = match self.frequency {
+            Frequency::Daily => "DAILY",
+            Frequency::Weekly => "WEEKLY",
+            Frequency::Monthly => "MONTHLY",
+            Frequency::Yearly => "YEARLY",
+        };
+        write!(f, "FREQ={frequency}")?;
+        if self.interval != 1 {
+            write!(f, ";INTERVAL={}", self.interval)?;
+        }
+        if !self.by_day.is_empty() {
+            let days: Vec<&str> = self
+                .by_day
+                .iter()
+                .filter_map(|day| WEEKDAYS.iter().find(|(_, weekday)| weekday == day))
+                .map(|(name, _)| *name)
+                .collect();
+            write!(f, ";BYDAY={}", days.join(","))?;
+        }
+        if let Some(count) = self.count {
+            write!(f, ";COUNT={count}")?;
+        }
+        if let Some(until) = self.until {
+            write!(f, ";UNTIL={}", until.format("%Y%m%dT%H%M%SZ"))?;
+        }
+        Ok(())
+    }
+}
+
+/// Validates a recurrence rule and returns it in canonical form.
+pub fn normalize_recurrence(value: &str) -> Result<String, String> {
+    Rule::parse(value).map(|rule| rule.to_string())
+}
+
+/// Wakes the materializer right away instead of at its next periodic check.
+pub fn notify_completed() {
+    COMPLETED.notify_one();
+}
+
+/// Creates the next occurrence of every completed recurring todo. Returns how many were created.
+pub fn materialize_pending(
+    pool: &DbPool,
+) -> Result<usize, Box<dyn std::error::Error + Send + Sync>> {
+    let now = Utc::now();
+    let mut created = 0;
+    for todo in database::get_completed_recurring_todos(pool)? {
+        let Some(rule) = todo
+            .recurrence
+            .as_deref()
+            .and_then(|rule| Rule::parse(rule).ok())
+        else {
+            // Rules are validated on write, so this only catches hand-edited rows
+            database::materialize_occurrence(pool, &todo, None, 0)?;
+            continue;
+        };
+        // Due dates keep their rhythm; todos without one repeat from when they were finished
+        let anchor = todo.due_date.unwrap_or(now);
+        let next_due = rule.next_occurrence(anchor, now);
+        let missed = rule.missed_between(anchor, next_due.unwrap_or(now));
+        let next = next_due.map(|due| {
+            (
+                due.to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+                rule.advanced().to_string(),
+            )
+        });
+        if database::materialize_occurrence(pool, &todo, next.clone(), missed)? && next.is_some() {
+            created += 1;
+        }
+    }
+    Ok(created)
+}
+
+/// Background task that turns completed recurring todos into their next occurrence.
+pub async fn run_materializer(pool: DbPool) {
+    let mut interval = tokio::time::interval(CHECK_INTERVAL);
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {}
+            _ = COMPLETED.notified() => {}
+        }
+        let pool = pool.clone();
+        match tokio::task::spawn_blocking(move || materialize_pending(&pool)).await {
+            Ok(Ok(0)) => {}
+            Ok(Ok(count)) => println!("🔁 Created {count} recurring todo occurrence(s)"),
+            Ok(Err(e)) => eprintln!("Recurring todo check failed: {e}"),
+            Err(e) => eprintln!("Recurring todo check panicked: {e}"),
+        }
+    }
+}
+
+/// How well something that should happen regularly is kept up: occurrences of a recurring
+/// todo, or weeks of a habit.
+#[derive(Debug, Serialize)]
+pub struct StreakStats {
+    pub completed: usize,
+    pub missed: u32,
+    /// Share of the past periods that were done; `null` before the first one
+    pub completion_rate: Option<f64>,
+    /// Periods done in a row, up to now
+    pub current_streak: usize,
+    pub longest_streak: usize,
+}
+
+/// Summarizes a sequence of periods, oldest first, each done (`true`) or missed.
+pub fn streak_stats(outcomes: impl IntoIterator<Item = bool>) -> StreakStats {
+    let mut completed = 0;
+    let mut missed = 0;
+    let mut run = 0;
+    let mut longest_streak = 0;
+    for done in outcomes {
+        if done {
+            completed += 1;
+            run += 1;
+            longest_streak = longest_streak.max(run);
+        } else {
+            missed += 1;
+            run = 0;
+        }
+    }
+    let past = completed + missed as usize;
+    StreakStats {
+        completed,
+        missed,
+        completion_rate: (past > 0).then(|| completed as f64 / past as f64),
+        current_streak: run,
+        longest_streak,
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct RecurrenceStats {
+    /// Id of the todo the series started with
+    pub series_id: String,
+    /// `missed` includes the occurrences the open occurrence has already been overtaken by
+    #[serde(flatten)]
+    pub streaks: StreakStats,
+    pub last_completed_at: Option<String>,
+}
+
+fn error(status: StatusCode, message: &str) -> (StatusCode, Json<Value>) {
+    (status, Json(json!({"error": message})))
+}
+
+/// Occurrences the open occurrence of a series has been overtaken by without being done.
+fn missed_so_far(open: Option<&Todo>, now: DateTime<Utc>) -> u32 {
+    let Some(open) = open else {
+        return 0;
+    };
+    let rule = open
+        .recurrence
+        .as_deref()
+        .and_then(|rule| Rule::parse(rule).ok());
+    match (rule, open.due_date) {
+        (Some(rule), Some(due)) => rule.missed_between(due, now),
+        _ => 0,
+    }
+}
+
+pub fn compute_stats(
+    series_id: String,
+    completions: &[RecurrenceCompletion],
+    missed_so_far: u32,
+) -> RecurrenceStats {
+    let outcomes = completions
+        .iter()
+        .flat_map(|completion| {
+            std::iter::once(true)
+                .chain(std::iter::repeat_n(false, completion.missed_after as usize))
+        })
+        .chain(std::iter::repeat_n(false, missed_so_far as usize));
+    RecurrenceStats {
+        series_id,
+        streaks: streak_stats(outcomes),
+        last_completed_at: completions
+            .last()
+            .and_then(|completion| completion.completed_at.clone()),
+    }
+}
+
+/// Completion statistics of the recurring series a todo belongs to; any occurrence of the
+/// series gives the same result.
+pub async fn get_recurrence_stats_handler(
+    State(pool): State<DbPool>,
+    Path(id): Path<String>,
+) -> Result<Json<RecurrenceStats>, (StatusCode, Json<Value>)> {
+    let todo = match database::get_todo(&pool, &id) {
+        Ok(Some(todo)) => todo,
+        Ok(None) => return Err(error(StatusCode::NOT_FOUND, "Todo not found")),
+        Err(_) => {
+            return Err(error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to get todo",
+            ))
+        }
+    };
+    let series_id = todo.series_id.clone().unwrap_or(id);
+    let (completions, open) = database::get_recurrence_completions(&pool, &series_id)
+        .and_then(|completions| {
+            Ok((
+                completions,
+                database::get_open_occurrence(&pool, &series_id)?,
+            ))
+        })
+        .map_err(|_| {
+            error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to get recurrence history",
+            )
+        })?;
+    if completions.is_empty() && open.is_none() {
+        return Err(error(StatusCode::NOT_FOUND, "The todo does not recur"));
+    }
+    let missed_so_far = missed_so_far(open.as_ref(), Utc::now());
+    Ok(Json(compute_stats(series_id, &completions, missed_so_far)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(value: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(value)
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    #[test]
+    fn parses_a_rule_into_canonical_form() {
+        let rule = Rule::parse("RRULE:freq=weekly;byday=fr,mo,MO;interval=2").unwrap();
+        assert_eq!(rule.frequency, Frequency::Weekly);
+        assert_eq!(rule.interval, 2);
+        assert_eq!(rule.by_day, [Weekday::Mon, Weekday::Fri]);
+        assert_eq!(rule.to_string(), "FREQ=WEEKLY;INTERVAL=2;BYDAY=MO,FR");
+    }
+
+    #[test]
+    fn reads_until_as_a_time_or_the_end_of_a_day() {
+        let rule = Rule::parse("FREQ=DAILY;UNTIL=20261231").unwrap();
+        assert_eq!(rule.until, Some(at("2026-12-31T23:59:59Z")));
+        let rule = Rule::parse("FREQ=DAILY;UNTIL=20261231T120000Z").unwrap();
+        assert_eq!(rule.until, Some(at("2026-12-31T12:00:00Z")));
+    }
+
+    #[test]
+    fn rejects_unsupported_rules() {
+        for rule in [
+            "INTERVAL=2",
+            "FREQ=HOURLY",
+            "FREQ=DAILY;INTERVAL=0",
+            "FREQ=DAILY;COUNT=0",
+            "FREQ=DAILY;BYDAY=MO",
+            "FREQ=WEEKLY;BYDAY=XX",
+            "FREQ=DAILY;COUNT=3;UNTIL=20261231",
+            "FREQ=DAILY;BYMONTH=1",
+            "FREQ",
+        ] {
+            assert!(Rule::parse(rule).is_err(), "{rule}");
+        }
+    }
+
+    #[test]
+    fn steps_by_the_interval_keeping_the_time_of_day() {
+        let rule = Rule::parse("FREQ=DAILY;INTERVAL=3").unwrap();
+        assert_eq!(
+            rule.step(at("2026-10-12T09:30:00Z")),
+            Some(at("2026-10-15T09:30:00Z"))
+        );
+        let rule = Rule::parse("FREQ=WEEKLY").unwrap();
+        assert_eq!(
+            rule.step(at("2026-10-12T09:30:00Z")),
+            Some(at("2026-10-19T09:30:00Z"))
+        );
+    }
+
+    #[test]
+    fn steps_through_the_days_of_every_other_week() {
+        let rule = Rule::parse("FREQ=WEEKLY;INTERVAL=2;BYDAY=MO,FR").unwrap();
+        // Monday to Friday of the same week, then on to Monday two weeks after the first
+        let friday = rule.step(at("2026-10-12T08:00:00Z")).unwrap();
+        assert_eq!(friday, at("2026-10-16T08:00:00Z"));
+        assert_eq!(rule.step(friday), Some(at("2026-10-26T08:00:00Z")));
+    }
+
+    #[test]
+    fn skips_months_without_the_day() {
+        let rule = Rule::parse("FREQ=MONTHLY").unwrap();
+        assert_eq!(
+            rule.step(at("2026-01-31T10:00:00Z")),
+            Some(at("2026-03-31T10:00:00Z"))
+        );
+        let rule = Rule::parse("FREQ=YEARLY").unwrap();
+        assert_eq!(
+            rule.step(at("2024-02-29T10:00:00Z")),
+            Some(at("2028-02-29T10:00:00Z"))
+        );
+    }
+
+    #[test]
+    fn next_occurrence_is_the_first_one_in_the_future() {
+        let rule = Rule::parse("FREQ=DAILY").unwrap();
+        assert_eq!(
+            rule.next_occurrence(at("2026-10-01T09:00:00Z"), at("2026-10-15T12:00:00Z")),
+            Some(at("2026-10-16T09:00:00Z"))
+        );
+        let rule = Rule::parse("FREQ=DAILY;COUNT=1").unwrap();
+        assert_eq!(
+            rule.next_occurrence(at("2026-10-01T09:00:00Z"), at("2026-10-01T12:00:00Z")),
+            None
+        );
+        let rule = Rule::parse("FREQ=DAILY;UNTIL=20261010").unwrap();
+        assert_eq!(
+            rule.next_occurrence(at("2026-10-01T09:00:00Z"), at("2026-10-15T12:00:00Z")),
+            None
+        );
+    }
+
+    #[test]
+    fn counts_missed_occurrences_before_the_end() {
+        let anchor = at("2026-10-01T09:00:00Z");
+        let rule = Rule::parse("FREQ=DAILY").unwrap();
+        assert_eq!(rule.missed_between(anchor, at("2026-10-04T10:00:00Z")), 3);
+        // An occurrence at the end itself is not missed yet
+        assert_eq!(rule.missed_between(anchor, at("2026-10-04T09:00:00Z")), 2);
+        let rule = Rule::parse("FREQ=DAILY;COUNT=3").unwrap();
+        assert_eq!(rule.missed_between(anchor, at("2026-10-10T10:00:00Z")), 2);
+        let rule = Rule::parse("FREQ=DAILY;UNTIL=20261002").unwrap();
+        assert_eq!(rule.missed_between(anchor, at("2026-10-10T10:00:00Z")), 1);
+    }
+
+    #[test]
+    fn advancing_uses_up_one_occurrence() {
+        let rule = Rule::parse("FREQ=DAILY;COUNT=3").unwrap();
+        assert_eq!(rule.advanced().count, Some(2));
+        assert_eq!(Rule::parse("FREQ=DAILY").unwrap().advanced().count, None);
+    }
+}
@@ -0,0 +1,111 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::alerts;
+use crate::database::{self, AlertRule, DbPool, EscalationRule, Todo};
+use crate::escalations;
+
+pub const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Delivers reminders that have come due, alerts that start firing and escalations. Implement
+/// it to send them somewhere other than the server log, e.g. email or push, and pass it to
+/// `run_reminder_scheduler`, `alerts::run_alert_evaluator` and
+/// `escalations::run_escalation_checker`.
+pub trait Notifier: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// An error leaves the reminder due, so it is tried again on the next scan.
+    fn notify(&self, todo: &Todo) -> Result<(), Box<dyn std::error::Error + Send + Sync>>;
+
+    /// An error leaves the rule not firing, so it is tried again on the next check.
+    fn alert(
+        &self,
+        rule: &AlertRule,
+        value: f64,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>>;
+
+    /// Reports that a todo was escalated. Lists have no owner, so the notifier decides who
+    /// hears about it. An error leaves the escalation unrecorded, so it is tried again on the
+    /// next check.
+    fn escalate(
+        &self,
+        todo: &Todo,
+        rule: &EscalationRule,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>>;
+}
+
+/// The default notifier; it prints reminders, alerts and escalations to the server log.
+pub struct LogNotifier;
+
+impl Notifier for LogNotifier {
+    fn name(&self) -> &'static str {
+        "log"
+    }
+
+    fn notify(&self, todo: &Todo) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        match &todo.due_date {
+            Some(due_date) => println!("⏰ Reminder: {} (due {due_date})", todo.title),
+            None => println!("⏰ Reminder: {}", todo.title),
+        }
+        Ok(())
+    }
+
+    fn alert(
+        &self,
+        rule: &AlertRule,
+        value: f64,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        eprintln!("🚨 {}", alerts::describe(rule, value));
+        Ok(())
+    }
+
+    fn escalate(
+        &self,
+        todo: &Todo,
+        rule: &EscalationRule,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        eprintln!("📣 {}", escalations::describe(todo, rule));
+        Ok(())
+    }
+}
+
+fn send_due_reminders(
+    pool: &DbPool,
+    notifier: &dyn Notifier,
+) -> Result<usize, Box<dyn std::error::Error + Send + Sync>> {
+    let mut sent = 0;
+    for todo in database::get_due_reminders(pool)? {
+        let Some(remind_at) = todo.remind_at else {
+            continue;
+        };
+        match notifier.notify(&todo) {
+            Ok(()) => {
+                database::mark_reminded(pool, &todo.id, &database::format_timestamp(remind_at))?;
+                sent += 1;
+            }
+            Err(e) => eprintln!(
+                "Reminder for todo {} via {} failed: {e}",
+                todo.id,
+                notifier.name()
+            ),
+        }
+    }
+    Ok(sent)
+}
+
+/// Background task that sends the reminders of open todos once their `remind_at` has passed.
+pub async fn run_reminder_scheduler(pool: DbPool, notifier: Arc<dyn Notifier>) {
+    let mut interval = tokio::time::interval(CHECK_INTERVAL);
+    loop {
+        interval.tick().await;
+        let pool = pool.clone();
+        let notifier = notifier.clone();
+        match tokio::task::spawn_blocking(move || send_due_reminders(&pool, notifier.as_ref()))
+            .await
+        {
+            Ok(Ok(_)) => {}
+            Ok(Err(e)) => eprintln!("Reminder check failed: {e}"),
+            Err(e) => eprintln!("Reminder check panicked: {e}"),
+        }
+    }
+}
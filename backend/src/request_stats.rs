@@ -0,0 +1,46 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use chrono::Utc;
+
+/// Per-minute counts are kept this long
+pub const MAX_WINDOW_MINUTES: u32 = 24 * 60;
+
+// (minute since the epoch, responses, server errors), oldest first
+static BUCKETS: Mutex<VecDeque<(i64, u64, u64)>> = Mutex::new(VecDeque::new());
+
+fn current_minute() -> i64 {
+    Utc::now().timestamp() / 60
+}
+
+/// Counts a response. Only this process's responses are counted, from its start.
+pub fn record(status: u16) {
+    let minute = current_minute();
+    let error = u64::from(status >= 500);
+    let mut buckets = BUCKETS.lock().unwrap();
+    match buckets.back_mut() {
+        Some((last, responses, errors)) if *last == minute => {
+            *responses += 1;
+            *errors += error;
+        }
+        _ => buckets.push_back((minute, 1, error)),
+    }
+    while buckets
+        .front()
+        .is_some_and(|(first, _, _)| minute - first >= i64::from(MAX_WINDOW_MINUTES))
+    {
+        buckets.pop_front();
+    }
+}
+
+/// Responses and server errors (5xx) over the last `minutes`, including the current one.
+pub fn counts(minutes: u32) -> (u64, u64) {
+    let since = current_minute() - i64::from(minutes);
+    let buckets = BUCKETS.lock().unwrap();
+    buckets
+        .iter()
+        .filter(|(minute, _, _)| *minute > since)
+        .fold((0, 0), |(responses, errors), (_, r, e)| {
+            (responses + r, errors + e)
+        })
+}
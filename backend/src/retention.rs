@@ -0,0 +1,59 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::activity;
+use crate::attachments;
+use crate::csv_import;
+use crate::database::{self, DbPool};
+use crate::events;
+use crate::idempotency;
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Applies the retention policies configured on lists and prunes old events, activity, CSV
+/// uploads and idempotency keys.
+/// Returns how many todos were archived and how many were purged from the trash.
+pub fn apply_retention(
+    pool: &DbPool,
+) -> Result<(usize, usize), Box<dyn std::error::Error + Send + Sync>> {
+    let archived = database::archive_expired_todos(pool)?;
+    let purged = database::purge_expired_trash(pool)?;
+    database::prune_events(pool, events::RETENTION_DAYS)?;
+    database::prune_activity(pool, activity::RETENTION_DAYS)?;
+    database::prune_import_uploads(pool, csv_import::UPLOAD_TTL_HOURS)?;
+    database::prune_idempotency_keys(pool, idempotency::TTL_HOURS)?;
+    Ok((archived, purged))
+}
+
+/// Periodically archives completed todos and empties the trash according to their list's
+/// retention policy, then removes the files of attachments that no longer exist.
+pub async fn run_retention_jobs(pool: DbPool, attachments_dir: PathBuf) {
+    let mut interval = tokio::time::interval(CHECK_INTERVAL);
+    loop {
+        interval.tick().await;
+        let job_pool = pool.clone();
+        match tokio::task::spawn_blocking(move || apply_retention(&job_pool)).await {
+            Ok(Ok((archived, purged))) => {
+                if archived > 0 {
+                    println!("🗄️ Archived {archived} completed todo(s)");
+                }
+                if purged > 0 {
+                    println!("🗑️ Purged {purged} todo(s) from the trash");
+                }
+            }
+            Ok(Err(e)) => eprintln!("Retention job failed: {e}"),
+            Err(e) => eprintln!("Retention job panicked: {e}"),
+        }
+        let job_pool = pool.clone();
+        let dir = attachments_dir.clone();
+        match tokio::task::spawn_blocking(move || {
+            attachments::remove_orphaned_files(&job_pool, &dir)
+        })
+        .await
+        {
+            Ok(Ok(_)) => {}
+            Ok(Err(e)) => eprintln!("Attachment cleanup failed: {e}"),
+            Err(e) => eprintln!("Attachment cleanup panicked: {e}"),
+        }
+    }
+}
@@ -0,0 +1,110 @@
+use std::collections::HashMap;
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::Json,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::database::{self, DbPool, ReviewStatus, SortDirection, SortKey, Todo};
+use crate::handlers;
+use crate::json_body::JsonBody;
+use crate::realtime;
+
+pub const REVIEW_COMMENT_MAX_LEN: usize = 1000;
+
+#[derive(Debug, Deserialize)]
+pub struct ReviewDecision {
+    /// Who signed off, e.g. the manager's name
+    pub reviewer: Option<String>,
+    pub comment: Option<String>,
+}
+
+fn error(status: StatusCode, message: &str) -> (StatusCode, Json<Value>) {
+    (status, Json(json!({"error": message})))
+}
+
+/// Completed todos waiting for review, longest waiting first; takes the same query
+/// parameters as `GET /api/todos`, e.g. `?list=<id>` for one team's queue.
+pub async fn get_pending_reviews_handler(
+    State(pool): State<DbPool>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<Json<Vec<Todo>>, (StatusCode, Json<Value>)> {
+    let mut filter = handlers::parse_todo_filter(&params)
+        .map_err(|message| error(StatusCode::BAD_REQUEST, &message))?;
+    filter.review = Some(ReviewStatus::Pending);
+    // A completed todo is waiting for review whatever its start date
+    filter.scheduled = None;
+    if filter.sort.is_empty() {
+        filter.sort = vec![SortKey {
+            column: "completed_at",
+            direction: SortDirection::Asc,
+        }];
+    }
+    database::get_todos(&pool, &filter)
+        .map(Json)
+        .map_err(|_| error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to get reviews"))
+}
+
+fn decide(
+    pool: &DbPool,
+    id: &str,
+    status: ReviewStatus,
+    decision: ReviewDecision,
+) -> Result<Json<Todo>, (StatusCode, Json<Value>)> {
+    let reviewer = decision
+        .reviewer
+        .as_deref()
+        .map(realtime::normalize_user_name)
+        .transpose()
+        .map_err(|message| error(StatusCode::BAD_REQUEST, &message))?;
+    let comment = decision
+        .comment
+        .as_deref()
+        .map(str::trim)
+        .filter(|comment| !comment.is_empty());
+    if comment.is_some_and(|comment| comment.chars().count() > REVIEW_COMMENT_MAX_LEN) {
+        return Err(error(
+            StatusCode::BAD_REQUEST,
+            &format!("Review comments can be at most {REVIEW_COMMENT_MAX_LEN} characters"),
+        ));
+    }
+    match database::review_todo(pool, id, status, reviewer.as_deref(), comment) {
+        Ok(Some(todo)) => Ok(Json(todo)),
+        Ok(None) => match database::get_todo(pool, id) {
+            Ok(Some(_)) => Err(error(
+                StatusCode::CONFLICT,
+                "The todo is not waiting for review",
+            )),
+            Ok(None) => Err(error(StatusCode::NOT_FOUND, "Todo not found")),
+            Err(_) => Err(error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to get todo",
+            )),
+        },
+        Err(_) => Err(error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to review todo",
+        )),
+    }
+}
+
+/// Signs off a completed todo.
+pub async fn approve_review_handler(
+    State(pool): State<DbPool>,
+    Path(id): Path<String>,
+    JsonBody(decision): JsonBody<ReviewDecision>,
+) -> Result<Json<Todo>, (StatusCode, Json<Value>)> {
+    decide(&pool, &id, ReviewStatus::Approved, decision)
+}
+
+/// Sends a completed todo back: it is reopened, with the comment saying why.
+pub async fn reject_review_handler(
+    State(pool): State<DbPool>,
+    Path(id): Path<String>,
+    JsonBody(decision): JsonBody<ReviewDecision>,
+) -> Result<Json<Todo>, (StatusCode, Json<Value>)> {
+    decide(&pool, &id, ReviewStatus::Rejected, decision)
+}
@@ -0,0 +1,86 @@
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::Json,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::database::{self, DbPool, SEARCH_MATCH_END, SEARCH_MATCH_START};
+
+const DEFAULT_LIMIT: u32 = 20;
+pub const MAX_LIMIT: u32 = 100;
+
+#[derive(Debug, Deserialize)]
+pub struct SearchParams {
+    pub q: Option<String>,
+    pub limit: Option<u32>,
+}
+
+fn error(status: StatusCode, message: &str) -> (StatusCode, Json<Value>) {
+    (status, Json(json!({"error": message})))
+}
+
+// Every word is matched as a literal prefix, so `deplo rel` finds "Deploy the release" and
+// FTS5 operators typed by users cannot cause syntax errors
+fn match_query(q: &str) -> Option<String> {
+    let terms: Vec<String> = q
+        .split_whitespace()
+        .map(|term| format!("\"{}\"*", term.replace('"', "\"\"")))
+        .collect();
+    (!terms.is_empty()).then(|| terms.join(" "))
+}
+
+// Snippets are HTML-escaped, then the matches are wrapped in `<mark>`
+fn highlight(snippet: &str) -> String {
+    let mut html = String::with_capacity(snippet.len());
+    for c in snippet.chars() {
+        match c {
+            SEARCH_MATCH_START => html.push_str("<mark>"),
+            SEARCH_MATCH_END => html.push_str("</mark>"),
+            '&' => html.push_str("&amp;"),
+            '<' => html.push_str("&lt;"),
+            '>' => html.push_str("&gt;"),
+            '"' => html.push_str("&quot;"),
+            '\'' => html.push_str("&#39;"),
+            c => html.push(c),
+        }
+    }
+    html
+}
+
+/// Searches titles and descriptions, most relevant first, with the matches highlighted.
+pub async fn search_todos_handler(
+    State(pool): State<DbPool>,
+    Query(params): Query<SearchParams>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let query = params
+        .q
+        .as_deref()
+        .and_then(match_query)
+        .ok_or_else(|| error(StatusCode::BAD_REQUEST, "`q` is required"))?;
+    let limit = params.limit.unwrap_or(DEFAULT_LIMIT);
+    if !(1..=MAX_LIMIT).contains(&limit) {
+        return Err(error(
+            StatusCode::BAD_REQUEST,
+            &format!("limit must be between 1 and {MAX_LIMIT}"),
+        ));
+    }
+
+    let hits = database::search_todos(&pool, &query, limit)
+        .map_err(|_| error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to search todos"))?;
+    let results: Vec<Value> = hits
+        .into_iter()
+        .map(|hit| {
+            json!({
+                "todo": hit.todo,
+                "highlights": {
+                    "title": highlight(&hit.title),
+                    "description": hit.description.as_deref().map(highlight),
+                },
+                "rank": hit.rank,
+            })
+        })
+        .collect();
+    Ok(Json(json!({ "results": results })))
+}
@@ -0,0 +1,64 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderMap, HeaderName, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+
+use crate::config::Config;
+
+/// Used for HTML responses unless `CONTENT_SECURITY_POLICY` is set. The frontend injects
+/// its styles with `<style>` tags, hence `'unsafe-inline'` for styles only.
+pub const DEFAULT_CONTENT_SECURITY_POLICY: &str = "default-src 'self'; script-src 'self'; \
+    style-src 'self' 'unsafe-inline'; img-src 'self' data:; connect-src 'self'; \
+    object-src 'none'; base-uri 'self'; frame-ancestors 'none'";
+
+const REFERRER_POLICY: &str = "strict-origin-when-cross-origin";
+
+fn is_html(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("text/html"))
+}
+
+// Headers a handler already set are left alone
+fn set_default(headers: &mut HeaderMap, name: HeaderName, value: &str) {
+    if headers.contains_key(&name) {
+        return;
+    }
+    if let Ok(value) = HeaderValue::from_str(value) {
+        headers.insert(name, value);
+    }
+}
+
+/// Adds `X-Content-Type-Options` and `Referrer-Policy` to every response, a
+/// `Content-Security-Policy` to HTML pages, and `Strict-Transport-Security` when
+/// `HSTS_MAX_AGE` is set.
+pub async fn security_headers_layer(
+    State(config): State<Arc<Config>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let mut response = next.run(request).await;
+    let headers = response.headers_mut();
+    set_default(headers, header::X_CONTENT_TYPE_OPTIONS, "nosniff");
+    set_default(headers, header::REFERRER_POLICY, REFERRER_POLICY);
+    if is_html(headers) {
+        set_default(
+            headers,
+            header::CONTENT_SECURITY_POLICY,
+            &config.content_security_policy,
+        );
+    }
+    if let Some(max_age) = config.hsts_max_age {
+        set_default(
+            headers,
+            header::STRICT_TRANSPORT_SECURITY,
+            &format!("max-age={max_age}; includeSubDomains"),
+        );
+    }
+    response
+}
@@ -0,0 +1,30 @@
+use axum::{extract::State, response::Json};
+use serde_json::{json, Value};
+
+use crate::handlers::AppState;
+use crate::slow_query;
+
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+pub const GIT_COMMIT: &str = env!("GIT_COMMIT");
+const BUILD_TIMESTAMP: &str = env!("BUILD_TIMESTAMP");
+
+fn build_time() -> Option<String> {
+    let seconds = BUILD_TIMESTAMP.parse().ok()?;
+    chrono::DateTime::from_timestamp(seconds, 0).map(|time| time.to_rfc3339())
+}
+
+pub async fn get_server_info_handler(State(state): State<AppState>) -> Json<Value> {
+    Json(json!({
+        "name": env!("CARGO_PKG_NAME"),
+        "version": VERSION,
+        "git_commit": GIT_COMMIT,
+        "build_time": build_time(),
+        "uptime_seconds": state.started_at.elapsed().as_secs(),
+        "storage": {
+            "backend": "sqlite",
+            "sqlite_version": rusqlite::version(),
+            "slow_query_threshold_ms": slow_query::threshold().map(|t| t.as_millis() as u64),
+            "slow_queries": slow_query::slow_query_count(),
+        },
+    }))
+}
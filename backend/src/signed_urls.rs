@@ -0,0 +1,161 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{Json, Response},
+};
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use sha2::Sha256;
+
+use crate::attachments;
+use crate::config::Config;
+use crate::database::{self, DbPool, Todo};
+use crate::json_body::OptionalJsonBody;
+
+pub const DEFAULT_TTL_SECONDS: u64 = 60 * 60;
+/// Longest a link can stay valid
+pub const MAX_TTL_SECONDS: u64 = 7 * 24 * 60 * 60;
+
+/// Links to the todo itself unless `attachment_id` names one of its attachments.
+#[derive(Debug, Deserialize)]
+pub struct CreateSignedUrl {
+    pub attachment_id: Option<String>,
+    pub expires_in_seconds: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SignatureParams {
+    pub expires: i64,
+    pub signature: String,
+}
+
+fn error(status: StatusCode, message: &str) -> (StatusCode, Json<Value>) {
+    (status, Json(json!({"error": message})))
+}
+
+// The path and expiry are signed together, so neither can be swapped for another
+fn mac(secret: &str, path: &str, expires: i64) -> Hmac<Sha256> {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(format!("{path}\n{expires}").as_bytes());
+    mac
+}
+
+pub fn sign(secret: &str, path: &str, expires: i64) -> String {
+    hex::encode(mac(secret, path, expires).finalize().into_bytes())
+}
+
+fn verify(
+    secret: &str,
+    path: &str,
+    params: &SignatureParams,
+) -> Result<(), (StatusCode, Json<Value>)> {
+    let valid = hex::decode(&params.signature).is_ok_and(|signature| {
+        mac(secret, path, params.expires)
+            .verify_slice(&signature)
+            .is_ok()
+    });
+    if !valid {
+        return Err(error(StatusCode::FORBIDDEN, "Invalid link signature"));
+    }
+    if params.expires <= Utc::now().timestamp() {
+        return Err(error(StatusCode::GONE, "This link has expired"));
+    }
+    Ok(())
+}
+
+/// Creates a link to a todo, or one of its attachments, that works without credentials
+/// until it expires. The URL is relative to this server.
+pub async fn create_signed_url_handler(
+    State(pool): State<DbPool>,
+    State(config): State<Arc<Config>>,
+    Path(id): Path<String>,
+    OptionalJsonBody(body): OptionalJsonBody<CreateSignedUrl>,
+) -> Result<(StatusCode, Json<Value>), (StatusCode, Json<Value>)> {
+    let (attachment_id, expires_in) = match body {
+        Some(body) => (body.attachment_id, body.expires_in_seconds),
+        None => (None, None),
+    };
+    let expires_in = expires_in.unwrap_or(DEFAULT_TTL_SECONDS);
+    if !(1..=MAX_TTL_SECONDS).contains(&expires_in) {
+        return Err(error(
+            StatusCode::BAD_REQUEST,
+            &format!("expires_in_seconds must be between 1 and {MAX_TTL_SECONDS}"),
+        ));
+    }
+    match database::get_todo(&pool, &id) {
+        Ok(Some(_)) => {}
+        Ok(None) => return Err(error(StatusCode::NOT_FOUND, "Todo not found")),
+        Err(_) => {
+            return Err(error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to get todo",
+            ))
+        }
+    }
+    let path = match attachment_id {
+        Some(attachment_id) => match database::get_attachment(&pool, &attachment_id) {
+            Ok(Some(attachment)) if attachment.todo_id == id => {
+                format!("/api/signed/attachments/{attachment_id}")
+            }
+            Ok(_) => return Err(error(StatusCode::NOT_FOUND, "Attachment not found")),
+            Err(_) => {
+                return Err(error(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "Failed to get attachment",
+                ))
+            }
+        },
+        None => format!("/api/signed/todos/{id}"),
+    };
+
+    let expires_at = Utc::now() + chrono::Duration::seconds(expires_in as i64);
+    let expires = expires_at.timestamp();
+    let signature = sign(&config.url_signing_secret, &path, expires);
+    Ok((
+        StatusCode::CREATED,
+        Json(json!({
+            "url": format!("{path}?expires={expires}&signature={signature}"),
+            "expires_at": database::format_timestamp(expires_at),
+        })),
+    ))
+}
+
+pub async fn signed_todo_handler(
+    State(pool): State<DbPool>,
+    State(config): State<Arc<Config>>,
+    Path(id): Path<String>,
+    Query(params): Query<SignatureParams>,
+) -> Result<Json<Todo>, (StatusCode, Json<Value>)> {
+    verify(
+        &config.url_signing_secret,
+        &format!("/api/signed/todos/{id}"),
+        &params,
+    )?;
+    match database::get_todo(&pool, &id) {
+        Ok(Some(todo)) => Ok(Json(todo)),
+        Ok(None) => Err(error(StatusCode::NOT_FOUND, "Todo not found")),
+        Err(_) => Err(error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to get todo",
+        )),
+    }
+}
+
+pub async fn signed_attachment_handler(
+    State(pool): State<DbPool>,
+    State(config): State<Arc<Config>>,
+    Path(id): Path<String>,
+    Query(params): Query<SignatureParams>,
+) -> Result<Response, (StatusCode, Json<Value>)> {
+    verify(
+        &config.url_signing_secret,
+        &format!("/api/signed/attachments/{id}"),
+        &params,
+    )?;
+    attachments::download_attachment_handler(State(pool), State(config), Path(id)).await
+}
@@ -0,0 +1,176 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+
+use axum::{
+    extract::{Request, State},
+    http::Method,
+    middleware::Next,
+    response::Response,
+};
+use tokio::sync::OnceCell;
+
+/// Coalesces concurrent calls with the same key: the first caller runs the work and
+/// everyone who arrives while it is in flight gets a clone of the same result.
+/// Nothing is cached once the flight has landed.
+pub struct SingleFlight<V> {
+    in_flight: Arc<Mutex<HashMap<String, Arc<OnceCell<V>>>>>,
+    // Part of every key, so calls after `invalidate` start a flight of their own
+    generation: Arc<AtomicU64>,
+}
+
+impl<V> Clone for SingleFlight<V> {
+    fn clone(&self) -> Self {
+        SingleFlight {
+            in_flight: self.in_flight.clone(),
+            generation: self.generation.clone(),
+        }
+    }
+}
+
+impl<V> Default for SingleFlight<V> {
+    fn default() -> Self {
+        SingleFlight {
+            in_flight: Arc::default(),
+            generation: Arc::default(),
+        }
+    }
+}
+
+impl<V: Clone> SingleFlight<V> {
+    /// Calls that start after this no longer join flights that started before it, e.g.
+    /// because the data changed in between.
+    pub fn invalidate(&self) {
+        self.generation.fetch_add(1, Ordering::SeqCst);
+    }
+
+    pub async fn run<F>(&self, key: String, work: F) -> V
+    where
+        F: Future<Output = V>,
+    {
+        let key = format!("{} {key}", self.generation.load(Ordering::SeqCst));
+        let cell = self
+            .in_flight
+            .lock()
+            .unwrap()
+            .entry(key.clone())
+            .or_default()
+            .clone();
+        let caller = Caller {
+            in_flight: &self.in_flight,
+            key,
+            cell: Some(cell),
+        };
+
+        // If the caller running `work` is cancelled, one of the waiters takes over
+        let cell = caller.cell.as_ref().expect("set until dropped");
+        cell.get_or_init(|| work).await.clone()
+    }
+}
+
+// Takes a flight out of the map once it has landed, or once every caller of it is gone,
+// even when the last one is cancelled mid-flight
+struct Caller<'a, V> {
+    in_flight: &'a Mutex<HashMap<String, Arc<OnceCell<V>>>>,
+    key: String,
+    cell: Option<Arc<OnceCell<V>>>,
+}
+
+impl<V> Drop for Caller<'_, V> {
+    fn drop(&mut self) {
+        let mut in_flight = self
+            .in_flight
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        // Callers let go of the cell under the lock, so the count is exact
+        let Some(cell) = self.cell.take() else {
+            return;
+        };
+        // The map and this caller hold one reference each; any more are other callers
+        let finished = cell.initialized() || Arc::strong_count(&cell) == 2;
+        if finished
+            && in_flight
+                .get(&self.key)
+                .is_some_and(|current| Arc::ptr_eq(current, &cell))
+        {
+            in_flight.remove(&self.key);
+        }
+    }
+}
+
+/// Invalidates the flights once a mutating request has been handled, so a read sent after its
+/// response never gets data from before the change.
+pub async fn invalidate_after_writes<V: Clone>(
+    State(flights): State<SingleFlight<V>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let read_only = matches!(
+        *request.method(),
+        Method::GET | Method::HEAD | Method::OPTIONS
+    );
+    let response = next.run(request).await;
+    if !read_only {
+        flights.invalidate();
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn joins_a_flight_in_progress() {
+        let flights = SingleFlight::<u32>::default();
+        let (first, second) = tokio::join!(
+            flights.run("key".to_string(), async {
+                tokio::task::yield_now().await;
+                1
+            }),
+            flights.run("key".to_string(), async { 2 }),
+        );
+        assert_eq!((first, second), (1, 1));
+        assert!(flights.in_flight.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn forgets_a_flight_whose_only_caller_is_cancelled() {
+        let flights = SingleFlight::<u32>::default();
+        let run = flights.run("key".to_string(), std::future::pending());
+        assert!(tokio::time::timeout(Duration::from_millis(10), run)
+            .await
+            .is_err());
+        assert!(flights.in_flight.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn keeps_a_flight_while_a_caller_waits_on_it() {
+        let flights = SingleFlight::<u32>::default();
+        let mut cancelled = Box::pin(flights.run("key".to_string(), std::future::pending()));
+        let mut waiter = Box::pin(flights.run("key".to_string(), async { 2 }));
+        // Both join the flight; the first one runs its work, which never finishes
+        tokio::select! {
+            biased;
+            _ = &mut cancelled => unreachable!(),
+            _ = &mut waiter => unreachable!(),
+            _ = tokio::time::sleep(Duration::from_millis(10)) => {}
+        }
+        drop(cancelled);
+        assert_eq!(flights.in_flight.lock().unwrap().len(), 1);
+        // The waiter takes the flight over and runs its own work
+        assert_eq!(waiter.await, 2);
+        assert!(flights.in_flight.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn starts_a_new_flight_after_invalidate() {
+        let flights = SingleFlight::<u32>::default();
+        assert_eq!(flights.run("key".to_string(), async { 1 }).await, 1);
+        flights.invalidate();
+        assert_eq!(flights.run("key".to_string(), async { 2 }).await, 2);
+    }
+}
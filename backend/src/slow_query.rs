@@ -0,0 +1,113 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+pub const DEFAULT_THRESHOLD_MS: u64 = 100;
+
+static THRESHOLD: OnceLock<Option<Duration>> = OnceLock::new();
+static SLOW_QUERIES: AtomicU64 = AtomicU64::new(0);
+
+/// Sets the threshold for the lifetime of the process; `0` disables slow query logging.
+/// Call once at startup, before the pool is created.
+pub fn init(threshold_ms: u64) {
+    let _ = THRESHOLD.set((threshold_ms > 0).then(|| Duration::from_millis(threshold_ms)));
+}
+
+pub fn threshold() -> Option<Duration> {
+    *THRESHOLD.get_or_init(|| Some(Duration::from_millis(DEFAULT_THRESHOLD_MS)))
+}
+
+/// Number of queries that exceeded the threshold since startup.
+pub fn slow_query_count() -> u64 {
+    SLOW_QUERIES.load(Ordering::Relaxed)
+}
+
+/// Replaces string and number literals with `?` and collapses whitespace, so the
+/// logged statement shows its shape without leaking any todo contents.
+fn redact(sql: &str) -> String {
+    let mut out = String::with_capacity(sql.len());
+    let mut chars = sql.chars().peekable();
+    // Digits inside identifiers such as `idx_todos_2` and placeholders such as `?1` are kept
+    let mut in_word = false;
+    while let Some(c) = chars.next() {
+        if c == '\'' {
+            // '' is an escaped quote inside the literal
+            while let Some(c) = chars.next() {
+                if c == '\'' && chars.next_if_eq(&'\'').is_none() {
+                    break;
+                }
+            }
+            out.push('?');
+            in_word = false;
+        } else if c.is_ascii_digit() && !in_word {
+            while chars.next_if(|c| c.is_ascii_digit() || *c == '.').is_some() {}
+            out.push('?');
+        } else if c.is_whitespace() {
+            if !out.ends_with(' ') {
+                out.push(' ');
+            }
+            in_word = false;
+        } else {
+            out.push(c);
+            in_word = c.is_alphanumeric() || c == '_' || c == '?';
+        }
+    }
+    out.trim().to_string()
+}
+
+/// Profiling callback registered on every pooled connection.
+pub fn record(sql: &str, duration: Duration) {
+    let Some(threshold) = threshold() else {
+        return;
+    };
+    if duration < threshold {
+        return;
+    }
+    SLOW_QUERIES.fetch_add(1, Ordering::Relaxed);
+    eprintln!(
+        "🐢 Slow query ({:.1}ms): {}",
+        duration.as_secs_f64() * 1000.0,
+        redact(sql)
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redacts_literals() {
+        assert_eq!(
+            redact("SELECT * FROM todos WHERE title = 'Buy milk' AND priority > 2"),
+            "SELECT * FROM todos WHERE title = ? AND priority > ?"
+        );
+        assert_eq!(
+            redact("UPDATE t SET x = 1.5 WHERE y = -3"),
+            "UPDATE t SET x = ? WHERE y = -?"
+        );
+    }
+
+    #[test]
+    fn redacts_quotes_escaped_inside_a_literal() {
+        assert_eq!(
+            redact("SELECT 1 WHERE title = 'It''s done' AND 1"),
+            "SELECT ? WHERE title = ? AND ?"
+        );
+    }
+
+    #[test]
+    fn keeps_identifiers_and_placeholders() {
+        assert_eq!(
+            redact("SELECT * FROM idx_todos_2 WHERE id = ?1 AND seq > ?12"),
+            "SELECT * FROM idx_todos_2 WHERE id = ?1 AND seq > ?12"
+        );
+    }
+
+    #[test]
+    fn collapses_whitespace() {
+        assert_eq!(
+            redact("  SELECT id\n       FROM todos\n\tLIMIT 10  "),
+            "SELECT id FROM todos LIMIT ?"
+        );
+    }
+}
@@ -0,0 +1,67 @@
+use std::io::Write;
+
+use axum::{
+    body::{Body, Bytes},
+    extract::State,
+    http::header,
+    response::{IntoResponse, Response},
+};
+use chrono::Utc;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+
+use crate::database::{self, DbPool};
+
+// Statements buffered between the database thread and the client
+const CHANNEL_CAPACITY: usize = 64;
+
+pub const CONTENT_TYPE: &str = "application/sql";
+
+/// Streams a logical dump of the whole database as a `.sql` download.
+pub async fn dump_handler(State(pool): State<DbPool>) -> Response {
+    let (tx, rx) = mpsc::channel::<Result<Bytes, std::io::Error>>(CHANNEL_CAPACITY);
+
+    tokio::task::spawn_blocking(move || {
+        // A failed send means the client went away
+        let result = database::dump_sql(&pool, |statement| {
+            tx.blocking_send(Ok(Bytes::from(statement))).is_ok()
+        });
+        // Headers are already sent, so the best we can do is abort the body
+        if let Err(e) = result {
+            let _ = tx.blocking_send(Err(std::io::Error::other(e.to_string())));
+        }
+    });
+
+    let filename = format!("todos-{}.sql", Utc::now().format("%Y%m%d-%H%M%S"));
+    (
+        [
+            (header::CONTENT_TYPE, CONTENT_TYPE.to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{filename}\""),
+            ),
+        ],
+        Body::from_stream(ReceiverStream::new(rx)),
+    )
+        .into_response()
+}
+
+/// `rust_todo dump`: writes the dump to stdout instead of starting the server.
+pub fn dump_to_stdout(pool: &DbPool) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let mut out = std::io::BufWriter::new(std::io::stdout().lock());
+    let mut write_error = None;
+    database::dump_sql(pool, |statement| {
+        match out.write_all(statement.as_bytes()) {
+            Ok(()) => true,
+            Err(e) => {
+                write_error = Some(e);
+                false
+            }
+        }
+    })?;
+    if let Some(e) = write_error {
+        return Err(e.into());
+    }
+    out.flush()?;
+    Ok(())
+}
@@ -0,0 +1,95 @@
+use std::time::Duration;
+
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::Json,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::database::{self, DbPool, SortDirection, SortKey, Todo, TodoFilter};
+
+const DEFAULT_OLDER_THAN: &str = "30d";
+const CHECK_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Debug, Deserialize)]
+pub struct StaleParams {
+    pub older_than: Option<String>,
+}
+
+/// Parses `12h`, `30d` or `2w` style durations.
+pub fn parse_age(value: &str) -> Option<chrono::Duration> {
+    let split = value.len().checked_sub(1)?;
+    let (amount, unit) = value.split_at(split);
+    let amount: i64 = amount.parse().ok().filter(|amount| *amount >= 0)?;
+    match unit {
+        "h" => chrono::Duration::try_hours(amount),
+        "d" => chrono::Duration::try_days(amount),
+        "w" => chrono::Duration::try_weeks(amount),
+        _ => None,
+    }
+}
+
+/// Open todos nobody has changed for `age`, least recently changed first.
+pub fn find_stale_todos(
+    pool: &DbPool,
+    age: chrono::Duration,
+) -> Result<Vec<Todo>, Box<dyn std::error::Error + Send + Sync>> {
+    let cutoff = chrono::Utc::now() - age;
+    database::get_todos(
+        pool,
+        &TodoFilter {
+            completed: Some(false),
+            // Same format as `updated_at`, so the text comparison orders correctly
+            updated_before: Some(cutoff.to_rfc3339_opts(chrono::SecondsFormat::Millis, true)),
+            sort: vec![SortKey {
+                column: "updated_at",
+                direction: SortDirection::Asc,
+            }],
+            ..Default::default()
+        },
+    )
+}
+
+pub async fn get_stale_todos_handler(
+    State(pool): State<DbPool>,
+    Query(params): Query<StaleParams>,
+) -> Result<Json<Vec<Todo>>, (StatusCode, Json<Value>)> {
+    let older_than = params.older_than.as_deref().unwrap_or(DEFAULT_OLDER_THAN);
+    let age = parse_age(older_than).ok_or((
+        StatusCode::BAD_REQUEST,
+        Json(json!({"error": "Invalid older_than: use a number followed by h, d or w (e.g. 30d)"})),
+    ))?;
+
+    match find_stale_todos(&pool, age) {
+        Ok(todos) => Ok(Json(todos)),
+        Err(_) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Failed to get stale todos"})),
+        )),
+    }
+}
+
+/// Periodically reports open todos that have gone untouched for `days` days.
+pub async fn run_stale_notifier(pool: DbPool, days: i64) {
+    let age = chrono::Duration::days(days);
+    let mut interval = tokio::time::interval(CHECK_INTERVAL);
+    loop {
+        interval.tick().await;
+        let pool = pool.clone();
+        match tokio::task::spawn_blocking(move || find_stale_todos(&pool, age)).await {
+            Ok(Ok(todos)) if !todos.is_empty() => {
+                println!("🕸️ {} todo(s) untouched for {days}+ days:", todos.len());
+                let now = chrono::Utc::now();
+                for todo in &todos {
+                    let untouched = (now - todo.updated_at).num_days();
+                    println!("   - {} ({untouched} days)", todo.title);
+                }
+            }
+            Ok(Ok(_)) => {}
+            Ok(Err(e)) => eprintln!("Stale todo check failed: {e}"),
+            Err(e) => eprintln!("Stale todo check panicked: {e}"),
+        }
+    }
+}
@@ -0,0 +1,81 @@
+use axum::{
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    response::Json,
+};
+use serde_json::{json, Value};
+
+use crate::activity;
+use crate::custom_fields;
+use crate::database::{self, CreateTodo, DbPool, Todo, TodoFilter};
+use crate::handlers;
+use crate::json_body::JsonBody;
+use crate::lists;
+
+fn error(status: StatusCode, message: &str) -> (StatusCode, Json<Value>) {
+    (status, Json(json!({"error": message})))
+}
+
+fn find_todo(pool: &DbPool, id: &str) -> Result<Todo, (StatusCode, Json<Value>)> {
+    match database::get_todo(pool, id) {
+        Ok(Some(todo)) => Ok(todo),
+        Ok(None) => Err(error(StatusCode::NOT_FOUND, "Todo not found")),
+        Err(_) => Err(error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to get todo",
+        )),
+    }
+}
+
+/// Direct subtasks of a todo, oldest first.
+pub async fn get_children_handler(
+    State(pool): State<DbPool>,
+    Path(id): Path<String>,
+) -> Result<Json<Vec<Todo>>, (StatusCode, Json<Value>)> {
+    find_todo(&pool, &id)?;
+    let filter = TodoFilter {
+        parent_id: Some(id),
+        sort: vec![database::SortKey {
+            column: "seq",
+            direction: database::SortDirection::Asc,
+        }],
+        ..Default::default()
+    };
+    database::get_todos(&pool, &filter)
+        .map(Json)
+        .map_err(|_| error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to get subtasks"))
+}
+
+/// Creates a todo as a subtask of `id`; the body is the same as for `POST /api/todos`.
+/// Without a `list_id` the subtask goes into its parent's list.
+pub async fn create_child_handler(
+    State(pool): State<DbPool>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+    JsonBody(mut create_todo): JsonBody<CreateTodo>,
+) -> Result<(StatusCode, Json<Todo>), (StatusCode, Json<Value>)> {
+    handlers::validate_create_todo(&mut create_todo)
+        .map_err(|message| error(StatusCode::BAD_REQUEST, &message))?;
+    let parent = find_todo(&pool, &id)?;
+    match &create_todo.list_id {
+        Some(list_id) => {
+            lists::ensure_list_exists(&pool, list_id)?;
+        }
+        None => create_todo.list_id = parent.list_id,
+    }
+    if let Some(values) = &create_todo.custom_fields {
+        custom_fields::check_values(&pool, values)?;
+    }
+    create_todo.parent_id = Some(id);
+    database::create_todo(&pool, create_todo)
+        .map(|todo| {
+            activity::record_todo(&pool, &headers, "create", &todo);
+            (StatusCode::CREATED, Json(todo))
+        })
+        .map_err(|_| {
+            error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to create subtask",
+            )
+        })
+}
@@ -0,0 +1,274 @@
+use std::collections::{HashMap, HashSet};
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::Json,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::autocomplete::AutocompleteIndex;
+use crate::database::{self, BulkSelection, BulkTagResult, DbPool, Tag, Todo};
+use crate::handlers;
+use crate::json_body::JsonBody;
+use crate::palette;
+
+#[derive(Debug, Deserialize)]
+pub struct CreateTag {
+    pub name: String,
+    /// Name of a palette color
+    pub color: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateTag {
+    pub name: Option<String>,
+    /// An empty string removes the color
+    pub color: Option<String>,
+}
+
+const BULK_MAX_IDS: usize = 1000;
+const BULK_MAX_TAGS: usize = 50;
+
+/// Selects todos either by `ids` or by `filter`, never both
+#[derive(Debug, Deserialize)]
+pub struct BulkTag {
+    pub ids: Option<Vec<String>>,
+    /// The query parameters of `GET /api/todos`, e.g. `{"list": "inbox", "completed": "true"}`
+    pub filter: Option<HashMap<String, String>>,
+    #[serde(default)]
+    pub add: Vec<String>,
+    #[serde(default)]
+    pub remove: Vec<String>,
+}
+
+fn error(status: StatusCode, message: &str) -> (StatusCode, Json<Value>) {
+    (status, Json(json!({"error": message})))
+}
+
+fn tag_name(name: &str) -> Result<String, (StatusCode, Json<Value>)> {
+    database::normalize_tag_name(name).map_err(|message| error(StatusCode::BAD_REQUEST, &message))
+}
+
+// Names are unique ignoring case; `except` is the tag being renamed
+fn ensure_name_free(
+    pool: &DbPool,
+    name: &str,
+    except: Option<&str>,
+) -> Result<(), (StatusCode, Json<Value>)> {
+    match database::find_tag_by_name(pool, name) {
+        Ok(Some(existing)) if Some(existing.id.as_str()) != except => Err((
+            StatusCode::CONFLICT,
+            Json(json!({"error": "A tag with this name already exists", "existing": existing})),
+        )),
+        Ok(_) => Ok(()),
+        Err(_) => Err(error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to check tag name",
+        )),
+    }
+}
+
+pub async fn get_tags_handler(
+    State(pool): State<DbPool>,
+) -> Result<Json<Vec<Tag>>, (StatusCode, Json<Value>)> {
+    database::get_tags(&pool)
+        .map(Json)
+        .map_err(|_| error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to get tags"))
+}
+
+pub async fn get_tag_handler(
+    State(pool): State<DbPool>,
+    Path(id): Path<String>,
+) -> Result<Json<Tag>, (StatusCode, Json<Value>)> {
+    match database::get_tag(&pool, &id) {
+        Ok(Some(tag)) => Ok(Json(tag)),
+        Ok(None) => Err(error(StatusCode::NOT_FOUND, "Tag not found")),
+        Err(_) => Err(error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to get tag",
+        )),
+    }
+}
+
+pub async fn create_tag_handler(
+    State(pool): State<DbPool>,
+    State(autocomplete): State<AutocompleteIndex>,
+    JsonBody(body): JsonBody<CreateTag>,
+) -> Result<(StatusCode, Json<Tag>), (StatusCode, Json<Value>)> {
+    let name = tag_name(&body.name)?;
+    ensure_name_free(&pool, &name, None)?;
+    let color = match &body.color {
+        Some(color) => Some(palette::resolve_color(&pool, color)?),
+        None => None,
+    };
+    let tag = database::create_tag(&pool, &name, color.as_deref())
+        .map_err(|_| error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to create tag"))?;
+    autocomplete.reload_tags(&pool);
+    Ok((StatusCode::CREATED, Json(tag)))
+}
+
+/// Renames and/or recolors a tag.
+pub async fn update_tag_handler(
+    State(pool): State<DbPool>,
+    State(autocomplete): State<AutocompleteIndex>,
+    Path(id): Path<String>,
+    JsonBody(body): JsonBody<UpdateTag>,
+) -> Result<Json<Tag>, (StatusCode, Json<Value>)> {
+    let name = match &body.name {
+        Some(name) => {
+            let name = tag_name(name)?;
+            ensure_name_free(&pool, &name, Some(&id))?;
+            Some(name)
+        }
+        None => None,
+    };
+    let color = match body.color.as_deref() {
+        Some("") => Some(String::new()),
+        Some(color) => Some(palette::resolve_color(&pool, color)?),
+        None => None,
+    };
+    match database::update_tag(&pool, &id, name.as_deref(), color.as_deref()) {
+        Ok(Some(tag)) => {
+            autocomplete.reload_tags(&pool);
+            Ok(Json(tag))
+        }
+        Ok(None) => Err(error(StatusCode::NOT_FOUND, "Tag not found")),
+        Err(_) => Err(error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to update tag",
+        )),
+    }
+}
+
+pub async fn delete_tag_handler(
+    State(pool): State<DbPool>,
+    State(autocomplete): State<AutocompleteIndex>,
+    Path(id): Path<String>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    match database::delete_tag(&pool, &id) {
+        Ok(true) => {
+            autocomplete.reload_tags(&pool);
+            Ok(Json(json!({"message": "Tag deleted successfully"})))
+        }
+        Ok(false) => Err(error(StatusCode::NOT_FOUND, "Tag not found")),
+        Err(_) => Err(error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to delete tag",
+        )),
+    }
+}
+
+fn bulk_tag_names(field: &str, names: &[String]) -> Result<Vec<String>, (StatusCode, Json<Value>)> {
+    if names.len() > BULK_MAX_TAGS {
+        return Err(error(
+            StatusCode::BAD_REQUEST,
+            &format!("`{field}` can list at most {BULK_MAX_TAGS} tags"),
+        ));
+    }
+    let mut seen = HashSet::new();
+    let mut normalized = Vec::new();
+    for name in names {
+        let name = tag_name(name)?;
+        if seen.insert(name.to_ascii_lowercase()) {
+            normalized.push(name);
+        }
+    }
+    Ok(normalized)
+}
+
+/// Adds and removes tags across many todos in one transaction.
+pub async fn bulk_tag_handler(
+    State(pool): State<DbPool>,
+    JsonBody(body): JsonBody<BulkTag>,
+) -> Result<Json<BulkTagResult>, (StatusCode, Json<Value>)> {
+    let add = bulk_tag_names("add", &body.add)?;
+    let remove = bulk_tag_names("remove", &body.remove)?;
+    if add.is_empty() && remove.is_empty() {
+        return Err(error(
+            StatusCode::BAD_REQUEST,
+            "Specify tags to `add` and/or `remove`",
+        ));
+    }
+    if let Some(name) = add
+        .iter()
+        .find(|name| remove.iter().any(|other| other.eq_ignore_ascii_case(name)))
+    {
+        return Err(error(
+            StatusCode::BAD_REQUEST,
+            &format!("Tag '{name}' cannot be both added and removed"),
+        ));
+    }
+    let selection = match (body.ids, body.filter) {
+        (Some(mut ids), None) => {
+            if ids.len() > BULK_MAX_IDS {
+                return Err(error(
+                    StatusCode::BAD_REQUEST,
+                    &format!("`ids` can list at most {BULK_MAX_IDS} todos"),
+                ));
+            }
+            let mut seen = HashSet::new();
+            ids.retain(|id| seen.insert(id.clone()));
+            BulkSelection::Ids(ids)
+        }
+        (None, Some(params)) => BulkSelection::Filter(Box::new(
+            handlers::parse_todo_filter(&params)
+                .map_err(|message| error(StatusCode::BAD_REQUEST, &message))?,
+        )),
+        _ => {
+            return Err(error(
+                StatusCode::BAD_REQUEST,
+                "Specify exactly one of `ids` or `filter`",
+            ))
+        }
+    };
+    database::bulk_tag(&pool, &selection, &add, &remove)
+        .map(Json)
+        .map_err(|_| error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to update tags"))
+}
+
+fn todo_response(pool: &DbPool, id: &str) -> Result<Json<Todo>, (StatusCode, Json<Value>)> {
+    match database::get_todo(pool, id) {
+        Ok(Some(todo)) => Ok(Json(todo)),
+        Ok(None) => Err(error(StatusCode::NOT_FOUND, "Todo not found")),
+        Err(_) => Err(error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to get todo",
+        )),
+    }
+}
+
+fn ensure_todo_exists(pool: &DbPool, id: &str) -> Result<(), (StatusCode, Json<Value>)> {
+    todo_response(pool, id).map(|_| ())
+}
+
+/// Tags a todo, creating the tag on first use.
+pub async fn attach_tag_handler(
+    State(pool): State<DbPool>,
+    Path((id, name)): Path<(String, String)>,
+) -> Result<Json<Todo>, (StatusCode, Json<Value>)> {
+    let name = tag_name(&name)?;
+    ensure_todo_exists(&pool, &id)?;
+    database::attach_tag(&pool, &id, &name)
+        .map_err(|_| error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to attach tag"))?;
+    todo_response(&pool, &id)
+}
+
+pub async fn detach_tag_handler(
+    State(pool): State<DbPool>,
+    Path((id, name)): Path<(String, String)>,
+) -> Result<Json<Todo>, (StatusCode, Json<Value>)> {
+    ensure_todo_exists(&pool, &id)?;
+    match database::detach_tag(&pool, &id, &name) {
+        Ok(true) => todo_response(&pool, &id),
+        Ok(false) => Err(error(
+            StatusCode::NOT_FOUND,
+            "The todo does not have this tag",
+        )),
+        Err(_) => Err(error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to detach tag",
+        )),
+    }
+}
@@ -0,0 +1,244 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode},
+    response::Json,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::activity;
+use crate::database::{self, CreateTodo, DbPool, Priority, Template, Todo, CHECKLIST_MAX_ITEMS};
+use crate::json_body::JsonBody;
+use crate::lists;
+
+#[derive(Debug, Deserialize)]
+pub struct CreateTemplate {
+    pub name: String,
+    pub title: String,
+    pub description: Option<String>,
+    pub priority: Option<Priority>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub checklist: Vec<String>,
+}
+
+/// Replaces the given fields; an empty `description` removes it.
+#[derive(Debug, Deserialize)]
+pub struct UpdateTemplate {
+    pub name: Option<String>,
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub priority: Option<Priority>,
+    pub tags: Option<Vec<String>>,
+    pub checklist: Option<Vec<String>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct InstantiateParams {
+    pub list_id: Option<String>,
+    pub due_date: Option<String>,
+}
+
+fn error(status: StatusCode, message: &str) -> (StatusCode, Json<Value>) {
+    (status, Json(json!({"error": message})))
+}
+
+fn bad_request(message: String) -> (StatusCode, Json<Value>) {
+    error(StatusCode::BAD_REQUEST, &message)
+}
+
+// Normalizes the template in place so it is saved the way it will be used
+fn validate(template: &mut Template) -> Result<(), String> {
+    template.name = database::normalize_template_name(&template.name)?;
+    template.title = template.title.trim().to_string();
+    if template.title.is_empty() {
+        return Err("Templates need a `title`".to_string());
+    }
+    let mut tags: Vec<String> = Vec::new();
+    for name in &template.tags {
+        let name = database::normalize_tag_name(name)?;
+        // Tag names are unique ignoring case
+        if !tags
+            .iter()
+            .any(|tag| tag.to_lowercase() == name.to_lowercase())
+        {
+            tags.push(name);
+        }
+    }
+    template.tags = tags;
+    if template.checklist.len() > CHECKLIST_MAX_ITEMS {
+        return Err(format!(
+            "A checklist can have at most {CHECKLIST_MAX_ITEMS} items"
+        ));
+    }
+    template.checklist = template
+        .checklist
+        .iter()
+        .map(|text| database::normalize_checklist_text(text))
+        .collect::<Result<_, _>>()?;
+    Ok(())
+}
+
+// Names are unique ignoring case; `except` is the template being renamed
+fn ensure_name_free(
+    pool: &DbPool,
+    name: &str,
+    except: Option<&str>,
+) -> Result<(), (StatusCode, Json<Value>)> {
+    match database::find_template_by_name(pool, name) {
+        Ok(Some(existing)) if Some(existing.id.as_str()) != except => Err((
+            StatusCode::CONFLICT,
+            Json(
+                json!({"error": "A template with this name already exists", "existing": existing}),
+            ),
+        )),
+        Ok(_) => Ok(()),
+        Err(_) => Err(error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to check template name",
+        )),
+    }
+}
+
+fn find_template(pool: &DbPool, id: &str) -> Result<Template, (StatusCode, Json<Value>)> {
+    match database::get_template(pool, id) {
+        Ok(Some(template)) => Ok(template),
+        Ok(None) => Err(error(StatusCode::NOT_FOUND, "Template not found")),
+        Err(_) => Err(error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to get template",
+        )),
+    }
+}
+
+fn save(pool: &DbPool, template: &Template) -> Result<(), (StatusCode, Json<Value>)> {
+    database::save_template(pool, template)
+        .map_err(|_| error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to save template"))
+}
+
+pub async fn get_templates_handler(
+    State(pool): State<DbPool>,
+) -> Result<Json<Vec<Template>>, (StatusCode, Json<Value>)> {
+    database::get_templates(&pool)
+        .map(Json)
+        .map_err(|_| error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to get templates"))
+}
+
+pub async fn get_template_handler(
+    State(pool): State<DbPool>,
+    Path(id): Path<String>,
+) -> Result<Json<Template>, (StatusCode, Json<Value>)> {
+    find_template(&pool, &id).map(Json)
+}
+
+pub async fn create_template_handler(
+    State(pool): State<DbPool>,
+    JsonBody(body): JsonBody<CreateTemplate>,
+) -> Result<(StatusCode, Json<Template>), (StatusCode, Json<Value>)> {
+    let now = database::now_timestamp();
+    let mut template = Template {
+        id: uuid::Uuid::new_v4().to_string(),
+        name: body.name,
+        title: body.title,
+        description: body
+            .description
+            .filter(|description| !description.is_empty()),
+        priority: body.priority,
+        tags: body.tags,
+        checklist: body.checklist,
+        created_at: now.clone(),
+        updated_at: now,
+    };
+    validate(&mut template).map_err(bad_request)?;
+    ensure_name_free(&pool, &template.name, None)?;
+    save(&pool, &template)?;
+    Ok((StatusCode::CREATED, Json(template)))
+}
+
+pub async fn update_template_handler(
+    State(pool): State<DbPool>,
+    Path(id): Path<String>,
+    JsonBody(body): JsonBody<UpdateTemplate>,
+) -> Result<Json<Template>, (StatusCode, Json<Value>)> {
+    let mut template = find_template(&pool, &id)?;
+    if let Some(name) = body.name {
+        template.name = name;
+    }
+    if let Some(title) = body.title {
+        template.title = title;
+    }
+    if let Some(description) = body.description {
+        template.description = Some(description).filter(|description| !description.is_empty());
+    }
+    if let Some(priority) = body.priority {
+        template.priority = Some(priority);
+    }
+    if let Some(tags) = body.tags {
+        template.tags = tags;
+    }
+    if let Some(checklist) = body.checklist {
+        template.checklist = checklist;
+    }
+    template.updated_at = database::now_timestamp();
+    validate(&mut template).map_err(bad_request)?;
+    ensure_name_free(&pool, &template.name, Some(&id))?;
+    save(&pool, &template)?;
+    Ok(Json(template))
+}
+
+/// Deletes a template. Todos created from it are kept.
+pub async fn delete_template_handler(
+    State(pool): State<DbPool>,
+    Path(id): Path<String>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    match database::delete_template(&pool, &id) {
+        Ok(true) => Ok(Json(json!({"message": "Template deleted successfully"}))),
+        Ok(false) => Err(error(StatusCode::NOT_FOUND, "Template not found")),
+        Err(_) => Err(error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to delete template",
+        )),
+    }
+}
+
+/// Creates a todo from a template, with its tags and checklist, optionally in a list and
+/// with a due date.
+pub async fn instantiate_template_handler(
+    State(pool): State<DbPool>,
+    Path(id): Path<String>,
+    Query(params): Query<InstantiateParams>,
+    headers: HeaderMap,
+) -> Result<(StatusCode, Json<Todo>), (StatusCode, Json<Value>)> {
+    let template = find_template(&pool, &id)?;
+    let due_date = params
+        .due_date
+        .as_deref()
+        .map(database::normalize_due_date)
+        .transpose()
+        .map_err(bad_request)?;
+    if let Some(list_id) = &params.list_id {
+        lists::ensure_list_exists(&pool, list_id)?;
+    }
+    let create = CreateTodo {
+        title: template.title,
+        description: template.description,
+        metadata: None,
+        custom_fields: None,
+        due_date,
+        start_date: None,
+        remind_at: None,
+        estimate_minutes: None,
+        priority: template.priority,
+        parent_id: None,
+        icon: None,
+        recurrence: None,
+        list_id: params.list_id,
+        tags: None,
+    };
+    let todo =
+        database::create_todo_with_extras(&pool, create, &template.tags, &template.checklist)
+            .map_err(|_| error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to create todo"))?;
+    activity::record_todo(&pool, &headers, "create", &todo);
+    Ok((StatusCode::CREATED, Json(todo)))
+}
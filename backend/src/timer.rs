@@ -0,0 +1,75 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::Json,
+};
+use serde_json::{json, Value};
+
+use crate::database::{self, DbPool, TimeEntry};
+
+fn error(status: StatusCode, message: &str) -> (StatusCode, Json<Value>) {
+    (status, Json(json!({"error": message})))
+}
+
+fn ensure_todo_exists(pool: &DbPool, id: &str) -> Result<(), (StatusCode, Json<Value>)> {
+    match database::get_todo(pool, id) {
+        Ok(Some(_)) => Ok(()),
+        Ok(None) => Err(error(StatusCode::NOT_FOUND, "Todo not found")),
+        Err(_) => Err(error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to get todo",
+        )),
+    }
+}
+
+/// Time entries of a todo, most recent first.
+pub async fn get_time_entries_handler(
+    State(pool): State<DbPool>,
+    Path(id): Path<String>,
+) -> Result<Json<Vec<TimeEntry>>, (StatusCode, Json<Value>)> {
+    ensure_todo_exists(&pool, &id)?;
+    database::get_time_entries(&pool, &id)
+        .map(Json)
+        .map_err(|_| {
+            error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to get time entries",
+            )
+        })
+}
+
+/// Starts timing work on a todo; the server's clock is used, not the client's.
+pub async fn start_timer_handler(
+    State(pool): State<DbPool>,
+    Path(id): Path<String>,
+) -> Result<(StatusCode, Json<TimeEntry>), (StatusCode, Json<Value>)> {
+    ensure_todo_exists(&pool, &id)?;
+    match database::start_timer(&pool, &id) {
+        Ok(Some(entry)) => Ok((StatusCode::CREATED, Json(entry))),
+        Ok(None) => Err(error(
+            StatusCode::CONFLICT,
+            "The timer is already running for this todo",
+        )),
+        Err(_) => Err(error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to start timer",
+        )),
+    }
+}
+
+pub async fn stop_timer_handler(
+    State(pool): State<DbPool>,
+    Path(id): Path<String>,
+) -> Result<Json<TimeEntry>, (StatusCode, Json<Value>)> {
+    match database::stop_timer(&pool, &id) {
+        Ok(Some(entry)) => Ok(Json(entry)),
+        Ok(None) => Err(error(
+            StatusCode::NOT_FOUND,
+            "The timer is not running for this todo",
+        )),
+        Err(_) => Err(error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to stop timer",
+        )),
+    }
+}
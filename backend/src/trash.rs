@@ -0,0 +1,98 @@
+use std::collections::HashMap;
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::Json,
+};
+use serde_json::{json, Value};
+
+use crate::database::{self, DbPool, SortDirection, SortKey, Todo};
+use crate::handlers;
+
+fn error(status: StatusCode, message: &str) -> (StatusCode, Json<Value>) {
+    (status, Json(json!({"error": message})))
+}
+
+/// Todos in the trash, most recently deleted first; takes the same query parameters as
+/// `GET /api/todos` except `archived`.
+pub async fn get_trash_handler(
+    State(pool): State<DbPool>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<Json<Vec<Todo>>, (StatusCode, Json<Value>)> {
+    let mut filter = handlers::parse_todo_filter(&params)
+        .map_err(|message| error(StatusCode::BAD_REQUEST, &message))?;
+    filter.deleted = true;
+    filter.archived = None;
+    filter.scheduled = None;
+    if filter.sort.is_empty() {
+        filter.sort = vec![SortKey {
+            column: "deleted_at",
+            direction: SortDirection::Desc,
+        }];
+    }
+    database::get_todos(&pool, &filter)
+        .map(Json)
+        .map_err(|_| error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to get trash"))
+}
+
+/// Takes a todo out of the trash, with the subtasks that were deleted along with it.
+pub async fn restore_todo_handler(
+    State(pool): State<DbPool>,
+    Path(id): Path<String>,
+) -> Result<Json<Todo>, (StatusCode, Json<Value>)> {
+    let todo = match database::get_trashed_todo(&pool, &id) {
+        Ok(Some(todo)) => todo,
+        Ok(None) => return Err(error(StatusCode::NOT_FOUND, "Todo not in trash")),
+        Err(_) => {
+            return Err(error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to get todo",
+            ))
+        }
+    };
+    // A subtask can't come back under a parent that is still in the trash
+    if let Some(parent_id) = &todo.parent_id {
+        match database::get_trashed_todo(&pool, parent_id) {
+            Ok(None) => {}
+            Ok(Some(_)) => {
+                return Err((
+                    StatusCode::CONFLICT,
+                    Json(json!({
+                        "error": "The parent todo is in the trash; restore it first",
+                        "parent_id": parent_id,
+                    })),
+                ))
+            }
+            Err(_) => {
+                return Err(error(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "Failed to get todo",
+                ))
+            }
+        }
+    }
+    match database::restore_todo(&pool, &id) {
+        Ok(Some(todo)) => Ok(Json(todo)),
+        Ok(None) => Err(error(StatusCode::NOT_FOUND, "Todo not in trash")),
+        Err(_) => Err(error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to restore todo",
+        )),
+    }
+}
+
+/// Permanently deletes a todo from the trash.
+pub async fn purge_todo_handler(
+    State(pool): State<DbPool>,
+    Path(id): Path<String>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    match database::purge_todo(&pool, &id) {
+        Ok(true) => Ok(Json(json!({"message": "Todo purged successfully"}))),
+        Ok(false) => Err(error(StatusCode::NOT_FOUND, "Todo not in trash")),
+        Err(_) => Err(error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to purge todo",
+        )),
+    }
+}
@@ -0,0 +1,330 @@
+use std::collections::{BTreeMap, HashMap};
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::Json,
+};
+use chrono::{DateTime, Duration, Utc};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::database::{self, DbPool, Todo, View, ViewDefinition};
+use crate::handlers;
+use crate::json_body::JsonBody;
+
+/// Todo fields a view can show
+pub const COLUMNS: [&str; 14] = [
+    "title",
+    "description",
+    "completed",
+    "priority",
+    "due_date",
+    "start_date",
+    "remind_at",
+    "tags",
+    "list_id",
+    "estimate_minutes",
+    "icon",
+    "created_at",
+    "updated_at",
+    "completed_at",
+];
+const DEFAULT_COLUMNS: [&str; 4] = ["title", "due_date", "priority", "tags"];
+/// Fields todos can be grouped by
+pub const GROUPINGS: [&str; 5] = ["list", "priority", "tag", "due_date", "completed"];
+// Filters that can be relative to when the view is shown
+const DATE_FILTERS: [&str; 4] = ["due_before", "due_after", "created_before", "created_after"];
+// Paging belongs to the client and sorting to `sort`
+const RESERVED_FILTERS: [&str; 4] = ["sort", "order", "offset", "cursor"];
+
+#[derive(Debug, Deserialize)]
+pub struct CreateView {
+    pub name: String,
+    pub columns: Option<Vec<String>>,
+    #[serde(default)]
+    pub filters: BTreeMap<String, String>,
+    pub sort: Option<String>,
+    pub group_by: Option<String>,
+    #[serde(default)]
+    pub is_default: bool,
+}
+
+/// Fields left out keep their value; an empty `sort` or `group_by` removes it.
+#[derive(Debug, Deserialize)]
+pub struct UpdateView {
+    pub name: Option<String>,
+    pub columns: Option<Vec<String>>,
+    pub filters: Option<BTreeMap<String, String>>,
+    pub sort: Option<String>,
+    pub group_by: Option<String>,
+    pub is_default: Option<bool>,
+}
+
+fn error(status: StatusCode, message: &str) -> (StatusCode, Json<Value>) {
+    (status, Json(json!({"error": message})))
+}
+
+fn bad_request(message: String) -> (StatusCode, Json<Value>) {
+    error(StatusCode::BAD_REQUEST, &message)
+}
+
+// `now`, `today` or `tomorrow` (midnight UTC), optionally shifted like `today+7d` or `now-12h`
+fn resolve_relative_date(value: &str, now: DateTime<Utc>) -> Option<String> {
+    let split = value.find(['+', '-']).unwrap_or(value.len());
+    let (base, offset) = value.split_at(split);
+    let midnight = now.date_naive().and_hms_opt(0, 0, 0)?.and_utc();
+    let base = match base {
+        "now" => now,
+        "today" => midnight,
+        "tomorrow" => midnight + Duration::days(1),
+        _ => return None,
+    };
+    if offset.is_empty() {
+        return Some(base.to_rfc3339_opts(chrono::SecondsFormat::Secs, true));
+    }
+    let (amount, unit) = offset.split_at(offset.len() - 1);
+    let amount: i64 = amount.parse().ok()?;
+    let shift = match unit {
+        "h" => Duration::hours(amount),
+        "d" => Duration::days(amount),
+        "w" => Duration::weeks(amount),
+        _ => return None,
+    };
+    Some((base + shift).to_rfc3339_opts(chrono::SecondsFormat::Secs, true))
+}
+
+// The query parameters the view stands for right now
+fn view_params(filters: &BTreeMap<String, String>, sort: Option<&str>) -> HashMap<String, String> {
+    let now = Utc::now();
+    let mut params: HashMap<String, String> = filters
+        .iter()
+        .map(|(name, value)| {
+            let value = if DATE_FILTERS.contains(&name.as_str()) {
+                resolve_relative_date(value, now).unwrap_or_else(|| value.clone())
+            } else {
+                value.clone()
+            };
+            (name.clone(), value)
+        })
+        .collect();
+    if let Some(sort) = sort {
+        params.insert("sort".to_string(), sort.to_string());
+    }
+    params
+}
+
+// Checks a definition can be shown and normalizes its name
+fn validate(definition: &mut ViewDefinition) -> Result<(), String> {
+    definition.name = database::normalize_view_name(&definition.name)?;
+    if definition.columns.is_empty() {
+        return Err("A view needs at least one column".to_string());
+    }
+    if let Some(column) = definition
+        .columns
+        .iter()
+        .find(|column| !COLUMNS.contains(&column.as_str()))
+    {
+        return Err(format!(
+            "Unknown column '{column}': expected one of {}",
+            COLUMNS.join(", ")
+        ));
+    }
+    if let Some(name) = definition
+        .filters
+        .keys()
+        .find(|name| RESERVED_FILTERS.contains(&name.as_str()))
+    {
+        return Err(format!("`{name}` cannot be used as a view filter"));
+    }
+    if let Some(group_by) = &definition.group_by {
+        if !GROUPINGS.contains(&group_by.as_str()) {
+            return Err(format!(
+                "Invalid group_by '{group_by}': expected one of {}",
+                GROUPINGS.join(", ")
+            ));
+        }
+    }
+    handlers::parse_todo_filter(&view_params(
+        &definition.filters,
+        definition.sort.as_deref(),
+    ))?;
+    Ok(())
+}
+
+// Names are unique ignoring case; `except` is the view being renamed
+fn ensure_name_free(
+    pool: &DbPool,
+    name: &str,
+    except: Option<&str>,
+) -> Result<(), (StatusCode, Json<Value>)> {
+    match database::find_view_by_name(pool, name) {
+        Ok(Some(existing)) if Some(existing.id.as_str()) != except => Err((
+            StatusCode::CONFLICT,
+            Json(json!({"error": "A view with this name already exists", "existing": existing})),
+        )),
+        Ok(_) => Ok(()),
+        Err(_) => Err(error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to check view name",
+        )),
+    }
+}
+
+fn find_view(pool: &DbPool, id: &str) -> Result<View, (StatusCode, Json<Value>)> {
+    match database::get_view(pool, id) {
+        Ok(Some(view)) => Ok(view),
+        Ok(None) => Err(error(StatusCode::NOT_FOUND, "View not found")),
+        Err(_) => Err(error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to get view",
+        )),
+    }
+}
+
+// The groups a todo belongs to; a todo with several tags is in each of their groups
+fn group_keys(todo: &Todo, group_by: &str) -> Vec<Value> {
+    match group_by {
+        "list" => vec![json!(todo.list_id)],
+        "priority" => vec![json!(todo.priority)],
+        "completed" => vec![json!(todo.completed)],
+        "due_date" => vec![json!(todo.due_date.map(|due| due.date_naive().to_string()))],
+        "tag" if !todo.tags.is_empty() => todo.tags.iter().map(|tag| json!(tag)).collect(),
+        _ => vec![Value::Null],
+    }
+}
+
+// Groups come in the order their first todo does
+fn group(todos: Vec<Todo>, group_by: Option<&str>) -> Vec<Value> {
+    let Some(group_by) = group_by else {
+        return vec![json!({"key": null, "todos": todos})];
+    };
+    let mut groups: Vec<(Value, Vec<Todo>)> = Vec::new();
+    for todo in todos {
+        for key in group_keys(&todo, group_by) {
+            match groups.iter_mut().find(|(existing, _)| *existing == key) {
+                Some((_, members)) => members.push(todo.clone()),
+                None => groups.push((key, vec![todo.clone()])),
+            }
+        }
+    }
+    groups
+        .into_iter()
+        .map(|(key, todos)| json!({"key": key, "todos": todos}))
+        .collect()
+}
+
+pub async fn get_views_handler(
+    State(pool): State<DbPool>,
+) -> Result<Json<Vec<View>>, (StatusCode, Json<Value>)> {
+    database::get_views(&pool)
+        .map(Json)
+        .map_err(|_| error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to get views"))
+}
+
+/// The view clients open first.
+pub async fn get_default_view_handler(
+    State(pool): State<DbPool>,
+) -> Result<Json<View>, (StatusCode, Json<Value>)> {
+    match database::get_default_view(&pool) {
+        Ok(Some(view)) => Ok(Json(view)),
+        Ok(None) => Err(error(StatusCode::NOT_FOUND, "No view is the default")),
+        Err(_) => Err(error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to get view",
+        )),
+    }
+}
+
+pub async fn get_view_handler(
+    State(pool): State<DbPool>,
+    Path(id): Path<String>,
+) -> Result<Json<View>, (StatusCode, Json<Value>)> {
+    find_view(&pool, &id).map(Json)
+}
+
+pub async fn create_view_handler(
+    State(pool): State<DbPool>,
+    JsonBody(body): JsonBody<CreateView>,
+) -> Result<(StatusCode, Json<View>), (StatusCode, Json<Value>)> {
+    let mut definition = ViewDefinition {
+        name: body.name,
+        columns: body
+            .columns
+            .unwrap_or_else(|| DEFAULT_COLUMNS.map(String::from).to_vec()),
+        filters: body.filters,
+        sort: body.sort.filter(|sort| !sort.is_empty()),
+        group_by: body.group_by.filter(|group_by| !group_by.is_empty()),
+        is_default: body.is_default,
+    };
+    validate(&mut definition).map_err(bad_request)?;
+    ensure_name_free(&pool, &definition.name, None)?;
+    database::create_view(&pool, &definition)
+        .map(|view| (StatusCode::CREATED, Json(view)))
+        .map_err(|_| error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to create view"))
+}
+
+pub async fn update_view_handler(
+    State(pool): State<DbPool>,
+    Path(id): Path<String>,
+    JsonBody(body): JsonBody<UpdateView>,
+) -> Result<Json<View>, (StatusCode, Json<Value>)> {
+    let view = find_view(&pool, &id)?;
+    let mut definition = ViewDefinition {
+        name: body.name.unwrap_or(view.name),
+        columns: body.columns.unwrap_or(view.columns),
+        filters: body.filters.unwrap_or(view.filters),
+        sort: match body.sort {
+            Some(sort) => Some(sort).filter(|sort| !sort.is_empty()),
+            None => view.sort,
+        },
+        group_by: match body.group_by {
+            Some(group_by) => Some(group_by).filter(|group_by| !group_by.is_empty()),
+            None => view.group_by,
+        },
+        is_default: body.is_default.unwrap_or(view.is_default),
+    };
+    validate(&mut definition).map_err(bad_request)?;
+    ensure_name_free(&pool, &definition.name, Some(&id))?;
+    match database::update_view(&pool, &id, &definition) {
+        Ok(Some(view)) => Ok(Json(view)),
+        Ok(None) => Err(error(StatusCode::NOT_FOUND, "View not found")),
+        Err(_) => Err(error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to update view",
+        )),
+    }
+}
+
+pub async fn delete_view_handler(
+    State(pool): State<DbPool>,
+    Path(id): Path<String>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    match database::delete_view(&pool, &id) {
+        Ok(true) => Ok(Json(json!({"message": "View deleted successfully"}))),
+        Ok(false) => Err(error(StatusCode::NOT_FOUND, "View not found")),
+        Err(_) => Err(error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to delete view",
+        )),
+    }
+}
+
+/// The todos of a view as of now, sorted and grouped the way the view says.
+pub async fn get_view_todos_handler(
+    State(pool): State<DbPool>,
+    Path(id): Path<String>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let view = find_view(&pool, &id)?;
+    let filter = handlers::parse_todo_filter(&view_params(&view.filters, view.sort.as_deref()))
+        .map_err(|message| {
+            error(
+                StatusCode::CONFLICT,
+                &format!("The view's filters can no longer be applied: {message}"),
+            )
+        })?;
+    let todos = database::get_todos(&pool, &filter)
+        .map_err(|_| error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to get todos"))?;
+    let groups = group(todos, view.group_by.as_deref());
+    Ok(Json(json!({ "view": view, "groups": groups })))
+}